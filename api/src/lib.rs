@@ -6,8 +6,9 @@
 //! All endpoints require a valid API key, passed via the `Authorization` header
 //! using the `ApiKey` scheme.
 use codedefender_config::{AnalysisResult, Config};
-use reqwest::{StatusCode, blocking::Client};
+use reqwest::{StatusCode, blocking::{Client, Response}};
 use std::collections::HashMap;
+use std::fmt;
 use once_cell::sync::Lazy;
 pub use codedefender_config;
 pub use serde_json;
@@ -27,12 +28,97 @@ pub static DEFEND_EP: Lazy<String> =
     Lazy::new(|| format!("{}/api/defend", *BASE_URL));
 pub static DOWNLOAD_EP: Lazy<String> =
     Lazy::new(|| format!("{}/api/download", *BASE_URL));
+pub static WHOAMI_EP: Lazy<String> =
+    Lazy::new(|| format!("{}/api/whoami", *BASE_URL));
 
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Status {
     Ready(String),
     Processing,
 }
 
+/// The account an API key belongs to, as returned by [`whoami`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Whoami {
+    /// Name of the account.
+    pub account: String,
+    /// Newest config version the service accepts, if it reports one.
+    pub config_version: Option<String>,
+    /// Newest symbol stream version the service reads, if it reports one.
+    pub symbol_format: Option<u16>,
+}
+
+/// Error returned by the `try_*` functions. `context` names the operation that failed.
+#[derive(Debug)]
+pub enum ApiError {
+    /// The request could not be sent or its response could not be read.
+    Network { context: &'static str, source: reqwest::Error },
+    /// The API key was rejected (401 or 403).
+    Unauthorized { context: &'static str, status: StatusCode },
+    /// The server responded with an unexpected status code.
+    Status { context: &'static str, status: StatusCode },
+    /// The response body did not have the expected shape.
+    InvalidResponse { context: &'static str, message: String },
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::Network { context, source } => write!(f, "Failed to {}: {}", context, source),
+            ApiError::Unauthorized { context, status } => write!(f, "Failed to {}: API key rejected ({})", context, status),
+            ApiError::Status { context, status } => write!(f, "Failed to {}: unexpected status {}", context, status),
+            ApiError::InvalidResponse { context, message } => write!(f, "Failed to {}: invalid response: {}", context, message),
+        }
+    }
+}
+
+impl ApiError {
+    /// Whether the request may succeed if sent again: network failures, rate limiting and server errors.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            ApiError::Network { source, .. } => !source.is_builder(),
+            ApiError::Status { status, .. } => {
+                status.is_server_error() || *status == StatusCode::TOO_MANY_REQUESTS || *status == StatusCode::REQUEST_TIMEOUT
+            }
+            ApiError::Unauthorized { .. } | ApiError::InvalidResponse { .. } => false,
+        }
+    }
+
+    /// Whether the request never reached the server because no connection could be made, so sending it again cannot repeat its effect.
+    pub fn is_unsent(&self) -> bool {
+        matches!(self, ApiError::Network { source, .. } if source.is_connect())
+    }
+}
+
+impl std::error::Error for ApiError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ApiError::Network { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+fn network(context: &'static str) -> impl FnOnce(reqwest::Error) -> ApiError {
+    move |source| ApiError::Network { context, source }
+}
+
+fn invalid(context: &'static str, message: impl ToString) -> ApiError {
+    ApiError::InvalidResponse { context, message: message.to_string() }
+}
+
+/// Turns 401/403 into [`ApiError::Unauthorized`] and any other non-success status into [`ApiError::Status`].
+fn check_status(response: Response, context: &'static str) -> Result<Response, ApiError> {
+    let status = response.status();
+    if status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN {
+        Err(ApiError::Unauthorized { context, status })
+    } else if !status.is_success() {
+        Err(ApiError::Status { context, status })
+    } else {
+        Ok(response)
+    }
+}
+
 /// Gets the presigned upload URL and file ID for uploading a file.
 ///
 /// # Arguments
@@ -55,6 +141,16 @@ pub fn get_upload_info(
     client: &Client,
     api_key: &str,
 ) -> (String, String) {
+    try_get_upload_info(file_size, file_name, client, api_key).unwrap_or_else(|e| panic!("{}", e))
+}
+/// Fallible version of [`get_upload_info`].
+pub fn try_get_upload_info(
+    file_size: usize,
+    file_name: Option<String>,
+    client: &Client,
+    api_key: &str,
+) -> Result<(String, String), ApiError> {
+    const CONTEXT: &str = "get upload URL";
     let mut query_params = HashMap::new();
     query_params.insert("fileSize".to_string(), file_size.to_string());
     if let Some(name) = file_name {
@@ -65,13 +161,11 @@ pub fn get_upload_info(
         .header("Authorization", format!("ApiKey {}", api_key))
         .query(&query_params)
         .send()
-        .expect("Failed to send request to get upload URL")
-        .error_for_status()
-        .expect("Non-success status when getting upload URL");
-    let json: HashMap<String, String> = response.json().expect("Failed to parse JSON response for upload info");
-    let upload_url = json.get("uploadUrl").cloned().expect("Missing 'uploadUrl' in response");
-    let file_id = json.get("fileId").cloned().expect("Missing 'fileId' in response");
-    (file_id, upload_url)
+        .map_err(network(CONTEXT))?;
+    let json: HashMap<String, String> = check_status(response, CONTEXT)?.json().map_err(|e| invalid(CONTEXT, e))?;
+    let upload_url = json.get("uploadUrl").cloned().ok_or_else(|| invalid(CONTEXT, "missing 'uploadUrl'"))?;
+    let file_id = json.get("fileId").cloned().ok_or_else(|| invalid(CONTEXT, "missing 'fileId'"))?;
+    Ok((file_id, upload_url))
 }
 /// Uploads file bytes to the presigned S3 URL.
 ///
@@ -89,15 +183,24 @@ pub fn upload_to_s3(
     file_bytes: Vec<u8>,
     client: &Client,
 ) {
-    client
+    try_upload_to_s3(upload_url, file_bytes, client).unwrap_or_else(|e| panic!("{}", e))
+}
+/// Fallible version of [`upload_to_s3`].
+pub fn try_upload_to_s3(
+    upload_url: &str,
+    file_bytes: Vec<u8>,
+    client: &Client,
+) -> Result<(), ApiError> {
+    const CONTEXT: &str = "upload to S3";
+    let response = client
         .put(upload_url)
         .header("Content-Type", "application/octet-stream")
         .header("Content-Length", file_bytes.len().to_string())
         .body(file_bytes)
         .send()
-        .expect("Failed to send upload request to S3")
-        .error_for_status()
-        .expect("Non-success status when uploading to S3");
+        .map_err(network(CONTEXT))?;
+    check_status(response, CONTEXT)?;
+    Ok(())
 }
 /// Uploads raw data bytes to CodeDefender with a specific filename and returns the file ID.
 ///
@@ -121,10 +224,19 @@ pub fn upload_data(
     client: &Client,
     api_key: &str,
 ) -> String {
+    try_upload_data(data, filename, client, api_key).unwrap_or_else(|e| panic!("{}", e))
+}
+/// Fallible version of [`upload_data`].
+pub fn try_upload_data(
+    data: Vec<u8>,
+    filename: String,
+    client: &Client,
+    api_key: &str,
+) -> Result<String, ApiError> {
     let file_size = data.len();
-    let (file_id, upload_url) = get_upload_info(file_size, Some(filename), client, api_key);
-    upload_to_s3(&upload_url, data, client);
-    file_id
+    let (file_id, upload_url) = try_get_upload_info(file_size, Some(filename), client, api_key)?;
+    try_upload_to_s3(&upload_url, data, client)?;
+    Ok(file_id)
 }
 /// Uploads a binary file to CodeDefender and returns a UUID representing the uploaded file.
 ///
@@ -146,10 +258,18 @@ pub fn upload_file(
     client: &Client,
     api_key: &str,
 ) -> String {
+    try_upload_file(file_bytes, client, api_key).unwrap_or_else(|e| panic!("{}", e))
+}
+/// Fallible version of [`upload_file`].
+pub fn try_upload_file(
+    file_bytes: Vec<u8>,
+    client: &Client,
+    api_key: &str,
+) -> Result<String, ApiError> {
     let file_size = file_bytes.len();
-    let (file_id, upload_url) = get_upload_info(file_size, None, client, api_key);
-    upload_to_s3(&upload_url, file_bytes, client);
-    file_id
+    let (file_id, upload_url) = try_get_upload_info(file_size, None, client, api_key)?;
+    try_upload_to_s3(&upload_url, file_bytes, client)?;
+    Ok(file_id)
 }
 /// Starts analysis of a previously uploaded binary file and optionally its PDB file.
 ///
@@ -173,6 +293,16 @@ pub fn start_analyze(
     client: &Client,
     api_key: &str,
 ) -> String {
+    try_start_analyze(file_id, pdb_file_id, client, api_key).unwrap_or_else(|e| panic!("{}", e))
+}
+/// Fallible version of [`start_analyze`].
+pub fn try_start_analyze(
+    file_id: String,
+    pdb_file_id: Option<String>,
+    client: &Client,
+    api_key: &str,
+) -> Result<String, ApiError> {
+    const CONTEXT: &str = "start analysis";
     let mut query_params = HashMap::new();
     query_params.insert("fileId".to_string(), file_id);
     if let Some(pdb_id) = pdb_file_id {
@@ -183,15 +313,11 @@ pub fn start_analyze(
         .header("Authorization", format!("ApiKey {}", api_key))
         .query(&query_params)
         .send()
-        .expect("Failed to send request to start analysis")
-        .error_for_status()
-        .expect("Non-success status when starting analysis");
-    let json: HashMap<String, String> = response.json().expect("Failed to parse JSON response for analysis start");
-    let execution_id = json
-        .get("executionId")
+        .map_err(network(CONTEXT))?;
+    let json: HashMap<String, String> = check_status(response, CONTEXT)?.json().map_err(|e| invalid(CONTEXT, e))?;
+    json.get("executionId")
         .cloned()
-        .expect("Missing 'executionId' in response");
-    execution_id
+        .ok_or_else(|| invalid(CONTEXT, "missing 'executionId'"))
 }
 /// Polls the analysis status.
 ///
@@ -211,6 +337,11 @@ pub fn start_analyze(
 ///
 /// Panics if the request fails, the server responds with a non-success status, or required fields are missing, with a descriptive message.
 pub fn get_analyze_status(execution_id: String, client: &Client, api_key: &str) -> Status {
+    try_get_analyze_status(execution_id, client, api_key).unwrap_or_else(|e| panic!("{}", e))
+}
+/// Fallible version of [`get_analyze_status`].
+pub fn try_get_analyze_status(execution_id: String, client: &Client, api_key: &str) -> Result<Status, ApiError> {
+    const CONTEXT: &str = "get analysis status";
     let mut query_params = HashMap::new();
     query_params.insert("executionId".to_string(), execution_id);
     let resp = client
@@ -218,16 +349,16 @@ pub fn get_analyze_status(execution_id: String, client: &Client, api_key: &str)
         .header("Authorization", format!("ApiKey {}", api_key))
         .query(&query_params)
         .send()
-        .expect("Failed to send request for analysis status");
+        .map_err(network(CONTEXT))?;
     let status = resp.status();
     if status == StatusCode::ACCEPTED {
-        Status::Processing
+        Ok(Status::Processing)
     } else if status == StatusCode::OK {
-        let json: serde_json::Value = resp.json().expect("Failed to parse JSON for analysis status");
-        let value = json["analysisUrl"].as_str().expect("Missing 'analysisUrl' in response");
-        Status::Ready(value.to_string())
+        let json: serde_json::Value = resp.json().map_err(|e| invalid(CONTEXT, e))?;
+        let value = json["analysisUrl"].as_str().ok_or_else(|| invalid(CONTEXT, "missing 'analysisUrl'"))?;
+        Ok(Status::Ready(value.to_string()))
     } else {
-        panic!("Unexpected status code when getting analysis status: {}", status);
+        Err(check_status(resp, CONTEXT).err().unwrap_or(ApiError::Status { context: CONTEXT, status }))
     }
 }
 /// Downloads and deserializes the analysis result from the presigned URL.
@@ -248,11 +379,17 @@ pub fn download_analysis_result(
     analysis_url: &str,
     client: &Client,
 ) -> AnalysisResult {
-    let response = client.get(analysis_url).send().expect("Failed to send request to download analysis result")
-        .error_for_status().expect("Non-success status when downloading analysis result");
-    let result_bytes = response.bytes().expect("Failed to read bytes from analysis response");
-    let analysis_result: AnalysisResult = serde_json::from_slice(&result_bytes).expect("Failed to deserialize analysis result");
-    analysis_result
+    try_download_analysis_result(analysis_url, client).unwrap_or_else(|e| panic!("{}", e))
+}
+/// Fallible version of [`download_analysis_result`].
+pub fn try_download_analysis_result(
+    analysis_url: &str,
+    client: &Client,
+) -> Result<AnalysisResult, ApiError> {
+    const CONTEXT: &str = "download analysis result";
+    let response = client.get(analysis_url).send().map_err(network(CONTEXT))?;
+    let result_bytes = check_status(response, CONTEXT)?.bytes().map_err(network(CONTEXT))?;
+    serde_json::from_slice(&result_bytes).map_err(|e| invalid(CONTEXT, e))
 }
 /// Starts the obfuscation process for a given file using the provided configuration.
 ///
@@ -276,7 +413,17 @@ pub fn defend(
     client: &Client,
     api_key: &str,
 ) -> String {
-    let body = serde_json::to_string(&config).expect("Failed to serialize Config");
+    try_defend(uuid, config, client, api_key).unwrap_or_else(|e| panic!("{}", e))
+}
+/// Fallible version of [`defend`].
+pub fn try_defend(
+    uuid: String,
+    config: Config,
+    client: &Client,
+    api_key: &str,
+) -> Result<String, ApiError> {
+    const CONTEXT: &str = "start obfuscation";
+    let body = serde_json::to_string(&config).map_err(|e| invalid(CONTEXT, e))?;
     let mut query_params = HashMap::new();
     query_params.insert("fileId", uuid);
     let response = client
@@ -286,10 +433,8 @@ pub fn defend(
         .query(&query_params)
         .body(body)
         .send()
-        .expect("Failed to send request to start obfuscation")
-        .error_for_status()
-        .expect("Non-success status when starting obfuscation");
-    response.text().expect("Failed to read text from obfuscation start response")
+        .map_err(network(CONTEXT))?;
+    check_status(response, CONTEXT)?.text().map_err(network(CONTEXT))
 }
 /// Polls the obfuscation status.
 ///
@@ -311,6 +456,11 @@ pub fn defend(
 ///
 /// Panics if the request fails, the server responds with a non-success status, or required fields are missing, with a descriptive message.
 pub fn download(execution_id: String, client: &Client, api_key: &str) -> Status {
+    try_download(execution_id, client, api_key).unwrap_or_else(|e| panic!("{}", e))
+}
+/// Fallible version of [`download`].
+pub fn try_download(execution_id: String, client: &Client, api_key: &str) -> Result<Status, ApiError> {
+    const CONTEXT: &str = "get download status";
     let mut query_params = HashMap::new();
     query_params.insert("executionId".to_string(), execution_id);
     let resp = client
@@ -318,16 +468,16 @@ pub fn download(execution_id: String, client: &Client, api_key: &str) -> Status
         .header("Authorization", format!("ApiKey {}", api_key))
         .query(&query_params)
         .send()
-        .expect("Failed to send request for download status");
+        .map_err(network(CONTEXT))?;
     let status = resp.status();
     if status == StatusCode::ACCEPTED {
-        Status::Processing
+        Ok(Status::Processing)
     } else if status == StatusCode::OK {
-        let json: serde_json::Value = resp.json().expect("Failed to parse JSON for download status");
-        let value = json["downloadUrl"].as_str().expect("Missing 'downloadUrl' in response");
-        Status::Ready(value.to_string())
+        let json: serde_json::Value = resp.json().map_err(|e| invalid(CONTEXT, e))?;
+        let value = json["downloadUrl"].as_str().ok_or_else(|| invalid(CONTEXT, "missing 'downloadUrl'"))?;
+        Ok(Status::Ready(value.to_string()))
     } else {
-        panic!("Unexpected status code when getting download status: {}", status);
+        Err(check_status(resp, CONTEXT).err().unwrap_or(ApiError::Status { context: CONTEXT, status }))
     }
 }
 /// Downloads the obfuscated file from the presigned URL.
@@ -348,8 +498,48 @@ pub fn download_obfuscated_file(
     download_url: &str,
     client: &Client,
 ) -> Vec<u8> {
-    let response = client.get(download_url).send().expect("Failed to send request to download obfuscated file")
-        .error_for_status().expect("Non-success status when downloading obfuscated file");
-    let bytes = response.bytes().expect("Failed to read bytes from download response");
-    bytes.to_vec()
-}
\ No newline at end of file
+    try_download_obfuscated_file(download_url, client).unwrap_or_else(|e| panic!("{}", e))
+}
+/// Fallible version of [`download_obfuscated_file`].
+pub fn try_download_obfuscated_file(
+    download_url: &str,
+    client: &Client,
+) -> Result<Vec<u8>, ApiError> {
+    const CONTEXT: &str = "download obfuscated file";
+    let response = client.get(download_url).send().map_err(network(CONTEXT))?;
+    let bytes = check_status(response, CONTEXT)?.bytes().map_err(network(CONTEXT))?;
+    Ok(bytes.to_vec())
+}
+/// Looks up the account of an API key, which also checks that the key is valid.
+///
+/// # Arguments
+///
+/// * `client` - A preconfigured `reqwest::blocking::Client`.
+/// * `api_key` - Your CodeDefender API key.
+///
+/// # Returns
+///
+/// The account name, and the config version and symbol format the service accepts.
+///
+/// # Panics
+///
+/// Panics if the request fails, the key is rejected, or required fields are missing, with a descriptive message.
+pub fn whoami(client: &Client, api_key: &str) -> Whoami {
+    try_whoami(client, api_key).unwrap_or_else(|e| panic!("{}", e))
+}
+/// Fallible version of [`whoami`].
+pub fn try_whoami(client: &Client, api_key: &str) -> Result<Whoami, ApiError> {
+    const CONTEXT: &str = "look up the API key";
+    let response = client
+        .get(&*WHOAMI_EP)
+        .header("Authorization", format!("ApiKey {}", api_key))
+        .send()
+        .map_err(network(CONTEXT))?;
+    let json: serde_json::Value = check_status(response, CONTEXT)?.json().map_err(|e| invalid(CONTEXT, e))?;
+    let account = json["account"].as_str().ok_or_else(|| invalid(CONTEXT, "missing 'account'"))?;
+    Ok(Whoami {
+        account: account.to_string(),
+        config_version: json["configVersion"].as_str().map(str::to_string),
+        symbol_format: json["symbolFormat"].as_u64().and_then(|v| u16::try_from(v).ok()),
+    })
+}
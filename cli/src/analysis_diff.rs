@@ -0,0 +1,184 @@
+//! `analysis-diff`, comparing the analysis results of two builds.
+
+use codedefender_api::codedefender_config::{AnalysisReject, AnalysisResult};
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    io::{self, Write},
+};
+
+/// A function rejected in the new build but not in the old one.
+#[derive(Serialize, Debug)]
+pub struct NewReject {
+    pub symbol: String,
+    pub rva: u64,
+    pub reason: String,
+    /// The function was protectable in the old build, rather than new code.
+    pub regression: bool,
+    /// Suggested fix, see [`AnalysisReject::remediation`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fix: Option<&'static str>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Rename {
+    pub rva: u64,
+    pub old: String,
+    pub new: String,
+}
+
+/// Differences between two analysis results. Named functions are matched by name, since their
+/// RVAs move between builds, and unnamed ones by RVA.
+#[derive(Serialize, Debug, Default)]
+pub struct AnalysisDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    /// Functions at the same RVA under another name, which usually means a renamed function in an
+    /// otherwise unchanged layout.
+    pub renamed: Vec<Rename>,
+    pub newly_rejected: Vec<NewReject>,
+    /// Functions rejected in the old build that are protectable now.
+    pub no_longer_rejected: Vec<String>,
+}
+
+/// Name a function is matched by.
+fn key(symbol: &str, rva: u64) -> String {
+    if symbol.is_empty() {
+        format!("{:#x}", rva)
+    } else {
+        symbol.to_owned()
+    }
+}
+
+fn functions(analysis: &AnalysisResult) -> BTreeMap<String, u64> {
+    analysis
+        .functions
+        .iter()
+        .map(|f| (key(&f.symbol, f.rva), f.rva))
+        .collect()
+}
+
+fn rejects(analysis: &AnalysisResult) -> BTreeMap<String, &AnalysisReject> {
+    analysis
+        .rejects
+        .iter()
+        .map(|r| (key(&r.symbol, r.rva), r))
+        .collect()
+}
+
+impl AnalysisDiff {
+    pub fn new(old: &AnalysisResult, new: &AnalysisResult) -> Self {
+        let (old_functions, new_functions) = (functions(old), functions(new));
+        let (old_rejects, new_rejects) = (rejects(old), rejects(new));
+
+        let mut diff = Self::default();
+        let mut removed: BTreeMap<u64, &String> = BTreeMap::new();
+        for (name, rva) in &old_functions {
+            if !new_functions.contains_key(name) && !new_rejects.contains_key(name) {
+                removed.insert(*rva, name);
+            }
+        }
+        for (name, rva) in &new_functions {
+            if old_functions.contains_key(name) {
+                continue;
+            }
+            if old_rejects.contains_key(name) {
+                diff.no_longer_rejected.push(name.clone());
+            } else if let Some(old_name) = removed.remove(rva) {
+                diff.renamed.push(Rename {
+                    rva: *rva,
+                    old: old_name.clone(),
+                    new: name.clone(),
+                });
+            } else {
+                diff.added.push(name.clone());
+            }
+        }
+        diff.removed = removed.into_values().cloned().collect();
+        diff.removed.sort();
+        for (name, reject) in &new_rejects {
+            if !old_rejects.contains_key(name) {
+                diff.newly_rejected.push(NewReject {
+                    symbol: name.clone(),
+                    rva: reject.rva,
+                    reason: reject.reason.clone(),
+                    regression: old_functions.contains_key(name),
+                    fix: reject.remediation(),
+                });
+            }
+        }
+        // Regressions first, they are what reviewers look for.
+        diff.newly_rejected.sort_by_key(|r| !r.regression);
+        diff
+    }
+
+    /// Whether a function that was protectable before is rejected now.
+    pub fn has_regressions(&self) -> bool {
+        self.newly_rejected.iter().any(|r| r.regression)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.renamed.is_empty()
+            && self.newly_rejected.is_empty()
+            && self.no_longer_rejected.is_empty()
+    }
+
+    pub fn print(&self, out: &mut impl Write) -> io::Result<()> {
+        if self.is_empty() {
+            return writeln!(out, "No differences");
+        }
+        if !self.newly_rejected.is_empty() {
+            writeln!(out, "Newly rejected ({}):", self.newly_rejected.len())?;
+            for reject in &self.newly_rejected {
+                let marker = if reject.regression {
+                    "was protectable"
+                } else {
+                    "new"
+                };
+                writeln!(
+                    out,
+                    "  ! {} ({:#x}, {}): {}",
+                    reject.symbol, reject.rva, marker, reject.reason
+                )?;
+                if let Some(fix) = reject.fix {
+                    writeln!(out, "    fix: {}", fix)?;
+                }
+            }
+        }
+        if !self.no_longer_rejected.is_empty() {
+            writeln!(
+                out,
+                "No longer rejected ({}):",
+                self.no_longer_rejected.len()
+            )?;
+            for name in &self.no_longer_rejected {
+                writeln!(out, "  * {}", name)?;
+            }
+        }
+        if !self.renamed.is_empty() {
+            writeln!(out, "Renamed ({}):", self.renamed.len())?;
+            for rename in &self.renamed {
+                writeln!(
+                    out,
+                    "  ~ {} -> {} ({:#x})",
+                    rename.old, rename.new, rename.rva
+                )?;
+            }
+        }
+        if !self.added.is_empty() {
+            writeln!(out, "Added ({}):", self.added.len())?;
+            for name in &self.added {
+                writeln!(out, "  + {}", name)?;
+            }
+        }
+        if !self.removed.is_empty() {
+            writeln!(out, "Removed ({}):", self.removed.len())?;
+            for name in &self.removed {
+                writeln!(out, "  - {}", name)?;
+            }
+        }
+        Ok(())
+    }
+}
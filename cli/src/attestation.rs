@@ -0,0 +1,121 @@
+//! Signed in-toto attestations of the obfuscation step, using SLSA provenance as predicate and a
+//! DSSE envelope signed with Ed25519.
+
+use crate::manifest::{self, Artifact, Manifest};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use codedefender_api::serde_json::{self, Value, json};
+use ring::signature::{Ed25519KeyPair, KeyPair};
+use std::{fs, path::Path};
+
+const PAYLOAD_TYPE: &str = "application/vnd.in-toto+json";
+const BUILD_TYPE: &str = "https://codedefender.io/cli/obfuscate/v1";
+const BUILDER_ID: &str = "https://app.codedefender.io";
+
+/// An Ed25519 signing key and its key id, the SHA-256 of the public key.
+pub struct SigningKey {
+    key_pair: Ed25519KeyPair,
+    key_id: String,
+}
+
+impl SigningKey {
+    /// Reads a PKCS#8 Ed25519 private key, PEM or DER encoded, as written by
+    /// `openssl genpkey -algorithm ed25519`.
+    pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let bytes = fs::read(path)?;
+        let der = match std::str::from_utf8(&bytes) {
+            Ok(pem) if pem.contains("-----BEGIN") => {
+                let body: String = pem
+                    .lines()
+                    .filter(|line| !line.starts_with("-----"))
+                    .collect();
+                STANDARD.decode(body.trim())?
+            }
+            _ => bytes,
+        };
+        let key_pair = Ed25519KeyPair::from_pkcs8_maybe_unchecked(&der)
+            .map_err(|e| format!("{:?} is not a PKCS#8 Ed25519 private key: {}", path, e))?;
+        let key_id = manifest::hex_digest(key_pair.public_key().as_ref());
+        Ok(Self { key_pair, key_id })
+    }
+}
+
+fn descriptor(artifact: &Artifact) -> Value {
+    json!({
+        "name": artifact.path.file_name().unwrap_or_default().to_string_lossy(),
+        "digest": { "sha256": artifact.sha256 },
+    })
+}
+
+/// The in-toto statement for a run: the outputs are the subjects, the input, PDB and config are
+/// the resolved dependencies.
+pub fn statement(manifest: &Manifest) -> Value {
+    let mut dependencies = vec![descriptor(&manifest.input)];
+    dependencies.extend(manifest.pdb.as_ref().map(descriptor));
+    dependencies.push(descriptor(&manifest.config));
+    json!({
+        "_type": "https://in-toto.io/Statement/v1",
+        "subject": manifest.outputs.iter().map(descriptor).collect::<Vec<_>>(),
+        "predicateType": "https://slsa.dev/provenance/v1",
+        "predicate": {
+            "buildDefinition": {
+                "buildType": BUILD_TYPE,
+                "externalParameters": {
+                    "input": manifest.input.path,
+                    "config": manifest.config.path,
+                    "overrides": manifest.overrides,
+                    "configVersion": manifest.config_version,
+                },
+                "internalParameters": {
+                    "fileId": manifest.file_id,
+                    "analysis": manifest.analysis,
+                },
+                "resolvedDependencies": dependencies,
+            },
+            "runDetails": {
+                "builder": {
+                    "id": BUILDER_ID,
+                    "version": { "codedefender-cli": manifest.cli_version },
+                },
+                "metadata": {
+                    "invocationId": manifest.execution_id,
+                    "startedOn": manifest.started_at,
+                    "finishedOn": manifest.finished_at,
+                },
+            },
+        },
+    })
+}
+
+/// DSSE pre-authentication encoding of a payload.
+fn pae(payload_type: &str, payload: &[u8]) -> Vec<u8> {
+    let mut message = format!(
+        "DSSEv1 {} {} {} ",
+        payload_type.len(),
+        payload_type,
+        payload.len()
+    )
+    .into_bytes();
+    message.extend_from_slice(payload);
+    message
+}
+
+/// Signs the statement of `manifest` and writes the DSSE envelope to `path`.
+pub fn write(
+    manifest: &Manifest,
+    key: &SigningKey,
+    path: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let payload = serde_json::to_vec(&statement(manifest))?;
+    let signature = key.key_pair.sign(&pae(PAYLOAD_TYPE, &payload));
+    let envelope = json!({
+        "payloadType": PAYLOAD_TYPE,
+        "payload": STANDARD.encode(&payload),
+        "signatures": [{
+            "keyid": key.key_id,
+            "sig": STANDARD.encode(signature.as_ref()),
+        }],
+    });
+    fs::write(path, serde_json::to_vec_pretty(&envelope)?)?;
+    log::info!("Attestation written to {:?}", path);
+    Ok(())
+}
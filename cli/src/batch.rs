@@ -0,0 +1,391 @@
+use crate::cache::{Cache, CacheEntry};
+use crate::exit::{self, ExitStatus, failure};
+use crate::glob;
+use crate::manifest;
+use crate::pdb_search;
+use crate::pipeline;
+use crate::report::Reporter;
+use codedefender_api::codedefender_config::YamlConfig;
+use codedefender_api::serde_json::json;
+use codedefender_symbols::SymbolOptions;
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// One binary of a batch, as listed in a manifest file.
+#[derive(Deserialize, Debug, Clone)]
+pub struct BatchEntry {
+    /// Input binary.
+    pub input: PathBuf,
+    /// Optional debug symbol (PDB) file.
+    #[serde(default)]
+    pub pdb: Option<PathBuf>,
+    /// Output path for the Zip file.
+    pub output: PathBuf,
+}
+
+/// Reads a YAML manifest listing `input`, `pdb` and `output` for each binary. Relative paths are
+/// resolved against the directory of the manifest.
+pub fn read_manifest(path: &Path) -> Result<Vec<BatchEntry>, Box<dyn std::error::Error>> {
+    let mut entries: Vec<BatchEntry> = serde_yaml::from_str(&fs::read_to_string(path)?)?;
+    let base = path.parent().unwrap_or(Path::new(""));
+    for entry in &mut entries {
+        entry.input = base.join(&entry.input);
+        entry.pdb = entry.pdb.as_ref().map(|pdb| base.join(pdb));
+        entry.output = base.join(&entry.output);
+    }
+    Ok(entries)
+}
+
+/// Builds entries for input file patterns. Each output is `output_template` filled from the input
+/// and placed in `output_dir`, or next to the input without one. A PDB with the same stem next to
+/// the input is picked up automatically, see [`pdb_search::find`].
+pub fn entries_from_inputs(
+    patterns: &[PathBuf],
+    output_dir: Option<&Path>,
+    output_template: &str,
+    servers: Option<&pdb_search::SymbolServers>,
+) -> Result<Vec<BatchEntry>, Box<dyn std::error::Error>> {
+    let mut entries = Vec::new();
+    for pattern in patterns {
+        for input in glob::expand(pattern)? {
+            let name = glob::render_template(output_template, &input)?;
+            let output = match output_dir {
+                Some(dir) => dir.join(name),
+                None => input.parent().unwrap_or(Path::new("")).join(name),
+            };
+            if output == input {
+                return Err(format!("Output template would overwrite input {:?}", input).into());
+            }
+            entries.push(BatchEntry {
+                pdb: pdb_search::find(&input, servers),
+                input,
+                output,
+            });
+        }
+    }
+    Ok(entries)
+}
+
+/// Spaces out status requests shared by all workers so that a batch stays within the rate limit of
+/// the status endpoints no matter how many binaries are in flight.
+pub struct RateLimiter {
+    interval: Duration,
+    next: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    /// Allows at most `per_second` requests per second.
+    pub fn new(per_second: u32) -> Self {
+        Self {
+            interval: Duration::from_secs(1) / per_second.max(1),
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Blocks until the next request slot.
+    pub fn wait(&self) {
+        let slot = {
+            let mut next = self.next.lock().unwrap_or_else(|e| e.into_inner());
+            let slot = (*next).max(Instant::now());
+            *next = slot + self.interval;
+            slot
+        };
+        std::thread::sleep(slot.saturating_duration_since(Instant::now()));
+    }
+}
+
+/// Settings shared by every worker of a batch.
+pub struct BatchOptions<'a> {
+    pub config: &'a YamlConfig,
+    pub client: &'a reqwest::blocking::Client,
+    pub api_key: &'a str,
+    pub poll_interval: Duration,
+    pub analysis_timeout: Duration,
+    pub obfuscation_timeout: Duration,
+    /// How many binaries are processed at the same time.
+    pub jobs: usize,
+    pub limiter: RateLimiter,
+    pub cache: Option<Cache>,
+    /// Verify every output, see [`pipeline::verify_output`].
+    pub verify: bool,
+    /// Required coverage of every binary, see [`pipeline::check_coverage`].
+    pub min_coverage: Option<f64>,
+    pub on_reject: pipeline::RejectPolicy,
+    /// How the symbols are preprocessed before they are uploaded.
+    pub symbols: SymbolOptions,
+}
+
+/// Binaries with identical contents and PDB, uploaded, analyzed and obfuscated once.
+struct Unit {
+    entries: Vec<BatchEntry>,
+    input_sha256: String,
+    pdb_sha256: Option<String>,
+    /// Upload and analysis reused from the cache.
+    cached: Option<CacheEntry>,
+    file_id: Option<String>,
+    execution_id: Option<String>,
+    output_sha256: Option<String>,
+    error: Option<(ExitStatus, String)>,
+}
+
+impl Unit {
+    fn name(&self) -> String {
+        self.entries[0].input.display().to_string()
+    }
+}
+
+/// Calls `f` on every item from `jobs` threads.
+fn for_each_parallel<T: Send>(items: &mut [T], jobs: usize, f: impl Fn(&mut T) + Sync) {
+    let workers = jobs.clamp(1, items.len().max(1));
+    let queue = Mutex::new(items.iter_mut());
+    std::thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| {
+                loop {
+                    let Some(item) = queue.lock().unwrap_or_else(|e| e.into_inner()).next() else {
+                        break;
+                    };
+                    f(item);
+                }
+            });
+        }
+    });
+}
+
+/// Polls `f` through the shared rate limiter until it yields a value or `timeout` passes.
+fn wait_for<T>(
+    stage: &str,
+    timeout: Duration,
+    options: &BatchOptions,
+    mut f: impl FnMut() -> Result<Option<T>, Box<dyn std::error::Error>>,
+) -> Result<T, Box<dyn std::error::Error>> {
+    let start_time = Instant::now();
+    loop {
+        if start_time.elapsed() > timeout {
+            return Err(failure(ExitStatus::Timeout, format!("{} timed out", stage)));
+        }
+        options.limiter.wait();
+        if let Some(value) = f()? {
+            return Ok(value);
+        }
+        std::thread::sleep(options.poll_interval);
+    }
+}
+
+/// Uploads, analyzes and obfuscates `unit`, uploading its symbols with `symbols`, the
+/// [negotiated](pipeline::negotiate_symbols) `options.symbols`.
+fn process(
+    unit: &mut Unit,
+    symbols: &SymbolOptions,
+    options: &BatchOptions,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (file_id, analysis) = match unit.cached.take() {
+        Some(entry) => {
+            log::info!("Reusing cached upload of {}", unit.name());
+            (entry.file_id, entry.analysis)
+        }
+        None => {
+            let entry = &unit.entries[0];
+            let input_bytes = fs::read(&entry.input)?;
+            let pdb_file_id = pipeline::upload_binary_symbols(
+                &input_bytes,
+                entry.pdb.as_deref(),
+                symbols,
+                options.config,
+                options.client,
+                options.api_key,
+            )?;
+            let file_id = pipeline::upload_binary(
+                input_bytes,
+                options.config,
+                options.client,
+                options.api_key,
+            )?;
+            log::info!("Uploaded {}, starting analysis...", unit.name());
+
+            let analyze_execution_id =
+                pipeline::start_analysis(&file_id, pdb_file_id, options.client, options.api_key)?;
+            let analysis = wait_for("Analysis", options.analysis_timeout, options, || {
+                pipeline::poll_analysis(&analyze_execution_id, options.client, options.api_key)
+            })?;
+            log::info!("Analysis of {} finished...", unit.name());
+            if let Some(cache) = &options.cache {
+                let key = Cache::key(
+                    &unit.input_sha256,
+                    unit.pdb_sha256.as_deref(),
+                    &options.symbols,
+                    options.config,
+                    options.api_key,
+                );
+                cache.put(&key, &file_id, &analysis);
+            }
+            (file_id, analysis)
+        }
+    };
+    unit.file_id = Some(file_id.clone());
+
+    let cdconfig = pipeline::build_config(options.config, &analysis, options.on_reject)?;
+    pipeline::check_coverage(&cdconfig, &analysis, options.min_coverage)?;
+    let execution_id =
+        pipeline::start_obfuscation(&file_id, cdconfig, options.client, options.api_key)?;
+    unit.execution_id = Some(execution_id.clone());
+    let url = wait_for("Obfuscation", options.obfuscation_timeout, options, || {
+        pipeline::poll_output(&execution_id, options.client, options.api_key)
+    })?;
+    let artifact = pipeline::download_output(&url, options.client)?;
+    finish_unit(options.config, options.verify, unit, &artifact)?;
+    log::info!("{} obfuscated", unit.name());
+    Ok(())
+}
+
+/// Uploads, analyzes and obfuscates up to `options.jobs` binaries at the same time. Identical
+/// binaries with the same PDB are only uploaded once, and not at all when cached. A failing binary
+/// does not stop the others.
+pub fn run(
+    entries: Vec<BatchEntry>,
+    options: &BatchOptions,
+    reporter: &mut Reporter,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut units: Vec<Unit> = Vec::new();
+    let mut unit_keys: HashMap<(String, Option<String>), usize> = HashMap::new();
+
+    for entry in entries {
+        let input = fs::read(&entry.input)?;
+        let input_sha256 = manifest::hex_digest(&input);
+        let pdb_sha256 = match &entry.pdb {
+            Some(path) => {
+                pdb_search::check(&input, path)
+                    .map_err(|e| failure(ExitStatus::Error, format!("{:?}: {}", entry.input, e)))?;
+                Some(manifest::hex_digest_file(path)?)
+            }
+            None => None,
+        };
+
+        let key = (input_sha256.clone(), pdb_sha256.clone());
+        if let Some(&index) = unit_keys.get(&key) {
+            log::info!(
+                "{:?} is identical to {}, sharing its upload",
+                entry.input,
+                units[index].name()
+            );
+            units[index].entries.push(entry);
+            continue;
+        }
+        let cached = options.cache.as_ref().and_then(|cache| {
+            cache.get(&Cache::key(
+                &input_sha256,
+                pdb_sha256.as_deref(),
+                &options.symbols,
+                options.config,
+                options.api_key,
+            ))
+        });
+        unit_keys.insert(key, units.len());
+        units.push(Unit {
+            entries: vec![entry],
+            input_sha256,
+            pdb_sha256,
+            cached,
+            file_id: None,
+            execution_id: None,
+            output_sha256: None,
+            error: None,
+        });
+    }
+
+    let symbols = match units.iter().all(|unit| unit.cached.is_some()) {
+        true => options.symbols.clone(),
+        false => pipeline::negotiate_symbols(&options.symbols, options.client, options.api_key),
+    };
+    for_each_parallel(&mut units, options.jobs, |unit| {
+        if let Err(e) = process(unit, &symbols, options) {
+            log::error!("{}: {}", unit.name(), e);
+            unit.error = Some((exit::status_of(&*e), e.to_string()));
+        }
+    });
+    reporter.stage_done("batch");
+
+    report(&units, reporter)
+}
+
+fn finish_unit(
+    config: &YamlConfig,
+    verify: bool,
+    unit: &mut Unit,
+    artifact: &[u8],
+) -> Result<(), Box<dyn std::error::Error>> {
+    pipeline::check_output(config, &unit.entries[0].input, artifact)?;
+    if verify {
+        pipeline::verify_output(config, &unit.entries[0].input, artifact)?;
+    }
+    unit.output_sha256 = Some(manifest::hex_digest(artifact));
+    for entry in &unit.entries {
+        if let Some(parent) = entry.output.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&entry.output, artifact)?;
+    }
+    Ok(())
+}
+
+/// Prints one line per binary in text mode, or a `binary` event per binary in JSON mode.
+fn report(units: &[Unit], reporter: &Reporter) -> Result<(), Box<dyn std::error::Error>> {
+    let mut failed = 0;
+    let mut total = 0;
+    let mut statuses = Vec::new();
+    for unit in units {
+        let error = unit.error.as_ref().map(|(status, error)| {
+            statuses.push(*status);
+            error.as_str()
+        });
+        for entry in &unit.entries {
+            total += 1;
+            if error.is_some() {
+                failed += 1;
+            }
+            if reporter.is_json() {
+                reporter.event(
+                    "binary",
+                    json!({
+                        "input": entry.input,
+                        "output": entry.output,
+                        "success": error.is_none(),
+                        "error": error,
+                        "file_id": unit.file_id,
+                        "execution_id": unit.execution_id,
+                        "input_sha256": unit.input_sha256,
+                        "output_sha256": unit.output_sha256,
+                    }),
+                );
+            } else {
+                match error {
+                    None => println!(
+                        "ok      {} -> {}",
+                        entry.input.display(),
+                        entry.output.display()
+                    ),
+                    Some(error) => println!("failed  {}: {}", entry.input.display(), error),
+                }
+            }
+        }
+    }
+    if failed > 0 {
+        // Report the common cause when every failure has the same one.
+        let status = match statuses.split_first() {
+            Some((first, rest)) if rest.iter().all(|s| s == first) => *first,
+            _ => ExitStatus::Error,
+        };
+        return Err(failure(
+            status,
+            format!("{} of {} binaries failed", failed, total),
+        ));
+    }
+    log::info!("All {} binaries obfuscated", total);
+    Ok(())
+}
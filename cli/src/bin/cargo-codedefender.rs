@@ -0,0 +1,29 @@
+//! `cargo codedefender ...`, forwarded to `codedefender-cli cargo ...` installed next to this
+//! binary.
+
+use std::{
+    env,
+    process::{Command, ExitCode},
+};
+
+fn main() -> ExitCode {
+    let cli = match env::current_exe() {
+        Ok(exe) => exe.with_file_name(format!("codedefender-cli{}", env::consts::EXE_SUFFIX)),
+        Err(e) => {
+            eprintln!("Cannot locate codedefender-cli: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let mut args = env::args_os().skip(1).peekable();
+    // Cargo passes the name of the subcommand as the first argument.
+    if args.peek().is_some_and(|arg| arg == "codedefender") {
+        args.next();
+    }
+    match Command::new(&cli).arg("cargo").args(args).status() {
+        Ok(status) => ExitCode::from(status.code().unwrap_or(1) as u8),
+        Err(e) => {
+            eprintln!("Failed to run {:?}: {}", cli, e);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,225 @@
+//! On-disk cache of uploads and analysis results, keyed by the content of the inputs.
+
+use crate::manifest;
+use codedefender_api::codedefender_config::{AnalysisResult, YamlConfig};
+use codedefender_api::serde_json;
+use codedefender_symbols::{Compression, SymbolOptions};
+use serde::{Deserialize, Serialize};
+use std::{
+    env, fs, io,
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// A cached upload and the analysis of it.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CacheEntry {
+    pub file_id: String,
+    /// Stored in the versioned binary format, so entries survive upgrades of the config crate.
+    #[serde(with = "stored_analysis")]
+    pub analysis: AnalysisResult,
+    /// Unix time the entry was last read or written.
+    pub last_used: u64,
+}
+
+/// State of the cache directory, as reported by `doctor`.
+#[derive(Serialize, Debug, Default)]
+pub struct CacheHealth {
+    pub entries: usize,
+    pub bytes: usize,
+    /// Entries that are corrupt or written by an incompatible version.
+    pub unreadable: usize,
+}
+
+pub struct Cache {
+    dir: PathBuf,
+}
+
+/// (De)serializes an analysis as base64 of its binary format. Entries written before it was used
+/// hold the analysis as plain JSON, which is still read.
+mod stored_analysis {
+    use base64::{Engine, engine::general_purpose::STANDARD};
+    use codedefender_api::codedefender_config::{AnalysisResult, persist::AnalysisFormat};
+    use codedefender_api::serde_json::{self, Value};
+    use serde::{Deserialize, Deserializer, Serializer, de::Error};
+
+    pub fn serialize<S: Serializer>(
+        analysis: &AnalysisResult,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let bytes = analysis
+            .to_bytes(AnalysisFormat::Binary)
+            .map_err(serde::ser::Error::custom)?;
+        serializer.serialize_str(&STANDARD.encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<AnalysisResult, D::Error> {
+        match Value::deserialize(deserializer)? {
+            Value::String(encoded) => {
+                let bytes = STANDARD.decode(encoded).map_err(D::Error::custom)?;
+                AnalysisResult::from_bytes(&bytes).map_err(D::Error::custom)
+            }
+            legacy => serde_json::from_value(legacy).map_err(D::Error::custom),
+        }
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+impl Cache {
+    /// `CODEDEFENDER_CACHE_DIR`, or `codedefender` in the platform cache directory.
+    pub fn default_dir() -> Option<PathBuf> {
+        if let Some(dir) = env::var_os("CODEDEFENDER_CACHE_DIR") {
+            return Some(PathBuf::from(dir));
+        }
+        let base = env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("LOCALAPPDATA").map(PathBuf::from))
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))?;
+        Some(base.join("codedefender"))
+    }
+
+    /// Opens the default cache. Returns `None` when `disabled` or no cache directory is usable.
+    pub fn open(disabled: bool) -> Option<Self> {
+        if disabled {
+            return None;
+        }
+        let dir = Self::default_dir()?;
+        if let Err(e) = fs::create_dir_all(&dir) {
+            log::warn!("Upload cache disabled, cannot create {:?}: {}", dir, e);
+            return None;
+        }
+        Some(Self { dir })
+    }
+
+    /// Cache key for a binary and PDB analyzed with the disassembly settings of `config`, the PDB
+    /// preprocessed with `symbols` and the contents of the static libraries it names. How the
+    /// symbols are compressed does not change the analysis and is left out. Uploads are only valid
+    /// on the backend they were made to, such as a `mock-server`, and for the account of
+    /// `api_key`, so both are part of the key.
+    pub fn key(
+        input_sha256: &str,
+        pdb_sha256: Option<&str>,
+        symbols: &SymbolOptions,
+        config: &YamlConfig,
+        api_key: &str,
+    ) -> String {
+        let settings = serde_json::to_string(&(
+            &config.disassembly_settings,
+            config.profile_disassembly_settings(),
+        ))
+        .unwrap_or_default();
+        let libraries: Vec<String> = symbols
+            .libraries
+            .iter()
+            .map(|path| manifest::hex_digest_file(path).unwrap_or_default())
+            .collect();
+        let symbols = serde_json::to_string(&SymbolOptions {
+            compression: Compression::default(),
+            ..symbols.clone()
+        })
+        .unwrap_or_default();
+        manifest::hex_digest(
+            format!(
+                "{}\n{}\n{}\n{:?}\n{}\n{}\n{}",
+                input_sha256,
+                pdb_sha256.unwrap_or_default(),
+                symbols,
+                libraries,
+                settings,
+                *codedefender_api::BASE_URL,
+                api_key
+            )
+            .as_bytes(),
+        )
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(key).with_extension("json")
+    }
+
+    /// Looks up an entry and marks it as used.
+    pub fn get(&self, key: &str) -> Option<CacheEntry> {
+        let bytes = fs::read(self.path(key)).ok()?;
+        let mut entry: CacheEntry = match serde_json::from_slice(&bytes) {
+            Ok(entry) => entry,
+            Err(e) => {
+                log::warn!("Ignoring unreadable cache entry {}: {}", key, e);
+                return None;
+            }
+        };
+        entry.last_used = now();
+        self.write(key, &entry);
+        Some(entry)
+    }
+
+    /// Stores an upload and its analysis. Failures only log a warning.
+    pub fn put(&self, key: &str, file_id: &str, analysis: &AnalysisResult) {
+        let entry = CacheEntry {
+            file_id: file_id.to_owned(),
+            analysis: analysis.clone(),
+            last_used: now(),
+        };
+        self.write(key, &entry);
+    }
+
+    fn write(&self, key: &str, entry: &CacheEntry) {
+        let result = serde_json::to_vec(entry)
+            .map_err(|e| e.to_string())
+            .and_then(|bytes| fs::write(self.path(key), bytes).map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            log::warn!("Failed to write cache entry {}: {}", key, e);
+        }
+    }
+
+    /// Counts the entries, their total size and those that cannot be read, and checks that new
+    /// entries can be written.
+    pub fn health(&self) -> io::Result<CacheHealth> {
+        let probe = self.dir.join(".write-test");
+        fs::write(&probe, b"")?;
+        fs::remove_file(&probe)?;
+        let mut health = CacheHealth::default();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().is_none_or(|ext| ext != "json") {
+                continue;
+            }
+            health.entries += 1;
+            let bytes = fs::read(&path)?;
+            health.bytes += bytes.len();
+            if serde_json::from_slice::<CacheEntry>(&bytes).is_err() {
+                health.unreadable += 1;
+            }
+        }
+        Ok(health)
+    }
+
+    /// Removes entries not used within `max_age`. Returns how many were removed and kept.
+    pub fn gc(&self, max_age: Duration) -> Result<(usize, usize), Box<dyn std::error::Error>> {
+        let cutoff = now().saturating_sub(max_age.as_secs());
+        let (mut removed, mut kept) = (0, 0);
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().is_none_or(|ext| ext != "json") {
+                continue;
+            }
+            let last_used = fs::read(&path)
+                .ok()
+                .and_then(|bytes| serde_json::from_slice::<CacheEntry>(&bytes).ok())
+                .map_or(0, |entry| entry.last_used);
+            if last_used < cutoff || max_age.is_zero() {
+                fs::remove_file(&path)?;
+                removed += 1;
+            } else {
+                kept += 1;
+            }
+        }
+        Ok((removed, kept))
+    }
+}
@@ -0,0 +1,239 @@
+//! Locating the artifacts of a Cargo project, for `cargo codedefender`.
+
+use clap::Args;
+use codedefender_api::serde_json;
+use serde::Deserialize;
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// Which artifact of a Cargo project to protect.
+#[derive(Args, Debug)]
+pub struct CargoTarget {
+    /// Path to Cargo.toml
+    #[arg(long, value_name = "PATH")]
+    pub manifest_path: Option<PathBuf>,
+    /// Package to protect, required in workspaces with several binary packages
+    #[arg(short, long, value_name = "SPEC")]
+    pub package: Option<String>,
+    /// Protect this binary of the package
+    #[arg(long, value_name = "NAME", conflicts_with = "lib")]
+    pub bin: Option<String>,
+    /// Protect the cdylib of the package
+    #[arg(long)]
+    pub lib: bool,
+    /// Cargo profile the artifact was built with
+    #[arg(long, value_name = "PROFILE", default_value = "release")]
+    pub profile: String,
+    /// Target triple the artifact was built for, e.g. `x86_64-pc-windows-msvc`
+    #[arg(long, value_name = "TRIPLE")]
+    pub target: Option<String>,
+    /// Use the existing artifact instead of running `cargo build` first
+    #[arg(long)]
+    pub no_build: bool,
+}
+
+/// The binary produced by Cargo and the PDB next to it.
+#[derive(Debug)]
+pub struct CargoArtifact {
+    pub binary: PathBuf,
+    pub pdb: Option<PathBuf>,
+}
+
+#[derive(Deserialize)]
+struct Metadata {
+    packages: Vec<Package>,
+    workspace_members: Vec<String>,
+    target_directory: PathBuf,
+}
+
+#[derive(Deserialize)]
+struct Package {
+    id: String,
+    name: String,
+    targets: Vec<Target>,
+}
+
+#[derive(Deserialize)]
+struct Target {
+    name: String,
+    kind: Vec<String>,
+}
+
+impl Target {
+    fn is_bin(&self) -> bool {
+        self.kind.iter().any(|k| k == "bin")
+    }
+
+    fn is_cdylib(&self) -> bool {
+        self.kind.iter().any(|k| k == "cdylib")
+    }
+}
+
+fn cargo() -> Command {
+    // Cargo sets `CARGO` for the subcommands it runs.
+    Command::new(env::var_os("CARGO").unwrap_or_else(|| "cargo".into()))
+}
+
+impl CargoTarget {
+    fn command(&self, subcommand: &str) -> Command {
+        let mut command = cargo();
+        command.arg(subcommand);
+        if let Some(path) = &self.manifest_path {
+            command.arg("--manifest-path").arg(path);
+        }
+        command
+    }
+
+    /// Runs `cargo build` for the selected artifact.
+    pub fn build(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let mut command = self.command("build");
+        command.args(["--profile", &self.profile]);
+        if let Some(package) = &self.package {
+            command.args(["--package", package]);
+        }
+        if let Some(bin) = &self.bin {
+            command.args(["--bin", bin]);
+        }
+        if self.lib {
+            command.arg("--lib");
+        }
+        if let Some(target) = &self.target {
+            command.args(["--target", target]);
+        }
+        log::info!("Running {:?}", command);
+        let status = command.status()?;
+        if !status.success() {
+            return Err(format!("cargo build failed with {}", status).into());
+        }
+        Ok(())
+    }
+
+    fn metadata(&self) -> Result<Metadata, Box<dyn std::error::Error>> {
+        let output = self
+            .command("metadata")
+            .args(["--format-version", "1", "--no-deps"])
+            .output()?;
+        if !output.status.success() {
+            return Err(format!(
+                "cargo metadata failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            )
+            .into());
+        }
+        Ok(serde_json::from_slice(&output.stdout)?)
+    }
+
+    /// Finds the artifact in the target directory from `cargo metadata`.
+    pub fn locate(&self) -> Result<CargoArtifact, Box<dyn std::error::Error>> {
+        let windows = match &self.target {
+            Some(target) => target.contains("windows"),
+            None => cfg!(windows),
+        };
+        if !windows {
+            return Err(
+                "CodeDefender protects Windows binaries, pass a Windows `--target` triple".into(),
+            );
+        }
+
+        let metadata = self.metadata()?;
+        let wanted = |target: &Target| match (&self.bin, self.lib) {
+            (Some(bin), _) => target.is_bin() && &target.name == bin,
+            (None, true) => target.is_cdylib(),
+            (None, false) => target.is_bin() || target.is_cdylib(),
+        };
+        let candidates: Vec<(&Package, &Target)> = metadata
+            .packages
+            .iter()
+            .filter(|p| metadata.workspace_members.contains(&p.id))
+            .filter(|p| self.package.as_ref().is_none_or(|name| &p.name == name))
+            .flat_map(|p| p.targets.iter().filter(|t| wanted(t)).map(move |t| (p, t)))
+            .collect();
+        let (package, target) = match candidates.as_slice() {
+            [candidate] => *candidate,
+            [] => return Err("No matching binary or cdylib target in the workspace".into()),
+            _ => {
+                let names: Vec<String> = candidates
+                    .iter()
+                    .map(|(p, t)| format!("{}/{}", p.name, t.name))
+                    .collect();
+                return Err(format!(
+                    "Several targets match, select one with --package, --bin or --lib: {}",
+                    names.join(", ")
+                )
+                .into());
+            }
+        };
+
+        let profile_dir = match self.profile.as_str() {
+            "dev" | "test" => "debug",
+            "bench" => "release",
+            profile => profile,
+        };
+        let mut dir = metadata.target_directory;
+        if let Some(triple) = &self.target {
+            dir.push(triple);
+        }
+        dir.push(profile_dir);
+
+        // rustc names libraries and debug files with underscores.
+        let crate_name = target.name.replace('-', "_");
+        let binary = if target.is_bin() {
+            dir.join(format!("{}.exe", target.name))
+        } else {
+            dir.join(format!("{}.dll", crate_name))
+        };
+        if !binary.is_file() {
+            return Err(format!(
+                "{:?} of package `{}` was not found, build it first",
+                binary, package.name
+            )
+            .into());
+        }
+        let pdb = Some(dir.join(format!("{}.pdb", crate_name))).filter(|pdb| pdb.is_file());
+        Ok(CargoArtifact { binary, pdb })
+    }
+}
+
+/// Moves the unprotected binary and PDB aside as `<name>.unprotected.<ext>` and puts the protected
+/// files from `extracted` in their place.
+pub fn replace(
+    artifact: &CargoArtifact,
+    extracted: &[PathBuf],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let originals = std::iter::once(&artifact.binary).chain(&artifact.pdb);
+    for original in originals {
+        let extension = original.extension().unwrap_or_default().to_string_lossy();
+        let protected = extracted.iter().find(|path| {
+            path.to_string_lossy()
+                .ends_with(&format!(".protected.{}", extension))
+        });
+        let Some(protected) = protected else {
+            continue;
+        };
+        let backup = with_suffix(original, "unprotected");
+        fs::rename(original, &backup)?;
+        fs::rename(protected, original)?;
+        log::info!(
+            "Replaced {:?}, the original is kept as {:?}",
+            original,
+            backup
+        );
+    }
+    Ok(())
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    match path.extension() {
+        Some(extension) => path.with_file_name(format!(
+            "{}.{}.{}",
+            stem,
+            suffix,
+            extension.to_string_lossy()
+        )),
+        None => path.with_file_name(format!("{}.{}", stem, suffix)),
+    }
+}
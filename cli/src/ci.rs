@@ -0,0 +1,199 @@
+//! Annotations and step outputs for CI systems, enabled with `--ci`.
+
+use crate::manifest;
+use crate::report::RunResult;
+use clap::ValueEnum;
+use codedefender_api::serde_json::{self, json};
+use std::{
+    env, fs,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{Mutex, OnceLock},
+};
+
+/// Code Quality report picked up with `artifacts:reports:codequality`.
+pub const GITLAB_CODE_QUALITY_REPORT: &str = "gl-code-quality-report.json";
+/// Step outputs picked up with `artifacts:reports:dotenv`.
+pub const GITLAB_DOTENV: &str = "codedefender.env";
+
+/// CI system the output is formatted for.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CiProvider {
+    /// Workflow command annotations and `$GITHUB_OUTPUT` step outputs.
+    Github,
+    /// A Code Quality report and a dotenv report with the step outputs.
+    Gitlab,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+/// A warning or error to show on the pipeline, optionally at a position in a file.
+#[derive(Debug, Clone)]
+pub struct Finding {
+    pub severity: Severity,
+    pub message: String,
+    pub file: Option<PathBuf>,
+    /// One based line and column.
+    pub position: Option<(usize, usize)>,
+}
+
+static PROVIDER: OnceLock<CiProvider> = OnceLock::new();
+/// Config file findings without their own file are reported against.
+static CONFIG: OnceLock<PathBuf> = OnceLock::new();
+static FINDINGS: Mutex<Vec<Finding>> = Mutex::new(Vec::new());
+
+pub fn init(provider: Option<CiProvider>) {
+    if let Some(provider) = provider {
+        let _ = PROVIDER.set(provider);
+    }
+}
+
+pub fn is_enabled() -> bool {
+    PROVIDER.get().is_some()
+}
+
+/// Remembers the config of this run, which GitLab needs as location of findings.
+pub fn set_config(path: &Path) {
+    let _ = CONFIG.set(path.to_path_buf());
+}
+
+fn escape_data(text: &str) -> String {
+    text.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+fn escape_property(text: &str) -> String {
+    escape_data(text).replace(':', "%3A").replace(',', "%2C")
+}
+
+/// Reports a finding. GitHub annotations are printed right away, GitLab findings are written to
+/// the Code Quality report by [`finish`].
+pub fn annotate(finding: Finding) {
+    match PROVIDER.get() {
+        Some(CiProvider::Github) => {
+            let command = match finding.severity {
+                Severity::Warning => "warning",
+                Severity::Error => "error",
+            };
+            let mut properties = vec!["title=CodeDefender".to_owned()];
+            if let Some(file) = &finding.file {
+                properties.push(format!("file={}", escape_property(&file.to_string_lossy())));
+            }
+            if let Some((line, column)) = finding.position {
+                properties.push(format!("line={}", line));
+                properties.push(format!("col={}", column));
+            }
+            // The runner reads workflow commands from stderr too, which keeps stdout parseable.
+            eprintln!(
+                "::{} {}::{}",
+                command,
+                properties.join(","),
+                escape_data(&finding.message)
+            );
+        }
+        Some(CiProvider::Gitlab) => {
+            if let Ok(mut findings) = FINDINGS.lock() {
+                findings.push(finding);
+            }
+        }
+        None => {}
+    }
+}
+
+fn outputs(result: &RunResult) -> Vec<(&'static str, String)> {
+    let mut outputs = vec![
+        ("success", result.success.to_string()),
+        ("exit_code", result.exit_code.to_string()),
+    ];
+    let optional = [
+        ("file_id", result.file_id.clone()),
+        ("execution_id", result.execution_id.clone()),
+        (
+            "output",
+            result.output.as_ref().map(|p| p.display().to_string()),
+        ),
+        ("input_sha256", result.input_sha256.clone()),
+        ("output_sha256", result.output_sha256.clone()),
+    ];
+    outputs.extend(
+        optional
+            .into_iter()
+            .filter_map(|(name, value)| Some((name, value?))),
+    );
+    if !result.artifacts.is_empty() {
+        outputs.push((
+            "artifacts",
+            serde_json::to_string(&result.artifacts).unwrap_or_default(),
+        ));
+    }
+    outputs
+}
+
+fn write_github_outputs(result: &RunResult) -> std::io::Result<()> {
+    let Some(path) = env::var_os("GITHUB_OUTPUT") else {
+        log::warn!("GITHUB_OUTPUT is not set, step outputs are not written");
+        return Ok(());
+    };
+    let mut file = fs::OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(path)?;
+    for (name, value) in outputs(result) {
+        writeln!(file, "{}={}", name, value)?;
+    }
+    Ok(())
+}
+
+fn write_gitlab_reports(result: &RunResult) -> std::io::Result<()> {
+    let dotenv: String = outputs(result)
+        .into_iter()
+        .map(|(name, value)| format!("CODEDEFENDER_{}={}\n", name.to_uppercase(), value))
+        .collect();
+    fs::write(GITLAB_DOTENV, dotenv)?;
+
+    let config = CONFIG.get();
+    let findings = FINDINGS.lock().map(|f| f.clone()).unwrap_or_default();
+    let report: Vec<_> = findings
+        .iter()
+        .map(|finding| {
+            let path = finding
+                .file
+                .as_ref()
+                .or(config)
+                .map(|p| p.display().to_string())
+                .unwrap_or_default();
+            let line = finding.position.map_or(1, |(line, _)| line);
+            json!({
+                "description": finding.message,
+                "check_name": "codedefender",
+                "fingerprint": manifest::hex_digest(format!("{}:{}:{}", path, line, finding.message).as_bytes()),
+                "severity": match finding.severity {
+                    Severity::Warning => "minor",
+                    Severity::Error => "major",
+                },
+                "location": { "path": path, "lines": { "begin": line } },
+            })
+        })
+        .collect();
+    fs::write(
+        GITLAB_CODE_QUALITY_REPORT,
+        serde_json::to_vec_pretty(&report).unwrap_or_default(),
+    )
+}
+
+/// Writes the step outputs, and for GitLab the Code Quality report.
+pub fn finish(result: &RunResult) {
+    let written = match PROVIDER.get() {
+        Some(CiProvider::Github) => write_github_outputs(result),
+        Some(CiProvider::Gitlab) => write_gitlab_reports(result),
+        None => Ok(()),
+    };
+    if let Err(e) = written {
+        log::warn!("Failed to write CI reports: {}", e);
+    }
+}
@@ -0,0 +1,194 @@
+//! `compare`, measuring what obfuscation changed between an original and a protected binary.
+
+use crate::output::{entropy, export_names, import_names};
+use object::{Object, ObjectSection};
+use serde::Serialize;
+use std::{
+    collections::BTreeSet,
+    io::{self, Write},
+};
+
+/// Size and entropy of a section.
+#[derive(Serialize, Debug, Clone, Copy)]
+pub struct SectionInfo {
+    /// Bytes in the file.
+    pub size: u64,
+    pub virtual_size: u64,
+    /// Bits per byte of the data in the file.
+    pub entropy: f64,
+}
+
+/// A section in either binary. `before` or `after` is `None` for added and removed sections.
+#[derive(Serialize, Debug)]
+pub struct SectionDelta {
+    pub name: String,
+    pub before: Option<SectionInfo>,
+    pub after: Option<SectionInfo>,
+}
+
+#[derive(Serialize, Debug, PartialEq, Eq)]
+pub struct EntryPoint {
+    pub rva: u64,
+    /// Section the entry point lies in.
+    pub section: Option<String>,
+}
+
+/// Differences between an original binary and its protected build.
+#[derive(Serialize, Debug)]
+pub struct PeComparison {
+    pub size_before: usize,
+    pub size_after: usize,
+    pub entry_before: EntryPoint,
+    pub entry_after: EntryPoint,
+    /// Sections in the order of the original, followed by the added ones.
+    pub sections: Vec<SectionDelta>,
+    pub imports_added: Vec<String>,
+    pub imports_removed: Vec<String>,
+    pub exports_added: Vec<String>,
+    pub exports_removed: Vec<String>,
+}
+
+fn sections(file: &object::File) -> object::Result<Vec<(String, SectionInfo)>> {
+    let mut sections = Vec::new();
+    for section in file.sections() {
+        let data = section.data()?;
+        let info = SectionInfo {
+            size: section.file_range().map_or(0, |(_, size)| size),
+            virtual_size: section.size(),
+            entropy: if data.is_empty() { 0.0 } else { entropy(data) },
+        };
+        sections.push((section.name()?.to_owned(), info));
+    }
+    Ok(sections)
+}
+
+fn entry_point(file: &object::File) -> EntryPoint {
+    let rva = file.entry().wrapping_sub(file.relative_address_base());
+    let section = file
+        .sections()
+        .find(|s| {
+            let start = s.address().wrapping_sub(file.relative_address_base());
+            (start..start + s.size()).contains(&rva)
+        })
+        .and_then(|s| s.name().ok().map(str::to_owned));
+    EntryPoint { rva, section }
+}
+
+fn difference(a: &BTreeSet<String>, b: &BTreeSet<String>) -> Vec<String> {
+    a.difference(b).cloned().collect()
+}
+
+impl PeComparison {
+    pub fn new(original: &[u8], protected: &[u8]) -> object::Result<Self> {
+        let before = object::File::parse(original)?;
+        let after = object::File::parse(protected)?;
+
+        let mut after_sections = sections(&after)?;
+        let mut deltas = Vec::new();
+        for (name, info) in sections(&before)? {
+            let matching = after_sections.iter().position(|(n, _)| *n == name);
+            deltas.push(SectionDelta {
+                after: matching.map(|i| after_sections.remove(i).1),
+                before: Some(info),
+                name,
+            });
+        }
+        deltas.extend(after_sections.into_iter().map(|(name, info)| SectionDelta {
+            name,
+            before: None,
+            after: Some(info),
+        }));
+
+        let (imports_before, imports_after) = (import_names(&before)?, import_names(&after)?);
+        let (exports_before, exports_after) = (export_names(&before)?, export_names(&after)?);
+        Ok(Self {
+            size_before: original.len(),
+            size_after: protected.len(),
+            entry_before: entry_point(&before),
+            entry_after: entry_point(&after),
+            sections: deltas,
+            imports_added: difference(&imports_after, &imports_before),
+            imports_removed: difference(&imports_before, &imports_after),
+            exports_added: difference(&exports_after, &exports_before),
+            exports_removed: difference(&exports_before, &exports_after),
+        })
+    }
+
+    pub fn print(&self, out: &mut impl Write) -> io::Result<()> {
+        let growth =
+            (self.size_after as f64 - self.size_before as f64) * 100.0 / self.size_before as f64;
+        writeln!(
+            out,
+            "Size: {} -> {} bytes ({:+.1}%)",
+            self.size_before, self.size_after, growth
+        )?;
+        let entry = |entry: &EntryPoint| match &entry.section {
+            Some(section) => format!("{:#x} ({})", entry.rva, section),
+            None => format!("{:#x}", entry.rva),
+        };
+        if self.entry_before == self.entry_after {
+            writeln!(
+                out,
+                "Entry point: {} (unchanged)",
+                entry(&self.entry_before)
+            )?;
+        } else {
+            writeln!(
+                out,
+                "Entry point: {} -> {}",
+                entry(&self.entry_before),
+                entry(&self.entry_after)
+            )?;
+        }
+
+        writeln!(out, "Sections:")?;
+        let width = self
+            .sections
+            .iter()
+            .map(|s| s.name.len())
+            .max()
+            .unwrap_or(0);
+        for section in &self.sections {
+            match (&section.before, &section.after) {
+                (Some(before), Some(after)) => writeln!(
+                    out,
+                    "    {:width$}  {} -> {} bytes ({:+}), entropy {:.2} -> {:.2}",
+                    section.name,
+                    before.size,
+                    after.size,
+                    after.size as i64 - before.size as i64,
+                    before.entropy,
+                    after.entropy
+                )?,
+                (None, Some(after)) => writeln!(
+                    out,
+                    "  + {:width$}  {} bytes, entropy {:.2}",
+                    section.name, after.size, after.entropy
+                )?,
+                (Some(before), None) => writeln!(
+                    out,
+                    "  - {:width$}  {} bytes, entropy {:.2}",
+                    section.name, before.size, before.entropy
+                )?,
+                (None, None) => {}
+            }
+        }
+
+        let lists = [
+            ("Imports added", "+", &self.imports_added),
+            ("Imports removed", "-", &self.imports_removed),
+            ("Exports added", "+", &self.exports_added),
+            ("Exports removed", "-", &self.exports_removed),
+        ];
+        for (title, marker, names) in lists {
+            if names.is_empty() {
+                continue;
+            }
+            writeln!(out, "{} ({}):", title, names.len())?;
+            for name in names {
+                writeln!(out, "  {} {}", marker, name)?;
+            }
+        }
+        Ok(())
+    }
+}
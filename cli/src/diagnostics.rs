@@ -0,0 +1,119 @@
+use crate::ci::{self, Finding, Severity};
+use codedefender_api::codedefender_config::{
+    ConfigDiagnostic, ConfigPathSegment, DiagnosticSeverity,
+};
+use std::path::Path;
+
+/// A non-blank, non-comment line of block-style YAML.
+struct Line<'a> {
+    /// Zero based line number.
+    number: usize,
+    /// Column of the first character.
+    indent: usize,
+    /// Column of the mapping key, past any `- ` sequence markers.
+    key_column: usize,
+    /// Text starting at `key_column`.
+    key_text: &'a str,
+    /// Whether the line starts a sequence item.
+    is_item: bool,
+}
+
+fn lines(source: &str) -> Vec<Line<'_>> {
+    source
+        .lines()
+        .enumerate()
+        .filter_map(|(number, raw)| {
+            let trimmed = raw.trim_start();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                return None;
+            }
+            let indent = raw.len() - trimmed.len();
+            let mut key_text = trimmed;
+            while let Some(rest) = key_text.strip_prefix("- ") {
+                key_text = rest.trim_start();
+            }
+            Some(Line {
+                number,
+                indent,
+                key_column: raw.len() - key_text.len(),
+                key_text,
+                is_item: trimmed.starts_with("- ") || trimmed == "-",
+            })
+        })
+        .collect()
+}
+
+/// Finds the one based line and column of the value at `path` in a block-style YAML document.
+///
+/// This is a best-effort lookup for diagnostics: flow-style collections and multi-line scalars
+/// are not understood, in which case the closest enclosing node is returned.
+pub fn locate(source: &str, path: &[ConfigPathSegment]) -> Option<(usize, usize)> {
+    let lines = lines(source);
+    let mut range = 0..lines.len();
+    let mut found = None;
+
+    for segment in path {
+        let scope = &lines[range.clone()];
+        match segment {
+            ConfigPathSegment::Key(key) => {
+                let column = scope.iter().map(|l| l.key_column).min()?;
+                let position = scope.iter().position(|l| {
+                    l.key_column == column
+                        && l.key_text
+                            .strip_prefix(key.as_str())
+                            .is_some_and(|rest| rest.starts_with(':'))
+                })?;
+                let start = range.start + position;
+                let end = lines[start + 1..range.end]
+                    .iter()
+                    .position(|l| {
+                        l.key_column <= column && !(l.is_item && l.indent == lines[start].indent)
+                    })
+                    .map_or(range.end, |p| start + 1 + p);
+                found = Some(&lines[start]);
+                range = start + 1..end;
+            }
+            ConfigPathSegment::Index(index) => {
+                let column = scope.iter().filter(|l| l.is_item).map(|l| l.indent).min()?;
+                let items: Vec<usize> = scope
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, l)| l.is_item && l.indent == column)
+                    .map(|(i, _)| range.start + i)
+                    .collect();
+                let start = *items.get(*index)?;
+                let end = items.get(index + 1).copied().unwrap_or(range.end);
+                found = Some(&lines[start]);
+                // The item's first key shares its line with the `- ` marker.
+                range = start..end;
+            }
+        }
+    }
+    found.map(|l| (l.number + 1, l.key_column + 1))
+}
+
+/// Prints a diagnostic in `file:line:column: severity: message` form.
+pub fn print_diagnostic(file: &Path, source: &str, diagnostic: &ConfigDiagnostic) {
+    let position = locate(source, &diagnostic.path);
+    ci::annotate(Finding {
+        severity: match diagnostic.severity {
+            DiagnosticSeverity::Error => Severity::Error,
+            DiagnosticSeverity::Warning => Severity::Warning,
+        },
+        message: format!("{} ({})", diagnostic.message, diagnostic.path_string()),
+        file: Some(file.to_path_buf()),
+        position,
+    });
+    match position {
+        Some((line, column)) => eprintln!(
+            "{}:{}:{}: {}: {} ({})",
+            file.display(),
+            line,
+            column,
+            diagnostic.severity,
+            diagnostic.message,
+            diagnostic.path_string()
+        ),
+        None => eprintln!("{}: {}", file.display(), diagnostic),
+    }
+}
@@ -0,0 +1,407 @@
+//! `doctor`, checks of everything the CLI needs to talk to the service, each with a hint on how to
+//! fix what is wrong.
+
+use crate::{api, cache::Cache, keyring, progress::format_size};
+use codedefender_api::{
+    ApiError, BASE_URL, Whoami,
+    codedefender_config::{MIN_YAML_CONFIG_VERSION, YAML_CONFIG_VERSION, parse_config_version},
+};
+use codedefender_symbols::StreamVersion;
+use reqwest::blocking::Client;
+use serde::Serialize;
+use std::{
+    io::{self, Write},
+    path::PathBuf,
+    time::Instant,
+};
+
+/// Proxy variables reqwest reads, in the order it prefers them.
+const PROXY_VARIABLES: &[&str] = &[
+    "HTTPS_PROXY",
+    "https_proxy",
+    "HTTP_PROXY",
+    "http_proxy",
+    "ALL_PROXY",
+    "all_proxy",
+];
+
+const NO_PROXY_VARIABLES: &[&str] = &["NO_PROXY", "no_proxy"];
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Ok,
+    Warn,
+    Fail,
+}
+
+/// Outcome of one check.
+#[derive(Serialize, Debug)]
+pub struct Check {
+    pub name: &'static str,
+    pub status: CheckStatus,
+    pub message: String,
+    /// What to do about a warning or failure.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hint: Option<String>,
+}
+
+impl Check {
+    fn ok(name: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            name,
+            status: CheckStatus::Ok,
+            message: message.into(),
+            hint: None,
+        }
+    }
+
+    fn warn(name: &'static str, message: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self {
+            name,
+            status: CheckStatus::Warn,
+            message: message.into(),
+            hint: Some(hint.into()),
+        }
+    }
+
+    fn fail(name: &'static str, message: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self {
+            name,
+            status: CheckStatus::Fail,
+            message: message.into(),
+            hint: Some(hint.into()),
+        }
+    }
+
+    pub fn print(&self, out: &mut impl Write) -> io::Result<()> {
+        let status = match self.status {
+            CheckStatus::Ok => "ok",
+            CheckStatus::Warn => "warn",
+            CheckStatus::Fail => "FAIL",
+        };
+        writeln!(out, "[{:4}] {:15} {}", status, self.name, self.message)?;
+        if let Some(hint) = &self.hint {
+            writeln!(out, "       {:15} {}", "", hint)?;
+        }
+        Ok(())
+    }
+}
+
+fn settings(files: &[PathBuf]) -> Check {
+    if files.is_empty() {
+        return Check::ok(
+            "settings",
+            "no config.toml found, using flags and environment",
+        );
+    }
+    let files: Vec<String> = files.iter().map(|f| f.display().to_string()).collect();
+    Check::ok("settings", format!("read {}", files.join(", ")))
+}
+
+/// `value` with the password of the URL, if any, masked.
+fn redact(value: &str) -> String {
+    match reqwest::Url::parse(value) {
+        Ok(mut url) if url.password().is_some() => {
+            let _ = url.set_password(Some("***"));
+            url.to_string()
+        }
+        _ => value.to_owned(),
+    }
+}
+
+fn proxy() -> Check {
+    let set = |names: &[&'static str]| -> Vec<(&'static str, String)> {
+        names
+            .iter()
+            .filter_map(|name| Some((*name, std::env::var(name).ok()?)))
+            .filter(|(_, value)| !value.is_empty())
+            .collect()
+    };
+    let proxies = set(PROXY_VARIABLES);
+    if proxies.is_empty() {
+        return Check::ok("proxy", "none configured, connecting directly");
+    }
+    for (name, value) in &proxies {
+        if let Err(e) = reqwest::Proxy::all(value.as_str()) {
+            return Check::fail(
+                "proxy",
+                format!("{}={} is not a valid proxy URL: {}", name, redact(value), e),
+                "Use the form http://[user:password@]host:port",
+            );
+        }
+    }
+    let described: Vec<String> = proxies
+        .iter()
+        .chain(&set(NO_PROXY_VARIABLES))
+        .map(|(name, value)| format!("{}={}", name, redact(value)))
+        .collect();
+    Check::ok("proxy", described.join(", "))
+}
+
+/// `error` followed by its causes, which hold the useful part of reqwest errors.
+fn with_causes(error: &dyn std::error::Error) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+    while let Some(cause) = source {
+        message.push_str(&format!(": {}", cause));
+        source = cause.source();
+    }
+    message
+}
+
+/// Whether the service answers at all, with any status.
+fn connectivity(client: &Client) -> Check {
+    let start = Instant::now();
+    match client.get(&*BASE_URL).send() {
+        Ok(response) => Check::ok(
+            "connectivity",
+            format!(
+                "{} answered {} in {} ms",
+                *BASE_URL,
+                response.status(),
+                start.elapsed().as_millis()
+            ),
+        ),
+        Err(e) => Check::fail(
+            "connectivity",
+            format!("{} cannot be reached: {}", *BASE_URL, with_causes(&e)),
+            "Check the network and BASE_URL. Behind a proxy, set HTTPS_PROXY or `proxy` in \
+             ~/.codedefender/config.toml",
+        ),
+    }
+}
+
+/// Resolves the API key like the other commands and looks up its account.
+fn api_key(
+    api_key: Option<String>,
+    account: &str,
+    reachable: bool,
+    client: &Client,
+) -> (Check, Option<Whoami>) {
+    let (api_key, source) = match api_key {
+        Some(api_key) => (api_key, "from --api-key or CD_API_KEY".to_owned()),
+        None => match keyring::load(account) {
+            Ok(Some(api_key)) => (api_key, format!("stored for account `{}`", account)),
+            Ok(None) => {
+                let check = Check::fail(
+                    "api key",
+                    "none given",
+                    format!(
+                        "Pass --api-key, set CD_API_KEY or run `codedefender-cli login --account {}`",
+                        account
+                    ),
+                );
+                return (check, None);
+            }
+            Err(e) => {
+                let check = Check::fail(
+                    "api key",
+                    format!(
+                        "the one stored for account `{}` cannot be read: {}",
+                        account, e
+                    ),
+                    "Pass --api-key or set CD_API_KEY instead",
+                );
+                return (check, None);
+            }
+        },
+    };
+    if !reachable {
+        let check = Check::warn(
+            "api key",
+            format!("{}, not checked", source),
+            "Fix connectivity first",
+        );
+        return (check, None);
+    }
+    match api::whoami(client, &api_key) {
+        Ok(whoami) => (
+            Check::ok(
+                "api key",
+                format!("{}, valid for account `{}`", source, whoami.account),
+            ),
+            Some(whoami),
+        ),
+        Err(e @ ApiError::Unauthorized { .. }) => (
+            Check::fail(
+                "api key",
+                format!("{} was rejected: {}", source, e),
+                "Create a new key in the CodeDefender web app and store it with \
+                 `codedefender-cli login`",
+            ),
+            None,
+        ),
+        Err(e) => (
+            Check::fail(
+                "api key",
+                format!("{} could not be checked: {}", source, with_causes(&e)),
+                "Retry later, or report the error to CodeDefender support",
+            ),
+            None,
+        ),
+    }
+}
+
+/// Compares the config version this CLI writes with the newest one the service accepts.
+fn config_version(whoami: Option<&Whoami>) -> Check {
+    const NAME: &str = "config version";
+    let Some(whoami) = whoami else {
+        return Check::warn(
+            NAME,
+            format!(
+                "this CLI writes {}, service not checked",
+                YAML_CONFIG_VERSION
+            ),
+            "Fix the API key first",
+        );
+    };
+    let Some(server) = &whoami.config_version else {
+        return Check::ok(
+            NAME,
+            format!(
+                "this CLI writes {}, the service does not report its version",
+                YAML_CONFIG_VERSION
+            ),
+        );
+    };
+    let (Some(server_version), Some(cli_version), Some(min_version)) = (
+        parse_config_version(server),
+        parse_config_version(YAML_CONFIG_VERSION),
+        parse_config_version(MIN_YAML_CONFIG_VERSION),
+    ) else {
+        return Check::warn(
+            NAME,
+            format!("the service reports an unknown version `{}`", server),
+            "Report this to CodeDefender support",
+        );
+    };
+    if server_version == cli_version {
+        Check::ok(
+            NAME,
+            format!("{}, same as the service", YAML_CONFIG_VERSION),
+        )
+    } else if server_version > cli_version {
+        Check::warn(
+            NAME,
+            format!(
+                "this CLI writes {}, the service accepts up to {}",
+                YAML_CONFIG_VERSION, server
+            ),
+            "Update the CLI to use the newest config options",
+        )
+    } else if server_version >= min_version {
+        Check::warn(
+            NAME,
+            format!(
+                "this CLI writes {}, the service only accepts up to {}",
+                YAML_CONFIG_VERSION, server
+            ),
+            "Options newer than the service may be rejected, use a CLI release matching it",
+        )
+    } else {
+        Check::fail(
+            NAME,
+            format!(
+                "this CLI writes {}, the service only accepts up to {}",
+                YAML_CONFIG_VERSION, server
+            ),
+            "Use a CLI release matching the service",
+        )
+    }
+}
+
+/// Compares the newest symbol format this CLI writes with the newest one the service reads.
+fn symbol_format(whoami: Option<&Whoami>) -> Check {
+    const NAME: &str = "symbol format";
+    let cli = StreamVersion::LATEST.number();
+    let Some(whoami) = whoami else {
+        return Check::warn(
+            NAME,
+            format!("this CLI writes up to v{}, service not checked", cli),
+            "Fix the API key first",
+        );
+    };
+    match whoami.symbol_format {
+        None => Check::ok(
+            NAME,
+            format!(
+                "this CLI writes up to v{}, the service does not report what it reads",
+                cli
+            ),
+        ),
+        Some(server) if server >= cli => Check::ok(
+            NAME,
+            format!(
+                "this CLI writes up to v{}, the service reads up to v{}",
+                cli, server
+            ),
+        ),
+        Some(server) => Check::warn(
+            NAME,
+            format!(
+                "this CLI writes up to v{}, the service only reads up to v{}",
+                cli, server
+            ),
+            "Newer --symbol-format values are lowered to what the service reads",
+        ),
+    }
+}
+
+fn cache() -> Check {
+    let Some(dir) = Cache::default_dir() else {
+        return Check::warn(
+            "cache",
+            "no cache directory, uploads are never reused",
+            "Set CODEDEFENDER_CACHE_DIR or `dir` under [cache] in ~/.codedefender/config.toml",
+        );
+    };
+    let health = Cache::open(false)
+        .ok_or_else(|| "cannot be created".to_owned())
+        .and_then(|cache| cache.health().map_err(|e| e.to_string()));
+    match health {
+        Ok(health) if health.unreadable > 0 => Check::warn(
+            "cache",
+            format!(
+                "{}, {} of {} entries cannot be read",
+                dir.display(),
+                health.unreadable,
+                health.entries
+            ),
+            "Clear it with `codedefender-cli cache gc --max-age-days 0`",
+        ),
+        Ok(health) => Check::ok(
+            "cache",
+            format!(
+                "{}, {} entries, {}",
+                dir.display(),
+                health.entries,
+                format_size(health.bytes)
+            ),
+        ),
+        Err(e) => Check::fail(
+            "cache",
+            format!("{} is not usable: {}", dir.display(), e),
+            "Fix its permissions, or point CODEDEFENDER_CACHE_DIR at a writable directory",
+        ),
+    }
+}
+
+/// Runs every check. `settings_files` are the settings files read at startup.
+pub fn run(
+    key: Option<String>,
+    account: &str,
+    settings_files: &[PathBuf],
+    client: &Client,
+) -> Vec<Check> {
+    let mut checks = vec![settings(settings_files), proxy()];
+    let connectivity = connectivity(client);
+    let reachable = connectivity.status == CheckStatus::Ok;
+    checks.push(connectivity);
+    let (key_check, whoami) = api_key(key, account, reachable, client);
+    checks.push(key_check);
+    checks.push(config_version(whoami.as_ref()));
+    checks.push(symbol_format(whoami.as_ref()));
+    checks.push(cache());
+    checks
+}
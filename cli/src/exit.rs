@@ -0,0 +1,76 @@
+//! Process exit codes, so scripts and CI can tell failures apart.
+
+use codedefender_api::ApiError;
+use std::{error::Error, fmt};
+
+/// Exit code of the CLI. `2` is left to clap for command line usage errors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitStatus {
+    Success = 0,
+    /// Any failure not covered below, e.g. an unreadable input file.
+    Error = 1,
+    /// The config file could not be parsed, failed validation or does not fit the binary.
+    Config = 3,
+    /// The API key was rejected, or none was given or stored.
+    Auth = 4,
+    /// The service could not be reached.
+    Network = 5,
+    /// Analysis or obfuscation did not finish in time.
+    Timeout = 6,
+    /// Analysis failed or returned an unusable result.
+    Analysis = 7,
+    /// Obfuscation failed or its output was rejected.
+    Obfuscation = 8,
+    /// An extracted artifact could not be signed.
+    Signing = 9,
+}
+
+/// An error tagged with the exit status it should produce.
+#[derive(Debug)]
+pub struct Failure {
+    pub status: ExitStatus,
+    pub message: String,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl Error for Failure {}
+
+/// Boxes a [`Failure`] for returning from a command.
+pub fn failure(status: ExitStatus, message: impl Into<String>) -> Box<dyn Error> {
+    Box::new(Failure {
+        status,
+        message: message.into(),
+    })
+}
+
+/// Maps an API error from `stage` to a [`Failure`]. Rejected keys and network errors keep their own
+/// status, anything else is reported as a failure of `stage`.
+pub fn api_failure(stage: ExitStatus) -> impl Fn(ApiError) -> Box<dyn Error> {
+    move |error| failure(api_status(&error, stage), error.to_string())
+}
+
+fn api_status(error: &ApiError, stage: ExitStatus) -> ExitStatus {
+    match error {
+        ApiError::Unauthorized { .. } => ExitStatus::Auth,
+        ApiError::Network { .. } => ExitStatus::Network,
+        _ => stage,
+    }
+}
+
+/// The exit status for an error returned by a command.
+pub fn status_of(error: &(dyn Error + 'static)) -> ExitStatus {
+    if let Some(failure) = error.downcast_ref::<Failure>() {
+        failure.status
+    } else if let Some(error) = error.downcast_ref::<ApiError>() {
+        api_status(error, ExitStatus::Error)
+    } else if error.is::<serde_yaml::Error>() {
+        ExitStatus::Config
+    } else {
+        ExitStatus::Error
+    }
+}
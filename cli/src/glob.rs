@@ -0,0 +1,267 @@
+//! Client-side expansion of `*`, `?` and `**` wildcards in input paths.
+
+use std::{
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+fn has_wildcard(text: &str) -> bool {
+    text.contains(['*', '?'])
+}
+
+/// Matches a single path component against a pattern where `*` matches any run of characters and
+/// `?` matches one. Like shells, wildcards do not match a leading `.`.
+fn matches(pattern: &str, name: &str) -> bool {
+    if name.starts_with('.') && !pattern.starts_with('.') {
+        return false;
+    }
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    let mut backtrack = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some('?') => {
+                p += 1;
+                n += 1;
+            }
+            Some(c) if *c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    p = star + 1;
+                    n = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+fn sorted_entries(dir: &Path) -> Vec<PathBuf> {
+    let mut entries: Vec<PathBuf> = fs::read_dir(if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    })
+    .map(|entries| {
+        entries
+            .filter_map(|e| e.ok())
+            .map(|e| dir.join(e.file_name()))
+            .collect()
+    })
+    .unwrap_or_default();
+    entries.sort();
+    entries
+}
+
+fn walk(path: PathBuf, rest: &[String], out: &mut Vec<PathBuf>) {
+    let Some((head, tail)) = rest.split_first() else {
+        if path.is_file() {
+            out.push(path);
+        }
+        return;
+    };
+    if head == "**" {
+        walk(path.clone(), tail, out);
+        for entry in sorted_entries(&path) {
+            let hidden = entry
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+            if entry.is_dir() && !hidden {
+                walk(entry, rest, out);
+            }
+        }
+    } else if has_wildcard(head) {
+        for entry in sorted_entries(&path) {
+            let name = entry.file_name().unwrap_or_default().to_string_lossy();
+            if matches(head, &name) {
+                walk(entry, tail, out);
+            }
+        }
+    } else {
+        walk(path.join(head), tail, out);
+    }
+}
+
+/// Expands a path pattern into the files it matches, sorted. Paths without wildcards are returned
+/// as-is so that missing files are reported when they are read.
+pub fn expand(pattern: &Path) -> Result<Vec<PathBuf>, String> {
+    if !has_wildcard(&pattern.to_string_lossy()) {
+        return Ok(vec![pattern.to_path_buf()]);
+    }
+    let mut base = PathBuf::new();
+    let mut rest = Vec::new();
+    for component in pattern.components() {
+        match component {
+            Component::Normal(name)
+                if !rest.is_empty() || has_wildcard(&name.to_string_lossy()) =>
+            {
+                rest.push(name.to_string_lossy().into_owned())
+            }
+            _ if !rest.is_empty() => {
+                rest.push(component.as_os_str().to_string_lossy().into_owned())
+            }
+            _ => base.push(component),
+        }
+    }
+
+    let mut out = Vec::new();
+    walk(base, &rest, &mut out);
+    out.sort();
+    out.dedup();
+    if out.is_empty() {
+        return Err(format!("Pattern {:?} matched no files", pattern));
+    }
+    Ok(out)
+}
+
+/// Fills `{stem}`, `{ext}` and `{name}` in `template` from `input`.
+pub fn render_template(template: &str, input: &Path) -> Result<PathBuf, String> {
+    let lossy = |s: Option<&std::ffi::OsStr>| s.unwrap_or_default().to_string_lossy().into_owned();
+    let mut out = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| format!("Unclosed `{{` in output template {:?}", template))?;
+        let value = match &rest[start + 1..start + end] {
+            "stem" => lossy(input.file_stem()),
+            "ext" => lossy(input.extension()),
+            "name" => lossy(input.file_name()),
+            other => {
+                return Err(format!(
+                    "Unknown placeholder `{{{}}}` in output template, expected stem, ext or name",
+                    other
+                ));
+            }
+        };
+        out.push_str(&value);
+        rest = &rest[start + end + 1..];
+    }
+    out.push_str(rest);
+    Ok(PathBuf::from(out))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A directory holding `files`, created empty for the test.
+    fn tree(name: &str, files: &[&str]) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("codedefender-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        for file in files {
+            let path = dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, b"").unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn wildcards_match_within_a_component() {
+        assert!(matches("*.exe", "app.exe"));
+        assert!(!matches("*.exe", ".exe.exe"));
+        assert!(matches("a*b*c", "aXbYbZc"));
+        assert!(matches("a*b", "ab"));
+        assert!(!matches("a*b", "abc"));
+        assert!(matches("???.dll", "abc.dll"));
+        assert!(!matches("???.dll", "ab.dll"));
+        assert!(matches("*", "anything"));
+        assert!(matches("**", ""));
+    }
+
+    #[test]
+    fn wildcards_skip_hidden_files_unless_the_pattern_starts_with_a_dot() {
+        assert!(!matches("*", ".git"));
+        assert!(!matches("?git", ".git"));
+        assert!(matches(".*", ".git"));
+        assert!(matches(".g?t", ".git"));
+    }
+
+    #[test]
+    fn double_star_matches_zero_or_more_directories() {
+        let dir = tree(
+            "glob-double-star",
+            &[
+                "a.exe",
+                "b.dll",
+                "sub/c.exe",
+                "sub/deeper/d.exe",
+                ".hidden/e.exe",
+                "sub/.f.exe",
+            ],
+        );
+        let found = expand(&dir.join("**").join("*.exe")).unwrap();
+        assert_eq!(
+            found,
+            [
+                dir.join("a.exe"),
+                dir.join("sub").join("c.exe"),
+                dir.join("sub").join("deeper").join("d.exe"),
+            ]
+        );
+        assert_eq!(
+            expand(&dir.join("*").join("*.exe")).unwrap(),
+            [dir.join("sub").join("c.exe")]
+        );
+        assert!(expand(&dir.join("**").join("*.sys")).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn paths_without_wildcards_are_returned_as_is() {
+        let path = Path::new("missing/app.exe");
+        assert_eq!(expand(path).unwrap(), [path]);
+    }
+
+    #[test]
+    fn templates_are_filled_from_the_input() {
+        let input = Path::new("build/bin/driver.sys");
+        assert_eq!(
+            render_template("{stem}.protected.{ext}", input).unwrap(),
+            Path::new("driver.protected.sys")
+        );
+        assert_eq!(
+            render_template("out/{name}.zip", input).unwrap(),
+            Path::new("out/driver.sys.zip")
+        );
+    }
+
+    #[test]
+    fn names_without_an_extension_fill_ext_with_nothing() {
+        let input = Path::new("bin/tool");
+        assert_eq!(
+            render_template("{stem}.zip", input).unwrap(),
+            Path::new("tool.zip")
+        );
+        assert_eq!(
+            render_template("{name}-{ext}.zip", input).unwrap(),
+            Path::new("tool-.zip")
+        );
+    }
+
+    #[test]
+    fn unknown_and_unclosed_placeholders_are_rejected() {
+        let input = Path::new("app.exe");
+        assert_eq!(
+            render_template("{base}.zip", input).unwrap_err(),
+            "Unknown placeholder `{base}` in output template, expected stem, ext or name"
+        );
+        assert_eq!(
+            render_template("{stem.zip", input).unwrap_err(),
+            "Unclosed `{` in output template \"{stem.zip\""
+        );
+    }
+}
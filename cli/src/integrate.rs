@@ -0,0 +1,119 @@
+//! Post-build steps for CMake and MSBuild projects, generated by `integrate`.
+
+use std::path::{Component, Path, PathBuf};
+
+/// Components of an absolute path with `.` and `..` resolved.
+fn normalized(path: &Path) -> Vec<Component<'_>> {
+    let mut components = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if matches!(components.last(), Some(Component::Normal(_))) {
+                    components.pop();
+                }
+            }
+            component => components.push(component),
+        }
+    }
+    components
+}
+
+/// `path` relative to `base`, both absolute. Falls back to `path` on another drive.
+pub fn relative_to(path: &Path, base: &Path) -> PathBuf {
+    let path = normalized(path);
+    let base = normalized(base);
+    if path.first() != base.first() {
+        return path.iter().collect();
+    }
+    let common = path.iter().zip(&base).take_while(|(a, b)| a == b).count();
+    let mut relative: PathBuf = base[common..].iter().map(|_| "..").collect();
+    relative.extend(&path[common..]);
+    relative
+}
+
+/// `path` prefixed with `base_dir` unless it is absolute. Forward slashes work in both CMake and
+/// MSBuild and need no escaping.
+fn reference(path: &Path, base_dir: &str) -> String {
+    let path = path.to_string_lossy().replace('\\', "/");
+    if Path::new(&path).is_absolute() || path.starts_with('/') {
+        path
+    } else {
+        format!("{}{}", base_dir, path)
+    }
+}
+
+/// A CMake module defining `codedefender_protect(<target> [CONFIG <file>])`, which adds a post
+/// build step protecting the target. A relative `config` is resolved from the module's directory.
+/// When `target` is given the module also protects it.
+pub fn cmake(config: &Path, target: Option<&str>) -> String {
+    let mut module = format!(
+        r#"# Generated by `codedefender-cli integrate --cmake`. Include it after defining your targets:
+#
+#   include(codedefender.cmake)
+#   codedefender_protect(<target>)
+#
+# The protected binary and PDB are written next to the target as <name>.protected.<ext>. The API key is read from
+# the CD_API_KEY environment variable.
+
+find_program(CODEDEFENDER_CLI codedefender-cli REQUIRED)
+set(CODEDEFENDER_CONFIG "{config}" CACHE FILEPATH "CodeDefender config file")
+
+function(codedefender_protect target)
+  cmake_parse_arguments(CD "" "CONFIG" "" ${{ARGN}})
+  if(NOT CD_CONFIG)
+    set(CD_CONFIG "${{CODEDEFENDER_CONFIG}}")
+  endif()
+  add_custom_command(TARGET ${{target}} POST_BUILD
+    COMMAND "${{CODEDEFENDER_CLI}}" run
+      --config "${{CD_CONFIG}}"
+      --input-file "$<TARGET_FILE:${{target}}>"
+      "$<$<CXX_COMPILER_ID:MSVC>:--pdb-file=$<TARGET_PDB_FILE:${{target}}>>"
+      --output "$<TARGET_FILE:${{target}}>.codedefender.zip"
+      --extract-to "$<TARGET_FILE_DIR:${{target}}>"
+    COMMENT "Protecting ${{target}} with CodeDefender"
+    COMMAND_EXPAND_LISTS
+    VERBATIM)
+endfunction()
+"#,
+        config = reference(config, "${CMAKE_CURRENT_LIST_DIR}/")
+    );
+    if let Some(target) = target {
+        module.push_str(&format!("\ncodedefender_protect({})\n", target));
+    }
+    module
+}
+
+/// An MSBuild `.targets` file that protects the project output after `Build`. A relative `config`
+/// is resolved from the file's directory. Set `CodeDefenderEnabled` to `false` to skip it, by
+/// default only `Release` builds are protected.
+pub fn msbuild(config: &Path) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<!--
+  Generated by `codedefender-cli integrate` for MSBuild. Import it at the end of your project file:
+
+    <Import Project="CodeDefender.targets" />
+
+  The protected binary and PDB are written next to the output as <name>.protected.<ext>. The API key is read from the
+  CD_API_KEY environment variable.
+-->
+<Project>
+  <PropertyGroup>
+    <CodeDefenderCli Condition="'$(CodeDefenderCli)' == ''">codedefender-cli</CodeDefenderCli>
+    <CodeDefenderConfig Condition="'$(CodeDefenderConfig)' == ''">{config}</CodeDefenderConfig>
+    <CodeDefenderEnabled Condition="'$(CodeDefenderEnabled)' == '' And '$(Configuration)' == 'Release'">true</CodeDefenderEnabled>
+  </PropertyGroup>
+
+  <Target Name="CodeDefenderProtect" AfterTargets="Build" Condition="'$(CodeDefenderEnabled)' == 'true'">
+    <PropertyGroup>
+      <CodeDefenderPdb>$(TargetDir)$(TargetName).pdb</CodeDefenderPdb>
+      <CodeDefenderPdbArg Condition="Exists('$(CodeDefenderPdb)')">--pdb-file "$(CodeDefenderPdb)"</CodeDefenderPdbArg>
+    </PropertyGroup>
+    <Exec Command="&quot;$(CodeDefenderCli)&quot; run --config &quot;$(CodeDefenderConfig)&quot; --input-file &quot;$(TargetPath)&quot; $(CodeDefenderPdbArg) --output &quot;$(TargetPath).codedefender.zip&quot; --extract-to &quot;$(TargetDir).&quot;" />
+  </Target>
+</Project>
+"#,
+        config = reference(config, "$(MSBuildThisFileDirectory)")
+    )
+}
@@ -0,0 +1,226 @@
+//! API keys in the platform credential store, one per named account: the Windows Credential
+//! Manager, the macOS keychain, and the Secret Service through `secret-tool` elsewhere.
+
+/// Service name the keys are stored under.
+const SERVICE: &str = "codedefender-cli";
+
+/// Stores the API key of `account`, replacing a stored one.
+pub fn store(account: &str, api_key: &str) -> Result<(), String> {
+    platform::store(account, api_key)
+}
+
+/// The stored API key of `account`, if there is one.
+pub fn load(account: &str) -> Result<Option<String>, String> {
+    platform::load(account)
+}
+
+/// Removes the API key of `account`. Returns whether one was stored.
+pub fn delete(account: &str) -> Result<bool, String> {
+    platform::delete(account)
+}
+
+#[cfg(windows)]
+mod platform {
+    use super::SERVICE;
+    use windows_sys::Win32::Foundation::{ERROR_NOT_FOUND, GetLastError};
+    use windows_sys::Win32::Security::Credentials::{
+        CRED_PERSIST_LOCAL_MACHINE, CRED_TYPE_GENERIC, CREDENTIALW, CredDeleteW, CredFree,
+        CredReadW, CredWriteW,
+    };
+
+    fn wide(text: &str) -> Vec<u16> {
+        text.encode_utf16().chain(std::iter::once(0)).collect()
+    }
+
+    fn target(account: &str) -> Vec<u16> {
+        wide(&format!("{}:{}", SERVICE, account))
+    }
+
+    pub fn store(account: &str, api_key: &str) -> Result<(), String> {
+        let mut target = target(account);
+        let mut user = wide(account);
+        let mut blob = api_key.as_bytes().to_vec();
+        let credential = CREDENTIALW {
+            Type: CRED_TYPE_GENERIC,
+            TargetName: target.as_mut_ptr(),
+            CredentialBlobSize: blob.len() as u32,
+            CredentialBlob: blob.as_mut_ptr(),
+            Persist: CRED_PERSIST_LOCAL_MACHINE,
+            UserName: user.as_mut_ptr(),
+            ..Default::default()
+        };
+        // SAFETY: every pointer in `credential` outlives the call.
+        if unsafe { CredWriteW(&credential, 0) } == 0 {
+            return Err(format!("CredWriteW failed with error {}", unsafe {
+                GetLastError()
+            }));
+        }
+        Ok(())
+    }
+
+    pub fn load(account: &str) -> Result<Option<String>, String> {
+        let target = target(account);
+        let mut credential: *mut CREDENTIALW = std::ptr::null_mut();
+        // SAFETY: `target` is NUL terminated and `credential` is freed with CredFree below.
+        if unsafe { CredReadW(target.as_ptr(), CRED_TYPE_GENERIC, 0, &mut credential) } == 0 {
+            return match unsafe { GetLastError() } {
+                ERROR_NOT_FOUND => Ok(None),
+                error => Err(format!("CredReadW failed with error {}", error)),
+            };
+        }
+        // SAFETY: CredReadW succeeded, so `credential` points to a valid CREDENTIALW.
+        let blob = unsafe {
+            let credential = &*credential;
+            std::slice::from_raw_parts(
+                credential.CredentialBlob,
+                credential.CredentialBlobSize as usize,
+            )
+            .to_vec()
+        };
+        unsafe { CredFree(credential as *const _) };
+        String::from_utf8(blob)
+            .map(Some)
+            .map_err(|_| "the stored API key is not valid UTF-8".to_owned())
+    }
+
+    pub fn delete(account: &str) -> Result<bool, String> {
+        let target = target(account);
+        // SAFETY: `target` is NUL terminated.
+        if unsafe { CredDeleteW(target.as_ptr(), CRED_TYPE_GENERIC, 0) } == 0 {
+            return match unsafe { GetLastError() } {
+                ERROR_NOT_FOUND => Ok(false),
+                error => Err(format!("CredDeleteW failed with error {}", error)),
+            };
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(not(windows))]
+mod platform {
+    use super::SERVICE;
+    use std::{
+        io::Write,
+        process::{Command, Output, Stdio},
+    };
+
+    #[cfg(target_os = "macos")]
+    const TOOL: &str = "security";
+    #[cfg(not(target_os = "macos"))]
+    const TOOL: &str = "secret-tool";
+
+    /// Runs the credential tool, passing `input` on stdin so keys never show up in the process
+    /// list.
+    fn run(args: &[&str], input: Option<&str>) -> Result<Output, String> {
+        let mut child = Command::new(TOOL)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("cannot run `{}`: {}", TOOL, e))?;
+        if let Some(input) = input
+            && let Some(mut stdin) = child.stdin.take()
+        {
+            stdin
+                .write_all(input.as_bytes())
+                .map_err(|e| format!("cannot write to `{}`: {}", TOOL, e))?;
+        }
+        child
+            .wait_with_output()
+            .map_err(|e| format!("`{}` failed: {}", TOOL, e))
+    }
+
+    fn failed(output: &Output) -> String {
+        format!(
+            "`{}` failed: {}",
+            TOOL,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+    }
+
+    #[cfg(target_os = "macos")]
+    pub fn store(account: &str, api_key: &str) -> Result<(), String> {
+        let quote = |text: &str| format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""));
+        let command = format!(
+            "add-generic-password -U -s {} -a {} -w {}\n",
+            quote(SERVICE),
+            quote(account),
+            quote(api_key)
+        );
+        let output = run(&["-i"], Some(&command))?;
+        if !output.status.success() || !output.stderr.is_empty() {
+            return Err(failed(&output));
+        }
+        Ok(())
+    }
+
+    #[cfg(target_os = "macos")]
+    pub fn load(account: &str) -> Result<Option<String>, String> {
+        // Exit code 44 is errSecItemNotFound
+        let output = run(
+            &["find-generic-password", "-s", SERVICE, "-a", account, "-w"],
+            None,
+        )?;
+        match output.status.code() {
+            Some(0) => Ok(Some(
+                String::from_utf8_lossy(&output.stdout)
+                    .trim_end()
+                    .to_owned(),
+            )),
+            Some(44) => Ok(None),
+            _ => Err(failed(&output)),
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    pub fn delete(account: &str) -> Result<bool, String> {
+        let output = run(
+            &["delete-generic-password", "-s", SERVICE, "-a", account],
+            None,
+        )?;
+        match output.status.code() {
+            Some(0) => Ok(true),
+            Some(44) => Ok(false),
+            _ => Err(failed(&output)),
+        }
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    pub fn store(account: &str, api_key: &str) -> Result<(), String> {
+        let label = format!("CodeDefender API key ({})", account);
+        let output = run(
+            &[
+                "store", "--label", &label, "service", SERVICE, "account", account,
+            ],
+            Some(api_key),
+        )?;
+        if !output.status.success() {
+            return Err(failed(&output));
+        }
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    pub fn load(account: &str) -> Result<Option<String>, String> {
+        // A missing secret is exit code 1 with nothing on stderr
+        let output = run(&["lookup", "service", SERVICE, "account", account], None)?;
+        if output.status.success() {
+            Ok(Some(String::from_utf8_lossy(&output.stdout).into_owned()))
+        } else if output.stderr.is_empty() {
+            Ok(None)
+        } else {
+            Err(failed(&output))
+        }
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    pub fn delete(account: &str) -> Result<bool, String> {
+        let existed = load(account)?.is_some();
+        let output = run(&["clear", "service", SERVICE, "account", account], None)?;
+        if !output.status.success() && !output.stderr.is_empty() {
+            return Err(failed(&output));
+        }
+        Ok(existed)
+    }
+}
@@ -1,264 +1,2083 @@
-use clap::Parser;
+use clap::{ArgGroup, Args, CommandFactory, Parser, Subcommand, ValueEnum};
 use codedefender_api::codedefender_config::{
-    AnalysisResult, Config, Profile, YAML_CONFIG_VERSION, YamlConfig, YamlSymbol,
+    AnalysisResult, Config, ConfigPreset, DiagnosticSeverity, Timeouts, YamlConfig,
+    rejected_symbols_comment,
+};
+use codedefender_api::serde_json;
+use codedefender_symbols::{
+    Compression, Demangling, DuplicateNames, GeneratedFilter, ModuleFilter, StreamVersion,
+    SymbolOptions, decode_symbols_as, encode_symbols,
 };
-use codedefender_api::{Status, serde_json, upload_data};
 use std::{
     fs,
-    path::PathBuf,
-    time::{Duration, Instant},
+    io::Write,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    time::{Duration, SystemTime},
 };
 
-use crate::pdb::parse_pdb;
 mod api {
-    pub use codedefender_api::defend;
-    pub use codedefender_api::download;
-    pub use codedefender_api::download_analysis_result;
-    pub use codedefender_api::download_obfuscated_file;
-    pub use codedefender_api::get_analyze_status;
-    pub use codedefender_api::start_analyze;
-    pub use codedefender_api::upload_data;
-    pub use codedefender_api::upload_file;
+    pub use codedefender_api::try_defend as defend;
+    pub use codedefender_api::try_download as download;
+    pub use codedefender_api::try_download_analysis_result as download_analysis_result;
+    pub use codedefender_api::try_download_obfuscated_file as download_obfuscated_file;
+    pub use codedefender_api::try_get_analyze_status as get_analyze_status;
+    pub use codedefender_api::try_start_analyze as start_analyze;
+    pub use codedefender_api::try_upload_data as upload_data;
+    pub use codedefender_api::try_upload_file as upload_file;
+    pub use codedefender_api::try_whoami as whoami;
 }
 
-mod pdb;
+mod analysis_diff;
+mod attestation;
+mod batch;
+mod cache;
+mod cargo;
+mod ci;
+mod compare;
+mod diagnostics;
+mod doctor;
+mod exit;
+mod glob;
+mod integrate;
+mod keyring;
+mod log_file;
+mod manifest;
+mod migrate;
+mod mock_server;
+mod output;
+mod overrides;
+mod pdb_search;
+mod pipeline;
+mod progress;
+mod protection_report;
+mod report;
+mod retry;
+mod settings;
+mod sign;
+mod state;
+mod stdio;
+mod symbols;
+mod tui;
+mod yaml_edit;
+
+use cache::Cache;
+use exit::{ExitStatus, failure};
+use log_file::RotatingFile;
+use overrides::ConfigOverride;
+use pdb_search::SymbolServers;
+use report::{LogFormat, OutputFormat, Reporter};
+use serde_json::json;
+use sign::SignCommand;
+use state::{RunStage, RunState};
+use stdio::SpooledInput;
+use yaml_edit::YamlDocument;
 
-const CLI_DOWNLOAD_LINK: &str = "https://github.com/codedefender-io/api/releases";
+const EXIT_CODES_HELP: &str = "Exit codes:
+  0  success
+  1  other error
+  2  invalid command line
+  3  config error
+  4  API key rejected or missing
+  5  network error
+  6  timeout
+  7  analysis failed
+  8  obfuscation failed
+  9  signing failed";
 
 /// Commandline interface for CodeDefender
 #[derive(Parser, Debug)]
 #[command(name = "codedefender-cli")]
 #[command(about = "Commandline interface for CodeDefender", long_about = None)]
+#[command(after_help = EXIT_CODES_HELP)]
 pub struct Cli {
-    /// Path to the YAML configuration file
-    #[arg(short, long, value_name = "FILE")]
-    pub config: PathBuf,
     /// Log level (error, warn, info, debug, trace)
-    #[arg(long, value_enum, default_value = "info")]
+    #[arg(long, value_enum, default_value = "info", global = true)]
     pub log_level: log::LevelFilter,
+    /// Log line format, `json` prints one object per line for log collectors
+    #[arg(long, value_enum, default_value = "text", global = true)]
+    pub log_format: LogFormat,
+    /// Also write logs to this file, rotated when it grows past --log-file-max-size
+    #[arg(long, value_name = "PATH", global = true)]
+    pub log_file: Option<PathBuf>,
+    /// Size in MiB at which the log file is rotated
+    #[arg(long, value_name = "MIB", default_value_t = 10, global = true)]
+    pub log_file_max_size: u64,
+    /// Number of rotated log files to keep next to the log file
+    #[arg(long, value_name = "COUNT", default_value_t = 5, global = true)]
+    pub log_file_keep: usize,
+    /// Output format on stdout. `json` prints one event object per line and a final result
+    #[arg(long, value_enum, default_value = "text", global = true)]
+    pub format: OutputFormat,
+    /// Report warnings as annotations and results as step outputs of this CI system
+    #[arg(long, value_enum, value_name = "PROVIDER", global = true)]
+    pub ci: Option<ci::CiProvider>,
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Upload, analyze, obfuscate and download in one go
+    Run(Box<RunArgs>),
+    /// Run several binaries through the whole pipeline with one config
+    Batch(BatchArgs),
+    /// Upload and analyze a binary, writing the analysis result to disk
+    Analyze(AnalyzeArgs),
+    /// Submit a previously analyzed binary for obfuscation and print the execution id
+    Defend(DefendArgs),
+    /// Wait for an obfuscation execution and download the result
+    Download(DownloadArgs),
+    /// Check a config file without touching the network
+    Validate(ValidateArgs),
+    /// Upgrade a config written for an older version to the current one
+    MigrateConfig(MigrateConfigArgs),
+    /// Write a starter config file
+    Init(InitArgs),
+    /// Manage the local cache of uploads and analysis results
+    Cache(CacheArgs),
+    /// Build a Cargo project and protect its binary, also available as `cargo codedefender`
+    Cargo(CargoArgs),
+    /// Generate a post-build step that protects the output of a CMake or MSBuild project
+    Integrate(IntegrateArgs),
+    /// Browse an analysis result and assign functions to profiles interactively
+    Tui(TuiArgs),
+    /// List the functions, rejects and macro profiles found by analysis
+    Symbols(SymbolsArgs),
+    /// Compare the analysis results of two builds
+    AnalysisDiff(AnalysisDiffArgs),
+    /// Compare sections, entropy, imports, exports and entry point of a binary and its
+    /// protected build
+    Compare(CompareArgs),
+    /// Serve a local stand-in for the CodeDefender API, for testing CI integration and configs
+    MockServer(MockServerArgs),
+    /// Store an API key in the platform credential store
+    Login(LoginArgs),
+    /// Remove an API key stored with `login`
+    Logout(LogoutArgs),
+    /// Check connectivity, the API key, config version compatibility, proxy settings and the
+    /// cache
+    Doctor(DoctorArgs),
+    /// Print a shell completion script
+    Completions(CompletionsArgs),
+    /// Print the manual page, or write one for every command into a directory
+    Man(ManArgs),
+}
+
+/// Options shared by every command that talks to the CodeDefender service.
+#[derive(Args, Debug)]
+pub struct ApiArgs {
     /// API key provided by the CodeDefender web service. You can either pass it on the commandline or assign it to "CD_API_KEY" env variable.
+    /// Otherwise the key stored with `login` for --account is used.
     #[arg(long, env = "CD_API_KEY")]
-    pub api_key: String,
+    pub api_key: Option<String>,
+    /// Credential profile whose key stored with `login` is used when no API key is given
+    #[arg(long, env = "CD_ACCOUNT", default_value = "default")]
+    pub account: String,
     /// Poll timeout for downloading the obfuscated program (in milliseconds)
     /// Do not go below 500 otherwise you will be timed out.
-    #[arg(long, default_value_t = 500)]
+    #[arg(long, env = "CD_POLL_INTERVAL", default_value_t = 500)]
     pub timeout: u64,
-    /// Input binary to process
+    /// Seconds to wait for analysis. Overrides `timeouts.analysis_secs` in the config
+    /// [default: 300]
+    #[arg(long, value_name = "SECONDS", env = "CD_ANALYSIS_TIMEOUT", value_parser = clap::value_parser!(u64).range(1..))]
+    pub analysis_timeout: Option<u64>,
+    /// Seconds to wait for obfuscation. Overrides `timeouts.obfuscation_secs` in the config
+    /// [default: 300]
+    #[arg(long, value_name = "SECONDS", env = "CD_OBFUSCATION_TIMEOUT", value_parser = clap::value_parser!(u64).range(1..))]
+    pub obfuscation_timeout: Option<u64>,
+    /// How often a request that failed for a transient reason (network error, rate limiting,
+    /// server error) is retried. Requests starting an analysis or obfuscation are only retried when
+    /// they could not be sent
+    #[arg(long, env = "CD_RETRIES", default_value_t = 3)]
+    pub retries: u32,
+    /// Milliseconds to wait before the first retry, doubled for every further one
+    #[arg(
+        long,
+        value_name = "MILLISECONDS",
+        env = "CD_RETRY_BACKOFF",
+        default_value_t = 1000
+    )]
+    pub retry_backoff: u64,
+}
+
+impl ApiArgs {
+    /// Looks up the API key in the credential store when none was given, and applies the retry
+    /// flags to every API request of this process.
+    fn prepare(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if self.api_key.is_none() {
+            let stored = keyring::load(&self.account).map_err(|e| {
+                failure(
+                    ExitStatus::Auth,
+                    format!(
+                        "No API key given, and the one stored for account `{}` cannot be read: {}",
+                        self.account, e
+                    ),
+                )
+            })?;
+            let Some(api_key) = stored else {
+                return Err(failure(
+                    ExitStatus::Auth,
+                    format!(
+                        "No API key: pass --api-key, set CD_API_KEY or run `codedefender-cli login --account {}`",
+                        self.account
+                    ),
+                ));
+            };
+            self.api_key = Some(api_key);
+        }
+        self.configure_retries();
+        Ok(())
+    }
+
+    /// The API key, once [`ApiArgs::prepare`] found one.
+    fn api_key(&self) -> &str {
+        self.api_key.as_deref().unwrap_or_default()
+    }
+
+    /// Applies the retry flags to every API request of this process.
+    fn configure_retries(&self) {
+        retry::configure(retry::RetryPolicy {
+            retries: self.retries,
+            backoff: Duration::from_millis(self.retry_backoff),
+        });
+    }
+
+    fn poll_interval(&self) -> Duration {
+        Duration::from_millis(self.timeout)
+    }
+
+    fn analysis_timeout(&self, config: &Timeouts) -> Duration {
+        Duration::from_secs(
+            self.analysis_timeout
+                .or(config.analysis_secs)
+                .unwrap_or(pipeline::DEFAULT_STAGE_TIMEOUT_SECS),
+        )
+    }
+
+    fn obfuscation_timeout(&self, config: &Timeouts) -> Duration {
+        Duration::from_secs(
+            self.obfuscation_timeout
+                .or(config.obfuscation_secs)
+                .unwrap_or(pipeline::DEFAULT_STAGE_TIMEOUT_SECS),
+        )
+    }
+}
+
+/// Adjustments of the config made on the command line.
+#[derive(Args, Debug)]
+pub struct ConfigArgs {
+    /// Override a config value, e.g. `profiles[0].passes[2].probability=50` or
+    /// `module_settings.pack_output_file=false`. Can be repeated
+    #[arg(long, value_name = "PATH=VALUE", value_parser = ConfigOverride::parse)]
+    pub set: Vec<ConfigOverride>,
+    /// Apply only this profile from the config. Can be repeated
+    #[arg(long, value_name = "PROFILE")]
+    pub only_profile: Vec<String>,
+    /// Leave out this profile from the config. Can be repeated
+    #[arg(long, value_name = "PROFILE")]
+    pub skip_profile: Vec<String>,
+}
+
+impl ConfigArgs {
+    /// Loads the config with the overrides applied and drops the profiles not selected. Unknown
+    /// profile names are a config error.
+    fn load_config(&self, path: &Path) -> Result<YamlConfig, Box<dyn std::error::Error>> {
+        ci::set_config(path);
+        let mut config = pipeline::load_config(path, &self.set)?;
+        if self.only_profile.is_empty() && self.skip_profile.is_empty() {
+            return Ok(config);
+        }
+        let unknown = config.select_profiles(&self.only_profile, &self.skip_profile);
+        if !unknown.is_empty() {
+            return Err(failure(
+                ExitStatus::Config,
+                format!("No profile named {} in {:?}", unknown.join(", "), path),
+            ));
+        }
+        let names: Vec<&str> = config.profiles.iter().map(|p| p.name.as_str()).collect();
+        if names.is_empty() {
+            log::warn!("No profiles selected, only module settings are applied");
+        } else {
+            log::info!("Applying profiles: {}", names.join(", "));
+        }
+        Ok(config)
+    }
+}
+
+/// Options for how debug symbols are preprocessed before they are uploaded.
+#[derive(Args, Debug)]
+pub struct SymbolArgs {
+    /// Download the PDB from the symbol servers of `_NT_SYMBOL_PATH` when it is not found locally
+    #[arg(long)]
+    pub fetch_pdb: bool,
+    /// Symbol server to download the PDB from when it is not found locally, e.g.
+    /// `https://symbols.example.com`. Implies `--fetch-pdb`
+    #[arg(long, value_name = "URL", env = "CD_SYMBOL_SERVER")]
+    pub symbol_server: Option<String>,
+    /// Layout of the uploaded symbols. `v2` adds the source file and line of every function, `v3`
+    /// also its size, `v4` its mangled name and `v5` the functions inlined into it
+    #[arg(long, value_enum, value_name = "FORMAT", default_value = "v1")]
+    pub symbol_format: SymbolFormat,
+    /// How function names are demangled: the full signature, only the qualified name, or not at
+    /// all
+    #[arg(long, value_enum, value_name = "STYLE", default_value = "complete")]
+    pub demangle: DemangleStyle,
+    /// Upload the mangled name of every function alongside the demangled one. Implies
+    /// `--symbol-format v4`
+    #[arg(long)]
+    pub keep_mangled: bool,
+    /// How functions sharing a name are told apart. The one at the lowest address keeps the
+    /// plain name, the others get a counter, their RVA or their module appended, or are dropped
+    #[arg(long, value_enum, value_name = "STRATEGY", default_value = "counter")]
+    pub duplicate_names: DuplicateStrategy,
+    /// Mark functions that never return by analyzing the binary, for PDBs without procedure flags
+    /// and for DWARF or export symbols. Catches thunks to imports like `ExitProcess` and functions
+    /// called last by another function
+    #[arg(long)]
+    pub infer_noreturn: bool,
+    /// Upload only the functions of modules matching this pattern, e.g. `game_*.obj`. Patterns
+    /// match object files, compilation units and static libraries, ignoring case. Can be repeated
+    #[arg(long, value_name = "PATTERN")]
+    pub include_module: Vec<String>,
+    /// Leave out the functions of modules matching this pattern, e.g. `vcruntime*.obj` or
+    /// `*.lib`. Can be repeated
+    #[arg(long, value_name = "PATTERN")]
+    pub exclude_module: Vec<String>,
+    /// Also name the functions of this static library (`.lib`) or object file (`.obj`) by finding
+    /// their code in the binary, for third-party code the debug info does not cover. Can be
+    /// repeated
+    #[arg(long, value_name = "PATH")]
+    pub static_lib: Vec<PathBuf>,
+    /// Leave out thunks, such as jumps to imports, incremental linking jumps (`@ILT+...`) and
+    /// adjustor thunks
+    #[arg(long)]
+    pub drop_thunks: bool,
+    /// Leave out helpers the compiler calls on its own, such as `__security_check_cookie` and
+    /// `__chkstk`, and the bodies it generates, such as deleting destructors and dynamic
+    /// initializers
+    #[arg(long)]
+    pub drop_compiler_helpers: bool,
+    /// Leave out functions identical COMDAT folding (`/OPT:ICF`) merged with others, whose code
+    /// every one of them shares
+    #[arg(long)]
+    pub drop_folded: bool,
+    /// zstd level of the uploaded symbols, from 1, the fastest, to 22, the smallest. Higher levels
+    /// save bandwidth on slow links at the cost of preprocessing time
+    #[arg(long, value_name = "LEVEL", default_value_t = 3, value_parser = clap::value_parser!(i32).range(1..=22))]
+    pub zstd_level: i32,
+    /// Threads compressing the uploaded symbols, 0 for one per CPU core
+    #[arg(long, value_name = "N", default_value_t = 1)]
+    pub zstd_threads: u32,
+}
+
+impl SymbolArgs {
+    pub fn options(&self) -> SymbolOptions {
+        let version = StreamVersion::from(self.symbol_format);
+        SymbolOptions {
+            version: if self.keep_mangled {
+                version.max(StreamVersion::V4)
+            } else {
+                version
+            },
+            demangling: self.demangle.into(),
+            duplicates: self.duplicate_names.into(),
+            infer_noreturn: self.infer_noreturn,
+            modules: ModuleFilter {
+                include: self.include_module.clone(),
+                exclude: self.exclude_module.clone(),
+            },
+            generated: GeneratedFilter {
+                thunks: self.drop_thunks,
+                compiler_helpers: self.drop_compiler_helpers,
+                folded: self.drop_folded,
+            },
+            libraries: self.static_lib.clone(),
+            compression: Compression {
+                level: self.zstd_level,
+                threads: match self.zstd_threads {
+                    0 => std::thread::available_parallelism().map_or(1, |n| n.get() as u32),
+                    threads => threads,
+                },
+            },
+            // Decided once the service says what it reads, see `pipeline::negotiate_symbols`
+            headerless: false,
+        }
+    }
+
+    /// The symbol servers to look for PDBs on, `None` unless downloading is enabled.
+    pub fn servers(&self) -> Option<SymbolServers> {
+        (self.fetch_pdb || self.symbol_server.is_some())
+            .then(|| SymbolServers::new(self.symbol_server.as_deref()))
+    }
+}
+
+/// Layout of the uploaded symbols.
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum SymbolFormat {
+    V1,
+    V2,
+    V3,
+    V4,
+    V5,
+}
+
+impl From<SymbolFormat> for StreamVersion {
+    fn from(value: SymbolFormat) -> Self {
+        match value {
+            SymbolFormat::V1 => StreamVersion::V1,
+            SymbolFormat::V2 => StreamVersion::V2,
+            SymbolFormat::V3 => StreamVersion::V3,
+            SymbolFormat::V4 => StreamVersion::V4,
+            SymbolFormat::V5 => StreamVersion::V5,
+        }
+    }
+}
+
+/// How function names are demangled.
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum DemangleStyle {
+    Complete,
+    NameOnly,
+    None,
+}
+
+impl From<DemangleStyle> for Demangling {
+    fn from(value: DemangleStyle) -> Self {
+        match value {
+            DemangleStyle::Complete => Demangling::Complete,
+            DemangleStyle::NameOnly => Demangling::NameOnly,
+            DemangleStyle::None => Demangling::None,
+        }
+    }
+}
+
+/// How functions sharing a name are told apart.
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum DuplicateStrategy {
+    Counter,
+    Rva,
+    Module,
+    KeepFirst,
+}
+
+impl From<DuplicateStrategy> for DuplicateNames {
+    fn from(value: DuplicateStrategy) -> Self {
+        match value {
+            DuplicateStrategy::Counter => DuplicateNames::Counter,
+            DuplicateStrategy::Rva => DuplicateNames::Rva,
+            DuplicateStrategy::Module => DuplicateNames::Module,
+            DuplicateStrategy::KeepFirst => DuplicateNames::KeepFirst,
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+#[command(group(ArgGroup::new("extracted").multiple(true).args(["extract_to", "output_binary"])))]
+pub struct RunArgs {
+    /// Path to the YAML configuration file
+    #[arg(short, long, value_name = "FILE")]
+    pub config: PathBuf,
+    /// Input binary to process, `-` to read it from stdin
+    #[arg(long, value_name = "INPUT")]
+    pub input_file: PathBuf,
+    /// Debug symbol (PDB) file. Looked for next to the input, at the path in its debug directory
+    /// and in `_NT_SYMBOL_PATH` when not given
+    #[arg(long, value_name = "PDB")]
+    pub pdb_file: Option<PathBuf>,
+    /// Output path for the Zip file containing the obfuscated binary and dbg file, `-` to write
+    /// it to stdout
+    #[arg(long, value_name = "OUTPUT")]
+    pub output: PathBuf,
+    /// Also save the analysis result to this path, as JSON when it ends in `.json` and in the
+    /// compact binary format otherwise
+    #[arg(long, value_name = "ANALYSIS")]
+    pub analysis_out: Option<PathBuf>,
+    /// Reuse an analysis result saved by `analyze` or `--analysis-out` instead of uploading and
+    /// analyzing the input again. Requires `--file-id`.
+    #[arg(
+        long,
+        value_name = "ANALYSIS",
+        requires = "file_id",
+        conflicts_with = "analysis_out"
+    )]
+    pub analysis_in: Option<PathBuf>,
+    /// File id of the upload the reused analysis belongs to
+    #[arg(long, requires = "analysis_in")]
+    pub file_id: Option<String>,
+    /// Print the resolved config as JSON instead of submitting it
+    #[arg(long)]
+    pub dry_run: bool,
+    /// Fail unless at least this share of the analyzed functions is protected, e.g. `60%`
+    #[arg(long, value_name = "PERCENT", value_parser = pipeline::parse_percent)]
+    pub min_coverage: Option<f64>,
+    /// What to do when the config names a function that analysis rejected
+    #[arg(long, value_enum, value_name = "POLICY", default_value = "fail")]
+    pub on_reject: pipeline::RejectPolicy,
+    /// Always upload and analyze, ignoring uploads cached from earlier runs
+    #[arg(long)]
+    pub no_cache: bool,
+    /// Continue an interrupted run from the state file it left next to `--output`
+    #[arg(long, value_name = "STATE", conflicts_with_all = ["analysis_in", "file_id"])]
+    pub resume: Option<PathBuf>,
+    /// Also unpack the output into this directory as `<input>.protected.exe` and
+    /// `<input>.protected.pdb`
+    #[arg(long, value_name = "DIR")]
+    pub extract_to: Option<PathBuf>,
+    /// Also write the obfuscated binary from the output to exactly this path
+    #[arg(long, value_name = "FILE")]
+    pub output_binary: Option<PathBuf>,
+    /// Also write the regenerated PDB from the output to exactly this path
+    #[arg(long, value_name = "FILE")]
+    pub output_pdb: Option<PathBuf>,
+    /// Sign every extracted binary with this command, e.g. `signtool sign /a {file}`. Use
+    /// `{signed}` for tools that write the signed copy to a separate path
+    #[arg(long, value_name = "COMMAND", requires = "extracted", value_parser = SignCommand::parse)]
+    pub sign_with: Option<SignCommand>,
+    /// Check the output is still a loadable image of the input: entry point, imports, exports,
+    /// driver relocations and custom section name
+    #[arg(long)]
+    pub verify: bool,
+    /// With `--verify`, also upload the output and analyze it to confirm it still disassembles
+    #[arg(long, requires = "verify")]
+    pub verify_reanalyze: bool,
+    /// Write a JSON manifest with the hashes of the input, config and outputs, the execution ids,
+    /// tool versions and timestamps of this run
+    #[arg(long, value_name = "FILE")]
+    pub build_manifest: Option<PathBuf>,
+    /// Write a self-contained report of the run to this path: profiles, protected functions per
+    /// pass, rejects, module settings, output hashes and timings. Markdown for `.md`, HTML
+    /// otherwise
+    #[arg(long, value_name = "FILE")]
+    pub report: Option<PathBuf>,
+    /// Write a signed in-toto attestation of the obfuscation step (SLSA provenance in a DSSE
+    /// envelope) to this path
+    #[arg(long, value_name = "FILE", requires = "attestation_key")]
+    pub attestation: Option<PathBuf>,
+    /// PKCS#8 Ed25519 private key, PEM or DER, the attestation is signed with
+    #[arg(long, value_name = "KEY", requires = "attestation")]
+    pub attestation_key: Option<PathBuf>,
+    #[command(flatten)]
+    pub symbols: SymbolArgs,
+    #[command(flatten)]
+    pub config_args: ConfigArgs,
+    #[command(flatten)]
+    pub api: ApiArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct CargoArgs {
+    /// Path to the YAML configuration file
+    #[arg(short, long, value_name = "FILE")]
+    pub config: PathBuf,
+    #[command(flatten)]
+    pub target: cargo::CargoTarget,
+    /// Replace the binary and PDB in the target directory with the protected ones, keeping the
+    /// originals as `<name>.unprotected.<ext>`. Otherwise they are written next to them as
+    /// `<name>.protected.<ext>`
+    #[arg(long)]
+    pub replace: bool,
+    /// Print the resolved config as JSON instead of submitting it
+    #[arg(long)]
+    pub dry_run: bool,
+    /// Fail unless at least this share of the analyzed functions is protected, e.g. `60%`
+    #[arg(long, value_name = "PERCENT", value_parser = pipeline::parse_percent)]
+    pub min_coverage: Option<f64>,
+    /// What to do when the config names a function that analysis rejected
+    #[arg(long, value_enum, value_name = "POLICY", default_value = "fail")]
+    pub on_reject: pipeline::RejectPolicy,
+    /// Always upload and analyze, ignoring uploads cached from earlier runs
+    #[arg(long)]
+    pub no_cache: bool,
+    /// Check the output is still a loadable image of the input, see `run --verify`
+    #[arg(long)]
+    pub verify: bool,
+    /// Sign the protected binary with this command, see `run --sign-with`
+    #[arg(long, value_name = "COMMAND", value_parser = SignCommand::parse)]
+    pub sign_with: Option<SignCommand>,
+    #[command(flatten)]
+    pub symbols: SymbolArgs,
+    #[command(flatten)]
+    pub config_args: ConfigArgs,
+    #[command(flatten)]
+    pub api: ApiArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct BatchArgs {
+    /// Path to the YAML configuration file
+    #[arg(short, long, value_name = "FILE")]
+    pub config: PathBuf,
+    /// Input binaries to process, `*`, `?` and `**` are expanded. The PDB of each binary is looked
+    /// up like `run` does without `--pdb-file`.
+    #[arg(long, value_name = "INPUT", required_unless_present = "manifest")]
+    pub input_file: Vec<PathBuf>,
+    /// Directory the outputs for `--input-file` are written to. Defaults to the directory of
+    /// each input.
+    #[arg(long, value_name = "DIR")]
+    pub output_dir: Option<PathBuf>,
+    /// Output file name for each `--input-file`, with `{stem}`, `{ext}` and `{name}` taken from
+    /// the input
+    #[arg(long, value_name = "TEMPLATE", default_value = "{stem}.zip")]
+    pub output_template: String,
+    /// YAML file listing `input`, optional `pdb` and `output` for each binary
+    #[arg(long, value_name = "MANIFEST", conflicts_with = "input_file")]
+    pub manifest: Option<PathBuf>,
+    /// How many binaries are uploaded and processed at the same time
+    #[arg(long, default_value_t = 4)]
+    pub jobs: usize,
+    /// Status requests per second shared by all binaries in flight
+    #[arg(long, default_value_t = 2)]
+    pub max_polls_per_second: u32,
+    /// Always upload and analyze, ignoring uploads cached from earlier runs
+    #[arg(long)]
+    pub no_cache: bool,
+    /// Check every output is still a loadable image of its input, see `run --verify`
+    #[arg(long)]
+    pub verify: bool,
+    /// Fail unless at least this share of the analyzed functions is protected, e.g. `60%`
+    #[arg(long, value_name = "PERCENT", value_parser = pipeline::parse_percent)]
+    pub min_coverage: Option<f64>,
+    /// What to do when the config names a function that analysis rejected
+    #[arg(long, value_enum, value_name = "POLICY", default_value = "fail")]
+    pub on_reject: pipeline::RejectPolicy,
+    #[command(flatten)]
+    pub symbols: SymbolArgs,
+    #[command(flatten)]
+    pub config_args: ConfigArgs,
+    #[command(flatten)]
+    pub api: ApiArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct AnalyzeArgs {
+    /// Path to the YAML configuration file (used for its disassembly settings)
+    #[arg(short, long, value_name = "FILE")]
+    pub config: PathBuf,
+    /// Input binary to analyze
     #[arg(long, value_name = "INPUT")]
     pub input_file: PathBuf,
-    /// Optional debug symbol (PDB) file
+    /// Debug symbol (PDB) file. Looked for next to the input, at the path in its debug directory
+    /// and in `_NT_SYMBOL_PATH` when not given
     #[arg(long, value_name = "PDB")]
     pub pdb_file: Option<PathBuf>,
-    /// Output path for the Zip file containing the obfuscated binary and dbg file
+    /// Output path for the analysis result, JSON when it ends in `.json` and the compact binary
+    /// format otherwise
+    #[arg(long, value_name = "OUTPUT")]
+    pub output: PathBuf,
+    /// Always upload and analyze, ignoring uploads cached from earlier runs
+    #[arg(long)]
+    pub no_cache: bool,
+    #[command(flatten)]
+    pub symbols: SymbolArgs,
+    #[command(flatten)]
+    pub config_args: ConfigArgs,
+    #[command(flatten)]
+    pub api: ApiArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct DefendArgs {
+    /// Path to the YAML configuration file
+    #[arg(short, long, value_name = "FILE")]
+    pub config: PathBuf,
+    /// File id printed by `analyze`
+    #[arg(long)]
+    pub file_id: String,
+    /// Analysis result written by `analyze`
+    #[arg(long, value_name = "ANALYSIS", alias = "analysis")]
+    pub analysis_in: PathBuf,
+    /// Print the resolved config as JSON instead of submitting it
+    #[arg(long)]
+    pub dry_run: bool,
+    /// Fail unless at least this share of the analyzed functions is protected, e.g. `60%`
+    #[arg(long, value_name = "PERCENT", value_parser = pipeline::parse_percent)]
+    pub min_coverage: Option<f64>,
+    /// What to do when the config names a function that analysis rejected
+    #[arg(long, value_enum, value_name = "POLICY", default_value = "fail")]
+    pub on_reject: pipeline::RejectPolicy,
+    #[command(flatten)]
+    pub config_args: ConfigArgs,
+    #[command(flatten)]
+    pub api: ApiArgs,
+}
+
+#[derive(Args, Debug)]
+#[command(group(ArgGroup::new("extracted").multiple(true).args(["extract_to", "output_binary"])))]
+pub struct DownloadArgs {
+    /// Execution id printed by `defend`
+    #[arg(long)]
+    pub execution_id: String,
+    /// Output path for the Zip file containing the obfuscated binary and dbg file, `-` to write
+    /// it to stdout
     #[arg(long, value_name = "OUTPUT")]
     pub output: PathBuf,
+    /// Config whose output constraints the download is checked against
+    #[arg(short, long, value_name = "FILE", requires = "input_file")]
+    pub config: Option<PathBuf>,
+    /// Original binary the output constraints are compared to
+    #[arg(long, value_name = "INPUT", requires = "config")]
+    pub input_file: Option<PathBuf>,
+    /// Also unpack the output into this directory as `<input>.protected.exe` and
+    /// `<input>.protected.pdb`, named after `--output` when no input file is given
+    #[arg(long, value_name = "DIR")]
+    pub extract_to: Option<PathBuf>,
+    /// Also write the obfuscated binary from the output to exactly this path
+    #[arg(long, value_name = "FILE")]
+    pub output_binary: Option<PathBuf>,
+    /// Also write the regenerated PDB from the output to exactly this path
+    #[arg(long, value_name = "FILE")]
+    pub output_pdb: Option<PathBuf>,
+    /// Sign every extracted binary with this command, e.g. `signtool sign /a {file}`. Use
+    /// `{signed}` for tools that write the signed copy to a separate path
+    #[arg(long, value_name = "COMMAND", requires = "extracted", value_parser = SignCommand::parse)]
+    pub sign_with: Option<SignCommand>,
+    /// Check the output is still a loadable image of the input: entry point, imports, exports,
+    /// driver relocations and custom section name
+    #[arg(long, requires = "input_file")]
+    pub verify: bool,
+    /// With `--verify`, also upload the output and analyze it to confirm it still disassembles
+    #[arg(long, requires = "verify")]
+    pub verify_reanalyze: bool,
+    #[command(flatten)]
+    pub api: ApiArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct ValidateArgs {
+    /// Path to the YAML configuration file
+    #[arg(value_name = "FILE")]
+    pub config: PathBuf,
+    /// Fail on warnings as well as errors
+    #[arg(long)]
+    pub deny_warnings: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct MigrateConfigArgs {
+    /// Path to the YAML configuration file to upgrade
+    #[arg(value_name = "FILE")]
+    pub config: PathBuf,
+    /// Where to write the upgraded config, printed when omitted
+    #[arg(short, long, value_name = "OUTPUT")]
+    pub output: Option<PathBuf>,
+    /// Also change a value in the written config, e.g. `module_settings.pack_output_file=true`.
+    /// Can be repeated
+    #[arg(long, value_name = "PATH=VALUE", value_parser = ConfigOverride::parse)]
+    pub set: Vec<ConfigOverride>,
+}
+
+/// Protection strength of the generated config.
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum Preset {
+    Light,
+    Balanced,
+    Aggressive,
+}
+
+impl From<Preset> for ConfigPreset {
+    fn from(value: Preset) -> Self {
+        match value {
+            Preset::Light => ConfigPreset::Light,
+            Preset::Balanced => ConfigPreset::Balanced,
+            Preset::Aggressive => ConfigPreset::Aggressive,
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct InitArgs {
+    /// Where to write the config
+    #[arg(long, value_name = "OUTPUT", default_value = "config.yaml")]
+    pub output: PathBuf,
+    /// Analysis result written by `analyze`, used to pre-fill profiles and symbols
+    #[arg(long, value_name = "ANALYSIS")]
+    pub analysis: Option<PathBuf>,
+    /// Protection strength of the generated profiles
+    #[arg(long, value_enum, default_value = "balanced")]
+    pub preset: Preset,
+    /// Overwrite the output if it already exists
+    #[arg(long)]
+    pub force: bool,
+}
+
+#[derive(Args, Debug)]
+#[command(group(ArgGroup::new("source").required(true).args(["analysis", "input_file"])))]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+pub struct SymbolsArgs {
+    #[command(subcommand)]
+    pub command: Option<SymbolsCommand>,
+    /// Analysis result written by `analyze`
+    #[arg(long, value_name = "ANALYSIS")]
+    pub analysis: Option<PathBuf>,
+    /// Upload and analyze this binary instead of reading --analysis
+    #[arg(long, value_name = "INPUT", requires = "config")]
+    pub input_file: Option<PathBuf>,
+    /// Debug symbol (PDB) file. Looked for next to the input, at the path in its debug directory
+    /// and in `_NT_SYMBOL_PATH` when not given
+    #[arg(long, value_name = "PDB", requires = "input_file")]
+    pub pdb_file: Option<PathBuf>,
+    /// Config whose disassembly settings are used to analyze --input-file
+    #[arg(short, long, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+    /// Only list functions, rejects and macro profiles whose name contains this text, ignoring
+    /// case
+    #[arg(long, value_name = "TEXT")]
+    pub filter: Option<String>,
+    /// Order of the listed functions and rejects
+    #[arg(long, value_enum, default_value = "refs")]
+    pub sort: symbols::SortOrder,
+    /// Print one CSV table instead of aligned tables
+    #[arg(long)]
+    pub csv: bool,
+    /// Always upload and analyze, ignoring uploads cached from earlier runs
+    #[arg(long)]
+    pub no_cache: bool,
+    #[command(flatten)]
+    pub symbols: SymbolArgs,
+    #[command(flatten)]
+    pub config_args: ConfigArgs,
+    #[command(flatten)]
+    pub api: ApiArgs,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum SymbolsCommand {
+    /// Write the preprocessed symbols to JSON, exactly as the service will see them, without
+    /// uploading anything
+    Export(SymbolsExportArgs),
+}
+
+#[derive(Args, Debug)]
+#[command(group(ArgGroup::new("source").required(true).multiple(true).args(["input_file", "pdb_file"])))]
+pub struct SymbolsExportArgs {
+    /// Binary whose symbols are exported. Its PDB is looked for like for `run`, and without one
+    /// the symbols come from DWARF debug info or the export table
+    #[arg(long, value_name = "INPUT")]
+    pub input_file: Option<PathBuf>,
+    /// Debug symbol (PDB) file. Checked against --input-file when both are given
+    #[arg(long, value_name = "PDB", alias = "pdb")]
+    pub pdb_file: Option<PathBuf>,
+    /// Where to write the JSON, `-` for stdout
+    #[arg(short, long, value_name = "FILE")]
+    pub output: PathBuf,
+    #[command(flatten)]
+    pub symbols: SymbolArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct AnalysisDiffArgs {
+    /// Analysis result of the previous build
+    #[arg(value_name = "OLD")]
+    pub old: PathBuf,
+    /// Analysis result of the new build
+    #[arg(value_name = "NEW")]
+    pub new: PathBuf,
+    /// Exit with code 7 when a function that was protectable is rejected in the new build
+    #[arg(long)]
+    pub fail_on_regression: bool,
 }
 
-// Resolve symbol names to RVA's. If a symbol is specified via RVA
-// then validate it before including it in the result.
-fn resolve_symbols(
-    symbols: &[YamlSymbol],
+#[derive(Args, Debug)]
+pub struct CompareArgs {
+    /// Original binary
+    #[arg(value_name = "ORIGINAL")]
+    pub original: PathBuf,
+    /// Protected binary, or the output zip it is in
+    #[arg(value_name = "PROTECTED")]
+    pub protected: PathBuf,
+}
+
+#[derive(Args, Debug)]
+pub struct DoctorArgs {
+    /// API key to check, otherwise the one stored with `login` for --account
+    #[arg(long, env = "CD_API_KEY")]
+    pub api_key: Option<String>,
+    /// Credential profile whose stored key is checked when no API key is given
+    #[arg(long, env = "CD_ACCOUNT", default_value = "default")]
+    pub account: String,
+}
+
+#[derive(Args, Debug)]
+pub struct CompletionsArgs {
+    /// Shell to complete in
+    #[arg(value_enum)]
+    pub shell: clap_complete::Shell,
+}
+
+#[derive(Args, Debug)]
+pub struct ManArgs {
+    /// Write `codedefender-cli.1` and a page for every subcommand into this directory instead of
+    /// printing the top level page
+    #[arg(long, value_name = "DIR")]
+    pub out_dir: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+pub struct LoginArgs {
+    /// Name of the credential profile, picked with --account or CD_ACCOUNT by other commands
+    #[arg(long, env = "CD_ACCOUNT", default_value = "default")]
+    pub account: String,
+}
+
+#[derive(Args, Debug)]
+pub struct LogoutArgs {
+    /// Name of the credential profile to remove
+    #[arg(long, env = "CD_ACCOUNT", default_value = "default")]
+    pub account: String,
+}
+
+#[derive(Args, Debug)]
+pub struct MockServerArgs {
+    /// Address to listen on. Port 0 picks a free port
+    #[arg(long, default_value = "127.0.0.1:8787")]
+    pub listen: String,
+    /// Only accept this API key instead of any
+    #[arg(long)]
+    pub api_key: Option<String>,
+    /// Analysis result JSON returned for every analysis, instead of one naming the functions of
+    /// the uploaded PDB
+    #[arg(long, value_name = "ANALYSIS")]
+    pub analysis: Option<PathBuf>,
+    /// File returned for every obfuscation, instead of the uploaded binary
+    #[arg(long, value_name = "FILE")]
+    pub artifact: Option<PathBuf>,
+    /// Status polls answered with "processing" before an execution is done
+    #[arg(long, value_name = "COUNT", default_value_t = 1)]
+    pub processing_polls: u32,
+}
+
+#[derive(Args, Debug)]
+pub struct TuiArgs {
+    /// Analysis result written by `analyze`
+    #[arg(long, value_name = "ANALYSIS")]
+    pub analysis: PathBuf,
+    /// Existing config to edit instead of starting from one generated from the analysis
+    #[arg(short, long, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+    /// Where `save` writes the config, defaults to --config or `config.yaml`
+    #[arg(long, value_name = "OUTPUT")]
+    pub output: Option<PathBuf>,
+    /// Protection strength of generated profiles when starting without --config
+    #[arg(long, value_enum, default_value = "balanced")]
+    pub preset: Preset,
+}
+
+#[derive(Args, Debug)]
+#[command(group(ArgGroup::new("build_system").required(true).args(["cmake", "msbuild"])))]
+pub struct IntegrateArgs {
+    /// Generate a CMake module defining `codedefender_protect(<target>)`
+    #[arg(long)]
+    pub cmake: bool,
+    /// Generate an MSBuild `.targets` file that protects Release builds
+    #[arg(long)]
+    pub msbuild: bool,
+    /// Config file the build step protects with
+    #[arg(short, long, value_name = "FILE", default_value = "config.yaml")]
+    pub config: PathBuf,
+    /// CMake target to protect, otherwise call `codedefender_protect` yourself
+    #[arg(long, value_name = "TARGET", conflicts_with = "msbuild")]
+    pub target: Option<String>,
+    /// Where to write the build step [default: codedefender.cmake or CodeDefender.targets]
+    #[arg(long, value_name = "OUTPUT")]
+    pub output: Option<PathBuf>,
+    /// Overwrite the output if it already exists
+    #[arg(long)]
+    pub force: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct CacheArgs {
+    #[command(subcommand)]
+    pub command: CacheCommand,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum CacheCommand {
+    /// Remove cached uploads that were not used recently
+    Gc {
+        /// Remove entries unused for this many days. 0 removes everything.
+        #[arg(long, default_value_t = 7)]
+        max_age_days: u64,
+    },
+}
+
+fn read_analysis(path: &Path) -> Result<AnalysisResult, Box<dyn std::error::Error>> {
+    Ok(AnalysisResult::load(path)?)
+}
+
+fn write_analysis(
+    path: &Path,
     analysis: &AnalysisResult,
-) -> Result<Vec<u64>, Box<dyn std::error::Error>> {
-    let mut resolved = Vec::new();
-    for symbol in symbols {
-        match symbol {
-            YamlSymbol::Name(name) => {
-                // Search in returned in functions and rejects for symbol by name.
-                // If it was rejected for "ReadWriteToCode" we will force resolve it.
-                let rva = analysis
-                    .functions
-                    .iter()
-                    .find(|f| f.symbol == *name)
-                    .map(|e| e.rva)
-                    .or_else(|| {
-                        analysis
-                            .rejects
-                            .iter()
-                            .find(|r| r.symbol == *name && r.ty == "ReadWriteToCode")
-                            .map(|e| e.rva)
-                    });
-                match rva {
-                    Some(rva) => resolved.push(rva),
-                    None => {
-                        log::error!("Symbol `{}` not found in analysis result", name);
-                        return Err("Missing symbol".into());
-                    }
-                }
+) -> Result<(), Box<dyn std::error::Error>> {
+    analysis.save(path)?;
+    log::info!(
+        "Analysis result written to {:?} ({} functions, {} rejects, {} macro profiles)",
+        path,
+        analysis.functions.len(),
+        analysis.rejects.len(),
+        analysis.macros.len()
+    );
+    Ok(())
+}
+
+/// Prints the config that would be submitted, in the JSON form the service receives.
+fn print_dry_run(cdconfig: &Config, reporter: &Reporter) -> Result<(), Box<dyn std::error::Error>> {
+    if reporter.is_json() {
+        reporter.event("dry_run", json!({ "config": cdconfig }));
+    } else {
+        println!("{}", serde_json::to_string_pretty(cdconfig)?);
+    }
+    log::info!("Dry run, nothing was submitted");
+    Ok(())
+}
+
+/// Uploads and analyzes `input_file`, unless an upload of the same inputs and its analysis are
+/// cached from an earlier run. Stages already recorded in `state` are skipped.
+#[allow(clippy::too_many_arguments)]
+fn upload_and_analyze(
+    input_file: &Path,
+    pdb_file: Option<&Path>,
+    symbols: &SymbolOptions,
+    config: &YamlConfig,
+    client: &reqwest::blocking::Client,
+    api: &ApiArgs,
+    no_cache: bool,
+    state: &mut RunState,
+    reporter: &mut Reporter,
+) -> Result<(String, AnalysisResult), Box<dyn std::error::Error>> {
+    reporter.result.input_sha256 = Some(state.input_sha256.clone());
+    let cache = match Cache::open(no_cache || state.file_id.is_some()) {
+        Some(cache) => {
+            let pdb_sha256 = match pdb_file {
+                Some(path) => Some(manifest::hex_digest_file(path)?),
+                None => None,
+            };
+            let key = Cache::key(
+                &state.input_sha256,
+                pdb_sha256.as_deref(),
+                symbols,
+                config,
+                api.api_key(),
+            );
+            if let Some(entry) = cache.get(&key) {
+                log::info!("Reusing cached upload {} and its analysis", entry.file_id);
+                reporter.result.file_id = Some(entry.file_id.clone());
+                reporter.event("cache_hit", json!({ "file_id": entry.file_id }));
+                return Ok((entry.file_id, entry.analysis));
             }
-            YamlSymbol::Rva(rva) => {
-                if !is_valid_rva(*rva, analysis) {
-                    log::error!("RVA {:X} not found in analysis", rva);
-                    return Err("Invalid RVA".into());
+            Some((cache, key))
+        }
+        None => None,
+    };
+
+    let file_id = match state.file_id.clone() {
+        Some(file_id) => file_id,
+        None => {
+            let upload = pipeline::upload_inputs(
+                input_file,
+                pdb_file,
+                symbols,
+                config,
+                client,
+                api.api_key(),
+            )?;
+            state.file_id = Some(upload.file_id.clone());
+            state.pdb_file_id = upload.pdb_file_id;
+            state.advance(RunStage::Uploaded);
+            reporter.event("uploaded", json!({ "file_id": upload.file_id }));
+            reporter.stage_done("upload");
+            reporter
+                .result
+                .bytes
+                .insert("upload".to_owned(), upload.bytes);
+            upload.file_id
+        }
+    };
+    reporter.result.file_id = Some(file_id.clone());
+
+    let analyze_execution_id = match state.analyze_execution_id.clone() {
+        Some(id) => id,
+        None => {
+            log::info!("Starting analysis...");
+            let id = pipeline::start_analysis(
+                &file_id,
+                state.pdb_file_id.clone(),
+                client,
+                api.api_key(),
+            )?;
+            state.analyze_execution_id = Some(id.clone());
+            state.advance(RunStage::Analyzing);
+            id
+        }
+    };
+    let analysis = pipeline::wait_for_analysis(
+        &analyze_execution_id,
+        client,
+        api.api_key(),
+        api.poll_interval(),
+        api.analysis_timeout(&config.timeouts),
+    )?;
+    reporter.stage_done("analysis");
+    if let Some((cache, key)) = cache {
+        cache.put(&key, &file_id, &analysis);
+    }
+    Ok((file_id, analysis))
+}
+
+fn run(mut args: RunArgs, reporter: &mut Reporter) -> Result<(), Box<dyn std::error::Error>> {
+    args.api.prepare()?;
+    stdio::claim_stdout(&args.output, reporter)?;
+    let input_name = args.input_file.clone();
+    let _spooled_input = if stdio::is_stdio(&args.input_file) {
+        let input = SpooledInput::read_stdin()?;
+        args.input_file = input.path.clone();
+        Some(input)
+    } else {
+        None
+    };
+    if args.pdb_file.is_none() && args.analysis_in.is_none() {
+        args.pdb_file = pdb_search::find(&args.input_file, args.symbols.servers().as_ref());
+    }
+    let started_at = manifest::timestamp(SystemTime::now());
+    let config = args.config_args.load_config(&args.config)?;
+    let attestation_key = args
+        .attestation_key
+        .as_deref()
+        .map(attestation::SigningKey::load)
+        .transpose()?;
+    let client = reqwest::blocking::Client::new();
+    let input_sha256 = manifest::hex_digest(&fs::read(&args.input_file)?);
+    reporter.result.input_sha256 = Some(input_sha256.clone());
+    let mut state = match &args.resume {
+        Some(path) => {
+            let state = RunState::load(path, &input_sha256)?;
+            log::info!("Resuming run from stage {:?}", state.stage);
+            state
+        }
+        None if args.dry_run || stdio::is_stdio(&args.output) => RunState::new(input_sha256, None),
+        None => RunState::new(input_sha256, Some(RunState::default_path(&args.output))),
+    };
+
+    // The submitted config and the analysis it was built from, for --report.
+    let mut submitted = None;
+    let execution_id = match state.execution_id.clone() {
+        Some(execution_id) => {
+            log::info!("Waiting for obfuscation {} again...", execution_id);
+            reporter.result.file_id = state.file_id.clone();
+            execution_id
+        }
+        None => {
+            let (file_id, analysis) = match (&args.analysis_in, &args.file_id) {
+                (Some(path), Some(file_id)) => {
+                    log::info!("Reusing analysis {:?} of upload {}", path, file_id);
+                    (file_id.clone(), read_analysis(path)?)
                 }
-                resolved.push(*rva);
+                _ => upload_and_analyze(
+                    &args.input_file,
+                    args.pdb_file.as_deref(),
+                    &args.symbols.options(),
+                    &config,
+                    &client,
+                    &args.api,
+                    args.no_cache,
+                    &mut state,
+                    reporter,
+                )?,
+            };
+            reporter.result.file_id = Some(file_id.clone());
+            state.analysis = Some(manifest::AnalysisSummary::of(&analysis));
+            if let Some(path) = &args.analysis_out {
+                write_analysis(path, &analysis)?;
             }
-            YamlSymbol::All => {
-                resolved.extend(
-                    analysis
-                        .functions
-                        .iter()
-                        .map(|e| e.rva)
-                        .collect::<Vec<u64>>(),
-                );
+            let cdconfig = pipeline::build_config(&config, &analysis, args.on_reject)?;
+            let fake_pdb = &cdconfig.module_settings.fake_pdb_string;
+            state.fake_pdb_string = fake_pdb.enabled.then(|| fake_pdb.value.clone());
+            let coverage = pipeline::check_coverage(&cdconfig, &analysis, args.min_coverage)?;
+            reporter.result.coverage_percent = Some(coverage.percent());
+            if args.dry_run {
+                return print_dry_run(&cdconfig, reporter);
             }
+
+            if args.report.is_some() {
+                submitted = Some((cdconfig.clone(), analysis));
+            }
+
+            log::info!("Obfuscating program...");
+            let execution_id =
+                pipeline::start_obfuscation(&file_id, cdconfig, &client, args.api.api_key())?;
+            state.execution_id = Some(execution_id.clone());
+            state.advance(RunStage::Obfuscating);
+            reporter.event("submitted", json!({ "execution_id": execution_id }));
+            execution_id
+        }
+    };
+    reporter.result.execution_id = Some(execution_id.clone());
+    let url = pipeline::wait_for_output(
+        &execution_id,
+        &client,
+        args.api.api_key(),
+        args.api.poll_interval(),
+        args.api.obfuscation_timeout(&config.timeouts),
+    )?;
+    reporter.stage_done("obfuscation");
+    let bytes = pipeline::download_output(&url, &client)?;
+    reporter.stage_done("download");
+    reporter
+        .result
+        .bytes
+        .insert("download".to_owned(), bytes.len());
+    reporter.result.output_sha256 = Some(manifest::hex_digest(&bytes));
+    pipeline::check_output(&config, &args.input_file, &bytes)?;
+    if args.verify {
+        pipeline::verify_output(&config, &args.input_file, &bytes)?;
+    }
+    if args.verify_reanalyze {
+        pipeline::reanalyze_output(
+            &bytes,
+            &config,
+            &client,
+            args.api.api_key(),
+            args.api.poll_interval(),
+            args.api.analysis_timeout(&config.timeouts),
+        )?;
+        reporter.stage_done("verification");
+    }
+    stdio::write_output(&args.output, &bytes)?;
+    reporter.result.output = Some(args.output.clone());
+    extract(
+        &bytes,
+        &args.input_file,
+        args.extract_to.as_deref(),
+        args.output_binary.as_deref(),
+        args.output_pdb.as_deref(),
+        args.sign_with.as_ref(),
+        reporter,
+    )?;
+    if args.build_manifest.is_some() || args.attestation.is_some() || args.report.is_some() {
+        let mut outputs = vec![manifest::Artifact::of_bytes(&args.output, &bytes)];
+        for artifact in &reporter.result.artifacts {
+            outputs.push(manifest::Artifact::of(artifact)?);
+        }
+        let manifest = manifest::Manifest {
+            manifest_version: manifest::MANIFEST_VERSION,
+            cli_version: env!("CARGO_PKG_VERSION"),
+            config_version: config.version.clone(),
+            started_at,
+            finished_at: manifest::timestamp(SystemTime::now()),
+            input: manifest::Artifact {
+                path: input_name,
+                ..manifest::Artifact::of(&args.input_file)?
+            },
+            pdb: args
+                .pdb_file
+                .as_deref()
+                .map(manifest::Artifact::of)
+                .transpose()?,
+            config: manifest::Artifact::of_config(&args.config, &config)?,
+            overrides: args
+                .config_args
+                .set
+                .iter()
+                .map(ToString::to_string)
+                .collect(),
+            analysis: state.analysis.clone(),
+            fake_pdb_string: state.fake_pdb_string.clone(),
+            file_id: reporter.result.file_id.clone(),
+            execution_id,
+            outputs,
+        };
+        if let Some(path) = &args.build_manifest {
+            manifest.write(path)?;
+        }
+        if let (Some(path), Some(key)) = (&args.attestation, &attestation_key) {
+            attestation::write(&manifest, key, path)?;
+        }
+        if let Some(path) = &args.report {
+            let report = protection_report::ProtectionReport {
+                manifest: &manifest,
+                config: submitted.as_ref().map(|(config, _)| config),
+                analysis: submitted.as_ref().map(|(_, analysis)| analysis),
+                result: &reporter.result,
+            };
+            report.write(path)?;
         }
     }
-    Ok(resolved)
+    state.finish();
+    Ok(())
 }
 
-fn is_valid_rva(rva: u64, analysis: &AnalysisResult) -> bool {
-    analysis.functions.iter().any(|f| f.rva == rva)
-        || analysis
-            .rejects
-            .iter()
-            .any(|r| r.rva == rva && r.ty == "ReadWriteToCode")
+/// Builds the artifact of a Cargo project and runs it through the pipeline like `run`.
+fn cargo_run(args: CargoArgs, reporter: &mut Reporter) -> Result<(), Box<dyn std::error::Error>> {
+    if !args.target.no_build {
+        args.target.build()?;
+    }
+    let artifact = args.target.locate()?;
+    log::info!("Protecting {:?}", artifact.binary);
+
+    // Cargo considers a replaced binary up to date, so it is not protected a second time.
+    let marker = PathBuf::from(format!("{}.codedefender", artifact.binary.display()));
+    let binary_sha256 = manifest::hex_digest(&fs::read(&artifact.binary)?);
+    if args.replace && fs::read_to_string(&marker).is_ok_and(|sha256| sha256 == binary_sha256) {
+        log::info!("{:?} is already protected", artifact.binary);
+        return Ok(());
+    }
+
+    let dir = artifact
+        .binary
+        .parent()
+        .ok_or("The artifact has no parent directory")?
+        .to_path_buf();
+    let output = dir.join(format!(
+        "{}.codedefender.zip",
+        artifact
+            .binary
+            .file_stem()
+            .unwrap_or_default()
+            .to_string_lossy()
+    ));
+    run(
+        RunArgs {
+            config: args.config,
+            input_file: artifact.binary.clone(),
+            pdb_file: artifact.pdb.clone(),
+            output,
+            analysis_out: None,
+            analysis_in: None,
+            file_id: None,
+            dry_run: args.dry_run,
+            min_coverage: args.min_coverage,
+            on_reject: args.on_reject,
+            no_cache: args.no_cache,
+            resume: None,
+            extract_to: Some(dir),
+            output_binary: None,
+            output_pdb: None,
+            sign_with: args.sign_with,
+            verify: args.verify,
+            verify_reanalyze: false,
+            build_manifest: None,
+            report: None,
+            attestation: None,
+            attestation_key: None,
+            symbols: args.symbols,
+            config_args: args.config_args,
+            api: args.api,
+        },
+        reporter,
+    )?;
+    if args.replace && !args.dry_run {
+        cargo::replace(&artifact, &reporter.result.artifacts)?;
+        fs::write(&marker, manifest::hex_digest(&fs::read(&artifact.binary)?))?;
+    }
+    Ok(())
 }
 
-fn upload_disassembly_settings(
-    file_id: &str,
-    client: &reqwest::blocking::Client,
-    api_key: &str,
-    config: &YamlConfig,
-) {
-    let settings_bytes = serde_json::to_vec(&config.disassembly_settings).unwrap();
-    let settings_file_name = format!("{}-disasm-settings.json", file_id);
-    api::upload_data(settings_bytes, settings_file_name, client, api_key);
+fn batch(mut args: BatchArgs, reporter: &mut Reporter) -> Result<(), Box<dyn std::error::Error>> {
+    args.api.prepare()?;
+    let config = args.config_args.load_config(&args.config)?;
+    let entries = match &args.manifest {
+        Some(manifest) => batch::read_manifest(manifest)?,
+        None => batch::entries_from_inputs(
+            &args.input_file,
+            args.output_dir.as_deref(),
+            &args.output_template,
+            args.symbols.servers().as_ref(),
+        )?,
+    };
+    if entries.is_empty() {
+        return Err("No binaries to process".into());
+    }
+    log::info!("Processing {} binaries...", entries.len());
+    let client = reqwest::blocking::Client::new();
+    let options = batch::BatchOptions {
+        config: &config,
+        client: &client,
+        api_key: args.api.api_key(),
+        poll_interval: args.api.poll_interval(),
+        analysis_timeout: args.api.analysis_timeout(&config.timeouts),
+        obfuscation_timeout: args.api.obfuscation_timeout(&config.timeouts),
+        jobs: args.jobs,
+        limiter: batch::RateLimiter::new(args.max_polls_per_second),
+        cache: Cache::open(args.no_cache),
+        verify: args.verify,
+        min_coverage: args.min_coverage,
+        on_reject: args.on_reject,
+        symbols: args.symbols.options(),
+    };
+    batch::run(entries, &options, reporter)
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let cli = Cli::parse();
-    env_logger::builder().filter_level(cli.log_level).init();
-    let config_contents = fs::read_to_string(&cli.config)?;
-    let config: YamlConfig = serde_yaml::from_str(&config_contents)?;
+fn analyze(
+    mut args: AnalyzeArgs,
+    reporter: &mut Reporter,
+) -> Result<(), Box<dyn std::error::Error>> {
+    args.api.prepare()?;
+    if args.pdb_file.is_none() {
+        args.pdb_file = pdb_search::find(&args.input_file, args.symbols.servers().as_ref());
+    }
+    let config = args.config_args.load_config(&args.config)?;
+    let client = reqwest::blocking::Client::new();
+    let mut state = RunState::new(manifest::hex_digest(&fs::read(&args.input_file)?), None);
+    let (file_id, analysis) = upload_and_analyze(
+        &args.input_file,
+        args.pdb_file.as_deref(),
+        &args.symbols.options(),
+        &config,
+        &client,
+        &args.api,
+        args.no_cache,
+        &mut state,
+        reporter,
+    )?;
+    write_analysis(&args.output, &analysis)?;
+    reporter.result.output = Some(args.output.clone());
+    log::info!(
+        "Pass `--file-id {}` to `defend` to obfuscate this upload",
+        file_id
+    );
+    if !reporter.is_json() {
+        println!("{}", file_id);
+    }
+    Ok(())
+}
 
-    if config.version != YAML_CONFIG_VERSION {
-        log::error!(
-            "Invalid config version: {}, expected: {}",
-            config.version,
-            YAML_CONFIG_VERSION
-        );
-        log::error!("Latest version available at: {CLI_DOWNLOAD_LINK}");
-        return Ok(());
+fn defend(mut args: DefendArgs, reporter: &mut Reporter) -> Result<(), Box<dyn std::error::Error>> {
+    args.api.prepare()?;
+    let config = args.config_args.load_config(&args.config)?;
+    let analysis = read_analysis(&args.analysis_in)?;
+    reporter.result.file_id = Some(args.file_id.clone());
+    let cdconfig = pipeline::build_config(&config, &analysis, args.on_reject)?;
+    let coverage = pipeline::check_coverage(&cdconfig, &analysis, args.min_coverage)?;
+    reporter.result.coverage_percent = Some(coverage.percent());
+    if args.dry_run {
+        return print_dry_run(&cdconfig, reporter);
     }
 
+    log::info!("Obfuscating program...");
     let client = reqwest::blocking::Client::new();
-    let binary_file_bytes = fs::read(&cli.input_file)?;
-    let binary_file_uuid = api::upload_file(binary_file_bytes, &client, &cli.api_key);
+    let execution_id =
+        pipeline::start_obfuscation(&args.file_id, cdconfig, &client, args.api.api_key())?;
+    reporter.result.execution_id = Some(execution_id.clone());
+    reporter.event("submitted", json!({ "execution_id": execution_id }));
+    log::info!(
+        "Pass `--execution-id {}` to `download` to fetch the result",
+        execution_id
+    );
+    if !reporter.is_json() {
+        println!("{}", execution_id);
+    }
+    Ok(())
+}
 
-    let pdb_file_uuid = match &cli.pdb_file {
-        Some(path) => {
-            let pdb_bytes = fs::read(path)?;
-            Some(upload_data(
-                parse_pdb(&pdb_bytes).expect("Failed to preparse PDB file!"),
-                "debug.pdb".to_owned(),
+fn download(
+    mut args: DownloadArgs,
+    reporter: &mut Reporter,
+) -> Result<(), Box<dyn std::error::Error>> {
+    args.api.prepare()?;
+    stdio::claim_stdout(&args.output, reporter)?;
+    let config = args
+        .config
+        .as_deref()
+        .map(|path| pipeline::load_config(path, &[]))
+        .transpose()?;
+    let client = reqwest::blocking::Client::new();
+    let timeouts = config.as_ref().map(|c| c.timeouts).unwrap_or_default();
+    let url = pipeline::wait_for_output(
+        &args.execution_id,
+        &client,
+        args.api.api_key(),
+        args.api.poll_interval(),
+        args.api.obfuscation_timeout(&timeouts),
+    )?;
+    reporter.result.execution_id = Some(args.execution_id.clone());
+    reporter.stage_done("obfuscation");
+    let bytes = pipeline::download_output(&url, &client)?;
+    reporter.stage_done("download");
+    reporter
+        .result
+        .bytes
+        .insert("download".to_owned(), bytes.len());
+    reporter.result.output_sha256 = Some(manifest::hex_digest(&bytes));
+    if let (Some(config), Some(input_file)) = (&config, &args.input_file) {
+        reporter.result.input_sha256 = Some(manifest::hex_digest(&fs::read(input_file)?));
+        pipeline::check_output(config, input_file, &bytes)?;
+        if args.verify {
+            pipeline::verify_output(config, input_file, &bytes)?;
+        }
+        if args.verify_reanalyze {
+            pipeline::reanalyze_output(
+                &bytes,
+                config,
                 &client,
-                &cli.api_key,
-            ))
+                args.api.api_key(),
+                args.api.poll_interval(),
+                args.api.analysis_timeout(&timeouts),
+            )?;
+            reporter.stage_done("verification");
         }
-        None => None,
+    }
+    stdio::write_output(&args.output, &bytes)?;
+    reporter.result.output = Some(args.output.clone());
+    extract(
+        &bytes,
+        args.input_file.as_deref().unwrap_or(&args.output),
+        args.extract_to.as_deref(),
+        args.output_binary.as_deref(),
+        args.output_pdb.as_deref(),
+        args.sign_with.as_ref(),
+        reporter,
+    )?;
+    Ok(())
+}
+
+/// Unpacks the downloaded artifact into `dir` and writes its binary and PDB to `binary` and `pdb`,
+/// signs the binaries with `sign_with` and prints the path of every file.
+fn extract(
+    artifact: &[u8],
+    input: &Path,
+    dir: Option<&Path>,
+    binary: Option<&Path>,
+    pdb: Option<&Path>,
+    sign_with: Option<&SignCommand>,
+    reporter: &mut Reporter,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut paths = match dir {
+        Some(dir) => output::extract_artifacts(artifact, input, dir)?,
+        None => Vec::new(),
     };
+    paths.extend(output::write_members(artifact, binary, pdb)?);
+    if let Some(command) = sign_with {
+        for path in paths.iter().filter(|path| sign::is_signable(path)) {
+            command.sign(path)?;
+        }
+        reporter.stage_done("signing");
+    }
+    for path in &paths {
+        if reporter.prints_results() {
+            println!("{}", path.display());
+        }
+        log::info!("Extracted {:?}", path);
+    }
+    reporter.result.artifacts = paths;
+    Ok(())
+}
 
-    log::info!("Uploaded file(s)...");
-    upload_disassembly_settings(&binary_file_uuid, &client, &cli.api_key, &config);
+fn validate(args: ValidateArgs) -> Result<(), Box<dyn std::error::Error>> {
+    ci::set_config(&args.config);
+    let source = fs::read_to_string(&args.config)?;
+    let config: YamlConfig = match serde_yaml::from_str(&source) {
+        Ok(config) => config,
+        Err(e) => {
+            ci::annotate(ci::Finding {
+                severity: ci::Severity::Error,
+                message: e.to_string(),
+                file: Some(args.config.clone()),
+                position: e.location().map(|l| (l.line(), l.column())),
+            });
+            match e.location() {
+                Some(location) => eprintln!(
+                    "{}:{}:{}: error: {}",
+                    args.config.display(),
+                    location.line(),
+                    location.column(),
+                    e
+                ),
+                None => eprintln!("{}: error: {}", args.config.display(), e),
+            }
+            return Err(failure(ExitStatus::Config, "Config failed to parse"));
+        }
+    };
 
-    log::info!("Uploaded disassembly settings...");
-    log::info!("Starting analysis...");
+    let diagnostics = config.validate();
+    for diagnostic in &diagnostics {
+        diagnostics::print_diagnostic(&args.config, &source, diagnostic);
+    }
+    let errors = diagnostics
+        .iter()
+        .filter(|d| d.severity == DiagnosticSeverity::Error)
+        .count();
+    let warnings = diagnostics.len() - errors;
+    if errors > 0 || (args.deny_warnings && warnings > 0) {
+        return Err(failure(
+            ExitStatus::Config,
+            format!("Config has {errors} error(s) and {warnings} warning(s)"),
+        ));
+    }
+    log::info!("Config summary:\n{}", config);
+    log::info!("{:?} is valid", args.config);
+    Ok(())
+}
 
-    let analyze_execution_id = api::start_analyze(
-        binary_file_uuid.clone(),
-        pdb_file_uuid,
-        &client,
-        &cli.api_key,
-    );
+fn migrate_config(args: MigrateConfigArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let source = fs::read_to_string(&args.config)?;
+    let mut migration = migrate::migrate(&source).map_err(|e| {
+        failure(
+            ExitStatus::Config,
+            format!("Failed to migrate {:?}: {}", args.config, e),
+        )
+    })?;
+    for change in &migration.changes {
+        log::info!("{}", change);
+    }
+    if migration.changes.is_empty() {
+        log::info!("{:?} is already up to date", args.config);
+    }
+    for set in &args.set {
+        set.apply_to_document(&mut migration.document)
+            .map_err(|e| failure(ExitStatus::Config, e))?;
+    }
+    if !args.set.is_empty() {
+        serde_yaml::from_value::<YamlConfig>(migration.document.value()?).map_err(|e| {
+            failure(
+                ExitStatus::Config,
+                format!("The overridden config is invalid: {}", e),
+            )
+        })?;
+    }
+    let yaml = migration.to_yaml();
+    match &args.output {
+        Some(output) => {
+            fs::write(output, yaml)?;
+            log::info!("Wrote the upgraded config to {:?}", output);
+        }
+        None => print!("{}", yaml),
+    }
+    Ok(())
+}
+
+fn init(args: InitArgs) -> Result<(), Box<dyn std::error::Error>> {
+    if args.output.exists() && !args.force {
+        log::error!(
+            "{:?} already exists, pass --force to overwrite it",
+            args.output
+        );
+        return Err("Output already exists".into());
+    }
+    let preset = ConfigPreset::from(args.preset);
+    let contents = match &args.analysis {
+        Some(path) => {
+            let analysis = read_analysis(path)?;
+            let config = YamlConfig::from_analysis(&analysis, preset);
+            format!(
+                "{}{}",
+                serde_yaml::to_string(&config)?,
+                rejected_symbols_comment(&analysis)
+            )
+        }
+        None => {
+            let config = YamlConfig::from_analysis(&AnalysisResult::default(), preset)
+                .with_profile(preset.profile("default".to_owned(), vec![]));
+            serde_yaml::to_string(&config)?
+        }
+    };
+    fs::write(&args.output, contents)?;
+    log::info!("Config written to {:?}", args.output);
+    Ok(())
+}
 
-    let start_time = Instant::now();
-    let timeout_duration = Duration::from_secs(300); // 5 min
-    let analysis: Option<AnalysisResult>;
+fn symbols(
+    mut args: SymbolsArgs,
+    reporter: &mut Reporter,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(SymbolsCommand::Export(args)) = args.command {
+        return export_symbols(args, reporter);
+    }
+    let analysis = match (&args.analysis, &args.input_file, &args.config) {
+        (Some(path), _, _) => read_analysis(path)?,
+        (None, Some(input_file), Some(config)) => {
+            args.api.prepare()?;
+            let config = args.config_args.load_config(config)?;
+            let pdb_file = args
+                .pdb_file
+                .clone()
+                .or_else(|| pdb_search::find(input_file, args.symbols.servers().as_ref()));
+            let client = reqwest::blocking::Client::new();
+            let mut state = RunState::new(manifest::hex_digest(&fs::read(input_file)?), None);
+            let (file_id, analysis) = upload_and_analyze(
+                input_file,
+                pdb_file.as_deref(),
+                &args.symbols.options(),
+                &config,
+                &client,
+                &args.api,
+                args.no_cache,
+                &mut state,
+                reporter,
+            )?;
+            reporter.result.file_id = Some(file_id);
+            analysis
+        }
+        _ => unreachable!("clap requires --analysis or --input-file with --config"),
+    };
+    let listing = symbols::Listing::new(&analysis, args.filter.as_deref(), args.sort);
+    let mut stdout = std::io::stdout().lock();
+    if reporter.is_json() {
+        reporter.event("symbols", listing.to_json());
+    } else if args.csv {
+        listing.write_csv(&mut stdout)?;
+    } else {
+        listing.print(&mut stdout)?;
+    }
+    Ok(())
+}
 
-    loop {
-        if start_time.elapsed() > timeout_duration {
-            log::error!("Timeout: analysis exceeded 5 minutes");
-            return Ok(());
+/// Writes the symbols `run` would upload for the binary or PDB to JSON.
+fn export_symbols(
+    args: SymbolsExportArgs,
+    reporter: &mut Reporter,
+) -> Result<(), Box<dyn std::error::Error>> {
+    stdio::claim_stdout(&args.output, reporter)?;
+    let options = args.symbols.options();
+    let collected = match &args.input_file {
+        Some(input_file) => {
+            let pdb_file = args
+                .pdb_file
+                .clone()
+                .or_else(|| pdb_search::find(input_file, args.symbols.servers().as_ref()));
+            pipeline::collect_symbols(&fs::read(input_file)?, pdb_file.as_deref(), &options)?
         }
-        match api::get_analyze_status(analyze_execution_id.clone(), &client, &cli.api_key) {
-            Status::Ready(url) => {
-                analysis = Some(api::download_analysis_result(&url, &client));
-                break;
+        None => {
+            let pdb_file = args
+                .pdb_file
+                .as_deref()
+                .expect("clap requires --input-file or --pdb-file");
+            if options.infer_noreturn {
+                log::warn!("--infer-noreturn needs the binary, pass it with --input-file");
             }
-            Status::Processing => {
-                log::info!("Still Analyzing...");
+            if !options.libraries.is_empty() {
+                log::warn!("--static-lib needs the binary, pass it with --input-file");
             }
+            let funcs = pipeline::read_pdb_symbols(pdb_file, &options)?;
+            Some(pipeline::CollectedSymbols {
+                source: pipeline::SymbolSource::Pdb,
+                funcs,
+            })
+        }
+    };
+    let Some(pipeline::CollectedSymbols { source, funcs }) = collected else {
+        return Err(failure(
+            ExitStatus::Error,
+            "The binary has no PDB, DWARF debug info, exported functions or functions of the static libraries",
+        ));
+    };
+    // Through the stream and back, so fields the stream version leaves out are left out here too
+    let funcs = encode_symbols(&funcs, options.version)
+        .and_then(|stream| decode_symbols_as(&stream, options.version))
+        .ok_or("Failed to encode the symbols")?;
+    let json = serde_json::to_vec_pretty(&symbols::export_json(&funcs))?;
+    if stdio::is_stdio(&args.output) {
+        let mut stdout = std::io::stdout().lock();
+        stdout.write_all(&json)?;
+        stdout.write_all(b"\n")?;
+    } else {
+        fs::write(&args.output, &json)?;
+    }
+    log::info!(
+        "Exported {} symbols from the {} to {:?}",
+        funcs.len(),
+        match source {
+            pipeline::SymbolSource::Pdb => "PDB",
+            #[cfg(feature = "dwarf")]
+            pipeline::SymbolSource::Dwarf => "DWARF debug info",
+            pipeline::SymbolSource::Exports => "export table",
+            pipeline::SymbolSource::Libraries => "static libraries",
+        },
+        args.output
+    );
+    reporter.event(
+        "symbols_exported",
+        json!({ "output": args.output, "source": source.as_str(), "symbols": funcs.len() }),
+    );
+    Ok(())
+}
+
+fn diff_analyses(
+    args: AnalysisDiffArgs,
+    reporter: &mut Reporter,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let diff =
+        analysis_diff::AnalysisDiff::new(&read_analysis(&args.old)?, &read_analysis(&args.new)?);
+    if reporter.is_json() {
+        reporter.event("analysis_diff", json!(diff));
+    } else {
+        diff.print(&mut std::io::stdout().lock())?;
+    }
+    if args.fail_on_regression && diff.has_regressions() {
+        return Err(failure(
+            ExitStatus::Analysis,
+            "Functions that were protectable are rejected in the new build",
+        ));
+    }
+    Ok(())
+}
+
+fn compare(args: CompareArgs, reporter: &mut Reporter) -> Result<(), Box<dyn std::error::Error>> {
+    let original = fs::read(&args.original)?;
+    let protected = output::extract_pe(&fs::read(&args.protected)?)
+        .ok_or_else(|| format!("{:?} is not a PE file or a zip holding one", args.protected))?;
+    let comparison = compare::PeComparison::new(&original, &protected)?;
+    if reporter.is_json() {
+        reporter.event("comparison", json!(comparison));
+    } else {
+        comparison.print(&mut std::io::stdout().lock())?;
+    }
+    Ok(())
+}
+
+/// Reads the API key from stdin, so it stays out of the shell history.
+fn login(args: LoginArgs) -> Result<(), Box<dyn std::error::Error>> {
+    use std::io::{BufRead, IsTerminal, Write};
+
+    let stdin = std::io::stdin();
+    if stdin.is_terminal() {
+        eprint!("API key for account `{}`: ", args.account);
+        std::io::stderr().flush()?;
+    }
+    let mut api_key = String::new();
+    stdin.lock().read_line(&mut api_key)?;
+    let api_key = api_key.trim();
+    if api_key.is_empty() {
+        return Err(failure(ExitStatus::Auth, "No API key given"));
+    }
+    keyring::store(&args.account, api_key).map_err(|e| {
+        failure(
+            ExitStatus::Auth,
+            format!(
+                "Cannot store the API key of account `{}`: {}",
+                args.account, e
+            ),
+        )
+    })?;
+    log::info!("Stored the API key of account `{}`", args.account);
+    Ok(())
+}
+
+fn logout(args: LogoutArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let removed = keyring::delete(&args.account).map_err(|e| {
+        failure(
+            ExitStatus::Auth,
+            format!(
+                "Cannot remove the API key of account `{}`: {}",
+                args.account, e
+            ),
+        )
+    })?;
+    if removed {
+        log::info!("Removed the API key of account `{}`", args.account);
+    } else {
+        log::warn!("No API key stored for account `{}`", args.account);
+    }
+    Ok(())
+}
+
+fn doctor(
+    args: DoctorArgs,
+    settings_files: &[PathBuf],
+    reporter: &mut Reporter,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let client = reqwest::blocking::Client::builder()
+        .timeout(Duration::from_secs(15))
+        .build()?;
+    let checks = doctor::run(args.api_key, &args.account, settings_files, &client);
+    for check in &checks {
+        if reporter.is_json() {
+            reporter.event("check", json!(check));
+        } else {
+            check.print(&mut std::io::stdout().lock())?;
         }
-        std::thread::sleep(Duration::from_millis(cli.timeout));
     }
+    let failed = checks
+        .iter()
+        .filter(|check| check.status == doctor::CheckStatus::Fail)
+        .count();
+    if failed > 0 {
+        return Err(format!("{} of {} checks failed", failed, checks.len()).into());
+    }
+    Ok(())
+}
+
+fn completions(args: CompletionsArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let mut command = Cli::command();
+    let bin = command.get_name().to_owned();
+    clap_complete::generate(args.shell, &mut command, bin, &mut std::io::stdout());
+    Ok(())
+}
+
+/// Writes the page of `command`, `<name>-<subcommand>.1` for subcommands, and the pages of its
+/// subcommands into `dir`.
+fn write_man_pages(command: clap::Command, source: &str, dir: &Path) -> std::io::Result<()> {
+    for sub in command.get_subcommands().filter(|sub| !sub.is_hide_set()) {
+        write_man_pages(sub.clone(), source, dir)?;
+    }
+    let path = clap_mangen::Man::new(command)
+        .source(source)
+        .generate_to(dir)?;
+    log::info!("Wrote {:?}", path);
+    Ok(())
+}
+
+fn man(args: ManArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let source = format!("codedefender-cli {}", env!("CARGO_PKG_VERSION"));
+    match &args.out_dir {
+        Some(dir) => {
+            fs::create_dir_all(dir)?;
+            // Building names subcommands after their parents, which the file names are taken from.
+            let mut command = Cli::command().disable_help_subcommand(true);
+            command.build();
+            write_man_pages(command, &source, dir)?;
+        }
+        None => clap_mangen::Man::new(Cli::command())
+            .source(source)
+            .render(&mut std::io::stdout())?,
+    }
+    Ok(())
+}
 
-    let analysis = analysis.ok_or("Analysis not completed")?;
-    log::debug!("Analysis info: {:#X?}", analysis);
-    log::info!("Analysis finished...");
-    log::info!("Constructing config...");
+fn mock_server(args: MockServerArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let options = mock_server::MockOptions {
+        api_key: args.api_key,
+        analysis: args.analysis.as_deref().map(read_analysis).transpose()?,
+        artifact: args.artifact.as_deref().map(fs::read).transpose()?,
+        processing_polls: args.processing_polls,
+    };
+    let listener = std::net::TcpListener::bind(&args.listen)?;
+    log::info!(
+        "Mock CodeDefender API listening, point the CLI at it with BASE_URL=http://{}",
+        listener.local_addr()?
+    );
+    mock_server::serve(listener, options)?;
+    Ok(())
+}
 
-    let mut cdconfig = Config {
-        module_settings: config.module_settings,
-        profiles: vec![],
+fn tui(args: TuiArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let analysis = read_analysis(&args.analysis)?;
+    let (config, document, output) = match args.config {
+        Some(path) => {
+            let config = pipeline::load_config(&path, &[])?;
+            let document = YamlDocument::parse(&fs::read_to_string(&path)?);
+            (config, Some(document), args.output.unwrap_or(path))
+        }
+        None => (
+            YamlConfig::from_analysis(&analysis, args.preset.into()),
+            None,
+            args.output.unwrap_or_else(|| PathBuf::from("config.yaml")),
+        ),
     };
+    let mut session = tui::Session::new(analysis, config, document, output);
+    session.run(std::io::stdin().lock(), &mut std::io::stdout())
+}
 
-    for profile in &config.profiles {
-        let symbols = resolve_symbols(profile.symbols.as_slice(), &analysis)?;
-        cdconfig.profiles.push(Profile {
-            name: profile.name.clone(),
-            passes: profile.passes.clone(),
-            compiler_settings: profile.compiler_settings.clone(),
-            symbols,
-        });
+fn integrate(args: IntegrateArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let output = args.output.unwrap_or_else(|| {
+        PathBuf::from(if args.cmake {
+            "codedefender.cmake"
+        } else {
+            "CodeDefender.targets"
+        })
+    });
+    if output.exists() && !args.force {
+        return Err(format!("{:?} already exists, pass --force to overwrite it", output).into());
+    }
+    if !args.config.exists() {
+        log::warn!(
+            "{:?} does not exist yet, create it with `codedefender-cli init`",
+            args.config
+        );
     }
 
-    for macro_profile in &analysis.macros {
-        let profile = cdconfig
-            .profiles
-            .iter_mut()
-            .find(|p| p.name == macro_profile.name);
+    // The build step runs from elsewhere, so the config is referenced relative to the output.
+    let cwd = std::env::current_dir()?;
+    let output_dir = cwd.join(&output);
+    let output_dir = output_dir.parent().unwrap_or(&cwd);
+    let config = integrate::relative_to(&cwd.join(&args.config), output_dir);
+    let contents = if args.cmake {
+        integrate::cmake(&config, args.target.as_deref())
+    } else {
+        integrate::msbuild(&config)
+    };
+    fs::create_dir_all(output_dir)?;
+    fs::write(&output, contents)?;
+    log::info!("Build step written to {:?}", output);
+    if args.cmake {
+        println!("Add `include({})` to your CMakeLists.txt", output.display());
+    } else {
+        println!(
+            "Add `<Import Project=\"{}\" />` to your project file",
+            output.display()
+        );
+    }
+    Ok(())
+}
 
-        match profile {
-            Some(p) => {
-                for rva in &macro_profile.rvas {
-                    if !is_valid_rva(*rva, &analysis) {
-                        log::error!("Macro-decorated function {:X} cannot be protected", rva);
-                        return Ok(());
-                    }
-                }
-                p.symbols.extend(macro_profile.rvas.clone());
-            }
-            None => {
-                log::error!(
-                    "Macro specifies profile `{}` which is not defined in the config",
-                    macro_profile.name
-                );
-                return Ok(());
-            }
+fn cache(args: CacheArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let cache = Cache::open(false).ok_or("No cache directory available")?;
+    match args.command {
+        CacheCommand::Gc { max_age_days } => {
+            let (removed, kept) = cache.gc(Duration::from_secs(max_age_days * 24 * 60 * 60))?;
+            log::info!("Removed {} cache entries, kept {}", removed, kept);
         }
     }
+    Ok(())
+}
 
-    log::info!("Obfuscating program...");
-    let execution_id = api::defend(binary_file_uuid, cdconfig, &client, &cli.api_key);
-    let start_time = Instant::now();
-
-    loop {
-        if start_time.elapsed() > timeout_duration {
-            log::error!("Timeout: obfuscation exceeded 5 minutes");
-            return Ok(());
-        }
-        match api::download(execution_id.clone(), &client, &cli.api_key) {
-            Status::Ready(url) => {
-                let bytes = api::download_obfuscated_file(&url, &client);
-                fs::write(&cli.output, bytes)?;
-                log::info!("Obfuscated binary written to {:?}", cli.output);
-                return Ok(());
-            }
-            Status::Processing => {
-                log::info!("Still Obfuscating...");
+fn main() -> ExitCode {
+    // SAFETY: no other thread is running yet.
+    let settings_files = match unsafe { settings::load() } {
+        Ok(files) => files,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::from(ExitStatus::Config as u8);
+        }
+    };
+    let cli = Cli::parse();
+    ci::init(cli.ci);
+    let log_file = match &cli.log_file {
+        Some(path) => {
+            match RotatingFile::open(path, cli.log_file_max_size * 1024 * 1024, cli.log_file_keep) {
+                Ok(file) => Some(file),
+                Err(e) => {
+                    eprintln!("Failed to open log file {:?}: {}", path, e);
+                    return ExitCode::FAILURE;
+                }
             }
         }
-        std::thread::sleep(Duration::from_millis(cli.timeout));
+        None => None,
+    };
+    report::init_logger(cli.log_level, cli.log_format, log_file);
+    for path in &settings_files {
+        log::debug!("Read settings from {:?}", path);
+    }
+    let mut reporter = Reporter::new(cli.format);
+    let outcome = match cli.command {
+        Command::Run(args) => run(*args, &mut reporter),
+        Command::Batch(args) => batch(args, &mut reporter),
+        Command::Analyze(args) => analyze(args, &mut reporter),
+        Command::Defend(args) => defend(args, &mut reporter),
+        Command::Download(args) => download(args, &mut reporter),
+        Command::Validate(args) => validate(args),
+        Command::MigrateConfig(args) => migrate_config(args),
+        Command::Init(args) => init(args),
+        Command::Cache(args) => cache(args),
+        Command::Cargo(args) => cargo_run(args, &mut reporter),
+        Command::Integrate(args) => integrate(args),
+        Command::Tui(args) => tui(args),
+        Command::Symbols(args) => symbols(args, &mut reporter),
+        Command::AnalysisDiff(args) => diff_analyses(args, &mut reporter),
+        Command::Compare(args) => compare(args, &mut reporter),
+        Command::MockServer(args) => mock_server(args),
+        Command::Login(args) => login(args),
+        Command::Logout(args) => logout(args),
+        Command::Doctor(args) => doctor(args, &settings_files, &mut reporter),
+        Command::Completions(args) => completions(args),
+        Command::Man(args) => man(args),
+    };
+    reporter.finish(&outcome);
+    match outcome {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            log::error!("{}", e);
+            ExitCode::from(exit::status_of(&*e) as u8)
+        }
     }
 }
@@ -0,0 +1,182 @@
+//! Build manifest of a run: what went in, what came out and which service executions produced it.
+
+use codedefender_api::codedefender_config::{AnalysisResult, PeEnvironment, YamlConfig};
+use codedefender_api::serde_json;
+use ring::digest::{Context, Digest, SHA256, digest};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Bumped when fields are removed or change meaning.
+pub const MANIFEST_VERSION: u32 = 1;
+
+/// A file and the SHA-256 of its contents.
+#[derive(Serialize, Debug, Clone)]
+pub struct Artifact {
+    pub path: PathBuf,
+    pub sha256: String,
+}
+
+impl Artifact {
+    /// Hashes the file at `path`.
+    pub fn of(path: &Path) -> io::Result<Self> {
+        Ok(Self::of_bytes(path, &fs::read(path)?))
+    }
+
+    /// Hashes `bytes`, recorded as the contents of `path`.
+    pub fn of_bytes(path: &Path, bytes: &[u8]) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            sha256: hex_digest(bytes),
+        }
+    }
+
+    /// Hashes `config` as it was submitted, with the overrides applied and only the selected
+    /// profiles, recorded as the config file at `path`.
+    pub fn of_config(path: &Path, config: &YamlConfig) -> Result<Self, serde_yaml::Error> {
+        Ok(Self::of_bytes(
+            path,
+            serde_yaml::to_string(config)?.as_bytes(),
+        ))
+    }
+}
+
+/// What analysis found, without the per-function details.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AnalysisSummary {
+    pub environment: PeEnvironment,
+    pub functions: usize,
+    pub rejected_functions: usize,
+    pub data_symbols: usize,
+}
+
+impl AnalysisSummary {
+    pub fn of(analysis: &AnalysisResult) -> Self {
+        Self {
+            environment: analysis.environment,
+            functions: analysis.functions.len(),
+            rejected_functions: analysis.rejects.len(),
+            data_symbols: analysis.data_symbols.len(),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Manifest {
+    pub manifest_version: u32,
+    pub cli_version: &'static str,
+    /// `version` of the YAML config.
+    pub config_version: String,
+    /// RFC 3339 UTC time the run started.
+    pub started_at: String,
+    /// RFC 3339 UTC time the outputs were written.
+    pub finished_at: String,
+    pub input: Artifact,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pdb: Option<Artifact>,
+    /// The config file, hashed as the config that was submitted.
+    pub config: Artifact,
+    /// `--set` overrides applied to the config file, as given.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub overrides: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub analysis: Option<AnalysisSummary>,
+    /// PDB path written into the output, as `fake_pdb_string` resolved it for this build. Missing
+    /// when it is disabled.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fake_pdb_string: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file_id: Option<String>,
+    pub execution_id: String,
+    /// The downloaded zip followed by any extracted files.
+    pub outputs: Vec<Artifact>,
+}
+
+impl Manifest {
+    /// Writes the manifest as pretty JSON.
+    pub fn write(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        fs::write(path, serde_json::to_vec_pretty(self)?)?;
+        log::info!("Build manifest written to {:?}", path);
+        Ok(())
+    }
+}
+
+fn hex(digest: Digest) -> String {
+    digest.as_ref().iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Lowercase hex SHA-256 digest of `data`.
+pub fn hex_digest(data: &[u8]) -> String {
+    hex(digest(&SHA256, data))
+}
+
+/// Lowercase hex SHA-256 digest of the file at `path`, read in chunks rather than at once.
+pub fn hex_digest_file(path: &Path) -> io::Result<String> {
+    let mut file = fs::File::open(path)?;
+    let mut context = Context::new(&SHA256);
+    let mut buffer = vec![0; 1 << 20];
+    loop {
+        match file.read(&mut buffer)? {
+            0 => return Ok(hex(context.finish())),
+            n => context.update(&buffer[..n]),
+        }
+    }
+}
+
+/// Formats `time` as RFC 3339 in UTC, e.g. `2024-05-01T12:30:00Z`.
+pub fn timestamp(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let (days, rest) = (secs / 86400, secs % 86400);
+    // Civil date from days since the epoch, see Howard Hinnant's `civil_from_days`.
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        rest / 3600,
+        rest % 3600 / 60,
+        rest % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::overrides::ConfigOverride;
+    use codedefender_api::codedefender_config::ConfigPreset;
+
+    #[test]
+    fn overrides_change_the_config_digest() {
+        let path = Path::new("config.yaml");
+        let config = YamlConfig::from_analysis(&AnalysisResult::default(), ConfigPreset::Light);
+        let mut value = serde_yaml::to_value(&config).unwrap();
+        ConfigOverride::parse("module_settings.pack_output_file=true")
+            .unwrap()
+            .apply(&mut value)
+            .unwrap();
+        let overridden: YamlConfig = serde_yaml::from_value(value).unwrap();
+
+        let plain = Artifact::of_config(path, &config).unwrap();
+        assert_eq!(
+            plain.sha256,
+            Artifact::of_config(path, &config).unwrap().sha256
+        );
+        assert_ne!(
+            plain.sha256,
+            Artifact::of_config(path, &overridden).unwrap().sha256
+        );
+    }
+}
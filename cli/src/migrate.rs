@@ -0,0 +1,221 @@
+//! `migrate-config`, upgrading a config written for an older version to the current one.
+
+use crate::yaml_edit::{Segment, YamlDocument, display_path};
+use codedefender_api::codedefender_config::{
+    CONFIG_MIGRATIONS, ConfigMigration, YAML_CONFIG_VERSION, YamlConfig,
+};
+use serde_yaml::Value;
+
+/// An upgraded config and what was changed in it.
+pub struct Migration {
+    /// Version the config was written for, if it names one.
+    pub from_version: Option<String>,
+    /// Each change as `path: what changed`.
+    pub changes: Vec<String>,
+    /// The upgraded document, with the comments and layout of the original.
+    pub document: YamlDocument,
+}
+
+impl Migration {
+    /// The upgraded config as YAML, starting with a comment listing the changes.
+    pub fn to_yaml(&self) -> String {
+        let mut out = String::new();
+        let newline = self.document.newline();
+        if !self.changes.is_empty() {
+            let versions = match &self.from_version {
+                Some(from) if from != YAML_CONFIG_VERSION => {
+                    format!("from version {} to {}", from, YAML_CONFIG_VERSION)
+                }
+                _ => format!("to version {}", YAML_CONFIG_VERSION),
+            };
+            out.push_str(&format!(
+                "# Migrated {} by `codedefender-cli migrate-config`:{}",
+                versions, newline
+            ));
+            for change in &self.changes {
+                out.push_str(&format!("#   {}{}", change, newline));
+            }
+        }
+        out.push_str(&self.document.to_string());
+        out
+    }
+}
+
+fn untag(mut value: &mut Value) -> &mut Value {
+    while let Value::Tagged(tagged) = value {
+        value = &mut tagged.value;
+    }
+    value
+}
+
+fn join(at: &str, key: &str) -> String {
+    if at.is_empty() {
+        key.to_owned()
+    } else {
+        format!("{}.{}", at, key)
+    }
+}
+
+/// Applies `migration` to every mapping its path leads to, collecting the path of each field
+/// renamed in `renamed`.
+fn rename(
+    value: &Value,
+    path: &[&str],
+    at: &mut Vec<Segment>,
+    migration: &ConfigMigration,
+    renamed: &mut Vec<Vec<Segment>>,
+) {
+    let mut value = value;
+    while let Value::Tagged(tagged) = value {
+        value = &tagged.value;
+    }
+    match (path.split_first(), value) {
+        (None, Value::Mapping(map))
+            if map.contains_key(migration.from) && !map.contains_key(migration.to) =>
+        {
+            at.push(Segment::Key(migration.from.to_owned()));
+            renamed.push(at.clone());
+            at.pop();
+        }
+        (Some((&"*", rest)), Value::Mapping(map)) => {
+            for (key, value) in map {
+                at.push(Segment::Key(key.as_str().unwrap_or("?").to_owned()));
+                rename(value, rest, at, migration, renamed);
+                at.pop();
+            }
+        }
+        (Some((&"*", rest)), Value::Sequence(seq)) => {
+            for (i, value) in seq.iter().enumerate() {
+                at.push(Segment::Index(i));
+                rename(value, rest, at, migration, renamed);
+                at.pop();
+            }
+        }
+        (Some((key, rest)), Value::Mapping(map)) => {
+            if let Some(value) = map.get(*key) {
+                at.push(Segment::Key((*key).to_owned()));
+                rename(value, rest, at, migration, renamed);
+                at.pop();
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Inserts the sections of `defaults` missing from `value`. Missing scalar fields are left out,
+/// their defaults apply without being spelled out.
+fn insert_sections(value: &mut Value, defaults: &Value, at: &str, changes: &mut Vec<String>) {
+    let mut defaults = defaults;
+    while let Value::Tagged(tagged) = defaults {
+        defaults = &tagged.value;
+    }
+    match (untag(value), defaults) {
+        (Value::Mapping(map), Value::Mapping(default_map)) => {
+            for (key, default) in default_map {
+                let at = join(at, key.as_str().unwrap_or("?"));
+                match map.get_mut(key) {
+                    Some(value) => insert_sections(value, default, &at, changes),
+                    None if default.is_mapping() => {
+                        map.insert(key.clone(), default.clone());
+                        changes.push(format!("{}: added with default values", at));
+                    }
+                    None => {}
+                }
+            }
+        }
+        (Value::Sequence(seq), Value::Sequence(default_seq)) => {
+            for (i, (value, default)) in seq.iter_mut().zip(default_seq).enumerate() {
+                insert_sections(value, default, &format!("{}[{}]", at, i), changes);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Renames deprecated fields, inserts sections that are now required with their defaults and
+/// sets the current version. Comments and the order of fields are kept.
+pub fn migrate(source: &str) -> Result<Migration, Box<dyn std::error::Error>> {
+    let mut edited = YamlDocument::parse(source);
+    let Value::Mapping(_) = edited.value()? else {
+        return Err("The config is not a YAML mapping".into());
+    };
+    let mut changes = Vec::new();
+    for migration in CONFIG_MIGRATIONS {
+        let mut renamed = Vec::new();
+        rename(
+            &edited.value()?,
+            migration.path,
+            &mut Vec::new(),
+            migration,
+            &mut renamed,
+        );
+        for path in renamed {
+            edited.rename_key(&path, migration.to)?;
+            let at = display_path(&path[..path.len() - 1]);
+            changes.push(format!("{}: {}", at, migration.description()));
+        }
+    }
+    let mut document = edited.value()?;
+
+    let from_version = document
+        .get("version")
+        .and_then(Value::as_str)
+        .map(str::to_owned);
+    if from_version.as_deref() != Some(YAML_CONFIG_VERSION)
+        && let Value::Mapping(map) = &mut document
+    {
+        if map.contains_key("version") {
+            map.insert(Value::from("version"), Value::from(YAML_CONFIG_VERSION));
+        } else {
+            let mut with_version = serde_yaml::Mapping::new();
+            with_version.insert(Value::from("version"), Value::from(YAML_CONFIG_VERSION));
+            with_version.extend(std::mem::take(map));
+            *map = with_version;
+        }
+        changes.push(match &from_version {
+            Some(from) => format!("version: {} was upgraded to {}", from, YAML_CONFIG_VERSION),
+            None => format!("version: set to {}", YAML_CONFIG_VERSION),
+        });
+    }
+
+    let config: YamlConfig = serde_yaml::from_value(document.clone())?;
+    let defaults = serde_yaml::to_value(&config)?;
+    insert_sections(&mut document, &defaults, "", &mut changes);
+    edited.update(&document)?;
+    Ok(Migration {
+        from_version,
+        changes,
+        document: edited,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(from_version: Option<&str>) -> String {
+        let migration = Migration {
+            from_version: from_version.map(str::to_owned),
+            changes: vec!["a: renamed".to_owned()],
+            document: YamlDocument::parse("a: 1\n"),
+        };
+        migration.to_yaml().lines().next().unwrap().to_owned()
+    }
+
+    #[test]
+    fn header_names_the_source_version_only_when_it_differs() {
+        assert_eq!(
+            header(Some("0.9")),
+            format!(
+                "# Migrated from version 0.9 to {} by `codedefender-cli migrate-config`:",
+                YAML_CONFIG_VERSION
+            )
+        );
+        let to_current = format!(
+            "# Migrated to version {} by `codedefender-cli migrate-config`:",
+            YAML_CONFIG_VERSION
+        );
+        assert_eq!(header(None), to_current);
+        assert_eq!(header(Some(YAML_CONFIG_VERSION)), to_current);
+    }
+}
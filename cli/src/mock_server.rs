@@ -0,0 +1,433 @@
+//! `mock-server`, a local stand-in for the CodeDefender service implementing the upload, analyze,
+//! defend, download and whoami endpoints with canned responses. Point the CLI at it with
+//! `BASE_URL`.
+
+use crate::output;
+use codedefender_api::{
+    codedefender_config::{
+        AnalysisFunction, AnalysisResult, Config, PeEnvironment, YAML_CONFIG_VERSION,
+    },
+    serde_json::{self, json},
+};
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::{Arc, Mutex},
+};
+
+/// How the mock server answers.
+pub struct MockOptions {
+    /// Only this API key is accepted. Any key is accepted when unset.
+    pub api_key: Option<String>,
+    /// Served as the result of every analysis. Otherwise the functions are taken from the uploaded
+    /// PDB.
+    pub analysis: Option<AnalysisResult>,
+    /// Served as the result of every obfuscation. Otherwise the uploaded binary is returned as is.
+    pub artifact: Option<Vec<u8>>,
+    /// Status polls answered with "still processing" before an execution is done.
+    pub processing_polls: u32,
+}
+
+struct Upload {
+    name: Option<String>,
+    data: Option<Vec<u8>>,
+}
+
+enum Execution {
+    Analysis {
+        file_id: String,
+        pdb_file_id: Option<String>,
+        polls_left: u32,
+    },
+    Obfuscation {
+        file_id: String,
+        polls_left: u32,
+    },
+}
+
+#[derive(Default)]
+struct State {
+    uploads: HashMap<String, Upload>,
+    executions: HashMap<String, Execution>,
+    next_id: u64,
+}
+
+impl State {
+    fn new_id(&mut self, kind: &str) -> String {
+        self.next_id += 1;
+        format!("mock-{}-{}", kind, self.next_id)
+    }
+}
+
+struct Request {
+    method: String,
+    path: String,
+    query: HashMap<String, String>,
+    headers: HashMap<String, String>,
+    body: Vec<u8>,
+}
+
+impl Request {
+    fn read(stream: &mut BufReader<TcpStream>) -> io::Result<Self> {
+        let mut line = String::new();
+        stream.read_line(&mut line)?;
+        let mut parts = line.split_whitespace();
+        let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "malformed request line",
+            ));
+        };
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        let query = query
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .map(|(key, value)| (percent_decode(key), percent_decode(value)))
+            .collect();
+        let mut headers = HashMap::new();
+        loop {
+            let mut line = String::new();
+            if stream.read_line(&mut line)? == 0 || line.trim().is_empty() {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                headers.insert(name.trim().to_lowercase(), value.trim().to_owned());
+            }
+        }
+        let length = headers
+            .get("content-length")
+            .and_then(|l| l.parse().ok())
+            .unwrap_or(0);
+        let mut body = vec![0; length];
+        stream.read_exact(&mut body)?;
+        Ok(Self {
+            method: method.to_owned(),
+            path: path.to_owned(),
+            query,
+            headers,
+            body,
+        })
+    }
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' => match text
+                .get(i + 1..i + 3)
+                .and_then(|h| u8::from_str_radix(h, 16).ok())
+            {
+                Some(byte) => {
+                    out.push(byte);
+                    i += 3;
+                    continue;
+                }
+                None => out.push(b'%'),
+            },
+            b'+' => out.push(b' '),
+            byte => out.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+struct Response {
+    status: u16,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn json(status: u16, value: serde_json::Value) -> Self {
+        Self {
+            status,
+            content_type: "application/json",
+            body: value.to_string().into_bytes(),
+        }
+    }
+
+    fn text(status: u16, text: impl Into<String>) -> Self {
+        Self {
+            status,
+            content_type: "text/plain",
+            body: text.into().into_bytes(),
+        }
+    }
+
+    fn bytes(data: Vec<u8>) -> Self {
+        Self {
+            status: 200,
+            content_type: "application/octet-stream",
+            body: data,
+        }
+    }
+
+    fn write(&self, stream: &mut TcpStream) -> io::Result<()> {
+        let reason = match self.status {
+            200 => "OK",
+            202 => "Accepted",
+            400 => "Bad Request",
+            401 => "Unauthorized",
+            404 => "Not Found",
+            _ => "Error",
+        };
+        write!(
+            stream,
+            "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            self.status,
+            reason,
+            self.content_type,
+            self.body.len()
+        )?;
+        stream.write_all(&self.body)?;
+        stream.flush()
+    }
+}
+
+struct Server {
+    options: MockOptions,
+    state: Mutex<State>,
+}
+
+impl Server {
+    fn authorized(&self, request: &Request) -> bool {
+        let Some(key) = request
+            .headers
+            .get("authorization")
+            .and_then(|h| h.strip_prefix("ApiKey "))
+        else {
+            return false;
+        };
+        self.options.api_key.as_ref().is_none_or(|k| k == key)
+    }
+
+    /// Answers a status poll of an execution, counting down its processing polls.
+    fn poll(&self, request: &Request, url_key: &str, route: &str) -> Response {
+        let Some(id) = request.query.get("executionId") else {
+            return Response::text(400, "missing executionId");
+        };
+        let mut state = self.state.lock().unwrap();
+        let polls_left = match state.executions.get_mut(id) {
+            Some(Execution::Analysis { polls_left, .. }) if route == "analysis" => polls_left,
+            Some(Execution::Obfuscation { polls_left, .. }) if route == "artifact" => polls_left,
+            _ => return Response::text(404, "unknown execution"),
+        };
+        if *polls_left > 0 {
+            *polls_left -= 1;
+            return Response::text(202, "");
+        }
+        let host = request
+            .headers
+            .get("host")
+            .map_or("127.0.0.1", String::as_str);
+        Response::json(
+            200,
+            json!({ url_key: format!("http://{}/{}/{}", host, route, id) }),
+        )
+    }
+
+    /// The analysis result of an execution, named after the functions of the uploaded PDB and
+    /// with the module info of the uploaded binary.
+    fn analysis(&self, file_id: &str, pdb_file_id: Option<&String>) -> AnalysisResult {
+        if let Some(analysis) = &self.options.analysis {
+            return analysis.clone();
+        }
+        let state = self.state.lock().unwrap();
+        let symbols = pdb_file_id
+            .and_then(|id| state.uploads.get(id))
+            .and_then(|upload| upload.data.as_deref())
+            .and_then(codedefender_symbols::decode_symbols)
+            .unwrap_or_default();
+        let module = state
+            .uploads
+            .get(file_id)
+            .and_then(|upload| upload.data.as_deref())
+            .and_then(|binary| output::module_info(binary).ok().flatten());
+        AnalysisResult {
+            environment: PeEnvironment::UserMode,
+            functions: symbols
+                .into_iter()
+                .map(|symbol| AnalysisFunction {
+                    rva: symbol.address.into(),
+                    symbol: symbol.name,
+                    ref_count: 0,
+                })
+                .collect(),
+            rejects: Vec::new(),
+            macros: Vec::new(),
+            data_symbols: Vec::new(),
+            module,
+        }
+    }
+
+    fn handle(&self, request: &Request) -> Response {
+        let segments: Vec<&str> = request.path.trim_matches('/').split('/').collect();
+        match (request.method.as_str(), segments.as_slice()) {
+            // Presigned URLs carry no API key
+            ("PUT", ["upload", id]) => {
+                let mut state = self.state.lock().unwrap();
+                match state.uploads.get_mut(*id) {
+                    Some(upload) => {
+                        upload.data = Some(request.body.clone());
+                        Response::text(200, "")
+                    }
+                    None => Response::text(404, "unknown upload"),
+                }
+            }
+            ("GET", ["analysis", id]) => {
+                let (file_id, pdb_file_id) = match self.state.lock().unwrap().executions.get(*id) {
+                    Some(Execution::Analysis {
+                        file_id,
+                        pdb_file_id,
+                        ..
+                    }) => (file_id.clone(), pdb_file_id.clone()),
+                    _ => return Response::text(404, "unknown execution"),
+                };
+                Response::json(200, json!(self.analysis(&file_id, pdb_file_id.as_ref())))
+            }
+            ("GET", ["artifact", id]) => {
+                let state = self.state.lock().unwrap();
+                let Some(Execution::Obfuscation { file_id, .. }) = state.executions.get(*id) else {
+                    return Response::text(404, "unknown execution");
+                };
+                match &self.options.artifact {
+                    Some(artifact) => Response::bytes(artifact.clone()),
+                    None => {
+                        Response::bytes(state.uploads[file_id].data.clone().unwrap_or_default())
+                    }
+                }
+            }
+            (_, ["api", ..]) if !self.authorized(request) => Response::text(401, "invalid API key"),
+            ("GET", ["api", "get-upload-url"]) => {
+                if !request.query.contains_key("fileSize") {
+                    return Response::text(400, "missing fileSize");
+                }
+                let mut state = self.state.lock().unwrap();
+                let id = state.new_id("file");
+                let name = request.query.get("fileName").cloned();
+                state
+                    .uploads
+                    .insert(id.clone(), Upload { name, data: None });
+                let host = request
+                    .headers
+                    .get("host")
+                    .map_or("127.0.0.1", String::as_str);
+                Response::json(
+                    200,
+                    json!({ "uploadUrl": format!("http://{}/upload/{}", host, id), "fileId": id }),
+                )
+            }
+            ("PUT", ["api", "analyze"]) => {
+                let mut state = self.state.lock().unwrap();
+                let uploaded =
+                    |id: &String| state.uploads.get(id).is_some_and(|u| u.data.is_some());
+                let Some(file_id) = request.query.get("fileId").filter(|id| uploaded(id)) else {
+                    return Response::text(404, "unknown fileId");
+                };
+                let pdb_file_id = request.query.get("pdbFileId").cloned();
+                if pdb_file_id.as_ref().is_some_and(|id| !uploaded(id)) {
+                    return Response::text(404, "unknown pdbFileId");
+                }
+                log::info!(
+                    "Analyzing {} with {}",
+                    file_id,
+                    pdb_file_id
+                        .as_deref()
+                        .map_or("no symbols".to_owned(), |id| {
+                            format!(
+                                "symbols {}",
+                                state.uploads[id].name.as_deref().unwrap_or(id)
+                            )
+                        })
+                );
+                let id = state.new_id("analysis");
+                state.executions.insert(
+                    id.clone(),
+                    Execution::Analysis {
+                        file_id: file_id.clone(),
+                        pdb_file_id,
+                        polls_left: self.options.processing_polls,
+                    },
+                );
+                Response::json(200, json!({ "executionId": id }))
+            }
+            ("GET", ["api", "analyze-status"]) => self.poll(request, "analysisUrl", "analysis"),
+            ("POST", ["api", "defend"]) => {
+                let mut state = self.state.lock().unwrap();
+                let Some(file_id) = request
+                    .query
+                    .get("fileId")
+                    .filter(|id| state.uploads.contains_key(*id))
+                    .cloned()
+                else {
+                    return Response::text(404, "unknown fileId");
+                };
+                let config: Config = match serde_json::from_slice(&request.body) {
+                    Ok(config) => config,
+                    Err(e) => return Response::text(400, format!("invalid config: {}", e)),
+                };
+                log::info!(
+                    "Obfuscating {} with {} profile(s) covering {} function(s)",
+                    file_id,
+                    config.profiles.len(),
+                    config
+                        .profiles
+                        .iter()
+                        .map(|p| p.symbols.len())
+                        .sum::<usize>()
+                );
+                let id = state.new_id("obfuscation");
+                state.executions.insert(
+                    id.clone(),
+                    Execution::Obfuscation {
+                        file_id,
+                        polls_left: self.options.processing_polls,
+                    },
+                );
+                Response::text(200, id)
+            }
+            ("GET", ["api", "download"]) => self.poll(request, "downloadUrl", "artifact"),
+            ("GET", ["api", "whoami"]) => Response::json(
+                200,
+                json!({
+                    "account": "mock",
+                    "configVersion": YAML_CONFIG_VERSION,
+                    "symbolFormat": codedefender_symbols::StreamVersion::LATEST.number(),
+                }),
+            ),
+            _ => Response::text(404, "not found"),
+        }
+    }
+}
+
+fn handle_connection(server: &Server, stream: TcpStream) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let request = Request::read(&mut reader)?;
+    let response = server.handle(&request);
+    log::debug!("{} {} -> {}", request.method, request.path, response.status);
+    let mut stream = stream;
+    response.write(&mut stream)
+}
+
+/// Serves the mock API on `listener` until the process is stopped.
+pub fn serve(listener: TcpListener, options: MockOptions) -> io::Result<()> {
+    let server = Arc::new(Server {
+        options,
+        state: Mutex::new(State::default()),
+    });
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let server = server.clone();
+        std::thread::spawn(move || {
+            if let Err(e) = handle_connection(&server, stream) {
+                log::warn!("Mock server connection failed: {}", e);
+            }
+        });
+    }
+    Ok(())
+}
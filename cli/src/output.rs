@@ -0,0 +1,696 @@
+use crate::exit::{self, ExitStatus};
+use codedefender_api::codedefender_config::{
+    AnalysisExport, AnalysisImport, AnalysisModuleInfo, ModuleSettings, OutputConstraints,
+};
+use object::LittleEndian as LE;
+use object::pe;
+use object::read::pe::{ExportTarget, ImageNtHeaders, ImageOptionalHeader, ImageThunkData, PeFile};
+use object::{Object, ObjectSection, SectionFlags};
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+const ZIP_LOCAL_HEADER_SIG: u32 = 0x04034b50;
+const ZIP_CENTRAL_HEADER_SIG: u32 = 0x02014b50;
+const ZIP_END_OF_CENTRAL_DIR_SIG: u32 = 0x06054b50;
+/// `UNWIND_INFO` flags of functions with an exception or termination handler.
+const UNW_FLAG_EHANDLER: u8 = 0x1;
+const UNW_FLAG_UHANDLER: u8 = 0x2;
+
+/// Size, section entropy and imports of a PE file.
+#[derive(Debug, Clone)]
+pub struct PeMetrics {
+    /// File size in bytes.
+    pub size: usize,
+    /// Section names and their entropy in bits per byte.
+    pub sections: Vec<(String, f64)>,
+    /// Imported functions as `library!name`.
+    pub imports: BTreeSet<String>,
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        bytes.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        bytes.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+/// Reads the stored and deflated files of a zip archive as `(name, contents)` pairs. Returns `None`
+/// if `archive` is not a well formed zip.
+pub fn zip_entries(archive: &[u8]) -> Option<Vec<(String, Vec<u8>)>> {
+    // Walk the central directory since local headers may defer their sizes to a data descriptor.
+    let eocd = (0..archive.len().saturating_sub(21))
+        .rev()
+        .find(|i| read_u32(archive, *i) == Some(ZIP_END_OF_CENTRAL_DIR_SIG))?;
+    let entry_count = read_u16(archive, eocd + 10)?;
+    let mut offset = read_u32(archive, eocd + 16)? as usize;
+    let mut entries = Vec::new();
+    for _ in 0..entry_count {
+        if read_u32(archive, offset)? != ZIP_CENTRAL_HEADER_SIG {
+            return None;
+        }
+        let method = read_u16(archive, offset + 10)?;
+        let compressed_size = read_u32(archive, offset + 20)? as usize;
+        let name_len = read_u16(archive, offset + 28)? as usize;
+        let extra_len = read_u16(archive, offset + 30)? as usize;
+        let comment_len = read_u16(archive, offset + 32)? as usize;
+        let local = read_u32(archive, offset + 42)? as usize;
+        let name = String::from_utf8_lossy(archive.get(offset + 46..offset + 46 + name_len)?);
+        offset += 46 + name_len + extra_len + comment_len;
+
+        if read_u32(archive, local)? != ZIP_LOCAL_HEADER_SIG {
+            return None;
+        }
+        let data_start = local
+            + 30
+            + read_u16(archive, local + 26)? as usize
+            + read_u16(archive, local + 28)? as usize;
+        let data = archive.get(data_start..data_start + compressed_size)?;
+        let contents = match method {
+            0 => data.to_vec(),
+            8 => miniz_oxide::inflate::decompress_to_vec(data).ok()?,
+            _ => {
+                log::warn!(
+                    "Skipping {:?} in the output zip, unsupported compression",
+                    name
+                );
+                continue;
+            }
+        };
+        entries.push((name.into_owned(), contents));
+    }
+    Some(entries)
+}
+
+/// Returns the PE image inside a downloaded artifact, which is either the raw PE or a zip
+/// archive holding it next to its debug file.
+pub fn extract_pe(artifact: &[u8]) -> Option<Vec<u8>> {
+    if artifact.starts_with(b"MZ") {
+        return Some(artifact.to_vec());
+    }
+    zip_entries(artifact)?
+        .into_iter()
+        .map(|(_, contents)| contents)
+        .find(|contents| contents.starts_with(b"MZ"))
+}
+
+/// The files of a downloaded artifact as `(name, contents)` pairs. A raw PE is a single unnamed
+/// file. Returns `None` if the artifact is neither.
+fn artifact_entries(artifact: &[u8]) -> Option<Vec<(String, Vec<u8>)>> {
+    if artifact.starts_with(b"MZ") {
+        return Some(vec![(String::new(), artifact.to_vec())]);
+    }
+    zip_entries(artifact)
+}
+
+const NOT_AN_ARTIFACT: &str = "The downloaded artifact is not a PE file or zip archive";
+
+/// Unpacks a downloaded artifact into `dir`. The binary is written as `<stem>.protected.<ext>`,
+/// using the extension of `input`, and its debug file as `<stem>.protected.pdb`. Other files keep
+/// their name. Returns the written paths.
+pub fn extract_artifacts(
+    artifact: &[u8],
+    input: &Path,
+    dir: &Path,
+) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+    let entries = artifact_entries(artifact).ok_or(NOT_AN_ARTIFACT)?;
+    let stem = input.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = input
+        .extension()
+        .map_or("exe".into(), |e| e.to_string_lossy());
+
+    fs::create_dir_all(dir)?;
+    let mut written = Vec::new();
+    for (name, contents) in entries {
+        if name.ends_with('/') {
+            continue;
+        }
+        let file_name = if contents.starts_with(b"MZ") {
+            format!("{}.protected.{}", stem, extension)
+        } else if name.to_lowercase().ends_with(".pdb") {
+            format!("{}.protected.pdb", stem)
+        } else {
+            // Flatten the archive so entry names cannot escape `dir`.
+            match Path::new(&name).file_name() {
+                Some(file_name) => file_name.to_string_lossy().into_owned(),
+                None => continue,
+            }
+        };
+        let path = dir.join(file_name);
+        if written.contains(&path) {
+            return Err(
+                format!("The downloaded artifact contains more than one {:?}", path).into(),
+            );
+        }
+        fs::write(&path, contents)?;
+        written.push(path);
+    }
+    Ok(written)
+}
+
+/// Writes the first file of `entries` that `is_member` accepts to exactly `path`. Fails if there
+/// is none.
+fn write_member(
+    entries: &[(String, Vec<u8>)],
+    path: &Path,
+    what: &str,
+    is_member: impl Fn(&str, &[u8]) -> bool,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let Some((_, contents)) = entries
+        .iter()
+        .find(|(name, contents)| is_member(name, contents))
+    else {
+        return Err(exit::failure(
+            ExitStatus::Obfuscation,
+            format!("The downloaded artifact contains no {}", what),
+        ));
+    };
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, contents)?;
+    Ok(path.to_path_buf())
+}
+
+/// Writes the binary and the debug file of a downloaded artifact to exactly `binary` and `pdb`.
+/// Fails if the artifact lacks one that was asked for. Returns the written paths.
+pub fn write_members(
+    artifact: &[u8],
+    binary: Option<&Path>,
+    pdb: Option<&Path>,
+) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+    if binary.is_none() && pdb.is_none() {
+        return Ok(Vec::new());
+    }
+    let entries = artifact_entries(artifact).ok_or(NOT_AN_ARTIFACT)?;
+    let mut written = Vec::new();
+    if let Some(path) = binary {
+        written.push(write_member(&entries, path, "binary", |_, contents| {
+            contents.starts_with(b"MZ")
+        })?);
+    }
+    if let Some(path) = pdb {
+        written.push(write_member(&entries, path, "PDB", |name, _| {
+            name.to_lowercase().ends_with(".pdb")
+        })?);
+    }
+    Ok(written)
+}
+
+/// Shannon entropy of `data` in bits per byte.
+pub fn entropy(data: &[u8]) -> f64 {
+    let mut counts = [0usize; 256];
+    for byte in data {
+        counts[*byte as usize] += 1;
+    }
+    let len = data.len() as f64;
+    counts
+        .iter()
+        .filter(|c| **c > 0)
+        .map(|c| {
+            let p = *c as f64 / len;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+pub fn pe_metrics(pe_bytes: &[u8]) -> object::Result<PeMetrics> {
+    let file = object::File::parse(pe_bytes)?;
+    let mut sections = Vec::new();
+    for section in file.sections() {
+        let data = section.data()?;
+        if !data.is_empty() {
+            sections.push((section.name()?.to_owned(), entropy(data)));
+        }
+    }
+    Ok(PeMetrics {
+        size: pe_bytes.len(),
+        sections,
+        imports: import_names(&file)?,
+    })
+}
+
+/// Imported functions as `library!name`, the library in lower case.
+pub fn import_names(file: &object::File) -> object::Result<BTreeSet<String>> {
+    Ok(file
+        .imports()?
+        .iter()
+        .map(|i| {
+            format!(
+                "{}!{}",
+                String::from_utf8_lossy(i.library()).to_lowercase(),
+                String::from_utf8_lossy(i.name())
+            )
+        })
+        .collect())
+}
+
+/// Compares the obfuscated artifact against the input binary and returns every exceeded limit.
+pub fn check_output_constraints(
+    constraints: &OutputConstraints,
+    input: &[u8],
+    artifact: &[u8],
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let output = extract_pe(artifact).ok_or("No PE image found in the downloaded artifact")?;
+    let before = pe_metrics(input)?;
+    let after = pe_metrics(&output)?;
+    let mut violations = Vec::new();
+
+    if let Some(max) = constraints.max_size_growth_percent {
+        let growth = (after.size as f64 - before.size as f64) * 100.0 / before.size as f64;
+        if growth > f64::from(max) {
+            violations.push(format!(
+                "output grew by {growth:.1}% ({} -> {} bytes), limit is {max}%",
+                before.size, after.size
+            ));
+        }
+    }
+    if let Some(max) = constraints.max_section_entropy {
+        for (name, value) in &after.sections {
+            if *value > max.get() {
+                violations.push(format!(
+                    "section `{name}` has entropy {value:.2}, limit is {:.2}",
+                    max.get()
+                ));
+            }
+        }
+    }
+    if let Some(max) = constraints.max_new_imports {
+        let new_imports: Vec<&String> = after.imports.difference(&before.imports).collect();
+        if new_imports.len() > max as usize {
+            violations.push(format!(
+                "output adds {} imports ({}), limit is {max}",
+                new_imports.len(),
+                new_imports
+                    .iter()
+                    .map(|i| i.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        }
+    }
+    Ok(violations)
+}
+
+/// Subsystem and base relocation block count of a PE image.
+struct PeLayout {
+    native: bool,
+    relocation_blocks: Option<usize>,
+}
+
+fn pe_layout<Pe: ImageNtHeaders>(file: &PeFile<'_, Pe>) -> object::Result<PeLayout> {
+    let native = file.nt_headers().optional_header().subsystem() == pe::IMAGE_SUBSYSTEM_NATIVE;
+    let relocation_blocks = match file
+        .data_directories()
+        .relocation_blocks(file.data(), &file.section_table())?
+    {
+        Some(mut blocks) => {
+            let mut count = 0;
+            while blocks.next()?.is_some() {
+                count += 1;
+            }
+            Some(count)
+        }
+        None => None,
+    };
+    Ok(PeLayout {
+        native,
+        relocation_blocks,
+    })
+}
+
+fn layout(file: &object::File) -> object::Result<Option<PeLayout>> {
+    match file {
+        object::File::Pe32(file) => pe_layout(file).map(Some),
+        object::File::Pe64(file) => pe_layout(file).map(Some),
+        _ => Ok(None),
+    }
+}
+
+fn pe_module_info<Pe: ImageNtHeaders>(file: &PeFile<'_, Pe>) -> object::Result<AnalysisModuleInfo> {
+    let data = file.data();
+    let sections = file.section_table();
+    let directories = file.data_directories();
+    let directory = |index| {
+        directories
+            .get(index)
+            .filter(|d| d.virtual_address.get(LE) != 0)
+            .map(|d| d.data(data, &sections))
+            .transpose()
+    };
+    let header = file.nt_headers().optional_header();
+    let image_base = header.image_base();
+    let is_64 = file.is_64();
+    let pointer = |bytes: &[u8], offset: usize| {
+        if is_64 {
+            read_u32(bytes, offset + 4)
+                .zip(read_u32(bytes, offset))
+                .map(|(high, low)| u64::from(high) << 32 | u64::from(low))
+        } else {
+            read_u32(bytes, offset).map(u64::from)
+        }
+    };
+
+    let mut info = AnalysisModuleInfo {
+        entry_point: Some(u64::from(header.address_of_entry_point())).filter(|rva| *rva != 0),
+        imports: Some(pe_imports(file)?),
+        exports: Some(pe_exports(file)?),
+        ..Default::default()
+    };
+
+    // IMAGE_TLS_DIRECTORY holds the address of a null-terminated array of callback addresses.
+    if let Some(tls) = directory(pe::IMAGE_DIRECTORY_ENTRY_TLS)? {
+        let callbacks = pointer(tls, if is_64 { 24 } else { 12 })
+            .filter(|va| *va != 0)
+            .and_then(|va| u32::try_from(va.wrapping_sub(image_base)).ok())
+            .and_then(|rva| sections.pe_data_at(data, rva));
+        if let Some(callbacks) = callbacks {
+            let size = if is_64 { 8 } else { 4 };
+            info.tls_callbacks = (0..callbacks.len() / size)
+                .map_while(|i| pointer(callbacks, i * size).filter(|va| *va != 0))
+                .map(|va| va.wrapping_sub(image_base))
+                .collect();
+        }
+    }
+
+    if let Some(load_config) = directory(pe::IMAGE_DIRECTORY_ENTRY_LOAD_CONFIG)? {
+        info.load_config = true;
+        // GuardFlags, present when the directory is large enough to hold it.
+        let guard_flags = if is_64 { 0x90 } else { 0x58 };
+        info.cfg = read_u32(load_config, 0).is_some_and(|size| size as usize >= guard_flags + 4)
+            && read_u32(load_config, guard_flags)
+                .is_some_and(|flags| flags & pe::IMAGE_GUARD_CF_INSTRUMENTED != 0);
+    }
+
+    if let Some(exceptions) = directory(pe::IMAGE_DIRECTORY_ENTRY_EXCEPTION)? {
+        info.exception_directory = !exceptions.is_empty();
+        // x64 RUNTIME_FUNCTION entries point at UNWIND_INFO, whose flags say whether the
+        // function registers an exception or termination handler.
+        if file.nt_headers().file_header().machine.get(LE) == pe::IMAGE_FILE_MACHINE_AMD64 {
+            info.seh = exceptions.chunks_exact(12).any(|entry| {
+                read_u32(entry, 8)
+                    .and_then(|rva| sections.pe_data_at(data, rva))
+                    .and_then(|unwind| unwind.first())
+                    .is_some_and(|byte| byte >> 3 & (UNW_FLAG_EHANDLER | UNW_FLAG_UHANDLER) != 0)
+            });
+        }
+    }
+    Ok(info)
+}
+
+fn pe_imports<Pe: ImageNtHeaders>(file: &PeFile<'_, Pe>) -> object::Result<Vec<AnalysisImport>> {
+    let mut imports = Vec::new();
+    let Some(table) = file.import_table()? else {
+        return Ok(imports);
+    };
+    let mut descriptors = table.descriptors()?;
+    while let Some(descriptor) = descriptors.next()? {
+        let library = String::from_utf8_lossy(table.name(descriptor.name.get(LE))?).into_owned();
+        let thunk = match descriptor.original_first_thunk.get(LE) {
+            0 => descriptor.first_thunk.get(LE),
+            thunk => thunk,
+        };
+        let mut thunks = table.thunks(thunk)?;
+        let mut functions = Vec::new();
+        while let Some(thunk) = thunks.next::<Pe>()? {
+            functions.push(if thunk.is_ordinal() {
+                format!("#{}", thunk.ordinal())
+            } else {
+                String::from_utf8_lossy(table.hint_name(thunk.address())?.1).into_owned()
+            });
+        }
+        imports.push(AnalysisImport { library, functions });
+    }
+    Ok(imports)
+}
+
+fn pe_exports<Pe: ImageNtHeaders>(file: &PeFile<'_, Pe>) -> object::Result<Vec<AnalysisExport>> {
+    let Some(table) = file.export_table()? else {
+        return Ok(Vec::new());
+    };
+    let lossy = |bytes: &[u8]| String::from_utf8_lossy(bytes).into_owned();
+    Ok(table
+        .exports()?
+        .into_iter()
+        .map(|export| AnalysisExport {
+            name: export.name.map(lossy),
+            ordinal: export.ordinal,
+            rva: match export.target {
+                ExportTarget::Address(rva) => Some(rva.into()),
+                _ => None,
+            },
+            forward: match export.target {
+                ExportTarget::Address(_) => None,
+                ExportTarget::ForwardByName(library, name) => {
+                    Some(format!("{}.{}", lossy(library), lossy(name)))
+                }
+                ExportTarget::ForwardByOrdinal(library, ordinal) => {
+                    Some(format!("{}.#{}", lossy(library), ordinal))
+                }
+            },
+        })
+        .collect())
+}
+
+/// The export table of a PE image, `None` for other formats.
+fn export_table(file: &object::File) -> object::Result<Option<Vec<AnalysisExport>>> {
+    match file {
+        object::File::Pe32(file) => pe_exports(file).map(Some),
+        object::File::Pe64(file) => pe_exports(file).map(Some),
+        _ => Ok(None),
+    }
+}
+
+/// Entry point, TLS callbacks, load config, exception handling, imports and exports of a PE
+/// image, as analysis reports them. `None` for other formats.
+pub fn module_info(pe_bytes: &[u8]) -> object::Result<Option<AnalysisModuleInfo>> {
+    match object::File::parse(pe_bytes)? {
+        object::File::Pe32(file) => pe_module_info(&file).map(Some),
+        object::File::Pe64(file) => pe_module_info(&file).map(Some),
+        _ => Ok(None),
+    }
+}
+
+pub fn export_names(file: &object::File) -> object::Result<BTreeSet<String>> {
+    Ok(file
+        .exports()?
+        .iter()
+        .map(|e| String::from_utf8_lossy(e.name()).into_owned())
+        .collect())
+}
+
+/// Checks that the obfuscated artifact is still a loadable image of `input`: the entry point lies
+/// in an executable section, imports parse, every export keeps its name, ordinal and forwarder,
+/// drivers keep their base relocations and a configured custom section name was applied. Returns
+/// every problem found.
+pub fn verify_output(
+    settings: &ModuleSettings,
+    input: &[u8],
+    artifact: &[u8],
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let output = extract_pe(artifact).ok_or("No PE image found in the downloaded artifact")?;
+    let before = object::File::parse(input)?;
+    let after = match object::File::parse(&*output) {
+        Ok(after) => after,
+        Err(e) => return Ok(vec![format!("output is not a valid PE image: {}", e)]),
+    };
+    let mut problems = Vec::new();
+
+    let entry = after.entry();
+    let entry_section = after
+        .sections()
+        .find(|s| (s.address()..s.address() + s.size()).contains(&entry));
+    match entry_section {
+        None => problems.push(format!(
+            "entry point {:#x} is outside of every section",
+            entry
+        )),
+        Some(section) => {
+            if let SectionFlags::Coff { characteristics } = section.flags()
+                && characteristics & pe::IMAGE_SCN_MEM_EXECUTE == 0
+            {
+                problems.push(format!(
+                    "entry point {:#x} is in the non-executable section `{}`",
+                    entry,
+                    section.name().unwrap_or_default()
+                ));
+            }
+        }
+    }
+
+    match after.imports() {
+        Ok(imports) => {
+            if let Some(import) = imports.iter().find(|i| i.library().is_empty()) {
+                problems.push(format!(
+                    "import `{}` has no library name",
+                    String::from_utf8_lossy(import.name())
+                ));
+            }
+        }
+        Err(e) => problems.push(format!("import table is malformed: {}", e)),
+    }
+
+    match export_table(&after) {
+        Ok(exports) => {
+            let exports = exports.unwrap_or_default();
+            let missing: Vec<String> = export_table(&before)?
+                .unwrap_or_default()
+                .iter()
+                .filter(|e| !e.preserved_in(&exports))
+                .map(|e| e.to_string())
+                .collect();
+            if !missing.is_empty() {
+                problems.push(format!(
+                    "exports are missing or changed: {}",
+                    missing.join(", ")
+                ));
+            }
+        }
+        Err(e) => problems.push(format!("export table is malformed: {}", e)),
+    }
+
+    let input_layout = layout(&before)?;
+    match layout(&after) {
+        Ok(output_layout) => {
+            if let (Some(input_layout), Some(output_layout)) = (input_layout, output_layout)
+                && input_layout.native
+                && input_layout.relocation_blocks.unwrap_or(0) > 0
+                && output_layout.relocation_blocks.unwrap_or(0) == 0
+            {
+                problems.push("driver has no base relocations left".to_owned());
+            }
+        }
+        Err(e) => problems.push(format!("base relocations are malformed: {}", e)),
+    }
+
+    let custom = &settings.custom_section_name;
+    if custom.enabled
+        && !after
+            .sections()
+            .any(|s| s.name() == Ok(custom.value.as_str()))
+    {
+        problems.push(format!("no section is named `{}`", custom.value));
+    }
+    Ok(problems)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A zip archive of `(name, contents, deflated)` entries.
+    fn zip(entries: &[(&str, &[u8], bool)]) -> Vec<u8> {
+        let mut archive = Vec::new();
+        let mut central = Vec::new();
+        for (name, contents, deflated) in entries {
+            let (method, data) = if *deflated {
+                (8u16, miniz_oxide::deflate::compress_to_vec(contents, 6))
+            } else {
+                (0u16, contents.to_vec())
+            };
+            let local = archive.len() as u32;
+            archive.extend(ZIP_LOCAL_HEADER_SIG.to_le_bytes());
+            archive.extend([20, 0, 0, 0]);
+            archive.extend(method.to_le_bytes());
+            archive.extend([0; 8]);
+            archive.extend((data.len() as u32).to_le_bytes());
+            archive.extend((contents.len() as u32).to_le_bytes());
+            archive.extend((name.len() as u16).to_le_bytes());
+            archive.extend([0, 0]);
+            archive.extend(name.as_bytes());
+            archive.extend(&data);
+
+            central.extend(ZIP_CENTRAL_HEADER_SIG.to_le_bytes());
+            central.extend([20, 0, 20, 0, 0, 0]);
+            central.extend(method.to_le_bytes());
+            central.extend([0; 8]);
+            central.extend((data.len() as u32).to_le_bytes());
+            central.extend((contents.len() as u32).to_le_bytes());
+            central.extend((name.len() as u16).to_le_bytes());
+            central.extend([0; 12]);
+            central.extend(local.to_le_bytes());
+            central.extend(name.as_bytes());
+        }
+        let central_offset = archive.len() as u32;
+        archive.extend(&central);
+        archive.extend(ZIP_END_OF_CENTRAL_DIR_SIG.to_le_bytes());
+        archive.extend([0; 4]);
+        archive.extend((entries.len() as u16).to_le_bytes());
+        archive.extend((entries.len() as u16).to_le_bytes());
+        archive.extend((central.len() as u32).to_le_bytes());
+        archive.extend(central_offset.to_le_bytes());
+        archive.extend([0, 0]);
+        archive
+    }
+
+    /// An empty directory for a test to extract into.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("codedefender-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn stored_and_deflated_entries_are_read() {
+        let pdb = b"Microsoft C/C++ MSF 7.00".repeat(64);
+        let archive = zip(&[("app.exe", b"MZ stored", false), ("app.pdb", &pdb, true)]);
+        assert_eq!(
+            zip_entries(&archive).unwrap(),
+            [
+                ("app.exe".to_owned(), b"MZ stored".to_vec()),
+                ("app.pdb".to_owned(), pdb)
+            ]
+        );
+    }
+
+    #[test]
+    fn truncated_or_corrupt_archives_are_rejected() {
+        let archive = zip(&[
+            ("app.exe", b"MZ stored", false),
+            ("notes.txt", b"text", true),
+        ]);
+        for len in 0..archive.len() {
+            assert_eq!(zip_entries(&archive[..len]), None, "cut at {}", len);
+        }
+
+        // The end of central directory points past the end of the archive.
+        let mut corrupt = archive.clone();
+        let eocd = corrupt.len() - 22;
+        corrupt[eocd + 16..eocd + 20].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(zip_entries(&corrupt), None);
+
+        // A central directory entry claims more data than the archive holds.
+        let mut corrupt = archive;
+        let central = u32::from_le_bytes(corrupt[eocd + 16..eocd + 20].try_into().unwrap());
+        let size = central as usize + 20;
+        corrupt[size..size + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(zip_entries(&corrupt), None);
+
+        let dir = temp_dir("corrupt-zip");
+        assert!(extract_artifacts(&corrupt, Path::new("app.exe"), &dir).is_err());
+        assert!(!dir.exists());
+    }
+
+    #[test]
+    fn entry_names_cannot_escape_the_output_directory() {
+        let archive = zip(&[
+            ("../x.exe", b"not a PE", false),
+            ("../../nested/y.dll", b"not a PE either", true),
+            ("..", b"no file name", false),
+        ]);
+        let dir = temp_dir("zip-slip");
+        let written = extract_artifacts(&archive, Path::new("app.exe"), &dir).unwrap();
+        assert_eq!(written, [dir.join("x.exe"), dir.join("y.dll")]);
+        assert_eq!(fs::read(dir.join("x.exe")).unwrap(), b"not a PE");
+        assert!(!dir.parent().unwrap().join("x.exe").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,241 @@
+//! `--set path=value` overrides applied to the YAML config before it is parsed.
+
+use crate::yaml_edit::{Segment, YamlDocument};
+use serde_yaml::{Mapping, Value};
+use std::fmt;
+
+/// A value assigned to a path like `profiles[0].passes[2].iterations`.
+#[derive(Debug, Clone)]
+pub struct ConfigOverride {
+    /// `path=value` as given.
+    text: String,
+    path: String,
+    segments: Vec<Segment>,
+    value: Value,
+}
+
+impl fmt::Display for ConfigOverride {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+impl ConfigOverride {
+    /// Parses `path=value`. The value is read as YAML, so `5`, `false` and `[a, b]` keep their
+    /// type and anything else is a string.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let (path, value) = text
+            .split_once('=')
+            .ok_or_else(|| format!("Expected `path=value`, got {:?}", text))?;
+        let mut segments = Vec::new();
+        for part in path.split('.') {
+            let (key, mut indices) = part.split_at(part.find('[').unwrap_or(part.len()));
+            if !key.is_empty() {
+                segments.push(Segment::Key(key.to_owned()));
+            }
+            while let Some(rest) = indices.strip_prefix('[') {
+                let (index, rest) = rest
+                    .split_once(']')
+                    .ok_or_else(|| format!("Unclosed `[` in {:?}", path))?;
+                let index = index
+                    .parse()
+                    .map_err(|_| format!("Invalid index `{}` in {:?}", index, path))?;
+                segments.push(Segment::Index(index));
+                indices = rest;
+            }
+            if !indices.is_empty() || (key.is_empty() && part.is_empty()) {
+                return Err(format!("Invalid path {:?}", path));
+            }
+        }
+        let value = if value.is_empty() {
+            Value::String(String::new())
+        } else {
+            serde_yaml::from_str(value).map_err(|e| format!("Invalid value for {}: {}", path, e))?
+        };
+        Ok(Self {
+            text: text.to_owned(),
+            path: path.to_owned(),
+            segments,
+            value,
+        })
+    }
+
+    /// Assigns the value in `root`. Missing keys are created, list indices must exist or be the
+    /// length of the list to append.
+    pub fn apply(&self, root: &mut Value) -> Result<(), String> {
+        let mut current = root;
+        for (i, segment) in self.segments.iter().enumerate() {
+            // Tagged values such as `!ref name` are addressed by their content.
+            while let Value::Tagged(tagged) = current {
+                current = &mut tagged.value;
+            }
+            let last = i + 1 == self.segments.len();
+            let next = match self.segments.get(i + 1) {
+                Some(Segment::Index(_)) => Value::Sequence(Vec::new()),
+                _ => Value::Mapping(Mapping::new()),
+            };
+            current = match (segment, current) {
+                (Segment::Key(key), Value::Mapping(map)) => {
+                    let key = Value::String(key.clone());
+                    if last {
+                        map.insert(key, self.value.clone());
+                        return Ok(());
+                    }
+                    map.entry(key).or_insert(next)
+                }
+                (Segment::Index(index), Value::Sequence(seq)) => {
+                    if *index == seq.len() {
+                        seq.push(if last { self.value.clone() } else { next });
+                    } else if *index > seq.len() {
+                        return Err(format!(
+                            "{}: index {} is out of range, the list has {} items",
+                            self.path,
+                            index,
+                            seq.len()
+                        ));
+                    } else if last {
+                        seq[*index] = self.value.clone();
+                    }
+                    if last {
+                        return Ok(());
+                    }
+                    &mut seq[*index]
+                }
+                (Segment::Key(key), _) => {
+                    return Err(format!(
+                        "{}: cannot set `{}` on a non-mapping",
+                        self.path, key
+                    ));
+                }
+                (Segment::Index(index), _) => {
+                    return Err(format!(
+                        "{}: cannot index [{}] into a non-list",
+                        self.path, index
+                    ));
+                }
+            };
+        }
+        *current = self.value.clone();
+        Ok(())
+    }
+
+    /// Assigns the value in the config file `document`, leaving the rest of the file as it is.
+    pub fn apply_to_document(&self, document: &mut YamlDocument) -> Result<(), String> {
+        let mut value = document
+            .value()
+            .map_err(|e| format!("{}: {}", self.path, e))?;
+        self.apply(&mut value)?;
+        document
+            .update(&value)
+            .map_err(|e| format!("{}: {}", self.path, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(key: &str) -> Segment {
+        Segment::Key(key.to_owned())
+    }
+
+    fn yaml(text: &str) -> Value {
+        serde_yaml::from_str(text).unwrap()
+    }
+
+    /// `root` after applying `text`.
+    fn applied(root: &str, text: &str) -> Result<Value, String> {
+        let mut root = yaml(root);
+        ConfigOverride::parse(text)?.apply(&mut root)?;
+        Ok(root)
+    }
+
+    #[test]
+    fn paths_parse_into_keys_and_indices() {
+        let over = ConfigOverride::parse("a.b[2].c=value").unwrap();
+        assert_eq!(
+            over.segments,
+            [key("a"), key("b"), Segment::Index(2), key("c")]
+        );
+        assert_eq!(over.value, Value::from("value"));
+        assert_eq!(over.to_string(), "a.b[2].c=value");
+
+        let over = ConfigOverride::parse("list[0][1]=[1, true]").unwrap();
+        assert_eq!(
+            over.segments,
+            [key("list"), Segment::Index(0), Segment::Index(1)]
+        );
+        assert_eq!(over.value, yaml("[1, true]"));
+        assert_eq!(ConfigOverride::parse("a=").unwrap().value, Value::from(""));
+    }
+
+    #[test]
+    fn malformed_paths_are_rejected() {
+        for (text, error) in [
+            ("a.b", "Expected `path=value`, got \"a.b\""),
+            ("a[1=x", "Unclosed `[` in \"a[1\""),
+            ("a[x]=1", "Invalid index `x` in \"a[x]\""),
+            ("a[1]b=1", "Invalid path \"a[1]b\""),
+            ("a..b=1", "Invalid path \"a..b\""),
+            ("=1", "Invalid path \"\""),
+        ] {
+            assert_eq!(ConfigOverride::parse(text).unwrap_err(), error, "{}", text);
+        }
+    }
+
+    #[test]
+    fn values_are_set_and_missing_keys_created() {
+        assert_eq!(
+            applied("a:\n  b: 1\n  c: 2\n", "a.b=5").unwrap(),
+            yaml("a:\n  b: 5\n  c: 2\n")
+        );
+        assert_eq!(
+            applied("a: {}\n", "a.b.c[0].d=x").unwrap(),
+            yaml("a:\n  b:\n    c:\n      - d: x\n")
+        );
+    }
+
+    #[test]
+    fn index_equal_to_the_length_appends() {
+        assert_eq!(
+            applied("l: [1, 2]\n", "l[2]=3").unwrap(),
+            yaml("l: [1, 2, 3]\n")
+        );
+        assert_eq!(
+            applied("l: [1, 2]\n", "l[1]=9").unwrap(),
+            yaml("l: [1, 9]\n")
+        );
+        assert_eq!(
+            applied("l: [{a: 1}]\n", "l[1].a=2").unwrap(),
+            yaml("l: [{a: 1}, {a: 2}]\n")
+        );
+    }
+
+    #[test]
+    fn index_past_the_length_is_rejected() {
+        assert_eq!(
+            applied("l: [1, 2]\n", "l[3]=4").unwrap_err(),
+            "l[3]: index 3 is out of range, the list has 2 items"
+        );
+    }
+
+    #[test]
+    fn paths_through_scalars_are_rejected() {
+        assert_eq!(
+            applied("a: 1\n", "a.b=2").unwrap_err(),
+            "a.b: cannot set `b` on a non-mapping"
+        );
+        assert_eq!(
+            applied("a: 1\n", "a[0]=2").unwrap_err(),
+            "a[0]: cannot index [0] into a non-list"
+        );
+    }
+
+    #[test]
+    fn tagged_values_are_addressed_by_their_content() {
+        assert_eq!(
+            applied("s: !Name {a: 1}\n", "s.a=2").unwrap(),
+            yaml("s: !Name {a: 2}\n")
+        );
+    }
+}
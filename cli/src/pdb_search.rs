@@ -0,0 +1,323 @@
+//! Finding the PDB of an input when `--pdb-file` is not given, locally or on symbol servers, and
+//! checking that a given one belongs to it.
+
+use crate::cache::Cache;
+use object::{Object, read::File};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// Time allowed for downloading one PDB, which can be hundreds of megabytes.
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// The CodeView record of a PE's debug directory, which names the PDB it was linked with.
+struct CodeView {
+    guid: [u8; 16],
+    age: u32,
+    path: PathBuf,
+}
+
+impl CodeView {
+    fn read(input: &[u8]) -> Option<Self> {
+        let file = File::parse(input).ok()?;
+        let info = file.pdb_info().ok()??;
+        Some(Self {
+            guid: info.guid(),
+            age: info.age(),
+            path: PathBuf::from(String::from_utf8_lossy(info.path()).into_owned()),
+        })
+    }
+
+    /// File name of the PDB. The recorded path uses the separators of the machine it was built on.
+    fn file_name(&self) -> Option<String> {
+        let path = self.path.to_string_lossy();
+        let name = path.rsplit(['\\', '/']).next()?;
+        (!name.is_empty()).then(|| name.to_owned())
+    }
+
+    /// Directory name of the PDB in a symbol store, `<GUID><age>` in upper case hex.
+    fn store_key(&self) -> String {
+        format!("{}{:X}", format_guid(&self.guid).replace('-', ""), self.age)
+    }
+
+    /// Whether the PDB at `path` is the one the PE was linked with.
+    fn matches(&self, path: &Path) -> bool {
+        identify(path).is_ok_and(|(guid, age)| guid == self.guid && age == self.age)
+    }
+}
+
+/// `guid` in the registry form, e.g. `6B29FC40-CA47-1067-B31D-00DD010662DA`.
+fn format_guid(guid: &[u8; 16]) -> String {
+    let data1 = u32::from_le_bytes([guid[0], guid[1], guid[2], guid[3]]);
+    let data2 = u16::from_le_bytes([guid[4], guid[5]]);
+    let data3 = u16::from_le_bytes([guid[6], guid[7]]);
+    let data4: String = guid[8..10].iter().map(|b| format!("{:02X}", b)).collect();
+    let data5: String = guid[10..].iter().map(|b| format!("{:02X}", b)).collect();
+    format!(
+        "{:08X}-{:04X}-{:04X}-{}-{}",
+        data1, data2, data3, data4, data5
+    )
+}
+
+/// GUID and age of the PDB at `path`, as the CodeView record of its PE repeats them. The age is
+/// the one of the debug info stream, which the linker copies into the PE.
+fn identify(path: &Path) -> pdb::Result<([u8; 16], u32)> {
+    let mut pdb = pdb::PDB::open(fs::File::open(path)?)?;
+    let info = pdb.pdb_information()?;
+    let age = pdb.debug_information()?.age().unwrap_or(info.age);
+    Ok((info.guid.to_bytes_le(), age))
+}
+
+/// Checks that `pdb` is the PDB the PE `input` was linked with. Inputs that record no PDB, such
+/// as non-PE binaries, are not checked.
+pub fn check(input: &[u8], pdb: &Path) -> Result<(), String> {
+    let Some(codeview) = CodeView::read(input) else {
+        return Ok(());
+    };
+    let (guid, age) =
+        identify(pdb).map_err(|e| format!("{:?} cannot be read as a PDB: {}", pdb, e))?;
+    if guid == codeview.guid && age == codeview.age {
+        return Ok(());
+    }
+    Err(format!(
+        "{:?} does not belong to the input: it has GUID {} age {}, but the input was linked with \
+         {:?}, GUID {} age {}. Pass the PDB written by the same build, or omit --pdb-file to \
+         look for it",
+        pdb,
+        format_guid(&guid),
+        age,
+        codeview.path,
+        format_guid(&codeview.guid),
+        codeview.age
+    ))
+}
+
+/// Local directories of `_NT_SYMBOL_PATH`, e.g. `C:\Symbols` and the cache of
+/// `srv*C:\Symbols*https://msdl.microsoft.com/download/symbols`. Its servers are only queried
+/// through [`SymbolServers`].
+fn symbol_path_dirs() -> Vec<PathBuf> {
+    let Some(symbol_path) = env::var_os("_NT_SYMBOL_PATH") else {
+        return Vec::new();
+    };
+    symbol_path
+        .to_string_lossy()
+        .split(';')
+        .flat_map(|entry| {
+            let parts: Vec<&str> = entry.split('*').collect();
+            match parts.first().map(|p| p.to_ascii_lowercase()).as_deref() {
+                Some("srv" | "symsrv" | "cache") => parts[1..].to_vec(),
+                _ => parts,
+            }
+        })
+        .filter(|part| !part.is_empty() && !part.contains("://") && !part.ends_with(".dll"))
+        .map(PathBuf::from)
+        .collect()
+}
+
+/// A symbol server and the local store its downloads are kept in.
+#[derive(Debug)]
+struct SymbolServer {
+    url: String,
+    store: PathBuf,
+}
+
+/// Symbol servers [`find`] downloads PDBs from when none is found locally.
+#[derive(Debug, Default)]
+pub struct SymbolServers {
+    servers: Vec<SymbolServer>,
+}
+
+impl SymbolServers {
+    /// The servers of `_NT_SYMBOL_PATH`, e.g.
+    /// `srv*C:\Symbols*https://msdl.microsoft.com/download/symbols`, then `extra`. Downloads are kept in the first local store of the `_NT_SYMBOL_PATH` entry, or
+    /// in the `symbols` directory of the cache.
+    pub fn new(extra: Option<&str>) -> Self {
+        let default_store = Cache::default_dir()
+            .unwrap_or_else(|| env::temp_dir().join("codedefender"))
+            .join("symbols");
+        let symbol_path = env::var("_NT_SYMBOL_PATH").unwrap_or_default();
+        let mut servers = Vec::new();
+        for entry in symbol_path.split(';') {
+            let parts: Vec<&str> = entry.split('*').collect();
+            if !matches!(
+                parts.first().map(|p| p.to_ascii_lowercase()).as_deref(),
+                Some("srv" | "symsrv")
+            ) {
+                continue;
+            }
+            let store = parts[1..]
+                .iter()
+                .find(|part| !part.is_empty() && !part.contains("://") && !part.ends_with(".dll"))
+                .map(PathBuf::from);
+            for url in parts[1..].iter().filter(|part| part.contains("://")) {
+                servers.push(SymbolServer {
+                    url: url.to_string(),
+                    store: store.clone().unwrap_or_else(|| default_store.clone()),
+                });
+            }
+        }
+        servers.extend(extra.map(|url| SymbolServer {
+            url: url.to_owned(),
+            store: default_store.clone(),
+        }));
+        Self { servers }
+    }
+
+    /// Downloads the PDB `codeview` names into the store of the first server that has it.
+    /// Earlier downloads are reused.
+    fn fetch(&self, codeview: &CodeView) -> Option<PathBuf> {
+        let name = codeview.file_name()?;
+        let key = codeview.store_key();
+        let client = reqwest::blocking::Client::builder()
+            .timeout(DOWNLOAD_TIMEOUT)
+            .build()
+            .ok()?;
+        for server in &self.servers {
+            let path = server.store.join(&name).join(&key).join(&name);
+            if path.is_file() && codeview.matches(&path) {
+                log::info!("Using PDB {:?} downloaded from {}", path, server.url);
+                return Some(path);
+            }
+            let url = format!(
+                "{}/{}/{}/{}",
+                server.url.trim_end_matches('/'),
+                name,
+                key,
+                name
+            );
+            log::info!("Downloading {}", url);
+            match download(&client, &url, &path) {
+                Ok(true) if codeview.matches(&path) => {
+                    log::info!("Using PDB {:?} downloaded from {}", path, server.url);
+                    return Some(path);
+                }
+                Ok(true) => {
+                    log::warn!(
+                        "Ignoring {}, it does not match the PDB the input was linked with",
+                        url
+                    );
+                    let _ = fs::remove_file(&path);
+                }
+                Ok(false) => log::info!("{} has no {}", server.url, name),
+                Err(e) => log::warn!("Cannot download {}: {}", url, e),
+            }
+        }
+        None
+    }
+}
+
+/// Downloads `url` to `path`. Returns `false` when the server does not have it.
+fn download(
+    client: &reqwest::blocking::Client,
+    url: &str,
+    path: &Path,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let mut response = client.get(url).send()?;
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(false);
+    }
+    if !response.status().is_success() {
+        return Err(format!("the server answered {}", response.status()).into());
+    }
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    // Written next to its final path first, so an interrupted download is never picked up
+    let partial = path.with_extension("part");
+    let mut file = fs::File::create(&partial)?;
+    response.copy_to(&mut file)?;
+    drop(file);
+    fs::rename(&partial, path)?;
+    Ok(true)
+}
+
+/// Candidate PDB paths for `input`, most likely first, and where each was found.
+fn candidates(input: &Path, codeview: Option<&CodeView>) -> Vec<(PathBuf, &'static str)> {
+    let dir = input.parent().unwrap_or(Path::new(""));
+    let mut candidates = vec![(input.with_extension("pdb"), "next to the input")];
+    let Some(codeview) = codeview else {
+        return candidates;
+    };
+    if let Some(name) = codeview.file_name() {
+        candidates.push((dir.join(&name), "next to the input"));
+        candidates.push((codeview.path.clone(), "in the debug directory"));
+        for symbol_dir in symbol_path_dirs() {
+            let store = symbol_dir
+                .join(&name)
+                .join(codeview.store_key())
+                .join(&name);
+            candidates.push((store, "in _NT_SYMBOL_PATH"));
+            candidates.push((symbol_dir.join(&name), "in _NT_SYMBOL_PATH"));
+        }
+    }
+    candidates
+}
+
+/// Looks for the PDB of `input` next to it, at the path recorded in its debug directory and in
+/// the local directories of `_NT_SYMBOL_PATH`, then downloads it from `servers`. When the input
+/// records which PDB it was linked with, only that PDB is accepted.
+pub fn find(input: &Path, servers: Option<&SymbolServers>) -> Option<PathBuf> {
+    let codeview = fs::read(input).ok().and_then(|data| CodeView::read(&data));
+    let mut seen = Vec::new();
+    for (path, source) in candidates(input, codeview.as_ref()) {
+        if seen.contains(&path) || !path.is_file() {
+            continue;
+        }
+        seen.push(path.clone());
+        if let Some(codeview) = &codeview
+            && !codeview.matches(&path)
+        {
+            log::warn!(
+                "Ignoring {:?}, it does not match the PDB {:?} was linked with",
+                path,
+                input
+            );
+            continue;
+        }
+        log::info!("Using PDB {:?} found {}", path, source);
+        return Some(path);
+    }
+    if let (Some(servers), Some(codeview)) = (servers, &codeview)
+        && let Some(path) = servers.fetch(codeview)
+    {
+        return Some(path);
+    }
+    #[cfg(feature = "dwarf")]
+    if fs::read(input).is_ok_and(|data| codedefender_symbols::has_dwarf(&data)) {
+        log::info!("No PDB found for {:?}, using its DWARF debug info", input);
+        return None;
+    }
+    let exports = fs::read(input)
+        .ok()
+        .and_then(|data| {
+            File::parse(&*data)
+                .and_then(|file| {
+                    codedefender_symbols::parse_exports_impl(&file, &Default::default())
+                })
+                .ok()
+        })
+        .map_or(0, |funcs| funcs.len());
+    if exports > 0 {
+        log::warn!(
+            "No PDB found for {:?}, only its {} exported functions can be targeted by name",
+            input,
+            exports
+        );
+        return None;
+    }
+    match &codeview {
+        Some(codeview) => log::warn!(
+            "PDB {:?} of {:?} was not found, analyzing without symbols. Functions can only be \
+             targeted by RVA, pass --pdb-file to use symbols",
+            codeview.path,
+            input
+        ),
+        None => log::warn!(
+            "{:?} records no PDB and none is next to it, analyzing without symbols",
+            input
+        ),
+    }
+    None
+}
@@ -0,0 +1,1060 @@
+use crate::api;
+use crate::exit::{self, ExitStatus, api_failure, failure};
+use crate::output;
+use crate::overrides::ConfigOverride;
+use crate::pdb_search;
+use crate::progress::{Progress, format_duration, format_size};
+use crate::retry::{retry, retry_unsent};
+use codedefender_api::codedefender_config::{
+    AnalysisReject, AnalysisResult, Config, Coverage, DataRange, HotFunctionPolicy,
+    MIN_YAML_CONFIG_VERSION, ModuleSettings, ObfuscationPass, Profile, RejectSeverity,
+    YAML_CONFIG_VERSION, YamlConfig, YamlSymbol, is_supported_config_version,
+};
+use codedefender_api::{Status, serde_json};
+use codedefender_symbols::{
+    DebugSymbolInfo, PdbCompleteness, StreamVersion, SymbolOptions, infer_noreturn, merge_symbols,
+    parse_exports_impl, parse_libraries_impl, parse_pdb_file_impl, pdb_completeness,
+};
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+const CLI_DOWNLOAD_LINK: &str = "https://github.com/codedefender-io/api/releases";
+
+/// How long analysis and obfuscation may each take before the CLI gives up, unless configured.
+pub const DEFAULT_STAGE_TIMEOUT_SECS: u64 = 300; // 5 min
+
+/// Reads and parses a YAML config with `overrides` applied, rejecting unsupported versions and
+/// warning about passes this CLI does not know.
+pub fn load_config(
+    path: &Path,
+    overrides: &[ConfigOverride],
+) -> Result<YamlConfig, Box<dyn std::error::Error>> {
+    let config_contents = fs::read_to_string(path)?;
+    let config: YamlConfig = if overrides.is_empty() {
+        serde_yaml::from_str(&config_contents)?
+    } else {
+        let mut value: serde_yaml::Value = serde_yaml::from_str(&config_contents)?;
+        for config_override in overrides {
+            config_override
+                .apply(&mut value)
+                .map_err(|e| failure(ExitStatus::Config, e))?;
+        }
+        serde_yaml::from_value(value)?
+    };
+
+    if !is_supported_config_version(&config.version) {
+        log::error!(
+            "Invalid config version: {}, expected: {} through {}",
+            config.version,
+            MIN_YAML_CONFIG_VERSION,
+            YAML_CONFIG_VERSION
+        );
+        log::error!("Latest version available at: {CLI_DOWNLOAD_LINK}");
+        return Err(failure(ExitStatus::Config, "Unsupported config version"));
+    }
+
+    for profile in &config.profiles {
+        for pass in &profile.passes {
+            if let ObfuscationPass::Unknown(unknown) = pass {
+                log::warn!(
+                    "Profile `{}` uses pass `{}` which this CLI does not know about, forwarding it as-is",
+                    profile.name,
+                    unknown.ty
+                );
+            }
+        }
+    }
+    Ok(config)
+}
+
+/// What happens when the config names a function that analysis rejected. Functions whose reject
+/// is force resolvable, or degraded with only safe passes in the profile, are always protected.
+/// See [`AnalysisReject::protectable_with`].
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RejectPolicy {
+    /// Stop with a config error
+    #[default]
+    Fail,
+    /// Leave the function unprotected and log a warning
+    Warn,
+    /// Leave the function unprotected
+    Skip,
+}
+
+impl RejectPolicy {
+    /// Reports a rejected function. Returns an error when the policy is to fail.
+    fn handle(
+        self,
+        what: &str,
+        reject: &AnalysisReject,
+        error: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        match self {
+            RejectPolicy::Fail => {
+                log_reject(what, reject);
+                Err(failure(ExitStatus::Config, error))
+            }
+            RejectPolicy::Warn => {
+                log::warn!(
+                    "{} was rejected during analysis and is left unprotected: {} ({})",
+                    what,
+                    reject.reason,
+                    reject.ty
+                );
+                if let Some(suggestion) = reject.remediation() {
+                    log::warn!("Suggested fix: {}", suggestion);
+                }
+                if let Some(hint) = safe_passes_hint(reject) {
+                    log::warn!("{}", hint);
+                }
+                Ok(())
+            }
+            RejectPolicy::Skip => {
+                log::debug!("Skipping {}, it was rejected: {}", what, reject.reason);
+                Ok(())
+            }
+        }
+    }
+}
+
+// Resolve symbol names to RVA's. If a symbol is specified via RVA
+// then validate it before including it in the result. Rejected
+// functions are kept if `passes` can still protect them (see
+// `reject_allows`) and handled according to `on_reject` otherwise.
+pub fn resolve_symbols(
+    symbols: &[YamlSymbol],
+    analysis: &AnalysisResult,
+    passes: Option<&[&str]>,
+    on_reject: RejectPolicy,
+) -> Result<Vec<u64>, Box<dyn std::error::Error>> {
+    let mut resolved = Vec::new();
+    for symbol in symbols {
+        match symbol {
+            YamlSymbol::Name(name) => {
+                // Search in returned in functions and rejects for symbol by name.
+                // Rejects the passes can still protect are resolved anyway.
+                let rva = analysis
+                    .functions
+                    .iter()
+                    .find(|f| f.symbol == *name)
+                    .map(|e| e.rva)
+                    .or_else(|| {
+                        analysis
+                            .rejects
+                            .iter()
+                            .find(|r| r.symbol == *name && reject_allows(r, passes))
+                            .map(|e| e.rva)
+                    });
+                match rva {
+                    Some(rva) => resolved.push(rva),
+                    None => match analysis.rejects.iter().find(|r| r.symbol == *name) {
+                        Some(reject) => on_reject.handle(
+                            &format!("Symbol `{}`", name),
+                            reject,
+                            "Missing symbol",
+                        )?,
+                        None => {
+                            log::error!("Symbol `{}` not found in analysis result", name);
+                            return Err(failure(ExitStatus::Config, "Missing symbol"));
+                        }
+                    },
+                }
+            }
+            YamlSymbol::Rva(rva) => {
+                if is_valid_rva(*rva, analysis, passes) {
+                    resolved.push(*rva);
+                    continue;
+                }
+                match analysis.rejects.iter().find(|r| r.rva == *rva) {
+                    Some(reject) => {
+                        on_reject.handle(&format!("RVA {:X}", rva), reject, "Invalid RVA")?
+                    }
+                    None => {
+                        log::error!("RVA {:X} not found in analysis", rva);
+                        return Err(failure(ExitStatus::Config, "Invalid RVA"));
+                    }
+                }
+            }
+            YamlSymbol::All => {
+                resolved.extend(
+                    analysis
+                        .functions
+                        .iter()
+                        .map(|e| e.rva)
+                        .collect::<Vec<u64>>(),
+                );
+            }
+            // Data symbols are resolved separately by `resolve_data_symbols`.
+            YamlSymbol::DataName(_) | YamlSymbol::DataRva(_) => {}
+        }
+    }
+    Ok(resolved)
+}
+
+/// Reports a requested function that analysis rejected, with the suggested fix if there is one.
+fn log_reject(what: &str, reject: &AnalysisReject) {
+    log::error!(
+        "{} was rejected during analysis: {} ({})",
+        what,
+        reject.reason,
+        reject.ty
+    );
+    if let Some(suggestion) = reject.remediation() {
+        log::error!("Suggested fix: {}", suggestion);
+    }
+    if let Some(hint) = safe_passes_hint(reject) {
+        log::error!("{}", hint);
+    }
+}
+
+/// Names the passes that could still protect a degraded function, if analysis reported any.
+fn safe_passes_hint(reject: &AnalysisReject) -> Option<String> {
+    let safe = reject
+        .safe_passes
+        .as_ref()
+        .filter(|safe| !safe.is_empty())?;
+    (reject.effective_severity() == RejectSeverity::Degraded).then(|| {
+        format!(
+            "It can still be protected by a profile applying only: {}",
+            safe.join(", ")
+        )
+    })
+}
+
+/// Whether a function analysis rejected can still be protected by the enabled `passes` of its
+/// profile, or by some passes when `None`.
+fn reject_allows(reject: &AnalysisReject, passes: Option<&[&str]>) -> bool {
+    match passes {
+        Some(passes) => reject.protectable_with(passes),
+        None => reject.effective_severity() != RejectSeverity::Fatal,
+    }
+}
+
+// Resolve data symbol names to ranges. Ranges given by RVA are passed through as-is.
+pub fn resolve_data_symbols(
+    symbols: &[YamlSymbol],
+    analysis: &AnalysisResult,
+) -> Result<Vec<DataRange>, Box<dyn std::error::Error>> {
+    let mut resolved = Vec::new();
+    for symbol in symbols {
+        match symbol {
+            YamlSymbol::DataName(name) => {
+                match analysis.data_symbols.iter().find(|d| d.symbol == *name) {
+                    Some(data) => resolved.push(DataRange {
+                        rva: data.rva,
+                        size: data.size,
+                    }),
+                    None => {
+                        log::error!("Data symbol `{}` not found in analysis result", name);
+                        return Err(failure(ExitStatus::Config, "Missing data symbol"));
+                    }
+                }
+            }
+            YamlSymbol::DataRva(range) => resolved.push(*range),
+            _ => {}
+        }
+    }
+    Ok(resolved)
+}
+
+pub fn is_valid_rva(rva: u64, analysis: &AnalysisResult, passes: Option<&[&str]>) -> bool {
+    analysis.functions.iter().any(|f| f.rva == rva)
+        || analysis
+            .rejects
+            .iter()
+            .any(|r| r.rva == rva && reject_allows(r, passes))
+}
+
+fn upload_disassembly_settings(
+    file_id: &str,
+    client: &reqwest::blocking::Client,
+    api_key: &str,
+    config: &YamlConfig,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let settings_bytes = serde_json::to_vec(&config.disassembly_settings)?;
+    let settings_file_name = format!("{}-disasm-settings.json", file_id);
+    retry(|| {
+        api::upload_data(
+            settings_bytes.clone(),
+            settings_file_name.clone(),
+            client,
+            api_key,
+        )
+    })
+    .map_err(api_failure(ExitStatus::Analysis))?;
+
+    // Profiles that override disassembly settings for their own symbols are uploaded separately.
+    let profile_settings = config.profile_disassembly_settings();
+    if !profile_settings.is_empty() {
+        let overrides_bytes = serde_json::to_vec(&profile_settings)?;
+        let overrides_file_name = format!("{}-disasm-overrides.json", file_id);
+        retry(|| {
+            api::upload_data(
+                overrides_bytes.clone(),
+                overrides_file_name.clone(),
+                client,
+                api_key,
+            )
+        })
+        .map_err(api_failure(ExitStatus::Analysis))?;
+    }
+    Ok(())
+}
+
+/// Uploads a preprocessed symbol stream. Returns the id of the upload.
+fn upload_symbols(
+    parsed: &[u8],
+    client: &reqwest::blocking::Client,
+    api_key: &str,
+) -> Result<String, Box<dyn std::error::Error>> {
+    retry(|| api::upload_data(parsed.to_vec(), "debug.pdb".to_owned(), client, api_key))
+        .map_err(api_failure(ExitStatus::Analysis))
+}
+
+/// Reads the functions of the PDB at `path`, reading it as its streams are needed rather than at
+/// once. Warns when the PDB only names its public functions.
+pub fn read_pdb_symbols(
+    path: &Path,
+    symbols: &SymbolOptions,
+) -> Result<Vec<DebugSymbolInfo>, String> {
+    let funcs = parse_pdb_file_impl(path, symbols)
+        .map_err(|e| format!("Failed to preparse PDB file {:?}: {}", path, e))?;
+    let completeness = fs::File::open(path)
+        .map_err(pdb::Error::from)
+        .and_then(|file| pdb::PDB::open(io::BufReader::new(file)))
+        .and_then(|mut pdb| pdb_completeness(&mut pdb));
+    let why = match completeness {
+        Ok(PdbCompleteness::Full) | Err(_) => return Ok(funcs),
+        Ok(PdbCompleteness::FastLink) => {
+            "was linked with /DEBUG:FASTLINK, which leaves the function records in the object files"
+        }
+        Ok(PdbCompleteness::Stripped) => "is a stripped PDB (/PDBSTRIPPED)",
+        Ok(PdbCompleteness::PublicsOnly) => {
+            "has no function records, the objects may have been compiled without /Zi or /Z7"
+        }
+    };
+    log::warn!(
+        "{:?} {}, so only its {} public functions are named, without sizes or noreturn flags. Pass a full PDB, linked with /DEBUG:FULL, to name the others",
+        path,
+        why,
+        funcs.len()
+    );
+    Ok(funcs)
+}
+
+/// Marks the functions `binary_bytes` shows never return, if `symbols` asks for it.
+fn mark_noreturn(binary_bytes: &[u8], funcs: &mut [DebugSymbolInfo], symbols: &SymbolOptions) {
+    if symbols.infer_noreturn {
+        let marked = infer_noreturn(binary_bytes, funcs);
+        log::info!("Inferred {marked} more noreturn function(s) from the binary");
+    }
+}
+
+/// Adds the functions of the static libraries `symbols` names that are found in `binary_bytes`.
+fn add_library_symbols(
+    binary_bytes: &[u8],
+    funcs: &mut Vec<DebugSymbolInfo>,
+    symbols: &SymbolOptions,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if symbols.libraries.is_empty() {
+        return Ok(());
+    }
+    let file = object::read::File::parse(binary_bytes)?;
+    let found = parse_libraries_impl(&file, symbols)
+        .map_err(|e| format!("Failed to read static library {}", e))?;
+    let count = found.len();
+    let added = merge_symbols(funcs, found);
+    log::info!(
+        "Found {} function(s) of the static libraries in the binary, {} of them not named otherwise",
+        count,
+        added
+    );
+    Ok(())
+}
+
+/// Where the symbols of a binary were read from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SymbolSource {
+    Pdb,
+    #[cfg(feature = "dwarf")]
+    Dwarf,
+    Exports,
+    /// Only the static libraries of `--static-lib`.
+    Libraries,
+}
+
+impl SymbolSource {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SymbolSource::Pdb => "pdb",
+            #[cfg(feature = "dwarf")]
+            SymbolSource::Dwarf => "dwarf",
+            SymbolSource::Exports => "exports",
+            SymbolSource::Libraries => "libraries",
+        }
+    }
+}
+
+/// Symbols of a binary and where they were read from.
+pub struct CollectedSymbols {
+    pub source: SymbolSource,
+    pub funcs: Vec<DebugSymbolInfo>,
+}
+
+/// The symbols uploaded with `binary_bytes`: from `pdb_file`, which must match the binary, else
+/// from DWARF debug info, else from the export table, along with the functions of the static
+/// libraries it asks for. `None` when there are none.
+pub fn collect_symbols(
+    binary_bytes: &[u8],
+    pdb_file: Option<&Path>,
+    symbols: &SymbolOptions,
+) -> Result<Option<CollectedSymbols>, Box<dyn std::error::Error>> {
+    let (source, mut funcs) = match pdb_file {
+        Some(path) => {
+            pdb_search::check(binary_bytes, path).map_err(|e| failure(ExitStatus::Error, e))?;
+            (SymbolSource::Pdb, read_pdb_symbols(path, symbols)?)
+        }
+        #[cfg(feature = "dwarf")]
+        None if codedefender_symbols::has_dwarf(binary_bytes) => {
+            let file = object::read::File::parse(binary_bytes)?;
+            let funcs = codedefender_symbols::parse_dwarf_impl(&file, symbols)
+                .map_err(|_| "Failed to preparse DWARF debug info")?;
+            (SymbolSource::Dwarf, funcs)
+        }
+        None => {
+            let funcs = object::read::File::parse(binary_bytes)
+                .ok()
+                .and_then(|file| parse_exports_impl(&file, symbols).ok())
+                .unwrap_or_default();
+            match funcs.is_empty() {
+                true => (SymbolSource::Libraries, funcs),
+                false => (SymbolSource::Exports, funcs),
+            }
+        }
+    };
+    add_library_symbols(binary_bytes, &mut funcs, symbols)?;
+    if source == SymbolSource::Libraries && funcs.is_empty() {
+        return Ok(None);
+    }
+    mark_noreturn(binary_bytes, &mut funcs, symbols);
+    Ok(Some(CollectedSymbols { source, funcs }))
+}
+
+/// Warns about the symbols `config` targets by name that have no code of their own because every
+/// call to them was inlined, which analysis would later report as missing.
+fn warn_inlined(funcs: &[DebugSymbolInfo], config: &YamlConfig) {
+    // The inlined name is qualified but has no signature, the targeted one may have both
+    let is_named = |target: &str, inlinee: &str| {
+        target == inlinee
+            || target
+                .match_indices(&format!("{}(", inlinee))
+                .any(|(at, _)| {
+                    target[..at]
+                        .chars()
+                        .next_back()
+                        .is_none_or(|c| !c.is_alphanumeric() && c != '_' && c != ':')
+                })
+    };
+    for profile in &config.profiles {
+        for symbol in &profile.symbols {
+            let YamlSymbol::Name(name) = symbol else {
+                continue;
+            };
+            if funcs.iter().any(|f| f.name == *name) {
+                continue;
+            }
+            let callers: Vec<&str> = funcs
+                .iter()
+                .filter(|f| f.inlinees.iter().any(|site| is_named(name, &site.name)))
+                .map(|f| f.name.as_str())
+                .collect();
+            if !callers.is_empty() {
+                log::warn!(
+                    "`{}` has no code of its own, it was inlined into {} function(s) ({}). Protect those or mark it __declspec(noinline)",
+                    name,
+                    callers.len(),
+                    callers.join(", ")
+                );
+            }
+        }
+    }
+}
+
+/// Encodes symbols into the stream the service reads.
+fn encode_stream(
+    funcs: &[DebugSymbolInfo],
+    symbols: &SymbolOptions,
+) -> Result<Vec<u8>, &'static str> {
+    codedefender_symbols::encode_with(funcs, &symbols.header(), symbols)
+        .ok_or("Failed to encode the symbols")
+}
+
+/// `symbols` with the newest format up to the requested one that the service reads, as `whoami`
+/// reports it. Services that do not report one are assumed to read the requested format, but not
+/// the stream header, so it is left out.
+pub fn negotiate_symbols(
+    symbols: &SymbolOptions,
+    client: &reqwest::blocking::Client,
+    api_key: &str,
+) -> SymbolOptions {
+    let mut symbols = symbols.clone();
+    // Every service reads v1
+    if symbols.version == StreamVersion::V1 {
+        return symbols;
+    }
+    let supported = match api::whoami(client, api_key) {
+        Ok(whoami) => whoami.symbol_format,
+        Err(e) => {
+            log::debug!(
+                "Could not ask which symbol formats the service reads: {}",
+                e
+            );
+            None
+        }
+    };
+    let Some(supported) = supported else {
+        symbols.headerless = true;
+        return symbols;
+    };
+    let version = symbols
+        .version
+        .negotiate(supported)
+        .unwrap_or(StreamVersion::V1);
+    if version != symbols.version {
+        log::warn!(
+            "The service reads symbol formats up to v{}, uploading v{} instead of v{}",
+            supported,
+            version.number(),
+            symbols.version.number()
+        );
+        symbols.version = version;
+    }
+    symbols
+}
+
+/// [`collect_symbols`], warning about the functions `config` targets by name that were inlined.
+fn collect_targeted_symbols(
+    binary_bytes: &[u8],
+    pdb_file: Option<&Path>,
+    symbols: &SymbolOptions,
+    config: &YamlConfig,
+) -> Result<Option<CollectedSymbols>, Box<dyn std::error::Error>> {
+    let collected = collect_symbols(binary_bytes, pdb_file, symbols)?;
+    if let Some(CollectedSymbols { funcs, .. }) = &collected {
+        warn_inlined(funcs, config);
+    }
+    Ok(collected)
+}
+
+/// Preprocesses and uploads the symbols of `binary_bytes` as [`upload_inputs`] does, with
+/// `symbols` already [negotiated](negotiate_symbols). Returns the id of the upload, `None` when
+/// there are no symbols.
+pub fn upload_binary_symbols(
+    binary_bytes: &[u8],
+    pdb_file: Option<&Path>,
+    symbols: &SymbolOptions,
+    config: &YamlConfig,
+    client: &reqwest::blocking::Client,
+    api_key: &str,
+) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    match collect_targeted_symbols(binary_bytes, pdb_file, symbols, config)? {
+        Some(CollectedSymbols { funcs, .. }) => Ok(Some(upload_symbols(
+            &encode_stream(&funcs, symbols)?,
+            client,
+            api_key,
+        )?)),
+        None => Ok(None),
+    }
+}
+
+/// Uploads a binary and the disassembly settings for it. Returns the file id of the binary.
+pub fn upload_binary(
+    binary_bytes: Vec<u8>,
+    config: &YamlConfig,
+    client: &reqwest::blocking::Client,
+    api_key: &str,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let binary_file_uuid = retry(|| api::upload_file(binary_bytes.clone(), client, api_key))
+        .map_err(api_failure(ExitStatus::Analysis))?;
+    upload_disassembly_settings(&binary_file_uuid, client, api_key, config)?;
+    Ok(binary_file_uuid)
+}
+
+/// What `upload_inputs` uploaded.
+pub struct Upload {
+    pub file_id: String,
+    pub pdb_file_id: Option<String>,
+    /// Bytes sent: the binary and its preprocessed symbols.
+    pub bytes: usize,
+}
+
+/// Uploads the binary, its preprocessed PDB and the disassembly settings. Without a PDB, the
+/// symbols are taken from DWARF debug info or the export table.
+pub fn upload_inputs(
+    input_file: &Path,
+    pdb_file: Option<&Path>,
+    symbols: &SymbolOptions,
+    config: &YamlConfig,
+    client: &reqwest::blocking::Client,
+    api_key: &str,
+) -> Result<Upload, Box<dyn std::error::Error>> {
+    let binary_file_bytes = fs::read(input_file)?;
+    let parsed_symbols =
+        match collect_targeted_symbols(&binary_file_bytes, pdb_file, symbols, config)? {
+            Some(CollectedSymbols { source, funcs }) => {
+                let symbols = &negotiate_symbols(symbols, client, api_key);
+                let label = match (source, pdb_file) {
+                    (SymbolSource::Pdb, Some(path)) => {
+                        let size = fs::metadata(path)?.len() as usize;
+                        format!("Uploading PDB ({})", format_size(size))
+                    }
+                    #[cfg(feature = "dwarf")]
+                    (SymbolSource::Dwarf, _) => "Uploading DWARF symbols".to_owned(),
+                    _ => "Uploading export symbols".to_owned(),
+                };
+                Some((label, encode_stream(&funcs, symbols)?))
+            }
+            None => None,
+        };
+    let mut bytes = binary_file_bytes.len();
+    let pdb_file_id = match parsed_symbols {
+        Some((label, parsed)) => {
+            let progress = Progress::start("upload_pdb", label);
+            let pdb_file_id = upload_symbols(&parsed, client, api_key)?;
+            progress.finish();
+            bytes += parsed.len();
+            Some(pdb_file_id)
+        }
+        None => None,
+    };
+    let progress = Progress::start(
+        "upload",
+        format!(
+            "Uploading binary ({})",
+            format_size(binary_file_bytes.len())
+        ),
+    );
+    let file_id = upload_binary(binary_file_bytes, config, client, api_key)?;
+    let took = progress.finish();
+    log::info!(
+        "Uploaded file(s) and disassembly settings in {}...",
+        format_duration(took)
+    );
+    Ok(Upload {
+        file_id,
+        pdb_file_id,
+        bytes,
+    })
+}
+
+/// Checks an analysis execution once. Returns `None` while it is still running.
+pub fn poll_analysis(
+    analyze_execution_id: &str,
+    client: &reqwest::blocking::Client,
+    api_key: &str,
+) -> Result<Option<AnalysisResult>, Box<dyn std::error::Error>> {
+    let on_error = api_failure(ExitStatus::Analysis);
+    match retry(|| api::get_analyze_status(analyze_execution_id.to_owned(), client, api_key))
+        .map_err(&on_error)?
+    {
+        Status::Ready(url) => {
+            let analysis =
+                retry(|| api::download_analysis_result(&url, client)).map_err(&on_error)?;
+            log::debug!("Analysis info: {:#X?}", analysis);
+            Ok(Some(analysis))
+        }
+        Status::Processing => Ok(None),
+    }
+}
+
+/// Checks an obfuscation execution once. Returns the URL of the artifact once it finished, `None`
+/// while it is still running.
+pub fn poll_output(
+    execution_id: &str,
+    client: &reqwest::blocking::Client,
+    api_key: &str,
+) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    match retry(|| api::download(execution_id.to_owned(), client, api_key))
+        .map_err(api_failure(ExitStatus::Obfuscation))?
+    {
+        Status::Ready(url) => Ok(Some(url)),
+        Status::Processing => Ok(None),
+    }
+}
+
+/// Downloads the artifact of a finished obfuscation from the URL returned by [`poll_output`].
+pub fn download_output(
+    url: &str,
+    client: &reqwest::blocking::Client,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    retry(|| api::download_obfuscated_file(url, client))
+        .map_err(api_failure(ExitStatus::Obfuscation))
+}
+
+/// Starts analysis of an uploaded binary. Returns the id of the analysis execution.
+pub fn start_analysis(
+    file_id: &str,
+    pdb_file_id: Option<String>,
+    client: &reqwest::blocking::Client,
+    api_key: &str,
+) -> Result<String, Box<dyn std::error::Error>> {
+    retry_unsent(|| api::start_analyze(file_id.to_owned(), pdb_file_id.clone(), client, api_key))
+        .map_err(api_failure(ExitStatus::Analysis))
+}
+
+/// Submits `config` for obfuscation of an uploaded binary. Returns the id of the execution.
+pub fn start_obfuscation(
+    file_id: &str,
+    config: Config,
+    client: &reqwest::blocking::Client,
+    api_key: &str,
+) -> Result<String, Box<dyn std::error::Error>> {
+    retry_unsent(|| api::defend(file_id.to_owned(), config.clone(), client, api_key))
+        .map_err(api_failure(ExitStatus::Obfuscation))
+}
+
+/// Polls an analysis execution every `poll_interval` until it finishes or `timeout` passes.
+pub fn wait_for_analysis(
+    analyze_execution_id: &str,
+    client: &reqwest::blocking::Client,
+    api_key: &str,
+    poll_interval: Duration,
+    timeout: Duration,
+) -> Result<AnalysisResult, Box<dyn std::error::Error>> {
+    let start_time = Instant::now();
+    let progress = Progress::start("analysis", "Analyzing");
+    loop {
+        if start_time.elapsed() > timeout {
+            log::error!(
+                "Timeout: analysis exceeded {}s, raise it with --analysis-timeout",
+                timeout.as_secs()
+            );
+            return Err(failure(ExitStatus::Timeout, "Analysis timed out"));
+        }
+        match poll_analysis(analyze_execution_id, client, api_key)? {
+            Some(analysis) => {
+                let took = progress.finish();
+                log::info!("Analysis finished in {}...", format_duration(took));
+                return Ok(analysis);
+            }
+            None => log::debug!("Still Analyzing..."),
+        }
+        std::thread::sleep(poll_interval);
+    }
+}
+
+/// Turns the YAML config into the config submitted for obfuscation by resolving every symbol
+/// against `analysis`.
+pub fn build_config(
+    config: &YamlConfig,
+    analysis: &AnalysisResult,
+    on_reject: RejectPolicy,
+) -> Result<Config, Box<dyn std::error::Error>> {
+    match config.validate_for_analysis(analysis) {
+        Ok(warnings) => {
+            for warning in warnings {
+                log::warn!("{}", warning);
+            }
+        }
+        Err(e) => {
+            log::error!("{}", e);
+            return Err(failure(
+                ExitStatus::Config,
+                "Config does not fit the module environment",
+            ));
+        }
+    }
+    if let Some(module) = &analysis.module {
+        for warning in config.validate_for_module(module) {
+            log::warn!("{}", warning);
+        }
+    }
+
+    log::info!("Constructing config...");
+
+    // Extension fields are for tools working on the config, not for the service.
+    let mut cdconfig = Config {
+        module_settings: ModuleSettings {
+            extensions: Default::default(),
+            ..config.module_settings.clone()
+        },
+        profiles: vec![],
+    };
+
+    if cdconfig.module_settings.fake_pdb_string.enabled {
+        let value = cdconfig.module_settings.fake_pdb_string.resolve();
+        log::info!("Fake PDB string for this build: {}", value);
+    }
+
+    for profile in &config.profiles {
+        let passes: Vec<&str> = profile
+            .passes
+            .iter()
+            .filter(|pass| pass.is_enabled())
+            .map(|pass| pass.name())
+            .collect();
+        let symbols = resolve_symbols(
+            profile.symbols.as_slice(),
+            analysis,
+            Some(&passes),
+            on_reject,
+        )?;
+        let data_symbols = resolve_data_symbols(profile.symbols.as_slice(), analysis)?;
+        if !data_symbols.is_empty()
+            && !profile
+                .passes
+                .iter()
+                .any(|p| p.is_enabled() && p.as_data_protection().is_some())
+        {
+            log::warn!(
+                "Profile `{}` targets data symbols but has no enabled DataProtection pass",
+                profile.name
+            );
+        }
+        cdconfig.profiles.push(Profile {
+            name: profile.name.clone(),
+            passes: profile
+                .passes
+                .iter()
+                .filter(|pass| pass.is_enabled())
+                .cloned()
+                .collect(),
+            compiler_settings: config
+                .resolve_compiler_settings(profile)
+                .map_err(|e| failure(ExitStatus::Config, e.to_string()))?,
+            symbols,
+            data_symbols,
+            priority: profile.priority,
+            seh_safe: profile.seh_safe,
+        });
+    }
+
+    for macro_profile in &analysis.macros {
+        let profile = cdconfig
+            .profiles
+            .iter_mut()
+            .find(|p| p.name == macro_profile.name);
+
+        match profile {
+            Some(p) => {
+                let passes: Vec<&str> = p.passes.iter().map(|pass| pass.name()).collect();
+                for rva in &macro_profile.rvas {
+                    if is_valid_rva(*rva, analysis, Some(&passes)) {
+                        p.symbols.push(*rva);
+                        continue;
+                    }
+                    match analysis.rejects.iter().find(|r| r.rva == *rva) {
+                        Some(reject) => on_reject.handle(
+                            &format!("Macro-decorated function {:X}", rva),
+                            reject,
+                            "Unprotectable macro-decorated function",
+                        )?,
+                        None => {
+                            log::error!("Macro-decorated function {:X} cannot be protected", rva);
+                            return Err(failure(
+                                ExitStatus::Config,
+                                "Unprotectable macro-decorated function",
+                            ));
+                        }
+                    }
+                }
+            }
+            None => {
+                log::error!(
+                    "Macro specifies profile `{}` which is not defined in the config",
+                    macro_profile.name
+                );
+                return Err(failure(ExitStatus::Config, "Undefined macro profile"));
+            }
+        }
+    }
+
+    let overlaps = cdconfig
+        .resolve_profile_overlaps()
+        .map_err(|e| failure(ExitStatus::Config, e.to_string()))?;
+    for overlap in overlaps {
+        log::warn!(
+            "RVA {:X} is targeted by multiple profiles, keeping it in `{}` (dropped from: {})",
+            overlap.rva,
+            overlap.winner,
+            overlap.losers.join(", ")
+        );
+    }
+
+    let mut hot_limits = BTreeMap::new();
+    for hot in &config.hot_functions.functions {
+        for rva in resolve_symbols(std::slice::from_ref(&hot.symbol), analysis, None, on_reject)? {
+            let limit = hot_limits.entry(rva).or_insert(hot.max_overhead);
+            *limit = (*limit).min(hot.max_overhead);
+        }
+    }
+    for adjustment in cdconfig.apply_hot_function_limits(&hot_limits, config.hot_functions.policy) {
+        let action = match &adjustment.moved_to {
+            Some(profile) => format!("moved to `{}`", profile),
+            None if config.hot_functions.policy == HotFunctionPolicy::Warn => "kept".to_owned(),
+            None => "left unprotected".to_owned(),
+        };
+        log::warn!(
+            "Hot function {:X} allows {:?} overhead but profile `{}` uses {}, {}",
+            adjustment.rva,
+            adjustment.max_overhead,
+            adjustment.profile,
+            adjustment.removed_passes.join(", "),
+            action
+        );
+    }
+
+    log::info!("Config summary:\n{}", cdconfig);
+    Ok(cdconfig)
+}
+
+/// Parses a percentage like `60%` or `60`.
+pub fn parse_percent(text: &str) -> Result<f64, String> {
+    let value: f64 = text
+        .trim()
+        .trim_end_matches('%')
+        .parse()
+        .map_err(|_| format!("expected a percentage like `60%`, got {:?}", text))?;
+    if !(0.0..=100.0).contains(&value) {
+        return Err(format!("{} is not between 0% and 100%", text));
+    }
+    Ok(value)
+}
+
+/// Logs how many of the analyzed functions `cdconfig` protects, and fails if that is less than
+/// `min_coverage` percent.
+pub fn check_coverage(
+    cdconfig: &Config,
+    analysis: &AnalysisResult,
+    min_coverage: Option<f64>,
+) -> Result<Coverage, Box<dyn std::error::Error>> {
+    let coverage = cdconfig.coverage(analysis);
+    log::info!("{}", coverage);
+    if let Some(min_coverage) = min_coverage
+        && coverage.percent() < min_coverage
+    {
+        log::error!(
+            "Coverage of {:.1}% is below the required {}%",
+            coverage.percent(),
+            min_coverage
+        );
+        return Err(failure(
+            ExitStatus::Config,
+            "Config protects too little of the binary",
+        ));
+    }
+    Ok(coverage)
+}
+
+/// Polls an obfuscation execution every `poll_interval` and returns the URL of the artifact, see
+/// [`download_output`]. Fails once `timeout` passes.
+pub fn wait_for_output(
+    execution_id: &str,
+    client: &reqwest::blocking::Client,
+    api_key: &str,
+    poll_interval: Duration,
+    timeout: Duration,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let start_time = Instant::now();
+    let progress = Progress::start("obfuscation", "Obfuscating");
+    loop {
+        if start_time.elapsed() > timeout {
+            log::error!(
+                "Timeout: obfuscation exceeded {}s, raise it with --obfuscation-timeout",
+                timeout.as_secs()
+            );
+            return Err(failure(ExitStatus::Timeout, "Obfuscation timed out"));
+        }
+        match poll_output(execution_id, client, api_key)? {
+            Some(url) => {
+                let took = progress.finish();
+                log::info!("Obfuscation finished in {}...", format_duration(took));
+                return Ok(url);
+            }
+            None => log::debug!("Still Obfuscating..."),
+        }
+        std::thread::sleep(poll_interval);
+    }
+}
+
+/// Fails if the artifact exceeds the output constraints of `config`, compared to `input_file`.
+pub fn check_output(
+    config: &YamlConfig,
+    input_file: &Path,
+    artifact: &[u8],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let constraints = &config.module_settings.output_constraints;
+    if constraints.is_empty() {
+        return Ok(());
+    }
+    let input_bytes = fs::read(input_file)?;
+    let violations = output::check_output_constraints(constraints, &input_bytes, artifact)?;
+    if !violations.is_empty() {
+        for violation in &violations {
+            log::error!("Output constraint exceeded: {}", violation);
+        }
+        return Err(failure(
+            ExitStatus::Obfuscation,
+            "Obfuscated binary exceeds the configured output constraints",
+        ));
+    }
+    log::info!("Obfuscated binary is within the configured output constraints");
+    Ok(())
+}
+
+/// Fails if the artifact no longer looks like a loadable image of `input_file`, see
+/// [`output::verify_output`].
+pub fn verify_output(
+    config: &YamlConfig,
+    input_file: &Path,
+    artifact: &[u8],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let input_bytes = fs::read(input_file)?;
+    let problems = output::verify_output(&config.module_settings, &input_bytes, artifact)?;
+    if !problems.is_empty() {
+        for problem in &problems {
+            log::error!("Verification failed: {}", problem);
+        }
+        return Err(failure(
+            ExitStatus::Obfuscation,
+            "Obfuscated binary failed verification",
+        ));
+    }
+    log::info!("Obfuscated binary passed verification");
+    Ok(())
+}
+
+/// Uploads the binary inside the artifact and analyzes it, to confirm the obfuscated output still
+/// disassembles.
+pub fn reanalyze_output(
+    artifact: &[u8],
+    config: &YamlConfig,
+    client: &reqwest::blocking::Client,
+    api_key: &str,
+    poll_interval: Duration,
+    timeout: Duration,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let pe = output::extract_pe(artifact).ok_or("No PE image found in the downloaded artifact")?;
+    let file_id = upload_binary(pe, config, client, api_key)?;
+    let analyze_execution_id = start_analysis(&file_id, None, client, api_key)?;
+    wait_for_analysis(
+        &analyze_execution_id,
+        client,
+        api_key,
+        poll_interval,
+        timeout,
+    )
+    .map_err(|e| match exit::status_of(&*e) {
+        ExitStatus::Analysis => failure(
+            ExitStatus::Obfuscation,
+            format!("Obfuscated binary could not be analyzed again: {}", e),
+        ),
+        _ => e,
+    })?;
+    log::info!("Obfuscated binary was analyzed again successfully");
+    Ok(())
+}
@@ -0,0 +1,176 @@
+//! Progress display for long running stages: a spinner with elapsed time and ETA on a terminal,
+//! periodic log lines otherwise.
+
+use crate::cache::Cache;
+use codedefender_api::serde_json;
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{IsTerminal, Write},
+    path::PathBuf,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+const FRAMES: [char; 4] = ['|', '/', '-', '\\'];
+const TICK: Duration = Duration::from_millis(120);
+/// How often a stage is logged when stderr is not a terminal.
+const LOG_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Width of the progress line currently on screen, 0 when none is drawn.
+static DRAWN: Mutex<usize> = Mutex::new(0);
+
+/// Erases the progress line so a log line can be printed in its place. It is redrawn on the next
+/// tick.
+pub fn clear_line() {
+    let Ok(mut drawn) = DRAWN.lock() else {
+        return;
+    };
+    if *drawn > 0 {
+        eprint!("\r{}\r", " ".repeat(*drawn));
+        *drawn = 0;
+    }
+}
+
+fn draw(line: &str) {
+    let Ok(mut drawn) = DRAWN.lock() else {
+        return;
+    };
+    let width = line.chars().count();
+    eprint!("\r{}{}", line, " ".repeat(drawn.saturating_sub(width)));
+    let _ = std::io::stderr().flush();
+    *drawn = width;
+}
+
+/// Formats a duration as `1m05s` or `12s`.
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    if secs >= 60 {
+        format!("{}m{:02}s", secs / 60, secs % 60)
+    } else {
+        format!("{}s", secs)
+    }
+}
+
+/// Formats a byte count as `12.3 MiB`.
+pub fn format_size(bytes: usize) -> String {
+    let mut size = bytes as f64;
+    for unit in ["B", "KiB", "MiB"] {
+        if size < 1024.0 {
+            return format!("{:.1} {}", size, unit);
+        }
+        size /= 1024.0;
+    }
+    format!("{:.1} GiB", size)
+}
+
+fn history_path() -> Option<PathBuf> {
+    Some(Cache::default_dir()?.join("stage-times.json"))
+}
+
+fn read_history() -> BTreeMap<String, f64> {
+    history_path()
+        .and_then(|path| fs::read(path).ok())
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default()
+}
+
+/// Remembers how long `stage` took, as a moving average over previous runs, for the ETA.
+fn record(stage: &str, took: Duration) {
+    let Some(path) = history_path() else {
+        return;
+    };
+    let mut history = read_history();
+    let secs = took.as_secs_f64();
+    let average = history
+        .get(stage)
+        .map_or(secs, |previous| previous * 0.7 + secs * 0.3);
+    history.insert(stage.to_owned(), average);
+    if let Some(parent) = path.parent() {
+        let _ = fs::create_dir_all(parent);
+    }
+    if let Ok(bytes) = serde_json::to_vec(&history) {
+        let _ = fs::write(path, bytes);
+    }
+}
+
+/// A running stage. Stops displaying when finished or dropped.
+pub struct Progress {
+    stage: &'static str,
+    start: Instant,
+    stop: Arc<AtomicBool>,
+    ticker: Option<JoinHandle<()>>,
+}
+
+impl Progress {
+    /// Starts showing `label` for `stage`. Nothing is shown when info logging is disabled.
+    pub fn start(stage: &'static str, label: impl Into<String>) -> Self {
+        let label = label.into();
+        let start = Instant::now();
+        let stop = Arc::new(AtomicBool::new(false));
+        let expected = read_history()
+            .get(stage)
+            .map(|secs| Duration::from_secs_f64(*secs));
+
+        let ticker = (log::max_level() >= log::LevelFilter::Info).then(|| {
+            let stop = stop.clone();
+            let tty = std::io::stderr().is_terminal();
+            std::thread::spawn(move || {
+                let mut frame = 0;
+                let mut last_log = Instant::now();
+                while !stop.load(Ordering::Relaxed) {
+                    let elapsed = start.elapsed();
+                    let eta = match expected {
+                        Some(expected) if expected > elapsed => {
+                            format!(", about {} left", format_duration(expected - elapsed))
+                        }
+                        Some(_) => ", taking longer than usual".to_owned(),
+                        None => String::new(),
+                    };
+                    if tty {
+                        draw(&format!(
+                            "{} {} ({}{})",
+                            FRAMES[frame % FRAMES.len()],
+                            label,
+                            format_duration(elapsed),
+                            eta
+                        ));
+                        frame += 1;
+                    } else if last_log.elapsed() >= LOG_INTERVAL {
+                        log::info!("{} ({} elapsed{})", label, format_duration(elapsed), eta);
+                        last_log = Instant::now();
+                    }
+                    std::thread::sleep(TICK);
+                }
+                clear_line();
+            })
+        });
+
+        Self {
+            stage,
+            start,
+            stop,
+            ticker,
+        }
+    }
+
+    /// Stops the display and records the duration of the stage for future ETAs.
+    pub fn finish(self) -> Duration {
+        let took = self.start.elapsed();
+        record(self.stage, took);
+        took
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(ticker) = self.ticker.take() {
+            let _ = ticker.join();
+        }
+    }
+}
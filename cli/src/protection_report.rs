@@ -0,0 +1,412 @@
+//! `--report`, a self-contained HTML or Markdown record of a run for compliance and pen-test
+//! documentation: what was protected and how, what analysis rejected, and what came out.
+
+use crate::{manifest::Manifest, progress::format_size, report::RunResult};
+use codedefender_api::codedefender_config::{AnalysisResult, Config};
+use codedefender_api::serde_json::{self, Value};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fs,
+    path::Path,
+};
+
+enum Block {
+    /// Name and value pairs.
+    Fields(Vec<(String, String)>),
+    Table {
+        header: &'static [&'static str],
+        rows: Vec<Vec<String>>,
+    },
+    Text(String),
+}
+
+struct Section {
+    title: &'static str,
+    blocks: Vec<Block>,
+}
+
+/// Everything known about a finished run.
+pub struct ProtectionReport<'a> {
+    pub manifest: &'a Manifest,
+    /// The config submitted for obfuscation. Missing when a resumed run skipped analysis, like
+    /// `analysis`.
+    pub config: Option<&'a Config>,
+    pub analysis: Option<&'a AnalysisResult>,
+    pub result: &'a RunResult,
+}
+
+/// Flattens nested objects into `parent.child` keys.
+fn flatten(prefix: &str, value: &Value, out: &mut Vec<(String, String)>) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                let key = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", prefix, key)
+                };
+                flatten(&key, value, out);
+            }
+        }
+        Value::String(text) => out.push((prefix.to_owned(), text.clone())),
+        other => out.push((prefix.to_owned(), other.to_string())),
+    }
+}
+
+impl ProtectionReport<'_> {
+    fn run_section(&self) -> Section {
+        let manifest = self.manifest;
+        let mut fields = vec![
+            (
+                "Input".to_owned(),
+                manifest.input.path.display().to_string(),
+            ),
+            (
+                "Config".to_owned(),
+                format!(
+                    "{} (version {})",
+                    manifest.config.path.display(),
+                    manifest.config_version
+                ),
+            ),
+            ("CLI version".to_owned(), manifest.cli_version.to_owned()),
+            ("Started".to_owned(), manifest.started_at.clone()),
+            ("Finished".to_owned(), manifest.finished_at.clone()),
+        ];
+        if let Some(file_id) = &manifest.file_id {
+            fields.push(("File id".to_owned(), file_id.clone()));
+        }
+        fields.push(("Execution id".to_owned(), manifest.execution_id.clone()));
+        if let Some(analysis) = &manifest.analysis {
+            fields.push((
+                "Environment".to_owned(),
+                format!("{:?}", analysis.environment),
+            ));
+            fields.push((
+                "Functions analyzed".to_owned(),
+                analysis.functions.to_string(),
+            ));
+        }
+        if let Some(coverage) = self.result.coverage_percent {
+            fields.push(("Coverage".to_owned(), format!("{:.1}%", coverage)));
+        }
+        Section {
+            title: "Run",
+            blocks: vec![Block::Fields(fields)],
+        }
+    }
+
+    /// Profiles, the functions each pass protects, and the protected functions by name.
+    fn protection_sections(&self, config: &Config) -> Vec<Section> {
+        let names: HashMap<u64, &str> = self
+            .analysis
+            .map(|analysis| {
+                analysis
+                    .functions
+                    .iter()
+                    .map(|f| (f.rva, f.symbol.as_str()))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let name = |rva: &u64| names.get(rva).copied().unwrap_or("").to_owned();
+
+        let mut profiles = Vec::new();
+        let mut passes: BTreeMap<&str, (BTreeSet<&str>, BTreeSet<u64>)> = BTreeMap::new();
+        let mut functions = Vec::new();
+        for profile in &config.profiles {
+            let enabled: Vec<&str> = profile
+                .passes
+                .iter()
+                .filter(|pass| pass.is_enabled())
+                .map(|pass| pass.name())
+                .collect();
+            for pass in &enabled {
+                let (pass_profiles, pass_functions) = passes.entry(pass).or_default();
+                pass_profiles.insert(profile.name.as_str());
+                pass_functions.extend(&profile.symbols);
+            }
+            profiles.push(vec![
+                profile.name.clone(),
+                enabled.join(", "),
+                profile.symbols.len().to_string(),
+                profile.data_symbols.len().to_string(),
+                profile.priority.to_string(),
+                if profile.seh_safe { "yes" } else { "no" }.to_owned(),
+            ]);
+            for rva in &profile.symbols {
+                functions.push(vec![name(rva), format!("{:#x}", rva), profile.name.clone()]);
+            }
+        }
+        let passes = passes
+            .into_iter()
+            .map(|(pass, (pass_profiles, pass_functions))| {
+                vec![
+                    pass.to_owned(),
+                    pass_profiles.into_iter().collect::<Vec<_>>().join(", "),
+                    pass_functions.len().to_string(),
+                ]
+            })
+            .collect();
+        vec![
+            Section {
+                title: "Profiles",
+                blocks: vec![Block::Table {
+                    header: &[
+                        "Profile",
+                        "Passes",
+                        "Functions",
+                        "Data ranges",
+                        "Priority",
+                        "SEH safe",
+                    ],
+                    rows: profiles,
+                }],
+            },
+            Section {
+                title: "Passes",
+                blocks: vec![Block::Table {
+                    header: &["Pass", "Profiles", "Functions"],
+                    rows: passes,
+                }],
+            },
+            Section {
+                title: "Protected functions",
+                blocks: vec![Block::Table {
+                    header: &["Function", "RVA", "Profile"],
+                    rows: functions,
+                }],
+            },
+        ]
+    }
+
+    fn rejects_section(&self, analysis: &AnalysisResult) -> Section {
+        let block = if analysis.rejects.is_empty() {
+            Block::Text("Analysis rejected no functions.".to_owned())
+        } else {
+            Block::Table {
+                header: &["Function", "RVA", "Reason", "Suggested fix"],
+                rows: analysis
+                    .rejects
+                    .iter()
+                    .map(|reject| {
+                        vec![
+                            reject.symbol.clone(),
+                            format!("{:#x}", reject.rva),
+                            reject.reason.clone(),
+                            reject.remediation().unwrap_or_default().to_owned(),
+                        ]
+                    })
+                    .collect(),
+            }
+        };
+        Section {
+            title: "Rejected functions",
+            blocks: vec![block],
+        }
+    }
+
+    fn module_settings_section(config: &Config) -> Section {
+        let mut fields = Vec::new();
+        if let Ok(value) = serde_json::to_value(&config.module_settings) {
+            flatten("", &value, &mut fields);
+        }
+        Section {
+            title: "Module settings",
+            blocks: vec![Block::Fields(fields)],
+        }
+    }
+
+    fn files_section(&self) -> Section {
+        let manifest = self.manifest;
+        let mut rows = vec![vec![
+            "Input".to_owned(),
+            manifest.input.path.display().to_string(),
+            manifest.input.sha256.clone(),
+        ]];
+        if let Some(pdb) = &manifest.pdb {
+            rows.push(vec![
+                "PDB".to_owned(),
+                pdb.path.display().to_string(),
+                pdb.sha256.clone(),
+            ]);
+        }
+        rows.push(vec![
+            "Config".to_owned(),
+            manifest.config.path.display().to_string(),
+            manifest.config.sha256.clone(),
+        ]);
+        for output in &manifest.outputs {
+            rows.push(vec![
+                "Output".to_owned(),
+                output.path.display().to_string(),
+                output.sha256.clone(),
+            ]);
+        }
+        Section {
+            title: "Files",
+            blocks: vec![Block::Table {
+                header: &["Role", "Path", "SHA-256"],
+                rows,
+            }],
+        }
+    }
+
+    fn timings_section(&self) -> Section {
+        let mut rows: Vec<Vec<String>> = self
+            .result
+            .stages()
+            .into_iter()
+            .map(|(stage, secs)| {
+                vec![
+                    stage.clone(),
+                    format!("{:.1}s", secs),
+                    self.result
+                        .bytes
+                        .get(stage)
+                        .map(|bytes| format_size(*bytes))
+                        .unwrap_or_default(),
+                ]
+            })
+            .collect();
+        rows.push(vec![
+            "total".to_owned(),
+            format!("{:.1}s", self.result.timings.values().sum::<f64>()),
+            String::new(),
+        ]);
+        Section {
+            title: "Timings",
+            blocks: vec![Block::Table {
+                header: &["Stage", "Time", "Transferred"],
+                rows,
+            }],
+        }
+    }
+
+    fn sections(&self) -> Vec<Section> {
+        let mut sections = vec![self.run_section()];
+        match self.config {
+            Some(config) => {
+                sections.extend(self.protection_sections(config));
+                if let Some(analysis) = self.analysis {
+                    sections.push(self.rejects_section(analysis));
+                }
+                sections.push(Self::module_settings_section(config));
+            }
+            None => sections.push(Section {
+                title: "Profiles",
+                blocks: vec![Block::Text(
+                    "This run was resumed after submission, so the profiles and rejects of its \
+                     analysis are not known."
+                        .to_owned(),
+                )],
+            }),
+        }
+        sections.push(self.files_section());
+        sections.push(self.timings_section());
+        sections
+    }
+
+    fn title(&self) -> String {
+        let input = &self.manifest.input.path;
+        let name = input.file_name().unwrap_or(input.as_os_str());
+        format!("CodeDefender protection report: {}", name.to_string_lossy())
+    }
+
+    pub fn markdown(&self) -> String {
+        let cell = |text: &str| text.replace('|', "\\|").replace('\n', " ");
+        let mut out = format!("# {}\n", self.title());
+        for section in self.sections() {
+            out.push_str(&format!("\n## {}\n\n", section.title));
+            for block in section.blocks {
+                match block {
+                    Block::Fields(fields) => {
+                        for (name, value) in fields {
+                            out.push_str(&format!("- **{}**: {}\n", name, cell(&value)));
+                        }
+                    }
+                    Block::Table { header, rows } => {
+                        out.push_str(&format!("| {} |\n", header.join(" | ")));
+                        out.push_str(&format!("|{}\n", "---|".repeat(header.len())));
+                        for row in rows {
+                            let row: Vec<String> = row.iter().map(|c| cell(c)).collect();
+                            out.push_str(&format!("| {} |\n", row.join(" | ")));
+                        }
+                    }
+                    Block::Text(text) => out.push_str(&format!("{}\n", text)),
+                }
+            }
+        }
+        out
+    }
+
+    pub fn html(&self) -> String {
+        let escape = |text: &str| {
+            text.replace('&', "&amp;")
+                .replace('<', "&lt;")
+                .replace('>', "&gt;")
+                .replace('"', "&quot;")
+        };
+        let title = escape(&self.title());
+        let mut out = format!(
+            "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n\
+             <style>\nbody {{ font-family: system-ui, sans-serif; margin: 2em auto; max-width: 70em; color: #222; }}\n\
+             table {{ border-collapse: collapse; margin: 0.5em 0 1.5em; }}\n\
+             th, td {{ border: 1px solid #ccc; padding: 0.3em 0.6em; text-align: left; vertical-align: top; }}\n\
+             th {{ background: #f0f0f0; }}\n\
+             td {{ font-family: ui-monospace, monospace; font-size: 0.9em; }}\n\
+             </style>\n</head>\n<body>\n<h1>{}</h1>\n",
+            title, title
+        );
+        for section in self.sections() {
+            out.push_str(&format!("<h2>{}</h2>\n", escape(section.title)));
+            for block in section.blocks {
+                match block {
+                    Block::Fields(fields) => {
+                        out.push_str("<table>\n");
+                        for (name, value) in fields {
+                            out.push_str(&format!(
+                                "<tr><th>{}</th><td>{}</td></tr>\n",
+                                escape(&name),
+                                escape(&value)
+                            ));
+                        }
+                        out.push_str("</table>\n");
+                    }
+                    Block::Table { header, rows } => {
+                        out.push_str("<table>\n<tr>");
+                        for name in header {
+                            out.push_str(&format!("<th>{}</th>", escape(name)));
+                        }
+                        out.push_str("</tr>\n");
+                        for row in rows {
+                            out.push_str("<tr>");
+                            for value in row {
+                                out.push_str(&format!("<td>{}</td>", escape(&value)));
+                            }
+                            out.push_str("</tr>\n");
+                        }
+                        out.push_str("</table>\n");
+                    }
+                    Block::Text(text) => out.push_str(&format!("<p>{}</p>\n", escape(&text))),
+                }
+            }
+        }
+        out.push_str("</body>\n</html>\n");
+        out
+    }
+
+    /// Writes Markdown for a `.md` or `.markdown` path and HTML otherwise.
+    pub fn write(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let markdown = path.extension().is_some_and(|ext| {
+            ext.eq_ignore_ascii_case("md") || ext.eq_ignore_ascii_case("markdown")
+        });
+        let contents = if markdown {
+            self.markdown()
+        } else {
+            self.html()
+        };
+        fs::write(path, contents)?;
+        log::info!("Protection report written to {:?}", path);
+        Ok(())
+    }
+}
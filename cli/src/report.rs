@@ -0,0 +1,271 @@
+use crate::ci::{self, Finding, Severity};
+use crate::exit::{self, ExitStatus};
+use crate::log_file::RotatingFile;
+use crate::progress::{format_duration, format_size};
+use clap::ValueEnum;
+use codedefender_api::serde_json::{self, Value, json};
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    io::Write,
+    path::PathBuf,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// How the CLI reports progress and results on stdout.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Human readable log lines only.
+    #[default]
+    Text,
+    /// One JSON event per line, ending with a `result` event.
+    Json,
+}
+
+/// How log lines are written to stderr and `--log-file`.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// `[timestamp LEVEL target] message`, colored in a terminal.
+    #[default]
+    Text,
+    /// One JSON object per line with `timestamp`, `level`, `target` and `message`.
+    Json,
+}
+
+/// Order stages are listed in by [`RunResult::summary`], the order they run in.
+const STAGE_ORDER: [&str; 6] = [
+    "upload",
+    "analysis",
+    "obfuscation",
+    "download",
+    "verification",
+    "signing",
+];
+
+/// Warnings logged during the run, collected for the final result.
+static WARNINGS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Forwards to env_logger while remembering every warning.
+struct CollectingLogger {
+    inner: env_logger::Logger,
+    /// Same records written to `--log-file`.
+    file: Option<env_logger::Logger>,
+}
+
+impl log::Log for CollectingLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &log::Record) {
+        if record.level() == log::Level::Warn
+            && let Ok(mut warnings) = WARNINGS.lock()
+        {
+            warnings.push(record.args().to_string());
+        }
+        if self.inner.enabled(record.metadata()) {
+            crate::progress::clear_line();
+            let severity = match record.level() {
+                log::Level::Error => Some(Severity::Error),
+                log::Level::Warn => Some(Severity::Warning),
+                _ => None,
+            };
+            if let Some(severity) = severity
+                && ci::is_enabled()
+            {
+                ci::annotate(Finding {
+                    severity,
+                    message: record.args().to_string(),
+                    file: None,
+                    position: None,
+                });
+            }
+        }
+        self.inner.log(record);
+        if let Some(file) = &self.file {
+            file.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+        if let Some(file) = &self.file {
+            file.flush();
+        }
+    }
+}
+
+fn json_line(buf: &mut env_logger::fmt::Formatter, record: &log::Record) -> std::io::Result<()> {
+    let line = json!({
+        "timestamp": buf.timestamp_millis().to_string(),
+        "level": record.level().as_str(),
+        "target": record.target(),
+        "message": record.args().to_string(),
+    });
+    writeln!(buf, "{}", line)
+}
+
+fn builder(level: log::LevelFilter, format: LogFormat) -> env_logger::Builder {
+    let mut builder = env_logger::Builder::new();
+    builder.filter_level(level);
+    if format == LogFormat::Json {
+        builder.format(json_line);
+    }
+    builder
+}
+
+/// Installs the logger. Logs always go to stderr so stdout stays parseable in JSON mode, and to
+/// `file` when given.
+pub fn init_logger(level: log::LevelFilter, format: LogFormat, file: Option<RotatingFile>) {
+    let inner = builder(level, format).build();
+    let file = file.map(|file| {
+        builder(level, format)
+            .target(env_logger::Target::Pipe(Box::new(file)))
+            .build()
+    });
+    let max_level = inner.filter();
+    log::set_boxed_logger(Box::new(CollectingLogger { inner, file }))
+        .expect("logger is only installed once");
+    log::set_max_level(max_level);
+}
+
+/// Final result of a CLI invocation.
+#[derive(Serialize, Default, Debug)]
+pub struct RunResult {
+    pub success: bool,
+    /// Process exit code, see [`crate::exit::ExitStatus`].
+    pub exit_code: u8,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub execution_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<PathBuf>,
+    /// Files unpacked by `--extract-to`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub artifacts: Vec<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub input_sha256: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output_sha256: Option<String>,
+    /// Share of the analyzed functions the resolved config protects, in percent.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub coverage_percent: Option<f64>,
+    /// Seconds spent in each stage.
+    pub timings: BTreeMap<String, f64>,
+    /// Bytes transferred in each stage, `upload` and `download`.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub bytes: BTreeMap<String, usize>,
+    pub warnings: Vec<String>,
+}
+
+impl RunResult {
+    /// The timed stages in the order they run.
+    pub fn stages(&self) -> Vec<(&String, &f64)> {
+        let mut stages: Vec<(&String, &f64)> = self.timings.iter().collect();
+        stages.sort_by_key(|(stage, _)| {
+            STAGE_ORDER
+                .iter()
+                .position(|s| s == stage)
+                .unwrap_or(usize::MAX)
+        });
+        stages
+    }
+
+    /// One line with the time spent in each stage and the bytes it transferred, like
+    /// `upload 2.1s (1.2 MiB), analysis 41.0s, total 43.1s`. `None` when no stage ran.
+    pub fn summary(&self) -> Option<String> {
+        if self.timings.is_empty() {
+            return None;
+        }
+        let seconds = |secs: f64| {
+            if secs < 60.0 {
+                format!("{:.1}s", secs)
+            } else {
+                format_duration(Duration::from_secs_f64(secs))
+            }
+        };
+        let mut parts: Vec<String> = self
+            .stages()
+            .into_iter()
+            .map(|(stage, secs)| match self.bytes.get(stage) {
+                Some(bytes) => format!("{} {} ({})", stage, seconds(*secs), format_size(*bytes)),
+                None => format!("{} {}", stage, seconds(*secs)),
+            })
+            .collect();
+        parts.push(format!("total {}", seconds(self.timings.values().sum())));
+        Some(parts.join(", "))
+    }
+}
+
+/// Emits structured events and collects the final [`RunResult`].
+pub struct Reporter {
+    format: OutputFormat,
+    stage_start: Instant,
+    pub result: RunResult,
+    /// The artifact is written to stdout, so nothing else may be printed there.
+    pub stdout_taken: bool,
+}
+
+impl Reporter {
+    pub fn new(format: OutputFormat) -> Self {
+        Self {
+            format,
+            stage_start: Instant::now(),
+            result: RunResult::default(),
+            stdout_taken: false,
+        }
+    }
+
+    pub fn is_json(&self) -> bool {
+        self.format == OutputFormat::Json
+    }
+
+    /// Whether plain results such as ids and paths are printed on stdout.
+    pub fn prints_results(&self) -> bool {
+        !self.is_json() && !self.stdout_taken
+    }
+
+    /// Prints `{"event": <event>, ...fields}` in JSON mode. Does nothing in text mode.
+    pub fn event(&self, event: &str, fields: Value) {
+        if !self.is_json() {
+            return;
+        }
+        let mut object = json!({ "event": event });
+        if let (Some(object), Value::Object(fields)) = (object.as_object_mut(), fields) {
+            object.extend(fields);
+        }
+        println!("{}", object);
+    }
+
+    /// Records the time since the previous stage ended and emits a `stage` event.
+    pub fn stage_done(&mut self, stage: &str) {
+        let seconds = self.stage_start.elapsed().as_secs_f64();
+        self.stage_start = Instant::now();
+        self.result.timings.insert(stage.to_owned(), seconds);
+        self.event("stage", json!({ "stage": stage, "seconds": seconds }));
+    }
+
+    /// Writes the CI step outputs and prints the final `result` event in JSON mode.
+    pub fn finish(mut self, outcome: &Result<(), Box<dyn std::error::Error>>) {
+        self.result.success = outcome.is_ok();
+        self.result.exit_code = match outcome {
+            Ok(()) => ExitStatus::Success as u8,
+            Err(e) => exit::status_of(&**e) as u8,
+        };
+        self.result.error = outcome.as_ref().err().map(|e| e.to_string());
+        self.result.warnings = WARNINGS.lock().map(|w| w.clone()).unwrap_or_default();
+        ci::finish(&self.result);
+        if !self.is_json() {
+            if let Some(summary) = self.result.summary() {
+                log::info!("Summary: {}", summary);
+            }
+            return;
+        }
+        let result = serde_json::to_value(&self.result).unwrap_or_default();
+        self.event("result", result);
+    }
+}
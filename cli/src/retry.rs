@@ -0,0 +1,83 @@
+//! Retrying of API calls that failed for transient reasons.
+
+use codedefender_api::ApiError;
+use std::{sync::OnceLock, time::Duration};
+
+/// Longest wait between two attempts.
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// How often and how patiently transient failures are retried.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt.
+    pub retries: u32,
+    /// Wait before the first retry, doubled for every further one.
+    pub backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            retries: 3,
+            backoff: Duration::from_secs(1),
+        }
+    }
+}
+
+static POLICY: OnceLock<RetryPolicy> = OnceLock::new();
+
+/// Sets the policy used by [`retry`] for the rest of the process.
+pub fn configure(policy: RetryPolicy) {
+    let _ = POLICY.set(policy);
+}
+
+/// Calls `f` until it succeeds, fails with an error that is not transient, or the retries run out.
+///
+/// Only for idempotent calls, such as status checks, downloads and uploads. Calls that start an
+/// execution use [`retry_unsent`].
+pub fn retry<T>(f: impl FnMut() -> Result<T, ApiError>) -> Result<T, ApiError> {
+    retry_if(f, ApiError::is_transient)
+}
+
+/// Same as [`retry`], for calls that are not idempotent, such as starting an execution. They are
+/// only retried when the request never reached the service, as retrying one whose response was lost
+/// would start a second execution.
+pub fn retry_unsent<T>(f: impl FnMut() -> Result<T, ApiError>) -> Result<T, ApiError> {
+    retry_if(f, ApiError::is_unsent)
+}
+
+/// Calls `f` until it succeeds, fails with an error that is not `retryable`, or the retries run
+/// out.
+fn retry_if<T>(
+    mut f: impl FnMut() -> Result<T, ApiError>,
+    retryable: fn(&ApiError) -> bool,
+) -> Result<T, ApiError> {
+    let policy = POLICY.get().copied().unwrap_or_default();
+    let mut delay = policy.backoff;
+    let mut retries = 0;
+    loop {
+        match f() {
+            Ok(value) => return Ok(value),
+            Err(e) if retryable(&e) && retries < policy.retries => {
+                retries += 1;
+                log::warn!(
+                    "{}, retrying in {:.1}s ({}/{})",
+                    e,
+                    delay.as_secs_f64(),
+                    retries,
+                    policy.retries
+                );
+                std::thread::sleep(delay);
+                delay = (delay * 2).min(MAX_BACKOFF);
+            }
+            Err(e) if retryable(&e) => {
+                log::error!("Giving up after {} retries", retries);
+                return Err(e);
+            }
+            Err(e) => {
+                log::error!("Request failed with an error that is not retryable");
+                return Err(e);
+            }
+        }
+    }
+}
@@ -0,0 +1,320 @@
+//! Default flags from `~/.codedefender/config.toml` and the `.codedefender/config.toml` of the
+//! repository the CLI runs in.
+//!
+//! Each setting stands in for an environment variable, so flags and variables that are set win
+//! over the files, and the repository file wins over the user file:
+//!
+//! ```toml
+//! [api]
+//! endpoint = "https://app.codedefender.io"   # BASE_URL
+//! proxy = "http://proxy.internal:3128"       # HTTPS_PROXY and HTTP_PROXY
+//! account = "prod"                           # --account
+//! retries = 5                                # --retries
+//! retry_backoff_ms = 2000                    # --retry-backoff
+//! poll_interval_ms = 1000                    # --timeout
+//!
+//! [timeouts]
+//! analysis_secs = 600                        # --analysis-timeout
+//! obfuscation_secs = 900                     # --obfuscation-timeout
+//!
+//! [cache]
+//! dir = "D:/codedefender-cache"              # CODEDEFENDER_CACHE_DIR
+//!
+//! [symbols]
+//! server = "https://symbols.example.com"     # --symbol-server
+//! ```
+//!
+//! Only the part of TOML needed for this is understood: tables, and string and integer values.
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+/// Name of the settings file inside a `.codedefender` directory.
+const FILE_NAME: &str = "config.toml";
+
+/// `(table, key, environment variables set from it, whether the value is a number)`.
+const SETTINGS: &[(&str, &str, &[&str], bool)] = &[
+    ("api", "endpoint", &["BASE_URL"], false),
+    ("api", "proxy", &["HTTPS_PROXY", "HTTP_PROXY"], false),
+    ("api", "account", &["CD_ACCOUNT"], false),
+    ("api", "retries", &["CD_RETRIES"], true),
+    ("api", "retry_backoff_ms", &["CD_RETRY_BACKOFF"], true),
+    ("api", "poll_interval_ms", &["CD_POLL_INTERVAL"], true),
+    ("timeouts", "analysis_secs", &["CD_ANALYSIS_TIMEOUT"], true),
+    (
+        "timeouts",
+        "obfuscation_secs",
+        &["CD_OBFUSCATION_TIMEOUT"],
+        true,
+    ),
+    ("cache", "dir", &["CODEDEFENDER_CACHE_DIR"], false),
+    ("symbols", "server", &["CD_SYMBOL_SERVER"], false),
+];
+
+/// Settings read from one file, as environment variable assignments.
+#[derive(Debug, Default)]
+pub struct Settings {
+    pub values: Vec<(&'static [&'static str], String)>,
+}
+
+/// Parses a TOML string, `"basic"` or `'literal'`. Returns the value and the rest of the line.
+fn parse_string(text: &str) -> Result<(String, &str), String> {
+    let quote = text.chars().next().unwrap_or_default();
+    let mut value = String::new();
+    let mut chars = text[1..].char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            c if c == quote => return Ok((value, &text[i + 2..])),
+            '\\' if quote == '"' => match chars.next().map(|(_, c)| c) {
+                Some('n') => value.push('\n'),
+                Some('t') => value.push('\t'),
+                Some(c @ ('"' | '\\')) => value.push(c),
+                other => return Err(format!("unsupported escape `\\{}`", other.unwrap_or(' '))),
+            },
+            c => value.push(c),
+        }
+    }
+    Err("unterminated string".to_owned())
+}
+
+/// Parses the value of a `key = value` line, without its trailing comment.
+fn parse_value(text: &str) -> Result<String, String> {
+    let (value, rest) = if text.starts_with(['"', '\'']) {
+        parse_string(text)?
+    } else {
+        let end = text.find('#').unwrap_or(text.len());
+        let value = text[..end].trim();
+        if value.replace('_', "").parse::<u64>().is_err() {
+            return Err(format!("expected a string or integer, got `{}`", value));
+        }
+        (value.replace('_', ""), &text[end..])
+    };
+    let rest = rest.trim_start();
+    if !rest.is_empty() && !rest.starts_with('#') {
+        return Err(format!("unexpected `{}` after the value", rest));
+    }
+    Ok(value)
+}
+
+impl Settings {
+    /// Parses a settings file. Unknown tables and keys are errors, so typos do not go unnoticed.
+    pub fn parse(path: &Path, text: &str) -> Result<Self, String> {
+        let mut settings = Self::default();
+        let mut table = String::new();
+        for (number, line) in text.lines().enumerate() {
+            let error = |message: String| format!("{}:{}: {}", path.display(), number + 1, message);
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(rest) = line.strip_prefix('[') {
+                let (name, comment) = rest
+                    .split_once(']')
+                    .ok_or_else(|| error("unterminated table header".to_owned()))?;
+                let comment = comment.trim();
+                if !comment.is_empty() && !comment.starts_with('#') {
+                    return Err(error(format!(
+                        "unexpected `{}` after the table header",
+                        comment
+                    )));
+                }
+                table = name.trim().to_owned();
+                if !SETTINGS.iter().any(|(t, ..)| *t == table) {
+                    return Err(error(format!("unknown table `[{}]`", table)));
+                }
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| error("expected `key = value`".to_owned()))?;
+            let key = key.trim().trim_matches('"');
+            let Some((_, _, vars, number_only)) =
+                SETTINGS.iter().find(|(t, k, ..)| *t == table && *k == key)
+            else {
+                return Err(error(format!("unknown setting `{}` in `[{}]`", key, table)));
+            };
+            let is_string = value.trim_start().starts_with(['"', '\'']);
+            let value = parse_value(value.trim()).map_err(&error)?;
+            if *number_only == is_string {
+                let expected = if *number_only {
+                    "an integer"
+                } else {
+                    "a string"
+                };
+                return Err(error(format!("`{}` must be {}", key, expected)));
+            }
+            settings.values.push((vars, value));
+        }
+        Ok(settings)
+    }
+
+    /// The variables to set and their values, leaving out settings with a variable that `is_set`.
+    fn assignments(&self, is_set: impl Fn(&str) -> bool) -> Vec<(&'static str, &str)> {
+        self.values
+            .iter()
+            .filter(|(vars, _)| !vars.iter().any(|var| is_set(var)))
+            .flat_map(|(vars, value)| vars.iter().map(move |var| (*var, value.as_str())))
+            .collect()
+    }
+
+    /// Sets the environment variables of the settings that are not set yet.
+    ///
+    /// # Safety
+    ///
+    /// Must be called before any other thread is started, see [`env::set_var`].
+    pub unsafe fn apply(&self) {
+        for (var, value) in self.assignments(|var| env::var_os(var).is_some()) {
+            // SAFETY: upheld by the caller.
+            unsafe { env::set_var(var, value) };
+        }
+    }
+}
+
+fn home_dir() -> Option<PathBuf> {
+    env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .map(PathBuf::from)
+}
+
+/// The settings files that apply in the current directory, the repository one first: the closest
+/// `.codedefender/config.toml` in it or a parent, then the one in the home directory.
+pub fn find() -> Vec<PathBuf> {
+    let user = home_dir().map(|home| home.join(".codedefender").join(FILE_NAME));
+    let mut files = Vec::new();
+    if let Ok(dir) = env::current_dir() {
+        let repo = dir
+            .ancestors()
+            .map(|dir| dir.join(".codedefender").join(FILE_NAME))
+            .find(|path| path.is_file() && Some(path) != user.as_ref());
+        files.extend(repo);
+    }
+    files.extend(user.filter(|path| path.is_file()));
+    files
+}
+
+/// Reads and applies the settings files. Returns the files read.
+///
+/// # Safety
+///
+/// Must be called before any other thread is started, see [`env::set_var`].
+pub unsafe fn load() -> Result<Vec<PathBuf>, String> {
+    let files = find();
+    for path in &files {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+        // SAFETY: upheld by the caller.
+        unsafe { Settings::parse(path, &text)?.apply() };
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn parse(text: &str) -> Result<Settings, String> {
+        Settings::parse(Path::new("config.toml"), text)
+    }
+
+    /// Applies `files` in order to `env` like `load` does to the process environment.
+    fn apply(env: &mut BTreeMap<String, String>, files: &[&Settings]) {
+        for settings in files {
+            for (var, value) in settings.assignments(|var| env.contains_key(var)) {
+                env.insert(var.to_owned(), value.to_owned());
+            }
+        }
+    }
+
+    #[test]
+    fn tables_strings_and_integers_are_read() {
+        let settings = parse(
+            r#"
+# Shared defaults
+[api]
+endpoint = "https://app.example.com"  # comment
+proxy = 'http://proxy:3128'
+retries = 1_000
+
+[ cache ]
+dir = "C:\\cache\tdir"
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            settings.values,
+            [
+                (&["BASE_URL"][..], "https://app.example.com".to_owned()),
+                (
+                    &["HTTPS_PROXY", "HTTP_PROXY"][..],
+                    "http://proxy:3128".to_owned()
+                ),
+                (&["CD_RETRIES"][..], "1000".to_owned()),
+                (&["CODEDEFENDER_CACHE_DIR"][..], "C:\\cache\tdir".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_tables_and_keys_are_errors() {
+        assert_eq!(
+            parse("[api]\nendpiont = \"x\"\n").unwrap_err(),
+            "config.toml:2: unknown setting `endpiont` in `[api]`"
+        );
+        assert_eq!(
+            parse("[apis]\n").unwrap_err(),
+            "config.toml:1: unknown table `[apis]`"
+        );
+        assert_eq!(
+            parse("endpoint = \"x\"\n").unwrap_err(),
+            "config.toml:1: unknown setting `endpoint` in `[]`"
+        );
+    }
+
+    #[test]
+    fn malformed_lines_are_errors() {
+        for (text, error) in [
+            ("[api\n", "config.toml:1: unterminated table header"),
+            ("[api]\nendpoint\n", "config.toml:2: expected `key = value`"),
+            (
+                "[api]\nendpoint = \"x\n",
+                "config.toml:2: unterminated string",
+            ),
+            (
+                "[api]\nendpoint = \"x\" y\n",
+                "config.toml:2: unexpected `y` after the value",
+            ),
+            (
+                "[api]\nretries = \"5\"\n",
+                "config.toml:2: `retries` must be an integer",
+            ),
+            (
+                "[api]\nendpoint = 5\n",
+                "config.toml:2: `endpoint` must be a string",
+            ),
+            (
+                "[api]\nretries = five\n",
+                "config.toml:2: expected a string or integer, got `five`",
+            ),
+        ] {
+            assert_eq!(parse(text).unwrap_err(), error, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn set_variables_and_earlier_files_win() {
+        let repo = parse("[api]\nendpoint = \"https://repo\"\n").unwrap();
+        let user = parse("[api]\nendpoint = \"https://user\"\nproxy = \"http://user\"\n").unwrap();
+        let mut env = BTreeMap::from([("HTTP_PROXY".to_owned(), "http://env".to_owned())]);
+        apply(&mut env, &[&repo, &user]);
+        assert_eq!(
+            env,
+            BTreeMap::from([
+                ("BASE_URL".to_owned(), "https://repo".to_owned()),
+                ("HTTP_PROXY".to_owned(), "http://env".to_owned()),
+            ])
+        );
+    }
+}
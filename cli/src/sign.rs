@@ -0,0 +1,100 @@
+//! Code signing of extracted artifacts with an external tool such as signtool or osslsigncode.
+
+use crate::exit::{ExitStatus, failure};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// A signing command line with `{file}` and `{signed}` placeholders.
+#[derive(Debug, Clone)]
+pub struct SignCommand {
+    args: Vec<String>,
+}
+
+impl SignCommand {
+    /// Splits `template` on whitespace, keeping double quoted parts together. Without a `{file}`
+    /// placeholder the path is appended as the last argument.
+    pub fn parse(template: &str) -> Result<Self, String> {
+        let mut args = Vec::new();
+        let mut current: Option<String> = None;
+        let mut quoted = false;
+        for c in template.chars() {
+            match c {
+                '"' => {
+                    quoted = !quoted;
+                    current.get_or_insert_default();
+                }
+                c if c.is_whitespace() && !quoted => args.extend(current.take()),
+                c => current.get_or_insert_default().push(c),
+            }
+        }
+        if quoted {
+            return Err(format!("Unclosed `\"` in signing command {:?}", template));
+        }
+        args.extend(current);
+        if args.is_empty() {
+            return Err("The signing command is empty".to_owned());
+        }
+        if !args.iter().any(|arg| arg.contains("{file}")) {
+            args.push("{file}".to_owned());
+        }
+        Ok(Self { args })
+    }
+
+    /// Signs `file` in place. Tools that write the signed copy elsewhere use `{signed}`, which is
+    /// a temporary path moved over `file` afterwards.
+    pub fn sign(&self, file: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let signed = PathBuf::from(format!("{}.signed", file.display()));
+        let writes_copy = self.args.iter().any(|arg| arg.contains("{signed}"));
+        let args: Vec<String> = self
+            .args
+            .iter()
+            .map(|arg| {
+                arg.replace("{file}", &file.to_string_lossy())
+                    .replace("{signed}", &signed.to_string_lossy())
+            })
+            .collect();
+
+        log::debug!("Signing {:?}: {:?}", file, args);
+        let output = Command::new(&args[0])
+            .args(&args[1..])
+            .output()
+            .map_err(|e| {
+                failure(
+                    ExitStatus::Signing,
+                    format!("Failed to run {:?}: {}", args[0], e),
+                )
+            })?;
+        for line in String::from_utf8_lossy(&output.stdout).lines() {
+            log::debug!("{}", line);
+        }
+        if !output.status.success() {
+            for line in String::from_utf8_lossy(&output.stderr).lines() {
+                log::error!("{}", line);
+            }
+            let _ = fs::remove_file(&signed);
+            return Err(failure(
+                ExitStatus::Signing,
+                format!("Signing {:?} failed with {}", file, output.status),
+            ));
+        }
+        if writes_copy {
+            fs::rename(&signed, file).map_err(|e| {
+                failure(
+                    ExitStatus::Signing,
+                    format!("Signed copy {:?} was not written: {}", signed, e),
+                )
+            })?;
+        }
+        log::info!("Signed {:?}", file);
+        Ok(())
+    }
+}
+
+/// Whether an extracted artifact is an image that can carry a signature, as opposed to a debug
+/// file.
+pub fn is_signable(path: &Path) -> bool {
+    fs::read(path).is_ok_and(|bytes| bytes.starts_with(b"MZ"))
+}
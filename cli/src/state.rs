@@ -0,0 +1,96 @@
+//! Progress of a `run`, saved after every stage so an interrupted run can be resumed.
+
+use crate::manifest::AnalysisSummary;
+use codedefender_api::serde_json;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Last stage a run completed.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RunStage {
+    #[default]
+    Started,
+    Uploaded,
+    Analyzing,
+    Obfuscating,
+}
+
+/// Ids and results needed to pick a run up again.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct RunState {
+    pub stage: RunStage,
+    /// SHA-256 of the input binary, so a state file is not resumed against another binary.
+    pub input_sha256: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pdb_file_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub analyze_execution_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub execution_id: Option<String>,
+    /// What analysis found, for the manifest of a resumed run.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub analysis: Option<AnalysisSummary>,
+    /// PDB path the submitted config writes into the output, resolved once per build.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fake_pdb_string: Option<String>,
+    /// Where the state is saved. `None` keeps it in memory only.
+    #[serde(skip)]
+    path: Option<PathBuf>,
+}
+
+impl RunState {
+    /// A fresh state saved to `path`, or kept in memory only without one.
+    pub fn new(input_sha256: String, path: Option<PathBuf>) -> Self {
+        Self {
+            input_sha256,
+            path,
+            ..Default::default()
+        }
+    }
+
+    /// State file written next to `output`, the one `--resume` takes to continue the run.
+    pub fn default_path(output: &Path) -> PathBuf {
+        let mut path = output.as_os_str().to_owned();
+        path.push(".state.json");
+        PathBuf::from(path)
+    }
+
+    /// Loads a saved state, refusing it if it was written for a different input binary.
+    pub fn load(path: &Path, input_sha256: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut state: Self = serde_json::from_slice(&fs::read(path)?)?;
+        if state.input_sha256 != input_sha256 {
+            return Err(format!("{:?} was written for a different input binary", path).into());
+        }
+        state.path = Some(path.to_path_buf());
+        Ok(state)
+    }
+
+    /// Moves to `stage` and saves the state. Failing to save only logs a warning.
+    pub fn advance(&mut self, stage: RunStage) {
+        self.stage = stage;
+        let Some(path) = &self.path else {
+            return;
+        };
+        let result = serde_json::to_vec_pretty(self)
+            .map_err(|e| e.to_string())
+            .and_then(|bytes| fs::write(path, bytes).map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            log::warn!("Failed to save run state to {:?}: {}", path, e);
+        }
+    }
+
+    /// Deletes the state file once the run has finished.
+    pub fn finish(&self) {
+        if let Some(path) = &self.path
+            && path.exists()
+            && let Err(e) = fs::remove_file(path)
+        {
+            log::warn!("Failed to remove run state {:?}: {}", path, e);
+        }
+    }
+}
@@ -0,0 +1,169 @@
+//! `-` as `--input-file` or `--output`, so the CLI can sit between the steps of a packaging
+//! pipeline that pipes artifacts along.
+
+use crate::exit::{self, ExitStatus};
+use crate::report::Reporter;
+use object::LittleEndian as LE;
+use object::pe;
+use object::read::pe::{ImageNtHeaders, ImageOptionalHeader, PeFile};
+use std::{
+    fs,
+    io::{self, IsTerminal, Read, Write},
+    path::{Path, PathBuf},
+};
+
+/// Whether `path` stands for stdin or stdout.
+pub fn is_stdio(path: &Path) -> bool {
+    path == Path::new("-")
+}
+
+/// The input binary read from stdin. It is spooled to a temporary file, since the input is read
+/// several times (upload, PDB search, output checks). The file is removed on drop.
+pub struct SpooledInput {
+    dir: PathBuf,
+    /// Path of the spooled binary in a directory of its own, named `stdin` with the extension
+    /// [`image_extension`] picks.
+    pub path: PathBuf,
+}
+
+impl SpooledInput {
+    pub fn read_stdin() -> Result<Self, Box<dyn std::error::Error>> {
+        let mut stdin = io::stdin().lock();
+        if stdin.is_terminal() {
+            return Err(exit::failure(
+                ExitStatus::Config,
+                "`--input-file -` reads the binary from stdin, but nothing is piped into it",
+            ));
+        }
+        let mut bytes = Vec::new();
+        stdin.read_to_end(&mut bytes)?;
+        if bytes.is_empty() {
+            return Err(exit::failure(
+                ExitStatus::Config,
+                "`--input-file -` read nothing from stdin",
+            ));
+        }
+        let dir = std::env::temp_dir().join(format!("codedefender-stdin-{}", std::process::id()));
+        fs::create_dir_all(&dir)?;
+        let input = Self {
+            path: dir.join("stdin").with_extension(image_extension(&bytes)),
+            dir,
+        };
+        fs::write(&input.path, &bytes)?;
+        log::debug!(
+            "Read {} bytes from stdin into {:?}",
+            bytes.len(),
+            input.path
+        );
+        Ok(input)
+    }
+}
+
+/// File extension for the PE image in `bytes`: `sys` for native (driver) images, `dll` for DLLs
+/// and `exe` for everything else, including input that does not parse as PE.
+fn image_extension(bytes: &[u8]) -> &'static str {
+    match object::File::parse(bytes) {
+        Ok(object::File::Pe32(file)) => pe_extension(&file),
+        Ok(object::File::Pe64(file)) => pe_extension(&file),
+        _ => "exe",
+    }
+}
+
+fn pe_extension<Pe: ImageNtHeaders>(file: &PeFile<'_, Pe>) -> &'static str {
+    let headers = file.nt_headers();
+    if headers.optional_header().subsystem() == pe::IMAGE_SUBSYSTEM_NATIVE {
+        "sys"
+    } else if headers.file_header().characteristics.get(LE) & pe::IMAGE_FILE_DLL != 0 {
+        "dll"
+    } else {
+        "exe"
+    }
+}
+
+impl Drop for SpooledInput {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Reserves stdout for the artifact when `output` is `-`. JSON events would corrupt it, so the two
+/// cannot be combined.
+pub fn claim_stdout(
+    output: &Path,
+    reporter: &mut Reporter,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if !is_stdio(output) {
+        return Ok(());
+    }
+    if reporter.is_json() {
+        return Err(exit::failure(
+            ExitStatus::Config,
+            "`--output -` writes the artifact to stdout and cannot be combined with `--format json`",
+        ));
+    }
+    reporter.stdout_taken = true;
+    Ok(())
+}
+
+/// Writes the artifact to `output`, or to stdout for `-`.
+pub fn write_output(output: &Path, bytes: &[u8]) -> io::Result<()> {
+    if is_stdio(output) {
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(bytes)?;
+        stdout.flush()?;
+        log::info!("Obfuscated binary written to stdout");
+    } else {
+        fs::write(output, bytes)?;
+        log::info!("Obfuscated binary written to {:?}", output);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Headers of a PE32+ image with a single empty section, enough for [`image_extension`].
+    fn pe64(characteristics: u16, subsystem: u16) -> Vec<u8> {
+        let mut image = vec![0u8; 0x40];
+        image[..2].copy_from_slice(b"MZ");
+        image[0x3c..0x40].copy_from_slice(&0x40u32.to_le_bytes());
+        image.extend(b"PE\0\0");
+        let mut file_header = [0u8; 20];
+        file_header[..2].copy_from_slice(&pe::IMAGE_FILE_MACHINE_AMD64.to_le_bytes());
+        file_header[2..4].copy_from_slice(&1u16.to_le_bytes());
+        file_header[16..18].copy_from_slice(&240u16.to_le_bytes());
+        file_header[18..20].copy_from_slice(&characteristics.to_le_bytes());
+        image.extend(file_header);
+        let mut optional_header = [0u8; 240];
+        optional_header[..2].copy_from_slice(&pe::IMAGE_NT_OPTIONAL_HDR64_MAGIC.to_le_bytes());
+        optional_header[68..70].copy_from_slice(&subsystem.to_le_bytes());
+        optional_header[108..112].copy_from_slice(&16u32.to_le_bytes());
+        image.extend(optional_header);
+        image.extend([0u8; 40]);
+        image
+    }
+
+    #[test]
+    fn extension_follows_the_image_kind() {
+        let executable = pe::IMAGE_FILE_EXECUTABLE_IMAGE;
+        let console = pe::IMAGE_SUBSYSTEM_WINDOWS_CUI;
+        assert_eq!(image_extension(&pe64(executable, console)), "exe");
+        assert_eq!(
+            image_extension(&pe64(
+                executable | pe::IMAGE_FILE_DLL,
+                pe::IMAGE_SUBSYSTEM_WINDOWS_GUI
+            )),
+            "dll"
+        );
+        assert_eq!(
+            image_extension(&pe64(executable, pe::IMAGE_SUBSYSTEM_NATIVE)),
+            "sys"
+        );
+    }
+
+    #[test]
+    fn unparsable_input_is_an_exe() {
+        assert_eq!(image_extension(b"not a PE image"), "exe");
+    }
+}
@@ -0,0 +1,223 @@
+//! `symbols`, listing the functions, rejects and macro profiles of an analysis result, and
+//! `symbols export`, writing the preprocessed debug symbols of a binary to JSON.
+
+use clap::ValueEnum;
+use codedefender_api::{
+    codedefender_config::{AnalysisFunction, AnalysisReject, AnalysisResult, RejectSeverity},
+    serde_json::{Value, json},
+};
+use codedefender_symbols::DebugSymbolInfo;
+use std::io::{self, Write};
+
+/// Order of the listed functions and rejects.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SortOrder {
+    /// Most referenced first. Rejects, which have no reference count, are ordered by RVA.
+    #[default]
+    Refs,
+    Name,
+    Rva,
+}
+
+/// The part of an analysis result matching a filter, in display order.
+pub struct Listing<'a> {
+    pub functions: Vec<&'a AnalysisFunction>,
+    pub rejects: Vec<&'a AnalysisReject>,
+    /// Macro profile names and the RVA and name of their functions.
+    pub macros: Vec<(&'a str, Vec<(u64, String)>)>,
+}
+
+impl<'a> Listing<'a> {
+    /// Functions, rejects and macro profiles whose name contains `filter`, ignoring case.
+    pub fn new(analysis: &'a AnalysisResult, filter: Option<&str>, sort: SortOrder) -> Self {
+        let filter = filter.map(str::to_lowercase);
+        let matches = |name: &str| {
+            filter
+                .as_ref()
+                .is_none_or(|filter| name.to_lowercase().contains(filter))
+        };
+        let mut functions: Vec<_> = analysis
+            .functions
+            .iter()
+            .filter(|f| matches(&f.symbol))
+            .collect();
+        let mut rejects: Vec<_> = analysis
+            .rejects
+            .iter()
+            .filter(|r| matches(&r.symbol))
+            .collect();
+        match sort {
+            SortOrder::Refs => {
+                functions.sort_by_key(|f| (std::cmp::Reverse(f.ref_count), f.rva));
+                rejects.sort_by_key(|r| r.rva);
+            }
+            SortOrder::Name => {
+                functions.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+                rejects.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+            }
+            SortOrder::Rva => {
+                functions.sort_by_key(|f| f.rva);
+                rejects.sort_by_key(|r| r.rva);
+            }
+        }
+        let name_of = |rva: &u64| {
+            analysis
+                .functions
+                .iter()
+                .find(|f| f.rva == *rva && !f.symbol.is_empty())
+                .map_or_else(
+                    || (*rva, format!("{:#x}", rva)),
+                    |f| (*rva, f.symbol.clone()),
+                )
+        };
+        let macros = analysis
+            .macros
+            .iter()
+            .filter(|m| matches(&m.name))
+            .map(|m| (m.name.as_str(), m.rvas.iter().map(name_of).collect()))
+            .collect();
+        Self {
+            functions,
+            rejects,
+            macros,
+        }
+    }
+
+    /// Aligned tables for the terminal.
+    pub fn print(&self, out: &mut impl Write) -> io::Result<()> {
+        writeln!(out, "Functions ({})", self.functions.len())?;
+        writeln!(out, "{:>10}  {:>6}  name", "rva", "refs")?;
+        for function in &self.functions {
+            writeln!(
+                out,
+                "{:>#10x}  {:>6}  {}",
+                function.rva, function.ref_count, function.symbol
+            )?;
+        }
+        writeln!(out)?;
+        writeln!(out, "Rejects ({})", self.rejects.len())?;
+        writeln!(out, "{:>10}  {:<40}  reason", "rva", "name")?;
+        for reject in &self.rejects {
+            writeln!(
+                out,
+                "{:>#10x}  {:<40}  {}",
+                reject.rva, reject.symbol, reject.reason
+            )?;
+            if let Some(suggestion) = reject.remediation() {
+                writeln!(out, "{:>10}  {:<40}  fix: {}", "", "", suggestion)?;
+            }
+            if reject.effective_severity() == RejectSeverity::Degraded
+                && let Some(safe) = &reject.safe_passes
+            {
+                writeln!(
+                    out,
+                    "{:>10}  {:<40}  safe passes: {}",
+                    "",
+                    "",
+                    safe.join(", ")
+                )?;
+            }
+        }
+        writeln!(out)?;
+        writeln!(out, "Macro profiles ({})", self.macros.len())?;
+        for (name, functions) in &self.macros {
+            let functions: Vec<&str> = functions.iter().map(|(_, name)| name.as_str()).collect();
+            writeln!(out, "{}: {}", name, functions.join(", "))?;
+        }
+        Ok(())
+    }
+
+    /// One CSV table with a `kind` column of `function`, `reject` or `macro`. `detail` is the
+    /// reason of a reject and the profile of a macro function.
+    pub fn write_csv(&self, out: &mut impl Write) -> io::Result<()> {
+        writeln!(out, "kind,rva,name,ref_count,detail")?;
+        for function in &self.functions {
+            writeln!(
+                out,
+                "function,{:#x},{},{},",
+                function.rva,
+                csv_field(&function.symbol),
+                function.ref_count
+            )?;
+        }
+        for reject in &self.rejects {
+            writeln!(
+                out,
+                "reject,{:#x},{},,{}",
+                reject.rva,
+                csv_field(&reject.symbol),
+                csv_field(&reject.reason)
+            )?;
+        }
+        for (name, functions) in &self.macros {
+            for (rva, function) in functions {
+                writeln!(
+                    out,
+                    "macro,{:#x},{},,{}",
+                    rva,
+                    csv_field(function),
+                    csv_field(name)
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn to_json(&self) -> Value {
+        let macros: Vec<Value> = self
+            .macros
+            .iter()
+            .map(|(name, functions)| {
+                let functions: Vec<Value> = functions
+                    .iter()
+                    .map(|(rva, symbol)| json!({ "rva": rva, "symbol": symbol }))
+                    .collect();
+                json!({ "name": name, "functions": functions })
+            })
+            .collect();
+        json!({
+            "functions": self.functions,
+            "rejects": self.rejects,
+            "macros": macros,
+        })
+    }
+}
+
+/// Quotes a field containing a separator, quote or line break. Demangled names often have commas.
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_owned()
+    }
+}
+
+/// Preprocessed symbols as JSON, one object per symbol with the fields of [`DebugSymbolInfo`].
+/// Fields the stream does not carry are `null`.
+pub fn export_json(funcs: &[DebugSymbolInfo]) -> Value {
+    Value::Array(
+        funcs
+            .iter()
+            .map(|symbol| {
+                json!({
+                    "address": symbol.address,
+                    "name": symbol.name,
+                    "noreturn": symbol.noreturn,
+                    "source": symbol.source.as_ref().map(|source| json!({
+                        "file": source.file,
+                        "line": source.line,
+                    })),
+                    "len": symbol.len,
+                    "mangled": symbol.mangled,
+                    "inlinees": symbol.inlinees.iter().map(|site| json!({
+                        "name": site.name,
+                        "ranges": site.ranges.iter().map(|range| json!({
+                            "address": range.address,
+                            "len": range.len,
+                        })).collect::<Vec<_>>(),
+                    })).collect::<Vec<_>>(),
+                })
+            })
+            .collect(),
+    )
+}
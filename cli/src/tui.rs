@@ -0,0 +1,383 @@
+//! `tui`, an interactive session for assigning analyzed functions to profiles.
+//!
+//! Commands are read line by line, so a session can also be scripted through stdin.
+
+use crate::yaml_edit::YamlDocument;
+use codedefender_api::codedefender_config::{
+    AnalysisFunction, AnalysisResult, ConfigPreset, YamlConfig, YamlSymbol,
+};
+use std::{
+    fs,
+    io::{self, BufRead, Write},
+    path::PathBuf,
+};
+
+/// Functions shown per page by `list`.
+const PAGE_SIZE: usize = 25;
+
+const HELP: &str = "\
+Commands:
+  list [page]                     functions matching the filter, in the current order
+  sort refs|name|rva              order of `list`, by reference count (default), name or RVA
+  filter [text]                   only list functions whose name contains text, empty clears it
+  rejects [text]                  rejected functions and why, optionally filtered by name
+  profiles                        profiles and how many symbols each targets
+  show <profile>                  symbols targeted by a profile
+  new <profile> [light|balanced|aggressive]
+                                  add a profile using a preset, balanced by default
+  assign <profile> <function>...  add functions to a profile
+  unassign <profile> <function>...
+                                  remove functions from a profile
+  save [path]                     write the config
+  quit                            leave, asks again if there are unsaved changes
+
+Functions are given as the # shown by the last `list`, an RVA like 0x1400, or a name.";
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum SortOrder {
+    Refs,
+    Name,
+    Rva,
+}
+
+pub struct Session {
+    analysis: AnalysisResult,
+    config: YamlConfig,
+    /// The config file the session started from, edited on `save` to keep its comments.
+    document: Option<YamlDocument>,
+    /// The config as last read or written.
+    saved: YamlConfig,
+    output: PathBuf,
+    sort: SortOrder,
+    filter: String,
+    /// Indices into `analysis.functions` in the order of the last `list`.
+    listed: Vec<usize>,
+    dirty: bool,
+}
+
+/// The symbol a profile uses to target `function`, by name when it has one.
+fn symbol_of(function: &AnalysisFunction) -> YamlSymbol {
+    if function.symbol.is_empty() {
+        YamlSymbol::Rva(function.rva)
+    } else {
+        YamlSymbol::Name(function.symbol.clone())
+    }
+}
+
+fn targets(symbols: &[YamlSymbol], function: &AnalysisFunction) -> bool {
+    symbols.iter().any(|symbol| match symbol {
+        YamlSymbol::Name(name) => *name == function.symbol,
+        YamlSymbol::Rva(rva) => *rva == function.rva,
+        YamlSymbol::All => true,
+        _ => false,
+    })
+}
+
+fn parse_rva(text: &str) -> Option<u64> {
+    let hex = text
+        .strip_prefix("0x")
+        .or_else(|| text.strip_prefix("0X"))?;
+    u64::from_str_radix(hex, 16).ok()
+}
+
+fn parse_preset(text: &str) -> Option<ConfigPreset> {
+    match text {
+        "light" => Some(ConfigPreset::Light),
+        "balanced" => Some(ConfigPreset::Balanced),
+        "aggressive" => Some(ConfigPreset::Aggressive),
+        _ => None,
+    }
+}
+
+impl Session {
+    pub fn new(
+        analysis: AnalysisResult,
+        config: YamlConfig,
+        document: Option<YamlDocument>,
+        output: PathBuf,
+    ) -> Self {
+        Self {
+            analysis,
+            saved: config.clone(),
+            config,
+            document,
+            output,
+            sort: SortOrder::Refs,
+            filter: String::new(),
+            listed: Vec::new(),
+            dirty: false,
+        }
+    }
+
+    /// Runs commands from `input` until `quit` or end of input.
+    pub fn run(
+        &mut self,
+        input: impl BufRead,
+        out: &mut impl Write,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        writeln!(
+            out,
+            "{} functions, {} rejected, {} profiles. Type `help` for commands.",
+            self.analysis.functions.len(),
+            self.analysis.rejects.len(),
+            self.config.profiles.len()
+        )?;
+        let mut lines = input.lines();
+        let mut confirm_quit = false;
+        loop {
+            write!(out, "> ")?;
+            out.flush()?;
+            let Some(line) = lines.next().transpose()? else {
+                writeln!(out)?;
+                if self.dirty {
+                    log::warn!("Input ended with unsaved changes");
+                }
+                return Ok(());
+            };
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let Some((&command, args)) = words.split_first() else {
+                continue;
+            };
+            if command == "quit" || command == "exit" {
+                if self.dirty && !confirm_quit {
+                    writeln!(
+                        out,
+                        "There are unsaved changes, `save` them or `quit` again"
+                    )?;
+                    confirm_quit = true;
+                    continue;
+                }
+                return Ok(());
+            }
+            confirm_quit = false;
+            if let Err(e) = self.execute(command, args, out) {
+                writeln!(out, "error: {}", e)?;
+            }
+        }
+    }
+
+    fn execute(
+        &mut self,
+        command: &str,
+        args: &[&str],
+        out: &mut impl Write,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        match command {
+            "help" | "?" => writeln!(out, "{}", HELP)?,
+            "list" | "ls" => {
+                let page = match args.first() {
+                    Some(page) => page.parse::<usize>()?.max(1),
+                    None => 1,
+                };
+                self.list(page, out)?;
+            }
+            "sort" => {
+                self.sort = match args.first().copied() {
+                    Some("refs") => SortOrder::Refs,
+                    Some("name") => SortOrder::Name,
+                    Some("rva") => SortOrder::Rva,
+                    _ => return Err("expected `sort refs`, `sort name` or `sort rva`".into()),
+                };
+                self.list(1, out)?;
+            }
+            "filter" => {
+                self.filter = args.join(" ");
+                self.list(1, out)?;
+            }
+            "rejects" => self.rejects(&args.join(" "), out)?,
+            "profiles" => {
+                for profile in &self.config.profiles {
+                    writeln!(
+                        out,
+                        "{:<24} {} symbols",
+                        profile.name,
+                        profile.symbols.len()
+                    )?;
+                }
+            }
+            "show" => {
+                let [name] = args else {
+                    return Err("expected `show <profile>`".into());
+                };
+                let profile = self
+                    .config
+                    .profiles
+                    .iter()
+                    .find(|p| p.name == *name)
+                    .ok_or_else(|| format!("no profile named `{}`", name))?;
+                for symbol in &profile.symbols {
+                    match symbol {
+                        YamlSymbol::Name(name) => writeln!(out, "  {}", name)?,
+                        YamlSymbol::Rva(rva) => writeln!(out, "  {:#x}", rva)?,
+                        symbol => writeln!(out, "  {:?}", symbol)?,
+                    }
+                }
+            }
+            "new" => {
+                let (name, preset) = match args {
+                    [name] => (*name, ConfigPreset::Balanced),
+                    [name, preset] => (
+                        *name,
+                        parse_preset(preset)
+                            .ok_or_else(|| format!("unknown preset `{}`", preset))?,
+                    ),
+                    _ => return Err("expected `new <profile> [preset]`".into()),
+                };
+                if self.config.profiles.iter().any(|p| p.name == name) {
+                    return Err(format!("profile `{}` already exists", name).into());
+                }
+                self.config
+                    .profiles
+                    .push(preset.profile(name.to_owned(), vec![]));
+                self.dirty = true;
+            }
+            "assign" | "unassign" => {
+                let Some((name, functions)) = args.split_first().filter(|(_, f)| !f.is_empty())
+                else {
+                    return Err(format!("expected `{} <profile> <function>...`", command).into());
+                };
+                let symbols: Vec<(YamlSymbol, usize)> = functions
+                    .iter()
+                    .map(|f| {
+                        self.resolve(f)
+                            .map(|i| (symbol_of(&self.analysis.functions[i]), i))
+                    })
+                    .collect::<Result<_, _>>()?;
+                let profile = self
+                    .config
+                    .profiles
+                    .iter_mut()
+                    .find(|p| p.name == *name)
+                    .ok_or_else(|| format!("no profile named `{}`, create it with `new`", name))?;
+                for (symbol, index) in symbols {
+                    let function = &self.analysis.functions[index];
+                    if command == "assign" {
+                        if !targets(&profile.symbols, function) {
+                            profile.symbols.push(symbol);
+                        }
+                    } else {
+                        profile
+                            .symbols
+                            .retain(|s| !targets(std::slice::from_ref(s), function));
+                    }
+                }
+                self.dirty = true;
+            }
+            "save" => {
+                if let Some(path) = args.first() {
+                    self.output = PathBuf::from(path);
+                }
+                let yaml = match &mut self.document {
+                    Some(document) => {
+                        document.apply_diff(
+                            &serde_yaml::to_value(&self.saved)?,
+                            &serde_yaml::to_value(&self.config)?,
+                        )?;
+                        document.to_string()
+                    }
+                    None => serde_yaml::to_string(&self.config)?,
+                };
+                fs::write(&self.output, yaml)?;
+                writeln!(out, "Config written to {:?}", self.output)?;
+                self.saved = self.config.clone();
+                self.dirty = false;
+            }
+            _ => return Err(format!("unknown command `{}`, see `help`", command).into()),
+        }
+        Ok(())
+    }
+
+    /// Index into `analysis.functions` of a function given by list position, RVA or name.
+    fn resolve(&self, text: &str) -> Result<usize, String> {
+        let functions = &self.analysis.functions;
+        if let Ok(position) = text.parse::<usize>() {
+            return position
+                .checked_sub(1)
+                .and_then(|p| self.listed.get(p).copied())
+                .ok_or_else(|| format!("#{} is not in the last list", position));
+        }
+        let rva = parse_rva(text);
+        let matches = |symbol: &str, function_rva: u64| match rva {
+            Some(rva) => function_rva == rva,
+            None => symbol == text,
+        };
+        if let Some(index) = functions.iter().position(|f| matches(&f.symbol, f.rva)) {
+            return Ok(index);
+        }
+        match self
+            .analysis
+            .rejects
+            .iter()
+            .find(|r| matches(&r.symbol, r.rva))
+        {
+            Some(reject) => Err(format!("`{}` was rejected: {}", text, reject.reason)),
+            None => Err(format!("no function `{}`", text)),
+        }
+    }
+
+    fn list(&mut self, page: usize, out: &mut impl Write) -> io::Result<()> {
+        let functions = &self.analysis.functions;
+        let mut listed: Vec<usize> = (0..functions.len())
+            .filter(|&i| functions[i].symbol.contains(&self.filter))
+            .collect();
+        match self.sort {
+            SortOrder::Refs => listed.sort_by_key(|&i| std::cmp::Reverse(functions[i].ref_count)),
+            SortOrder::Name => {
+                listed.sort_by(|&a, &b| functions[a].symbol.cmp(&functions[b].symbol))
+            }
+            SortOrder::Rva => listed.sort_by_key(|&i| functions[i].rva),
+        }
+        let pages = listed.len().div_ceil(PAGE_SIZE).max(1);
+        let start = (page.min(pages) - 1) * PAGE_SIZE;
+        writeln!(
+            out,
+            "{:>5}  {:>10}  {:>6}  {:<40}  profiles",
+            "#", "rva", "refs", "name"
+        )?;
+        for (position, &index) in listed.iter().enumerate().skip(start).take(PAGE_SIZE) {
+            let function = &functions[index];
+            let profiles: Vec<&str> = self
+                .config
+                .profiles
+                .iter()
+                .filter(|p| targets(&p.symbols, function))
+                .map(|p| p.name.as_str())
+                .collect();
+            let row = format!(
+                "{:>5}  {:>#10x}  {:>6}  {:<40}  {}",
+                position + 1,
+                function.rva,
+                function.ref_count,
+                function.symbol,
+                profiles.join(", ")
+            );
+            writeln!(out, "{}", row.trim_end())?;
+        }
+        writeln!(
+            out,
+            "page {} of {}, {} functions",
+            page.min(pages),
+            pages,
+            listed.len()
+        )?;
+        self.listed = listed;
+        Ok(())
+    }
+
+    fn rejects(&self, filter: &str, out: &mut impl Write) -> io::Result<()> {
+        let rejects: Vec<_> = self
+            .analysis
+            .rejects
+            .iter()
+            .filter(|r| r.symbol.contains(filter))
+            .collect();
+        for reject in &rejects {
+            writeln!(
+                out,
+                "{:>#10x}  {:<40}  {}",
+                reject.rva, reject.symbol, reject.reason
+            )?;
+        }
+        writeln!(out, "{} rejected functions", rejects.len())
+    }
+}
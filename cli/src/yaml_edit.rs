@@ -0,0 +1,898 @@
+//! Edits of YAML configs that keep their comments, anchors, blank lines and key order.
+//!
+//! The document is kept as its source lines and only the lines of values that change are
+//! rewritten. Block mappings and sequences are edited in place, anything else (flow collections,
+//! aliases, multi-line scalars) is replaced as a whole when something inside it changes.
+
+use codedefender_api::serde_json;
+use serde_yaml::{Mapping, Value};
+use std::fmt;
+
+/// A step of a path into a YAML document.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+/// `profiles[0].passes` for `path`.
+pub fn display_path(path: &[Segment]) -> String {
+    let mut out = String::new();
+    for segment in path {
+        match segment {
+            Segment::Key(key) if out.is_empty() => out.push_str(key),
+            Segment::Key(key) => out.push_str(&format!(".{}", key)),
+            Segment::Index(index) => out.push_str(&format!("[{}]", index)),
+        }
+    }
+    out
+}
+
+/// A change to the document. Paths address the document as left by the previous edits.
+#[derive(Debug)]
+enum Edit {
+    /// Replaces the value at the path, or adds it at the end of its mapping or list.
+    Set(Vec<Segment>, Value),
+    /// Adds a value before the key given, or at the end of the mapping when there is none. In a
+    /// list, the value goes before the item at the index of the path.
+    Insert(Vec<Segment>, Option<String>, Value),
+    Remove(Vec<Segment>),
+    Rename(Vec<Segment>, String),
+}
+
+impl Edit {
+    fn path(&self) -> &[Segment] {
+        match self {
+            Edit::Set(path, _)
+            | Edit::Insert(path, _, _)
+            | Edit::Remove(path)
+            | Edit::Rename(path, _) => path,
+        }
+    }
+}
+
+/// A YAML document edited in place.
+pub struct YamlDocument {
+    lines: Vec<String>,
+    crlf: bool,
+    /// Whether new lists in mappings are indented past their key, following the document.
+    indent_sequences: bool,
+}
+
+impl fmt::Display for YamlDocument {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for line in &self.lines {
+            write!(f, "{}{}", line, self.newline())?;
+        }
+        Ok(())
+    }
+}
+
+impl YamlDocument {
+    pub fn parse(source: &str) -> Self {
+        let lines: Vec<String> = source.lines().map(str::to_owned).collect();
+        Self {
+            indent_sequences: indents_sequences(&parse(&lines)).unwrap_or(false),
+            lines,
+            crlf: source.contains("\r\n"),
+        }
+    }
+
+    /// The line ending of the document.
+    pub fn newline(&self) -> &'static str {
+        if self.crlf { "\r\n" } else { "\n" }
+    }
+
+    /// The document as it reads now.
+    pub fn value(&self) -> Result<Value, serde_yaml::Error> {
+        serde_yaml::from_str(&self.to_string())
+    }
+
+    /// Renames the key at `path`, keeping its position and value.
+    pub fn rename_key(&mut self, path: &[Segment], to: &str) -> Result<(), serde_yaml::Error> {
+        self.apply(&Edit::Rename(path.to_vec(), to.to_owned()))
+    }
+
+    /// Changes the document to read as `new`.
+    pub fn update(&mut self, new: &Value) -> Result<(), serde_yaml::Error> {
+        let old = self.value()?;
+        self.apply_diff(&old, new)
+    }
+
+    /// Makes the changes from `old` to `new` to the document. `old` does not have to be what
+    /// the document reads, only the parts that changed are looked up in it.
+    pub fn apply_diff(&mut self, old: &Value, new: &Value) -> Result<(), serde_yaml::Error> {
+        let mut edits = Vec::new();
+        diff(&mut Vec::new(), old, new, &mut edits);
+        for edit in &edits {
+            self.apply(edit)?;
+        }
+        Ok(())
+    }
+
+    fn apply(&mut self, edit: &Edit) -> Result<(), serde_yaml::Error> {
+        let root = parse(&self.lines);
+        let path = edit.path();
+        let Some((last, parent_path)) = path.split_last() else {
+            if let Edit::Set(_, value) = edit {
+                self.replace_root(value)?;
+            }
+            return Ok(());
+        };
+        let parent = node_at(&root, parent_path);
+        let entry = parent.and_then(|parent| parent.entry(last));
+        match (edit, parent, entry) {
+            (Edit::Set(_, value), _, Some(entry)) => {
+                self.replace(entry, value)?;
+                return Ok(());
+            }
+            (Edit::Rename(_, to), _, Some(entry)) if entry.key.is_some() => {
+                let key = render_key(to)?;
+                let line = &mut self.lines[entry.line];
+                line.replace_range(entry.col..entry.value_col - 1, &key);
+                return Ok(());
+            }
+            (Edit::Remove(_), Some(Node::Mapping(entries)), Some(entry))
+            | (Edit::Remove(_), Some(Node::Sequence(entries)), Some(entry))
+                if entries.len() > 1 && !entry.is_compact(&self.lines) =>
+            {
+                self.lines.drain(entry.line..entry.end);
+                return Ok(());
+            }
+            (Edit::Set(_, value), Some(Node::Mapping(entries)), None)
+            | (Edit::Insert(_, _, value), Some(Node::Mapping(entries)), None) => {
+                if let Segment::Key(key) = last {
+                    let before = match edit {
+                        Edit::Insert(_, Some(before), _) => entries
+                            .iter()
+                            .find(|e| e.key.as_ref() == Some(before))
+                            .filter(|e| !e.is_compact(&self.lines)),
+                        _ => None,
+                    };
+                    let at = match before {
+                        Some(before) => self.leading_comments(before),
+                        None => entries[entries.len() - 1].end,
+                    };
+                    let col = entries[0].col;
+                    let head = format!("{}{}:", " ".repeat(col), render_key(key)?);
+                    let lines = entry_lines(&head, col, false, value, "", self.indent_sequences)?;
+                    self.lines.splice(at..at, lines);
+                    return Ok(());
+                }
+            }
+            (Edit::Set(_, value), Some(Node::Sequence(items)), _)
+            | (Edit::Insert(_, _, value), Some(Node::Sequence(items)), _) => {
+                if let Segment::Index(index) = *last
+                    && index <= items.len()
+                {
+                    let at = match items.get(index) {
+                        Some(item) => self.leading_comments(item),
+                        None => items[items.len() - 1].end,
+                    };
+                    let col = items[0].col;
+                    let lines = entry_lines(
+                        &format!("{}-", " ".repeat(col)),
+                        col,
+                        true,
+                        value,
+                        "",
+                        self.indent_sequences,
+                    )?;
+                    self.lines.splice(at..at, lines);
+                    return Ok(());
+                }
+            }
+            _ => {}
+        }
+        self.rewrite(&root, edit)
+    }
+
+    /// Applies `edit` to the closest ancestor of its target that the document has, replacing that
+    /// ancestor as a whole. Used when the edit cannot be made line by line.
+    fn rewrite(&mut self, root: &Node, edit: &Edit) -> Result<(), serde_yaml::Error> {
+        let path = edit.path();
+        let mut value = self.value()?;
+        let mut at = path.len() - 1;
+        while at > 0
+            && (entry_at(root, &path[..at]).is_none()
+                || value_at(&mut value, &path[..at]).is_none())
+        {
+            at -= 1;
+        }
+        let Some(node) = value_at(&mut value, &path[..at]) else {
+            return Ok(());
+        };
+        let before = node.clone();
+        edit_value(node, &path[at..], edit);
+        if *node == before {
+            return Ok(());
+        }
+        match entry_at(root, &path[..at]) {
+            Some(entry) => self.replace(entry, node),
+            None => self.replace_root(node),
+        }
+    }
+
+    /// Replaces the value of `entry`, keeping the comment on its first line.
+    fn replace(&mut self, entry: &Entry, value: &Value) -> Result<(), serde_yaml::Error> {
+        let line = &self.lines[entry.line];
+        let (head, comment) = (&line[..entry.value_col], &line[content(line).len()..]);
+        let old = content(line)[entry.value_col..].trim_start();
+        let lines = match quoted_like(old, value) {
+            Some(quoted) => vec![format!("{} {}{}", head, quoted, comment)],
+            None => entry_lines(
+                head,
+                entry.col,
+                entry.key.is_none(),
+                value,
+                comment,
+                self.indent_sequences,
+            )?,
+        };
+        self.lines.splice(entry.line..entry.end, lines);
+        Ok(())
+    }
+
+    /// Replaces everything after the comments at the top of the document.
+    fn replace_root(&mut self, value: &Value) -> Result<(), serde_yaml::Error> {
+        let first = self
+            .lines
+            .iter()
+            .position(|line| is_content(line))
+            .unwrap_or(self.lines.len());
+        self.lines.truncate(first);
+        match block(value, self.indent_sequences)? {
+            Some(lines) => self.lines.extend(lines),
+            None => self
+                .lines
+                .extend(serde_yaml::to_string(value)?.lines().map(str::to_owned)),
+        }
+        Ok(())
+    }
+
+    /// First line of the comments right above `entry`, which belong to it.
+    fn leading_comments(&self, entry: &Entry) -> usize {
+        let mut line = entry.line;
+        while line > 0 && self.lines[line - 1].trim_start().starts_with('#') {
+            line -= 1;
+        }
+        line
+    }
+}
+
+/// Where a value is in the lines of the document.
+#[derive(Debug)]
+enum Node {
+    Mapping(Vec<Entry>),
+    Sequence(Vec<Entry>),
+    /// Scalars, flow collections, aliases and anything else replaced as a whole.
+    Other,
+}
+
+/// `key: value` in a mapping or `- value` in a list.
+#[derive(Debug)]
+struct Entry {
+    /// `None` for list items.
+    key: Option<String>,
+    line: usize,
+    /// Column of the key or dash.
+    col: usize,
+    /// Column right after the `:` of the key or the dash.
+    value_col: usize,
+    /// Line after the last one of the value, comments and blank lines after it excluded.
+    end: usize,
+    value: Node,
+}
+
+impl Node {
+    fn entry(&self, segment: &Segment) -> Option<&Entry> {
+        match (self, segment) {
+            (Node::Mapping(entries), Segment::Key(key)) => {
+                entries.iter().find(|e| e.key.as_ref() == Some(key))
+            }
+            (Node::Sequence(items), Segment::Index(index)) => items.get(*index),
+            _ => None,
+        }
+    }
+}
+
+impl Entry {
+    /// Whether this is the first key of a mapping written on the line of a list item's dash.
+    fn is_compact(&self, lines: &[String]) -> bool {
+        indent(&lines[self.line]) != self.col
+    }
+}
+
+fn node_at<'a>(root: &'a Node, path: &[Segment]) -> Option<&'a Node> {
+    path.iter()
+        .try_fold(root, |node, segment| Some(&node.entry(segment)?.value))
+}
+
+fn entry_at<'a>(root: &'a Node, path: &[Segment]) -> Option<&'a Entry> {
+    let (last, parent) = path.split_last()?;
+    node_at(root, parent)?.entry(last)
+}
+
+fn indent(line: &str) -> usize {
+    line.len() - line.trim_start_matches(' ').len()
+}
+
+fn is_content(line: &str) -> bool {
+    let trimmed = line.trim_start();
+    !(trimmed.is_empty()
+        || trimmed.starts_with('#')
+        || line.starts_with("---")
+        || line.starts_with("..."))
+}
+
+/// `line` without its comment and the spaces before it.
+fn content(line: &str) -> &str {
+    let mut quote = None;
+    let mut escaped = false;
+    let mut prev = ' ';
+    for (i, c) in line.char_indices() {
+        match quote {
+            Some('"') if escaped => escaped = false,
+            Some('"') if c == '\\' => escaped = true,
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '#' && prev.is_whitespace() => return line[..i].trim_end(),
+            None if (c == '"' || c == '\'') && (prev.is_whitespace() || "[{,:-".contains(prev)) => {
+                quote = Some(c)
+            }
+            None => {}
+        }
+        prev = c;
+    }
+    line.trim_end()
+}
+
+fn is_dash(line: &str, col: usize) -> bool {
+    let rest = content(line).get(col..).unwrap_or("");
+    rest == "-" || rest.starts_with("- ")
+}
+
+/// The key starting at `col` of `line` and the column right after its `:`.
+fn key_at(line: &str, col: usize) -> Option<(String, usize)> {
+    let text = content(line).get(col..)?;
+    let (key, len) = match text.chars().next()? {
+        quote @ ('"' | '\'') => {
+            let close = text[1..].find(quote)? + 1;
+            (serde_yaml::from_str(&text[..=close]).ok()?, close + 1)
+        }
+        '-' if text.len() == 1 || text[1..].starts_with(' ') => return None,
+        '[' | '{' | '#' | '&' | '*' | '!' | '|' | '>' | '?' => return None,
+        _ => {
+            let len = text
+                .find(": ")
+                .or_else(|| text.ends_with(':').then(|| text.len() - 1))?;
+            (text[..len].trim_end().to_owned(), len)
+        }
+    };
+    let after = &text[len..];
+    (after == ":" || after.starts_with(": ")).then_some((key, col + len + 1))
+}
+
+fn parse(lines: &[String]) -> Node {
+    match lines.iter().position(|line| is_content(line)) {
+        Some(first) => parse_block(lines, first, indent(&lines[first]), lines.len()),
+        None => Node::Other,
+    }
+}
+
+/// The block node starting at `col` of line `first` and ending before line `end`.
+fn parse_block(lines: &[String], first: usize, col: usize, end: usize) -> Node {
+    if is_dash(&lines[first], col) {
+        parse_entries(lines, first, col, end, true)
+    } else if key_at(&lines[first], col).is_some() {
+        parse_entries(lines, first, col, end, false)
+    } else {
+        Node::Other
+    }
+}
+
+fn parse_entries(lines: &[String], first: usize, col: usize, end: usize, sequence: bool) -> Node {
+    let head = |line: usize| {
+        if sequence {
+            is_dash(&lines[line], col).then(|| (None, col + 1))
+        } else {
+            key_at(&lines[line], col).map(|(key, value_col)| (Some(key), value_col))
+        }
+    };
+    let starts: Vec<_> = (first..end)
+        .filter(|&line| line == first || (is_content(&lines[line]) && indent(&lines[line]) == col))
+        .filter_map(|line| Some((line, head(line)?)))
+        .collect();
+    let entries = starts
+        .iter()
+        .enumerate()
+        .map(|(n, (line, (key, value_col)))| {
+            let mut end = starts.get(n + 1).map_or(end, |next| next.0);
+            while end > line + 1 && !is_content(&lines[end - 1]) {
+                end -= 1;
+            }
+            Entry {
+                key: key.clone(),
+                line: *line,
+                col,
+                value_col: *value_col,
+                end,
+                value: parse_value(lines, *line, *value_col, end),
+            }
+        })
+        .collect();
+    if sequence {
+        Node::Sequence(entries)
+    } else {
+        Node::Mapping(entries)
+    }
+}
+
+/// The value starting at `col` of `line`, which may continue up to line `end`.
+fn parse_value(lines: &[String], line: usize, mut col: usize, end: usize) -> Node {
+    let text = content(&lines[line]);
+    // Anchors and tags come before the value they apply to.
+    loop {
+        let rest = text.get(col..).unwrap_or("");
+        let trimmed = rest.trim_start();
+        col += rest.len() - trimmed.len();
+        if !(trimmed.starts_with('&') || trimmed.starts_with('!')) {
+            break;
+        }
+        col += trimmed.find(' ').unwrap_or(trimmed.len());
+    }
+    if col < text.len() {
+        // On the line of its key or dash, only a mapping after a dash is a block node.
+        return match key_at(&lines[line], col) {
+            Some(_) => parse_entries(lines, line, col, end, false),
+            None => Node::Other,
+        };
+    }
+    match (line + 1..end).find(|&i| is_content(&lines[i])) {
+        Some(first) => parse_block(lines, first, indent(&lines[first]), end),
+        None => Node::Other,
+    }
+}
+
+fn render_key(key: &str) -> Result<String, serde_yaml::Error> {
+    Ok(serde_yaml::to_string(key)?.trim_end().to_owned())
+}
+
+/// The lines of an entry starting with `head` (its indentation and `key:` or `-`) followed by
+/// `value` and `comment`, with the block lines of the value indented past `col`.
+fn entry_lines(
+    head: &str,
+    col: usize,
+    item: bool,
+    value: &Value,
+    comment: &str,
+    indent_sequences: bool,
+) -> Result<Vec<String>, serde_yaml::Error> {
+    let (inline, block) = render(value, item, indent_sequences)?;
+    let first = format!("{}{}{}", head, prefixed(" ", &inline), comment);
+    Ok(std::iter::once(first)
+        .chain(block.iter().map(|line| prefixed(&" ".repeat(col), line)))
+        .collect())
+}
+
+/// `text` after `prefix`, or nothing when it is empty.
+fn prefixed(prefix: &str, text: &str) -> String {
+    if text.is_empty() {
+        String::new()
+    } else {
+        format!("{}{}", prefix, text)
+    }
+}
+
+/// What follows the key or dash holding `value` on its line, and the lines below it relative to
+/// the column of that key or dash. Lists in mappings are indented past their key when
+/// `indent_sequences` is set, as `serde_yaml` does not.
+fn render(
+    value: &Value,
+    item: bool,
+    indent_sequences: bool,
+) -> Result<(String, Vec<String>), serde_yaml::Error> {
+    let (tag, inner) = match value {
+        Value::Tagged(tagged) => (tagged.tag.to_string(), &tagged.value),
+        _ => (String::new(), value),
+    };
+    let Some(lines) = block(inner, indent_sequences)? else {
+        let mut lines = serde_yaml::to_string(value)?
+            .lines()
+            .map(str::to_owned)
+            .collect::<Vec<_>>();
+        let first = lines.remove(0);
+        return Ok((first, lines));
+    };
+    let shift = if inner.is_sequence() && !item && !indent_sequences {
+        ""
+    } else {
+        "  "
+    };
+    let mut lines: Vec<String> = lines.iter().map(|line| prefixed(shift, line)).collect();
+    if item && tag.is_empty() && inner.is_mapping() {
+        // `- key: value`, the first key goes on the line of the dash.
+        let first = lines.remove(0);
+        return Ok((first.trim_start().to_owned(), lines));
+    }
+    Ok((tag, lines))
+}
+
+/// The lines of a mapping or list starting at column 0, `None` for anything else or when it is
+/// empty.
+fn block(value: &Value, indent_sequences: bool) -> Result<Option<Vec<String>>, serde_yaml::Error> {
+    let mut lines = Vec::new();
+    match value {
+        Value::Mapping(map) if !map.is_empty() => {
+            for (key, value) in map {
+                let key = serde_yaml::to_string(key)?;
+                let (inline, block) = render(value, false, indent_sequences)?;
+                lines.push(format!("{}:{}", key.trim_end(), prefixed(" ", &inline)));
+                lines.extend(block);
+            }
+        }
+        Value::Sequence(seq) if !seq.is_empty() => {
+            for value in seq {
+                let (inline, block) = render(value, true, indent_sequences)?;
+                lines.push(format!("-{}", prefixed(" ", &inline)));
+                lines.extend(block);
+            }
+        }
+        _ => return Ok(None),
+    }
+    Ok(Some(lines))
+}
+
+/// Whether lists in mappings are indented past their key, when the document has any.
+fn indents_sequences(node: &Node) -> Option<bool> {
+    let (Node::Mapping(entries) | Node::Sequence(entries)) = node else {
+        return None;
+    };
+    entries.iter().find_map(|entry| match &entry.value {
+        Node::Sequence(items) if entry.key.is_some() => Some(items[0].col > entry.col),
+        value => indents_sequences(value),
+    })
+}
+
+/// `value` quoted the way `old` is, so replacing a quoted string keeps its quotes. Tagged strings
+/// like `!Name "main"` keep them too.
+fn quoted_like(old: &str, value: &Value) -> Option<String> {
+    let (tag, old, value) = match value {
+        Value::Tagged(tagged) => {
+            let tag = tagged.tag.to_string();
+            let old = old.strip_prefix(tag.as_str())?.strip_prefix(' ')?;
+            (format!("{} ", tag), old.trim_start(), &tagged.value)
+        }
+        _ => (String::new(), old, value),
+    };
+    let Value::String(text) = value else {
+        return None;
+    };
+    if text.contains('\n') {
+        return None;
+    }
+    let quoted = match old.chars().next()? {
+        '"' => serde_json::to_string(text).ok()?,
+        '\'' => format!("'{}'", text.replace('\'', "''")),
+        _ => return None,
+    };
+    Some(format!("{}{}", tag, quoted))
+}
+
+fn untag_mut(mut value: &mut Value) -> &mut Value {
+    while let Value::Tagged(tagged) = value {
+        value = &mut tagged.value;
+    }
+    value
+}
+
+fn value_at<'a>(value: &'a mut Value, path: &[Segment]) -> Option<&'a mut Value> {
+    path.iter()
+        .try_fold(value, |value, segment| match (untag_mut(value), segment) {
+            (Value::Mapping(map), Segment::Key(key)) => map.get_mut(key.as_str()),
+            (Value::Sequence(seq), Segment::Index(index)) => seq.get_mut(*index),
+            _ => None,
+        })
+}
+
+/// Makes `edit` to `value`, with `path` relative to it. Missing mappings on the way are created.
+fn edit_value(value: &mut Value, path: &[Segment], edit: &Edit) {
+    let Some((last, parent)) = path.split_last() else {
+        if let Edit::Set(_, new) = edit {
+            *value = new.clone();
+        }
+        return;
+    };
+    let mut current = value;
+    for segment in parent {
+        current = untag_mut(current);
+        if current.is_null() {
+            *current = Value::Mapping(Mapping::new());
+        }
+        current = match (current, segment) {
+            (Value::Mapping(map), Segment::Key(key)) => {
+                map.entry(Value::from(key.as_str())).or_insert(Value::Null)
+            }
+            (Value::Sequence(seq), Segment::Index(index)) if *index < seq.len() => &mut seq[*index],
+            _ => return,
+        };
+    }
+    let current = untag_mut(current);
+    if current.is_null() {
+        *current = match last {
+            Segment::Key(_) => Value::Mapping(Mapping::new()),
+            Segment::Index(_) => Value::Sequence(Vec::new()),
+        };
+    }
+    match (current, last, edit) {
+        (Value::Mapping(map), Segment::Key(key), Edit::Insert(_, Some(before), new))
+            if map.contains_key(before.as_str()) =>
+        {
+            let mut inserted = Mapping::new();
+            for (k, v) in std::mem::take(map) {
+                if k.as_str() == Some(before) {
+                    inserted.insert(Value::from(key.as_str()), new.clone());
+                }
+                inserted.insert(k, v);
+            }
+            *map = inserted;
+        }
+        (Value::Mapping(map), Segment::Key(key), Edit::Set(_, new) | Edit::Insert(_, _, new)) => {
+            map.insert(Value::from(key.as_str()), new.clone());
+        }
+        (Value::Mapping(map), Segment::Key(key), Edit::Remove(_)) => {
+            map.shift_remove(key.as_str());
+        }
+        (Value::Mapping(map), Segment::Key(key), Edit::Rename(_, to)) => {
+            *map = std::mem::take(map)
+                .into_iter()
+                .map(|(k, v)| match k.as_str() {
+                    Some(k) if k == key => (Value::from(to.as_str()), v),
+                    _ => (k, v),
+                })
+                .collect();
+        }
+        (Value::Sequence(seq), Segment::Index(index), Edit::Set(_, new)) => {
+            if *index < seq.len() {
+                seq[*index] = new.clone();
+            } else if *index == seq.len() {
+                seq.push(new.clone());
+            }
+        }
+        (Value::Sequence(seq), Segment::Index(index), Edit::Insert(_, _, new)) => {
+            seq.insert((*index).min(seq.len()), new.clone());
+        }
+        (Value::Sequence(seq), Segment::Index(index), Edit::Remove(_)) if *index < seq.len() => {
+            seq.remove(*index);
+        }
+        _ => {}
+    }
+}
+
+/// Longer lists are diffed item by item past their common start and end.
+const MAX_LCS_CELLS: usize = 1 << 22;
+
+/// Appends the edits turning `old` into `new` at `path`. Edits to lists run from the end
+/// backwards, so the indices of the edits that follow still point at the same items.
+fn diff(path: &mut Vec<Segment>, old: &Value, new: &Value, edits: &mut Vec<Edit>) {
+    if old == new {
+        return;
+    }
+    match (old, new) {
+        // The paths of the document only lead into tagged mappings and lists.
+        (Value::Tagged(old_tagged), Value::Tagged(new_tagged))
+            if old_tagged.tag == new_tagged.tag
+                && (old_tagged.value.is_mapping() || old_tagged.value.is_sequence()) =>
+        {
+            diff(path, &old_tagged.value, &new_tagged.value, edits);
+        }
+        (Value::Mapping(old_map), Value::Mapping(new_map))
+            if old_map.keys().chain(new_map.keys()).all(Value::is_string) =>
+        {
+            for key in old_map.keys().filter(|key| !new_map.contains_key(key)) {
+                path.push(Segment::Key(key.as_str().unwrap_or_default().to_owned()));
+                edits.push(Edit::Remove(path.clone()));
+                path.pop();
+            }
+            for (n, (key, value)) in new_map.iter().enumerate() {
+                path.push(Segment::Key(key.as_str().unwrap_or_default().to_owned()));
+                match old_map.get(key) {
+                    Some(old_value) => diff(path, old_value, value, edits),
+                    None => {
+                        let before = new_map
+                            .keys()
+                            .skip(n + 1)
+                            .find(|key| old_map.contains_key(key))
+                            .and_then(Value::as_str)
+                            .map(str::to_owned);
+                        edits.push(Edit::Insert(path.clone(), before, value.clone()));
+                    }
+                }
+                path.pop();
+            }
+        }
+        (Value::Sequence(old_seq), Value::Sequence(new_seq)) => {
+            diff_sequence(path, old_seq, new_seq, edits);
+        }
+        _ => edits.push(Edit::Set(path.clone(), new.clone())),
+    }
+}
+
+fn diff_sequence(path: &mut Vec<Segment>, old: &[Value], new: &[Value], edits: &mut Vec<Edit>) {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let (old_mid, new_mid) = (
+        &old[prefix..old.len() - suffix],
+        &new[prefix..new.len() - suffix],
+    );
+
+    // Items kept in between, from a longest common subsequence of the middle parts.
+    let mut kept = Vec::new();
+    if old_mid.len() * new_mid.len() <= MAX_LCS_CELLS {
+        let width = new_mid.len() + 1;
+        let mut table = vec![0u32; (old_mid.len() + 1) * width];
+        for i in (0..old_mid.len()).rev() {
+            for j in (0..new_mid.len()).rev() {
+                table[i * width + j] = if old_mid[i] == new_mid[j] {
+                    table[(i + 1) * width + j + 1] + 1
+                } else {
+                    table[(i + 1) * width + j].max(table[i * width + j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < old_mid.len() && j < new_mid.len() {
+            if old_mid[i] == new_mid[j] {
+                kept.push((prefix + i, prefix + j));
+                i += 1;
+                j += 1;
+            } else if table[(i + 1) * width + j] >= table[i * width + j + 1] {
+                i += 1;
+            } else {
+                j += 1;
+            }
+        }
+    }
+
+    // The gaps between kept items pair changed items up in order, the rest is removed or added.
+    let mut gaps = Vec::new();
+    let (mut old_start, mut new_start) = (prefix, prefix);
+    for &(i, j) in &kept {
+        gaps.push((old_start..i, new_start..j));
+        (old_start, new_start) = (i + 1, j + 1);
+    }
+    gaps.push((old_start..old.len() - suffix, new_start..new.len() - suffix));
+    for (old_gap, new_gap) in gaps.into_iter().rev() {
+        let paired = old_gap.len().min(new_gap.len());
+        for j in new_gap.clone().skip(paired).rev() {
+            path.push(Segment::Index(old_gap.start + paired));
+            edits.push(Edit::Insert(path.clone(), None, new[j].clone()));
+            path.pop();
+        }
+        for i in old_gap.clone().skip(paired).rev() {
+            path.push(Segment::Index(i));
+            edits.push(Edit::Remove(path.clone()));
+            path.pop();
+        }
+        for k in (0..paired).rev() {
+            path.push(Segment::Index(old_gap.start + k));
+            diff(
+                path,
+                &old[old_gap.start + k],
+                &new[new_gap.start + k],
+                edits,
+            );
+            path.pop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(key: &str) -> Segment {
+        Segment::Key(key.to_owned())
+    }
+
+    /// Applies `change` to the value of `source` and returns the edited document.
+    fn edited(source: &str, change: impl FnOnce(&mut Value)) -> String {
+        let mut document = YamlDocument::parse(source);
+        let mut value = document.value().unwrap();
+        change(&mut value);
+        document.update(&value).unwrap();
+        document.to_string()
+    }
+
+    #[test]
+    fn comments_and_blank_lines_survive_a_change() {
+        let source = "\
+# Release build
+version: \"1.0.4\" # pinned
+
+profiles:
+  # Only the hot path
+  - name: fast
+    passes: []
+";
+        let out = edited(source, |value| {
+            value["profiles"][0]["name"] = Value::from("slow");
+        });
+        assert_eq!(out, source.replace("name: fast", "name: slow"));
+    }
+
+    #[test]
+    fn anchors_and_aliases_survive_a_change_elsewhere() {
+        let source = "\
+defaults: &defaults
+  iterations: 2
+profiles:
+  - <<: *defaults
+    name: fast
+other: 1
+";
+        let out = edited(source, |value| {
+            value["other"] = Value::from(2);
+        });
+        assert_eq!(out, source.replace("other: 1", "other: 2"));
+    }
+
+    #[test]
+    fn key_order_is_kept_and_new_keys_go_last() {
+        let source = "zeta: 1\nalpha: 2\nmiddle: 3\n";
+        let out = edited(source, |value| {
+            value["alpha"] = Value::from(20);
+            value
+                .as_mapping_mut()
+                .unwrap()
+                .insert(Value::from("beta"), Value::from(4));
+        });
+        assert_eq!(out, "zeta: 1\nalpha: 20\nmiddle: 3\nbeta: 4\n");
+    }
+
+    #[test]
+    fn quoting_of_untouched_values_is_kept() {
+        let source = "single: 'a'\ndouble: \"b\"\nplain: c\nversion: \"1.0\"\n";
+        let out = edited(source, |value| {
+            value["plain"] = Value::from("d");
+        });
+        assert_eq!(out, source.replace("plain: c", "plain: d"));
+    }
+
+    #[test]
+    fn strings_that_read_as_other_types_are_quoted() {
+        let out = edited("version: x\n", |value| {
+            value["version"] = Value::from("1.0");
+        });
+        assert_eq!(
+            serde_yaml::from_str::<Value>(&out).unwrap()["version"],
+            Value::from("1.0")
+        );
+    }
+
+    #[test]
+    fn renamed_keys_keep_their_position_value_and_comment() {
+        let mut document = YamlDocument::parse("a: 1\nold: 2 # note\nc: 3\n");
+        document.rename_key(&[key("old")], "new").unwrap();
+        assert_eq!(document.to_string(), "a: 1\nnew: 2 # note\nc: 3\n");
+    }
+
+    #[test]
+    fn crlf_line_endings_are_kept() {
+        let out = edited("a: 1\r\nb: 2\r\n", |value| {
+            value["b"] = Value::from(3);
+        });
+        assert_eq!(out, "a: 1\r\nb: 3\r\n");
+    }
+
+    #[test]
+    fn paths_display_with_dots_and_indices() {
+        let path = [key("profiles"), Segment::Index(0), key("passes")];
+        assert_eq!(display_path(&path), "profiles[0].passes");
+    }
+}
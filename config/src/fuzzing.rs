@@ -0,0 +1,312 @@
+//! Structured generation of config values from raw fuzzer input.
+//!
+//! Enabled with the `fuzzing` feature, which implements [`arbitrary::Arbitrary`] for the config
+//! types. Fuzz targets can take a [`YamlConfig`] (or any pass struct) as their input, e.g.
+//! `fuzz_target!(|config: YamlConfig| ...)` with `cargo fuzz`/libFuzzer, and then exercise
+//! serialization round-trips or config handling with it. Generated values always satisfy the
+//! parse-time invariants of the validated types, so anything produced here must also survive a
+//! serialize/deserialize cycle.
+
+use crate::*;
+use arbitrary::{Arbitrary, Result, Unstructured};
+
+/// A chance within `0.0..=1.0`, matching how chances are used in the config. Never NaN or
+/// infinite, unlike an arbitrary `f64`.
+fn chance(u: &mut Unstructured<'_>) -> Result<f64> {
+    Ok(f64::from(u32::arbitrary(u)?) / f64::from(u32::MAX))
+}
+
+/// Extension fields: keys start with [`EXTENSION_PREFIX`] so they survive a round-trip.
+fn extensions(u: &mut Unstructured<'_>) -> Result<Extensions> {
+    u.arbitrary_iter::<(String, String)>()?
+        .map(|entry| {
+            let (key, value) = entry?;
+            Ok((
+                format!("{}{}", EXTENSION_PREFIX, key),
+                serde_json::Value::String(value),
+            ))
+        })
+        .collect()
+}
+
+/// Generates a field with its own [`Arbitrary`] impl, or with the function given.
+macro_rules! arbitrary_field {
+    ($u:ident) => {
+        Arbitrary::arbitrary($u)?
+    };
+    ($u:ident, $with:ident) => {
+        $with($u)?
+    };
+}
+
+/// Implements [`Arbitrary`] for a struct by generating each listed field in order, with
+/// `field: function` for fields that need a narrower range than their type.
+macro_rules! arbitrary_struct {
+    ($($ty:ident { $($field:ident $(: $with:ident)?),* $(,)? })*) => {
+        $(
+            impl<'a> Arbitrary<'a> for $ty {
+                fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+                    Ok(Self {
+                        $($field: arbitrary_field!(u $(, $with)?),)*
+                    })
+                }
+            }
+        )*
+    };
+}
+
+/// Implements [`Arbitrary`] for a fieldless enum by picking one of the listed variants.
+macro_rules! arbitrary_unit_enum {
+    ($($ty:ident { $($variant:ident),* $(,)? })*) => {
+        $(
+            impl<'a> Arbitrary<'a> for $ty {
+                fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+                    Ok(u.choose(&[$($ty::$variant),*])?.clone())
+                }
+            }
+        )*
+    };
+}
+
+arbitrary_unit_enum! {
+    MutationEngineExtension { Generic, SSE3, SSE42 }
+    PeEnvironment { UserMode, KernelMode, UEFI }
+    JunkFiller { Nop, PrefixedNop, MultiByteNop }
+    PaddingFill { Int3, Nop, Random }
+    UefiPhase { Pei, Dxe }
+    OverheadClass { None, Low, Medium, High }
+    HotFunctionPolicy { Warn, Downgrade }
+}
+
+arbitrary_struct! {
+    LifterSettings { lift_calls, calling_convention, max_stack_copy_size, split_on_calls_fallback }
+    OptimizationSettings {
+        constant_propagation,
+        instruction_combine,
+        dead_code_elim,
+        prune_useless_block_params,
+        iterations,
+        max_time_ms,
+        pass_iteration_caps,
+    }
+    OptimizationPassCaps {
+        constant_propagation,
+        instruction_combine,
+        dead_code_elim,
+        prune_useless_block_params,
+    }
+    JunkByteSettings { enabled, chance: chance, max_bytes, fillers }
+    FunctionPadding { min_bytes, max_bytes, fill }
+    AssemblerSettings {
+        shuffle_basic_blocks,
+        instruction_prefix,
+        random_prefix_chance: chance,
+        junk_bytes,
+        basic_block_alignment,
+        function_padding,
+    }
+    CompilerSettings { assembler_settings, optimization_settings, lifter_settings }
+    FakePdbString { enabled, value, mode }
+    CustomSectionName { enabled, value }
+    ModuleSettings {
+        ida_crasher,
+        import_protection,
+        pack_output_file,
+        obscure_entry_point,
+        clear_unwind_info,
+        fake_pdb_string,
+        custom_section_name,
+        uefi,
+        preserve_cfg_tables,
+        cet_compat,
+        output_constraints,
+        extensions: extensions,
+    }
+    OutputConstraints { max_size_growth_percent, max_section_entropy, max_new_imports }
+    UefiSettings { phase, no_runtime_relocations, avoid_packing }
+    Semantics { add, sub, and, xor, or, not, neg }
+    BitWidths { bit8, bit16, bit32, bit64 }
+    BitWidthWeights { bit8, bit16, bit32, bit64 }
+    SsaOrigins { normal, memop, fp_based_memop, sp_based_memop }
+    LoopEncodeSemantics { enabled, iterations, probability, semantics, bitwidths, bitwidth_weights, ethnicities }
+    MixedBooleanArithmetic { enabled, iterations, probability, semantics, bitwidths, bitwidth_weights, ethnicities }
+    MutationEngine { enabled,
+        iterations,
+        probability,
+        extension,
+        semantics,
+        bitwidths,
+        bitwidth_weights,
+        ethnicities,
+    }
+    SuppressConstants { enabled, mba_enhance, ethnicities }
+    ObscureConstants { enabled, mba_enhance, probability, iterations, bitwidths, ethnicities }
+    ObscureReferences { enabled, mba_enhance }
+    ObscureControlFlow { enabled, mba_enhance, probability }
+    TetherEndpoint { endpoint, port }
+    TetherExtraction { enabled,
+        min_extract_len,
+        endpoint,
+        port,
+        fallback_endpoints,
+        connect_timeout_ms,
+        use_tls,
+        server_public_key,
+    }
+    OpaqueBlockDuplication { enabled, iterations, probability }
+    SplitBlockPass { enabled, threshold }
+    LeaEncodeImm { enabled, mba_enhance, iterations, probability, ethnicities }
+    SigBreaker { enabled,
+        shuffle_insts,
+        random_segment_selector,
+        calling_convention,
+        shuffle_opcodes,
+        instruction_substitution,
+    }
+    DataProtection { enabled, reencrypt_after_access, mba_enhance }
+    DataRange { rva, size }
+    Profile { name, passes, compiler_settings, symbols, data_symbols, priority, seh_safe }
+    Config { module_settings, profiles }
+    DisassemblySettings {
+        allow_code_reads_and_writes,
+        allow_unknown_indirect_jumps,
+        allow_mismatched_branch_counts,
+        thunk_mismatched_branch_counts,
+        thunk_branch_target_identifiers,
+        thunk_no_prev_block,
+        thunk_data_references,
+        always_thunk_entry,
+        follow_faulting_instructions,
+        pass_interrupts,
+        pass_exceptions,
+        aggressive_pointer_analysis,
+        perform_relocation_analysis,
+        explore_catch_funclet_continuations,
+    }
+    DisassemblySettingsOverrides {
+        allow_code_reads_and_writes,
+        allow_unknown_indirect_jumps,
+        allow_mismatched_branch_counts,
+        thunk_mismatched_branch_counts,
+        thunk_branch_target_identifiers,
+        thunk_no_prev_block,
+        thunk_data_references,
+        always_thunk_entry,
+        follow_faulting_instructions,
+        pass_interrupts,
+        pass_exceptions,
+        aggressive_pointer_analysis,
+        perform_relocation_analysis,
+        explore_catch_funclet_continuations,
+    }
+    YamlProfile { name, passes, compiler_settings, symbols, priority, seh_safe, disassembly_overrides, extensions: extensions }
+    YamlConfig { version, disassembly_settings, module_settings, compiler_settings, profiles, hot_functions, timeouts, extensions: extensions }
+    HotFunction { symbol, max_overhead }
+    HotFunctions { policy, functions }
+    Timeouts { analysis_secs, obfuscation_secs }
+}
+
+impl<'a> Arbitrary<'a> for FakePdbStringMode {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(match u.choose_index(3)? {
+            0 => FakePdbStringMode::Fixed,
+            1 => FakePdbStringMode::RandomPerBuild,
+            _ => FakePdbStringMode::Template(u.arbitrary()?),
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for SectionName {
+    /// Printable ASCII of at most [`MAX_SECTION_NAME_LEN`] bytes, falling back to an empty name if
+    /// a reserved one comes out.
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let len = u.int_in_range(0..=MAX_SECTION_NAME_LEN)?;
+        let name = (0..len)
+            .map(|_| u.int_in_range(b'!'..=b'~').map(char::from))
+            .collect::<Result<String>>()?;
+        Ok(SectionName::new(name).unwrap_or_default())
+    }
+}
+
+impl<'a> Arbitrary<'a> for ServerPublicKey {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        const HEX: &[u8; 16] = b"0123456789abcdef";
+        let key = (0..SERVER_PUBLIC_KEY_HEX_LEN)
+            .map(|_| u.choose(HEX).map(|&digit| char::from(digit)))
+            .collect::<Result<String>>()?;
+        Ok(ServerPublicKey(key))
+    }
+}
+
+impl<'a> Arbitrary<'a> for ServerPublicKeys {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let mut keys = vec![ServerPublicKey::arbitrary(u)?];
+        keys.extend(Vec::<ServerPublicKey>::arbitrary(u)?);
+        Ok(ServerPublicKeys(keys))
+    }
+}
+
+impl<'a> Arbitrary<'a> for ParamValue {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(if u.arbitrary()? {
+            let (a, b): (u32, u32) = u.arbitrary()?;
+            ParamValue::Range {
+                min: a.min(b),
+                max: a.max(b),
+            }
+        } else {
+            ParamValue::Fixed(u.arbitrary()?)
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for BitsPerByte {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(BitsPerByte(chance(u)? * 8.0))
+    }
+}
+
+impl<'a> Arbitrary<'a> for CompilerSettingsSource {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(if u.arbitrary()? {
+            CompilerSettingsSource::Ref(u.arbitrary()?)
+        } else {
+            CompilerSettingsSource::Inline(u.arbitrary()?)
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for YamlSymbol {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(match u.choose_index(5)? {
+            0 => YamlSymbol::Name(u.arbitrary()?),
+            1 => YamlSymbol::Rva(u.arbitrary()?),
+            2 => YamlSymbol::DataName(u.arbitrary()?),
+            3 => YamlSymbol::DataRva(u.arbitrary()?),
+            _ => YamlSymbol::All,
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for ObfuscationPass {
+    /// Generates any known pass. Unknown passes are never produced.
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(match u.choose_index(15)? {
+            0 => ObfuscationPass::LoopEncodeSemantics(u.arbitrary()?),
+            1 => ObfuscationPass::MixedBooleanArithmetic(u.arbitrary()?),
+            2 => ObfuscationPass::MutationEngine(u.arbitrary()?),
+            3 => ObfuscationPass::TetherExtraction(u.arbitrary()?),
+            4 => ObfuscationPass::SplitBlockPass(u.arbitrary()?),
+            5 => ObfuscationPass::OpaqueBlockDuplication(u.arbitrary()?),
+            6 => ObfuscationPass::ObscureControlFlow(u.arbitrary()?),
+            7 => ObfuscationPass::LeaEncodeImm(u.arbitrary()?),
+            8 => ObfuscationPass::ObscureConstants(u.arbitrary()?),
+            9 => ObfuscationPass::SuppressConstants(u.arbitrary()?),
+            10 => ObfuscationPass::ObscureReferences(u.arbitrary()?),
+            11 => ObfuscationPass::SigBreaker(u.arbitrary()?),
+            12 => ObfuscationPass::DataProtection(u.arbitrary()?),
+            13 => ObfuscationPass::IDADecompilerCrasher,
+            _ => ObfuscationPass::AntiEmulator,
+        })
+    }
+}
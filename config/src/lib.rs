@@ -3,14 +3,102 @@
 //! the CodeDefender CLI and its backend services.
 //!
 //! This crate is intended to be consumed by tools that integrate with or generate CodeDefender config files.
+//!
+//! # Compatibility
+//!
+//! Serialized field names are part of the config format. When a field is renamed, the previous
+//! name is kept as a `#[serde(alias = "...")]` so existing configs keep parsing, and new fields are
+//! added with `#[serde(default)]`. Only changes that cannot be expressed this way bump
+//! [`MIN_YAML_CONFIG_VERSION`].
 
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+
+#[cfg(feature = "fuzzing")]
+pub mod fuzzing;
+#[cfg(feature = "persist")]
+pub mod persist;
 
 /// Current supported YAML config version.
 pub const YAML_CONFIG_VERSION: &str = "1.0.6";
 
+/// Oldest YAML config version that still parses with this crate.
+pub const MIN_YAML_CONFIG_VERSION: &str = "1.0.4";
+
+/// Parses a `major.minor.patch` version string.
+pub fn parse_config_version(version: &str) -> Option<(u32, u32, u32)> {
+    let mut parts = version.trim().split('.').map(|p| p.parse::<u32>().ok());
+    let version = (parts.next()??, parts.next()??, parts.next()??);
+    parts.next().is_none().then_some(version)
+}
+
+/// Whether a config written for `version` can be read by this crate, i.e. it lies between
+/// [`MIN_YAML_CONFIG_VERSION`] and [`YAML_CONFIG_VERSION`].
+pub fn is_supported_config_version(version: &str) -> bool {
+    let (Some(version), Some(min), Some(max)) = (
+        parse_config_version(version),
+        parse_config_version(MIN_YAML_CONFIG_VERSION),
+        parse_config_version(YAML_CONFIG_VERSION),
+    ) else {
+        return false;
+    };
+    (min..=max).contains(&version)
+}
+
+/// A deprecated field name and the name that replaced it, see [`CONFIG_MIGRATIONS`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConfigMigration {
+    /// Path to the mappings holding the field. `*` matches every key of a mapping or element of a
+    /// sequence.
+    pub path: &'static [&'static str],
+    /// Deprecated name, still accepted as a `#[serde(alias)]`.
+    pub from: &'static str,
+    /// Current name.
+    pub to: &'static str,
+}
+
+impl ConfigMigration {
+    /// Why the field was rewritten, for annotating migrated configs.
+    pub fn description(&self) -> String {
+        format!("`{}` was renamed to `{}`", self.from, self.to)
+    }
+}
+
+/// Field renames, applied in order by tools upgrading a config to [`YAML_CONFIG_VERSION`]. Every
+/// `#[serde(alias)]` in this crate has an entry here.
+pub const CONFIG_MIGRATIONS: &[ConfigMigration] = &[ConfigMigration {
+    path: &["profiles", "*", "passes", "*"],
+    from: "ssa_origins",
+    to: "ethnicities",
+}];
+
+/// Prefix of the extension fields kept in `extensions` by [`YamlConfig`], [`YamlProfile`] and
+/// [`ModuleSettings`]. Other unknown fields are dropped.
+pub const EXTENSION_PREFIX: &str = "x-";
+
+/// `x-` prefixed fields such as `x-owner` or `x-ticket`, kept for tools that store their own
+/// metadata in the config. CodeDefender ignores them.
+pub type Extensions = serde_json::Map<String, serde_json::Value>;
+
+/// Keeps the fields starting with [`EXTENSION_PREFIX`] out of those left over by a struct.
+fn extension_fields<'de, D>(deserializer: D) -> Result<Extensions, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let mut fields = Extensions::deserialize(deserializer)?;
+    fields.retain(|key, _| key.starts_with(EXTENSION_PREFIX));
+    Ok(fields)
+}
+
+/// Placeholder written in place of secret values by [`YamlConfig::redacted`].
+pub const REDACTED_PLACEHOLDER: &str = "<redacted>";
+
 /// Available SIMD extension types used by mutation engines.
-#[derive(Debug, Serialize, Deserialize, Clone)]
+///
+/// New extensions may be added in minor releases, so matches must include a wildcard arm.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
 pub enum MutationEngineExtension {
     /// All base instructions
     Generic,
@@ -21,9 +109,15 @@ pub enum MutationEngineExtension {
 }
 
 /// Supported PE environments.
-#[derive(Debug, Clone, Copy, Eq, PartialEq, PartialOrd, Ord, Serialize, Deserialize)]
+///
+/// New environments may be added in minor releases, so matches must include a wildcard arm.
+#[derive(
+    Default, Debug, Clone, Copy, Eq, PartialEq, PartialOrd, Ord, Serialize, Deserialize, Hash,
+)]
+#[non_exhaustive]
 pub enum PeEnvironment {
     /// User-mode PE (exe, dll)
+    #[default]
     UserMode,
     /// Kernel-mode PE (sys)
     KernelMode,
@@ -32,7 +126,7 @@ pub enum PeEnvironment {
 }
 
 /// Configuration settings for lifting x86 instructions into IR.
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
 pub struct LifterSettings {
     /// Whether to lift calls into IR.
     pub lift_calls: bool,
@@ -45,7 +139,7 @@ pub struct LifterSettings {
 }
 
 /// IR optimization settings.
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
 pub struct OptimizationSettings {
     /// Enable constant propagation.
     pub constant_propagation: bool,
@@ -57,10 +151,101 @@ pub struct OptimizationSettings {
     pub prune_useless_block_params: bool,
     /// Number of optimization iterations to run.
     pub iterations: u32,
+    /// Wall-clock budget for optimizing a single function (in milliseconds). Once exceeded the
+    /// remaining iterations are skipped. `None` means unlimited.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_time_ms: Option<u64>,
+    /// Per-pass iteration caps, applied on top of `iterations`.
+    #[serde(default)]
+    pub pass_iteration_caps: OptimizationPassCaps,
+}
+
+/// Upper bounds on how many times each optimization pass may run per function.
+/// `None` means the pass runs for every one of the global `iterations`.
+#[derive(Default, Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+pub struct OptimizationPassCaps {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub constant_propagation: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instruction_combine: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dead_code_elim: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prune_useless_block_params: Option<u32>,
+}
+
+/// Benign filler sequences that may be inserted between emitted instructions.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum JunkFiller {
+    /// Single byte `nop` (0x90).
+    Nop,
+    /// Operand-size prefixed `nop` (0x66 0x90).
+    PrefixedNop,
+    /// Intel recommended multi-byte `nop` forms (0x0F 0x1F ...).
+    MultiByteNop,
+}
+
+/// Inter-instruction junk byte insertion settings.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct JunkByteSettings {
+    /// Whether junk byte insertion is enabled.
+    #[serde(default)]
+    pub enabled: bool,
+    /// Chance of inserting junk after each instruction (0.0–1.0).
+    #[serde(default)]
+    pub chance: f64,
+    /// Maximum number of junk bytes inserted at a single location.
+    #[serde(default)]
+    pub max_bytes: u32,
+    /// Filler sequences that may be used. Only these are ever emitted.
+    #[serde(default = "default_junk_fillers")]
+    pub fillers: Vec<JunkFiller>,
+}
+
+fn default_junk_fillers() -> Vec<JunkFiller> {
+    vec![JunkFiller::Nop, JunkFiller::MultiByteNop]
+}
+
+impl Default for JunkByteSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            chance: 0.0,
+            max_bytes: 0,
+            fillers: default_junk_fillers(),
+        }
+    }
+}
+
+/// Byte used to fill padding emitted after functions.
+#[derive(Default, Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PaddingFill {
+    /// `int3` (0xCC), matching what MSVC emits between functions.
+    #[default]
+    Int3,
+    /// `nop` (0x90).
+    Nop,
+    /// Random bytes.
+    Random,
+}
+
+/// Padding emitted after each obfuscated function.
+#[derive(Default, Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+pub struct FunctionPadding {
+    /// Minimum number of padding bytes after each function.
+    #[serde(default)]
+    pub min_bytes: u32,
+    /// Maximum number of padding bytes after each function. A random amount in
+    /// `min_bytes..=max_bytes` is chosen per function.
+    #[serde(default)]
+    pub max_bytes: u32,
+    /// Byte used to fill the padding.
+    #[serde(default)]
+    pub fill: PaddingFill,
 }
 
 /// Assembler-level codegen settings.
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct AssemblerSettings {
     /// Whether to shuffle basic blocks.
     pub shuffle_basic_blocks: bool,
@@ -68,10 +253,19 @@ pub struct AssemblerSettings {
     pub instruction_prefix: String,
     /// Chance of randomly applying the prefix.
     pub random_prefix_chance: f64,
+    /// Junk byte insertion between instructions.
+    #[serde(default)]
+    pub junk_bytes: JunkByteSettings,
+    /// Alignment of basic blocks in bytes, must be a power of two. 0 or 1 disables alignment.
+    #[serde(default)]
+    pub basic_block_alignment: u32,
+    /// Padding emitted after each function.
+    #[serde(default)]
+    pub function_padding: FunctionPadding,
 }
 
 /// Compiler configuration (IR + codegen) for a profile.
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct CompilerSettings {
     /// Assembler settings.
     pub assembler_settings: AssemblerSettings,
@@ -81,26 +275,277 @@ pub struct CompilerSettings {
     pub lifter_settings: LifterSettings,
 }
 
+/// Placeholder substituted with a random token by [`FakePdbStringMode::Template`].
+pub const FAKE_PDB_RANDOM_PLACEHOLDER: &str = "{random}";
+
+/// How the fake PDB string value is chosen.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Hash)]
+pub enum FakePdbStringMode {
+    /// Use `value` verbatim.
+    #[default]
+    Fixed,
+    /// Generate a fresh random `<token>.pdb` path for every build.
+    RandomPerBuild,
+    /// Substitute every `{random}` in the template with a fresh random token for every build.
+    Template(String),
+}
+
 /// Fake PDB string settings to confuse debuggers.
-#[derive(Default, Debug, Serialize, Deserialize)]
+#[derive(Default, Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
 pub struct FakePdbString {
     /// Whether the fake PDB string is enabled.
     pub enabled: bool,
-    /// Value to emit as the fake PDB string.
+    /// Value to emit as the fake PDB string. Only used as-is in [`FakePdbStringMode::Fixed`].
     pub value: String,
+    /// How the emitted value is chosen.
+    #[serde(default)]
+    pub mode: FakePdbStringMode,
+}
+
+impl FakePdbString {
+    /// Computes the value to emit for this build according to [`FakePdbString::mode`].
+    pub fn generate_value(&self) -> String {
+        match &self.mode {
+            FakePdbStringMode::Fixed => self.value.clone(),
+            FakePdbStringMode::RandomPerBuild => format!("{}.pdb", random_token()),
+            FakePdbStringMode::Template(template) => {
+                template.replace(FAKE_PDB_RANDOM_PLACEHOLDER, &random_token())
+            }
+        }
+    }
+
+    /// Replaces a randomized mode with the concrete value chosen for this build and returns it.
+    ///
+    /// After resolving, `mode` is [`FakePdbStringMode::Fixed`] so the value is stable from here on.
+    pub fn resolve(&mut self) -> &str {
+        self.value = self.generate_value();
+        self.mode = FakePdbStringMode::Fixed;
+        &self.value
+    }
+}
+
+/// Produces a 16 character random hex token using the std hasher seed.
+fn random_token() -> String {
+    use std::hash::{BuildHasher, Hasher};
+    let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+    if let Ok(elapsed) = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH) {
+        hasher.write_u128(elapsed.as_nanos());
+    }
+    format!("{:016x}", hasher.finish())
+}
+
+/// Maximum length of a PE section name in bytes.
+pub const MAX_SECTION_NAME_LEN: usize = 8;
+
+/// Section names that are already used by the PE loader or toolchain and cannot be reused.
+pub const RESERVED_SECTION_NAMES: &[&str] = &[
+    ".reloc", ".rsrc", ".pdata", ".xdata", ".idata", ".edata", ".tls", ".data", ".rdata", ".bss",
+    ".CRT", ".debug",
+];
+
+/// Reasons a [`SectionName`] can be rejected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SectionNameError {
+    /// Name is longer than [`MAX_SECTION_NAME_LEN`] bytes.
+    TooLong(String),
+    /// Name contains non-ASCII or control characters.
+    NotAscii(String),
+    /// Name collides with one of [`RESERVED_SECTION_NAMES`].
+    Reserved(String),
+}
+
+impl fmt::Display for SectionNameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SectionNameError::TooLong(name) => write!(
+                f,
+                "section name `{name}` is longer than {MAX_SECTION_NAME_LEN} bytes"
+            ),
+            SectionNameError::NotAscii(name) => write!(
+                f,
+                "section name `{name}` must only contain printable ASCII characters"
+            ),
+            SectionNameError::Reserved(name) => {
+                write!(f, "section name `{name}` is reserved")
+            }
+        }
+    }
+}
+
+impl std::error::Error for SectionNameError {}
+
+/// A PE section name, validated at parse time.
+///
+/// Must be at most [`MAX_SECTION_NAME_LEN`] bytes of printable ASCII and must not be one of
+/// [`RESERVED_SECTION_NAMES`]. An empty name is accepted and means "not set".
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Hash)]
+#[serde(try_from = "String", into = "String")]
+pub struct SectionName(String);
+
+impl SectionName {
+    /// Validates and wraps a section name.
+    pub fn new(name: impl Into<String>) -> Result<Self, SectionNameError> {
+        let name = name.into();
+        if name.len() > MAX_SECTION_NAME_LEN {
+            return Err(SectionNameError::TooLong(name));
+        }
+        if !name.bytes().all(|b| b.is_ascii_graphic()) {
+            return Err(SectionNameError::NotAscii(name));
+        }
+        if RESERVED_SECTION_NAMES
+            .iter()
+            .any(|reserved| reserved.eq_ignore_ascii_case(&name))
+        {
+            return Err(SectionNameError::Reserved(name));
+        }
+        Ok(Self(name))
+    }
+
+    /// The section name as a string slice.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Whether no name has been set.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl TryFrom<String> for SectionName {
+    type Error = SectionNameError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Self::new(value)
+    }
+}
+
+impl From<SectionName> for String {
+    fn from(value: SectionName) -> Self {
+        value.0
+    }
+}
+
+impl fmt::Display for SectionName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
 }
 
 /// Custom `.text` section name override.
-#[derive(Default, Debug, Serialize, Deserialize)]
+#[derive(Default, Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
 pub struct CustomSectionName {
     /// Whether this feature is enabled.
     pub enabled: bool,
     /// Custom section name value.
-    pub value: String,
+    pub value: SectionName,
+}
+
+/// Boot phase a UEFI image runs in.
+#[derive(Default, Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum UefiPhase {
+    /// Pre-EFI Initialization module, usually executed in place from flash before memory is up.
+    Pei,
+    /// Driver Execution Environment driver or UEFI application.
+    #[default]
+    Dxe,
+}
+
+/// Constraints of UEFI firmware images. Ignored for user-mode and kernel-mode modules.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+pub struct UefiSettings {
+    /// Boot phase the image runs in.
+    #[serde(default)]
+    pub phase: UefiPhase,
+    /// The image is a runtime driver that keeps running after `ExitBootServices`, so obfuscated
+    /// code must not rely on relocations beyond the ones applied by `SetVirtualAddressMap`.
+    #[serde(default)]
+    pub no_runtime_relocations: bool,
+    /// Refuse `pack_output_file` for this image. Only turn this off when the loader is known to
+    /// handle packed images.
+    #[serde(default = "default_true")]
+    pub avoid_packing: bool,
+}
+
+impl Default for UefiSettings {
+    fn default() -> Self {
+        Self {
+            phase: UefiPhase::default(),
+            no_runtime_relocations: false,
+            avoid_packing: true,
+        }
+    }
+}
+
+/// Shannon entropy in bits per byte, between 0 and 8.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Serialize, Deserialize)]
+#[serde(try_from = "f64", into = "f64")]
+pub struct BitsPerByte(f64);
+
+impl BitsPerByte {
+    /// The entropy value.
+    pub fn get(self) -> f64 {
+        self.0
+    }
+}
+
+impl TryFrom<f64> for BitsPerByte {
+    type Error = String;
+
+    fn try_from(value: f64) -> Result<Self, Self::Error> {
+        if (0.0..=8.0).contains(&value) {
+            Ok(Self(value))
+        } else {
+            Err(format!(
+                "entropy {value} is outside of 0 to 8 bits per byte"
+            ))
+        }
+    }
+}
+
+impl From<BitsPerByte> for f64 {
+    fn from(value: BitsPerByte) -> Self {
+        value.0
+    }
+}
+
+// Construction rejects NaN, so the bit pattern is a valid identity.
+impl Eq for BitsPerByte {}
+
+impl std::hash::Hash for BitsPerByte {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.0.to_bits().hash(state);
+    }
+}
+
+/// Limits on the obfuscated artifact, enforced by the CLI after download.
+///
+/// Large size growth, high-entropy sections and unexpected imports are common antivirus
+/// heuristics. Unset limits are not checked.
+#[derive(Default, Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+pub struct OutputConstraints {
+    /// Maximum growth of the output file over the input, in percent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_size_growth_percent: Option<u32>,
+    /// Maximum entropy of any section in the output.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_section_entropy: Option<BitsPerByte>,
+    /// Maximum number of imported functions the output may add over the input.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_new_imports: Option<u32>,
+}
+
+impl OutputConstraints {
+    /// Whether no limit is set.
+    pub fn is_empty(&self) -> bool {
+        self.max_size_growth_percent.is_none()
+            && self.max_section_entropy.is_none()
+            && self.max_new_imports.is_none()
+    }
 }
 
 /// Global obfuscation settings for the module.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
 pub struct ModuleSettings {
     /// Whether to crash the IDA decompiler intentionally.
     #[serde(default)]
@@ -124,10 +569,34 @@ pub struct ModuleSettings {
     /// Custom PE section name settings.
     #[serde(default)]
     pub custom_section_name: CustomSectionName,
+    /// UEFI-specific constraints.
+    #[serde(default)]
+    pub uefi: UefiSettings,
+    /// Keep the Control Flow Guard function table valid so the module still loads with CFG
+    /// enforced.
+    #[serde(default)]
+    pub preserve_cfg_tables: bool,
+    /// Keep obfuscated code compatible with CET shadow stacks (hardware-enforced stack protection).
+    #[serde(default)]
+    pub cet_compat: bool,
+    /// Limits the obfuscated artifact must stay within.
+    #[serde(default, skip_serializing_if = "OutputConstraints::is_empty")]
+    pub output_constraints: OutputConstraints,
+    /// Extension fields, see [`Extensions`].
+    #[serde(flatten, deserialize_with = "extension_fields")]
+    pub extensions: Extensions,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn is_true(value: &bool) -> bool {
+    *value
 }
 
 /// Instruction-level semantics used in transformations.
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
 pub struct Semantics {
     #[serde(default)]
     pub add: bool,
@@ -145,8 +614,89 @@ pub struct Semantics {
     pub neg: bool,
 }
 
+/// A pass parameter that is either a fixed value or a range sampled independently for every
+/// protected function.
+///
+/// In YAML a fixed value is written as a plain number (`probability: 50`) and a range as a map
+/// (`probability: { min: 25, max: 75 }`). Both bounds are inclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Hash)]
+#[serde(try_from = "ParamValueRepr", into = "ParamValueRepr")]
+pub enum ParamValue {
+    /// The same value for every function.
+    Fixed(u32),
+    /// A value sampled from `min..=max` per function.
+    Range { min: u32, max: u32 },
+}
+
+impl ParamValue {
+    /// Smallest value this parameter can take.
+    pub fn min(&self) -> u32 {
+        match *self {
+            ParamValue::Fixed(value) => value,
+            ParamValue::Range { min, .. } => min,
+        }
+    }
+
+    /// Largest value this parameter can take.
+    pub fn max(&self) -> u32 {
+        match *self {
+            ParamValue::Fixed(value) => value,
+            ParamValue::Range { max, .. } => max,
+        }
+    }
+
+    /// Picks a value using caller supplied randomness, so the sampling stays deterministic for a
+    /// seed. A range built with `min` above `max` samples between the two.
+    pub fn sample(&self, random: u64) -> u32 {
+        match *self {
+            ParamValue::Fixed(value) => value,
+            ParamValue::Range { min, max } => {
+                let span = u64::from(min.abs_diff(max)) + 1;
+                min.min(max) + (random % span) as u32
+            }
+        }
+    }
+}
+
+impl From<u32> for ParamValue {
+    fn from(value: u32) -> Self {
+        ParamValue::Fixed(value)
+    }
+}
+
+/// On-disk representation of [`ParamValue`].
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(untagged)]
+enum ParamValueRepr {
+    Fixed(u32),
+    Range { min: u32, max: u32 },
+}
+
+impl TryFrom<ParamValueRepr> for ParamValue {
+    type Error = String;
+
+    fn try_from(value: ParamValueRepr) -> Result<Self, Self::Error> {
+        match value {
+            ParamValueRepr::Fixed(value) => Ok(ParamValue::Fixed(value)),
+            ParamValueRepr::Range { min, max } if min > max => {
+                Err(format!("range minimum {min} is greater than maximum {max}"))
+            }
+            ParamValueRepr::Range { min, max } => Ok(ParamValue::Range { min, max }),
+        }
+    }
+}
+
+impl From<ParamValue> for ParamValueRepr {
+    fn from(value: ParamValue) -> Self {
+        match value {
+            ParamValue::Fixed(value) => ParamValueRepr::Fixed(value),
+            ParamValue::Range { min, max } => ParamValueRepr::Range { min, max },
+        }
+    }
+}
+
 /// Bit widths to apply transformations to.
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
 pub struct BitWidths {
     #[serde(default)]
     pub bit8: bool,
@@ -158,10 +708,47 @@ pub struct BitWidths {
     pub bit64: bool,
 }
 
+/// Per bit width probability weights (0–100) of the Loop Encode Semantics, Mixed Boolean
+/// Arithmetic and Mutation Engine passes.
+///
+/// When a pass sets `bitwidth_weights`, they are used instead of its boolean `bitwidths`.
+/// Otherwise the enabled widths weigh 100 and the others 0, see [`BitWidthWeights::resolve`]. A
+/// weight of 0 skips that width entirely, 100 always considers it.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+pub struct BitWidthWeights {
+    #[serde(default)]
+    pub bit8: u32,
+    #[serde(default)]
+    pub bit16: u32,
+    #[serde(default)]
+    pub bit32: u32,
+    #[serde(default)]
+    pub bit64: u32,
+}
+
+impl From<&BitWidths> for BitWidthWeights {
+    fn from(value: &BitWidths) -> Self {
+        let weight = |enabled: bool| if enabled { 100 } else { 0 };
+        Self {
+            bit8: weight(value.bit8),
+            bit16: weight(value.bit16),
+            bit32: weight(value.bit32),
+            bit64: weight(value.bit64),
+        }
+    }
+}
+
+impl BitWidthWeights {
+    /// The weights a pass uses, preferring explicit `weights` over its boolean `bitwidths`.
+    pub fn resolve(bitwidths: &BitWidths, weights: Option<&BitWidthWeights>) -> Self {
+        weights.cloned().unwrap_or_else(|| bitwidths.into())
+    }
+}
+
 /// The origin of SSA value from within the instruction.
 /// Please refer to this documentation for more info:
 /// https://docs.codedefender.io/features/ethnicity
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
 pub struct SsaOrigins {
     pub normal: bool,
     pub memop: bool,
@@ -170,78 +757,272 @@ pub struct SsaOrigins {
 }
 
 /// Configuration for the Loop Encode Semantics pass.
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
 pub struct LoopEncodeSemantics {
+    /// Whether the pass runs.
+    #[serde(default = "default_true", skip_serializing_if = "is_true")]
+    pub enabled: bool,
     /// Number of times to attempt transformation.
-    pub iterations: u32,
+    pub iterations: ParamValue,
     /// Percent chance to apply transformation (0–100).
-    pub probability: u32,
+    pub probability: ParamValue,
     /// Instruction semantics to consider.
     pub semantics: Semantics,
     /// Bit widths to target.
     pub bitwidths: BitWidths,
+    /// See [`BitWidthWeights`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bitwidth_weights: Option<BitWidthWeights>,
+    #[serde(alias = "ssa_origins")]
     pub ethnicities: SsaOrigins,
 }
 
 /// Configuration for Mixed Boolean Arithmetic pass.
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
 pub struct MixedBooleanArithmetic {
-    pub iterations: u32,
-    pub probability: u32,
+    /// Whether the pass runs.
+    #[serde(default = "default_true", skip_serializing_if = "is_true")]
+    pub enabled: bool,
+    pub iterations: ParamValue,
+    pub probability: ParamValue,
     pub semantics: Semantics,
     pub bitwidths: BitWidths,
+    /// See [`BitWidthWeights`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bitwidth_weights: Option<BitWidthWeights>,
+    #[serde(alias = "ssa_origins")]
     pub ethnicities: SsaOrigins,
 }
 
 /// Configuration for Mutation Engine pass.
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
 pub struct MutationEngine {
-    pub iterations: u32,
-    pub probability: u32,
+    /// Whether the pass runs.
+    #[serde(default = "default_true", skip_serializing_if = "is_true")]
+    pub enabled: bool,
+    pub iterations: ParamValue,
+    pub probability: ParamValue,
     pub extension: MutationEngineExtension,
     pub semantics: Semantics,
     pub bitwidths: BitWidths,
+    /// See [`BitWidthWeights`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bitwidth_weights: Option<BitWidthWeights>,
+    #[serde(alias = "ssa_origins")]
     pub ethnicities: SsaOrigins,
 }
 
 /// Pass that crashes IDA’s decompiler.
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
 pub struct IDADecompilerCrasher;
 
 /// Suppress constants and prevent them from rematerializing at runtime.
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
 pub struct SuppressConstants {
+    /// Whether the pass runs.
+    #[serde(default = "default_true", skip_serializing_if = "is_true")]
+    pub enabled: bool,
     pub mba_enhance: bool,
+    #[serde(alias = "ssa_origins")]
     pub ethnicities: SsaOrigins,
 }
 
 /// Statically obscure constants, this does not prevent rematerialization at runtime.
 /// Use the SuppressConstants pass in tandem with this!
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
 pub struct ObscureConstants {
+    /// Whether the pass runs.
+    #[serde(default = "default_true", skip_serializing_if = "is_true")]
+    pub enabled: bool,
     pub mba_enhance: bool,
-    pub probability: u32,
-    pub iterations: u32,
+    pub probability: ParamValue,
+    pub iterations: ParamValue,
     pub bitwidths: BitWidths,
+    #[serde(alias = "ssa_origins")]
     pub ethnicities: SsaOrigins,
 }
 
 /// Memory reference obfuscation pass.
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
 pub struct ObscureReferences {
+    /// Whether the pass runs.
+    #[serde(default = "default_true", skip_serializing_if = "is_true")]
+    pub enabled: bool,
     pub mba_enhance: bool,
 }
 
 /// Control-flow obfuscation pass.
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
 pub struct ObscureControlFlow {
+    /// Whether the pass runs.
+    #[serde(default = "default_true", skip_serializing_if = "is_true")]
+    pub enabled: bool,
     pub mba_enhance: bool,
-    pub probability: u32,
+    pub probability: ParamValue,
+}
+
+/// Default tether server connection timeout in milliseconds.
+pub const DEFAULT_TETHER_CONNECT_TIMEOUT_MS: u32 = 5000;
+
+fn default_tether_connect_timeout_ms() -> u32 {
+    DEFAULT_TETHER_CONNECT_TIMEOUT_MS
+}
+
+/// Additional tether server tried when the primary endpoint is unreachable.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+pub struct TetherEndpoint {
+    /// Tether server endpoint
+    pub endpoint: String,
+    /// Tether server port
+    pub port: u16,
+}
+
+/// Length of a hex-encoded tether server public key.
+pub const SERVER_PUBLIC_KEY_HEX_LEN: usize = 64;
+
+/// Reasons a [`ServerPublicKey`] can be rejected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ServerPublicKeyError {
+    /// Key is not [`SERVER_PUBLIC_KEY_HEX_LEN`] characters long.
+    InvalidLength(usize),
+    /// Key contains non-hex characters.
+    InvalidHex,
+    /// No keys were provided.
+    Empty,
+}
+
+impl fmt::Display for ServerPublicKeyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ServerPublicKeyError::InvalidLength(len) => write!(
+                f,
+                "server public key must be {SERVER_PUBLIC_KEY_HEX_LEN} hex characters, got {len}"
+            ),
+            ServerPublicKeyError::InvalidHex => {
+                write!(f, "server public key must only contain hex characters")
+            }
+            ServerPublicKeyError::Empty => write!(f, "at least one server public key is required"),
+        }
+    }
+}
+
+impl std::error::Error for ServerPublicKeyError {}
+
+/// Hex-encoded tether server public key, validated at parse time.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Hash)]
+#[serde(try_from = "String", into = "String")]
+pub struct ServerPublicKey(String);
+
+impl ServerPublicKey {
+    /// Validates and wraps a hex-encoded public key.
+    pub fn new(key: impl Into<String>) -> Result<Self, ServerPublicKeyError> {
+        let key = key.into();
+        if key.len() != SERVER_PUBLIC_KEY_HEX_LEN {
+            return Err(ServerPublicKeyError::InvalidLength(key.len()));
+        }
+        if !key.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(ServerPublicKeyError::InvalidHex);
+        }
+        Ok(Self(key))
+    }
+
+    /// An all-zero key, used as a stand-in when redacting configs.
+    pub fn zeroed() -> Self {
+        Self("0".repeat(SERVER_PUBLIC_KEY_HEX_LEN))
+    }
+
+    /// The key as a hex string.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl TryFrom<String> for ServerPublicKey {
+    type Error = ServerPublicKeyError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Self::new(value)
+    }
+}
+
+impl From<ServerPublicKey> for String {
+    fn from(value: ServerPublicKey) -> Self {
+        value.0
+    }
+}
+
+/// Either a single value or a list of values in YAML/JSON.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+enum OneOrMany<T> {
+    One(T),
+    Many(Vec<T>),
+}
+
+/// Ordered, non-empty list of pinned tether server public keys.
+///
+/// Serializes as a plain string when it holds a single key so existing configs keep their shape.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Hash)]
+#[serde(try_from = "OneOrMany<String>", into = "OneOrMany<ServerPublicKey>")]
+pub struct ServerPublicKeys(Vec<ServerPublicKey>);
+
+impl ServerPublicKeys {
+    /// Builds a key list, rejecting an empty one.
+    pub fn new(keys: Vec<ServerPublicKey>) -> Result<Self, ServerPublicKeyError> {
+        if keys.is_empty() {
+            return Err(ServerPublicKeyError::Empty);
+        }
+        Ok(Self(keys))
+    }
+
+    /// The currently active key (the first one).
+    pub fn primary(&self) -> &ServerPublicKey {
+        &self.0[0]
+    }
+
+    /// All pinned keys in validity order.
+    pub fn keys(&self) -> &[ServerPublicKey] {
+        &self.0
+    }
+}
+
+impl From<ServerPublicKey> for ServerPublicKeys {
+    fn from(value: ServerPublicKey) -> Self {
+        Self(vec![value])
+    }
+}
+
+impl TryFrom<OneOrMany<String>> for ServerPublicKeys {
+    type Error = ServerPublicKeyError;
+
+    fn try_from(value: OneOrMany<String>) -> Result<Self, Self::Error> {
+        match value {
+            OneOrMany::One(key) => Ok(ServerPublicKey::new(key)?.into()),
+            OneOrMany::Many(keys) => Self::new(
+                keys.into_iter()
+                    .map(ServerPublicKey::new)
+                    .collect::<Result<_, _>>()?,
+            ),
+        }
+    }
+}
+
+impl From<ServerPublicKeys> for OneOrMany<ServerPublicKey> {
+    fn from(mut value: ServerPublicKeys) -> Self {
+        if value.0.len() == 1 {
+            OneOrMany::One(value.0.remove(0))
+        } else {
+            OneOrMany::Many(value.0)
+        }
+    }
 }
 
 /// Tether extraction pass.
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
 pub struct TetherExtraction {
+    /// Whether the pass runs.
+    #[serde(default = "default_true", skip_serializing_if = "is_true")]
+    pub enabled: bool,
     /// Min length of a sequence of instructions that should be extracted.
     /// Its a bad idea for this to be 1 usually because its easy to synthesize
     pub min_extract_len: usize,
@@ -249,40 +1030,75 @@ pub struct TetherExtraction {
     pub endpoint: String,
     /// Tether server port
     pub port: u16,
-    /// Hex string of the servers public key. This is used for public key pinning.
-    /// This needs to be length 64...
-    pub server_public_key: String,
+    /// Fallback servers, tried in order after the primary endpoint.
+    #[serde(default)]
+    pub fallback_endpoints: Vec<TetherEndpoint>,
+    /// How long to wait for a connection to each server before moving on (in milliseconds).
+    #[serde(default = "default_tether_connect_timeout_ms")]
+    pub connect_timeout_ms: u32,
+    /// Wrap the tether connection in TLS.
+    #[serde(default)]
+    pub use_tls: bool,
+    /// Hex string(s) of the servers public key, used for public key pinning.
+    ///
+    /// Accepts a single key or a list. Keys are tried in order, so list the currently active key
+    /// first followed by the key(s) it is being rotated to.
+    pub server_public_key: ServerPublicKeys,
+}
+
+impl TetherExtraction {
+    /// All servers in the order they are tried: the primary endpoint followed by the fallbacks.
+    pub fn endpoints(&self) -> impl Iterator<Item = (&str, u16)> {
+        std::iter::once((self.endpoint.as_str(), self.port)).chain(
+            self.fallback_endpoints
+                .iter()
+                .map(|e| (e.endpoint.as_str(), e.port)),
+        )
+    }
 }
 
 /// Opaque block duplication pass.
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
 pub struct OpaqueBlockDuplication {
+    /// Whether the pass runs.
+    #[serde(default = "default_true", skip_serializing_if = "is_true")]
+    pub enabled: bool,
     /// Number of iterations to attempt transformation.
-    pub iterations: u32,
+    pub iterations: ParamValue,
     /// Percent chance to apply transformation (0–100).
-    pub probability: u32,
+    pub probability: ParamValue,
 }
 
 /// Split block pass, used to create more control flow points for other passes to transform.
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
 pub struct SplitBlockPass {
+    /// Whether the pass runs.
+    #[serde(default = "default_true", skip_serializing_if = "is_true")]
+    pub enabled: bool,
     /// The number of SSA values required to be within a block for it to be split into two seperate blocks.
     pub threshold: u32,
 }
 
 /// Encode immediate ssa values into lea's
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
 pub struct LeaEncodeImm {
+    /// Whether the pass runs.
+    #[serde(default = "default_true", skip_serializing_if = "is_true")]
+    pub enabled: bool,
     pub mba_enhance: bool,
     /// Number of iterations to attempt transformation.
-    pub iterations: u32,
+    pub iterations: ParamValue,
     /// Percent chance to apply transformation (0–100).
-    pub probability: u32,
+    pub probability: ParamValue,
+    #[serde(alias = "ssa_origins")]
     pub ethnicities: SsaOrigins,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
 pub struct SigBreaker {
+    /// Whether the pass runs.
+    #[serde(default = "default_true", skip_serializing_if = "is_true")]
+    pub enabled: bool,
     pub shuffle_insts: bool,
     pub random_segment_selector: bool,
     /// Calling convention used for lifting, only `WindowsAbi`, and `Conservative` are supported.
@@ -291,9 +1107,30 @@ pub struct SigBreaker {
     pub instruction_substitution: bool,
 }
 
+/// Encrypts protected data symbols in the image and decrypts them where they are accessed.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+pub struct DataProtection {
+    /// Whether the pass runs.
+    #[serde(default = "default_true", skip_serializing_if = "is_true")]
+    pub enabled: bool,
+    /// Encrypt the data again once the accessing function is done with it, so the plaintext only
+    /// lives in memory while it is in use.
+    #[serde(default)]
+    pub reencrypt_after_access: bool,
+    /// Obfuscate the decryption stubs with mixed boolean arithmetic.
+    #[serde(default)]
+    pub mba_enhance: bool,
+}
+
 /// All possible obfuscation passes.
-#[derive(Debug, Serialize, Deserialize, Clone)]
+///
+/// New passes are added regularly, so matches must include a wildcard arm. Build passes with the
+/// `From` impls of the pass structs (e.g. `ObfuscationPass::from(mutation_engine)`) and inspect
+/// them with [`ObfuscationPass::name`] and the `as_*` accessors.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
 #[serde(tag = "type")]
+#[cfg_attr(feature = "unknown-passes", serde(try_from = "serde_json::Value"))]
+#[non_exhaustive]
 pub enum ObfuscationPass {
     LoopEncodeSemantics(LoopEncodeSemantics),
     MixedBooleanArithmetic(MixedBooleanArithmetic),
@@ -307,12 +1144,100 @@ pub enum ObfuscationPass {
     SuppressConstants(SuppressConstants),
     ObscureReferences(ObscureReferences),
     SigBreaker(SigBreaker),
+    DataProtection(DataProtection),
     IDADecompilerCrasher,
     AntiEmulator,
+    /// A pass this version does not know about, kept verbatim so it survives a round-trip.
+    #[cfg(feature = "unknown-passes")]
+    #[serde(untagged, skip_deserializing)]
+    Unknown(UnknownPass),
+}
+
+/// `type` tags of every pass known to this version of the crate.
+pub const KNOWN_PASS_TYPES: &[&str] = &[
+    "LoopEncodeSemantics",
+    "MixedBooleanArithmetic",
+    "MutationEngine",
+    "TetherExtraction",
+    "SplitBlockPass",
+    "OpaqueBlockDuplication",
+    "ObscureControlFlow",
+    "LeaEncodeImm",
+    "ObscureConstants",
+    "SuppressConstants",
+    "ObscureReferences",
+    "SigBreaker",
+    "DataProtection",
+    "IDADecompilerCrasher",
+    "AntiEmulator",
+];
+
+/// Obfuscation pass with a `type` tag that is not in [`KNOWN_PASS_TYPES`].
+#[cfg(feature = "unknown-passes")]
+#[derive(Debug, Serialize, Clone, PartialEq, Eq, Hash)]
+#[serde(into = "serde_json::Value")]
+pub struct UnknownPass {
+    /// The pass `type` tag.
+    pub ty: String,
+    /// The complete pass object, including the `type` tag.
+    pub raw: serde_json::Value,
+}
+
+#[cfg(feature = "unknown-passes")]
+impl TryFrom<serde_json::Value> for ObfuscationPass {
+    type Error = String;
+
+    fn try_from(raw: serde_json::Value) -> Result<Self, Self::Error> {
+        let Some(ty) = raw.get("type").and_then(|t| t.as_str()).map(str::to_owned) else {
+            return Err("obfuscation pass is missing a `type` tag".to_owned());
+        };
+        let pass = match ty.as_str() {
+            "LoopEncodeSemantics" => {
+                serde_json::from_value(raw).map(ObfuscationPass::LoopEncodeSemantics)
+            }
+            "MixedBooleanArithmetic" => {
+                serde_json::from_value(raw).map(ObfuscationPass::MixedBooleanArithmetic)
+            }
+            "MutationEngine" => serde_json::from_value(raw).map(ObfuscationPass::MutationEngine),
+            "TetherExtraction" => {
+                serde_json::from_value(raw).map(ObfuscationPass::TetherExtraction)
+            }
+            "SplitBlockPass" => serde_json::from_value(raw).map(ObfuscationPass::SplitBlockPass),
+            "OpaqueBlockDuplication" => {
+                serde_json::from_value(raw).map(ObfuscationPass::OpaqueBlockDuplication)
+            }
+            "ObscureControlFlow" => {
+                serde_json::from_value(raw).map(ObfuscationPass::ObscureControlFlow)
+            }
+            "LeaEncodeImm" => serde_json::from_value(raw).map(ObfuscationPass::LeaEncodeImm),
+            "ObscureConstants" => {
+                serde_json::from_value(raw).map(ObfuscationPass::ObscureConstants)
+            }
+            "SuppressConstants" => {
+                serde_json::from_value(raw).map(ObfuscationPass::SuppressConstants)
+            }
+            "ObscureReferences" => {
+                serde_json::from_value(raw).map(ObfuscationPass::ObscureReferences)
+            }
+            "SigBreaker" => serde_json::from_value(raw).map(ObfuscationPass::SigBreaker),
+            "DataProtection" => serde_json::from_value(raw).map(ObfuscationPass::DataProtection),
+            "IDADecompilerCrasher" => Ok(ObfuscationPass::IDADecompilerCrasher),
+            "AntiEmulator" => Ok(ObfuscationPass::AntiEmulator),
+            _ => return Ok(ObfuscationPass::Unknown(UnknownPass { ty, raw })),
+        };
+        pass.map_err(|e| format!("invalid `{ty}` pass: {e}"))
+    }
+}
+
+#[cfg(feature = "unknown-passes")]
+impl From<UnknownPass> for serde_json::Value {
+    fn from(value: UnknownPass) -> Self {
+        value.raw
+    }
 }
 
 /// Profile definition used to apply passes to symbols.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct Profile {
     /// Name of the profile.
     pub name: String,
@@ -322,10 +1247,19 @@ pub struct Profile {
     pub compiler_settings: CompilerSettings,
     /// List of symbol RVAs this profile targets.
     pub symbols: Vec<u64>,
+    /// Data ranges protected by this profile's [`DataProtection`] pass.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub data_symbols: Vec<DataRange>,
+    /// Priority used to decide which profile wins when several target the same RVA.
+    #[serde(default)]
+    pub priority: i32,
+    /// Keep stack unwinding intact for this profile's symbols.
+    #[serde(default)]
+    pub seh_safe: bool,
 }
 
 /// Top-level config file structure.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct Config {
     /// Module-wide settings.
     pub module_settings: ModuleSettings,
@@ -334,7 +1268,7 @@ pub struct Config {
 }
 
 /// Information about a single function found during analysis.
-#[derive(Deserialize, Serialize, Clone, Debug)]
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct AnalysisFunction {
     /// RVA of the function.
     pub rva: u64,
@@ -345,7 +1279,7 @@ pub struct AnalysisFunction {
 }
 
 /// Reason why a function was rejected from analysis.
-#[derive(Deserialize, Serialize, Clone, Debug)]
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct AnalysisReject {
     /// RVA of the rejected function.
     pub rva: u64,
@@ -355,10 +1289,122 @@ pub struct AnalysisReject {
     pub ty: String,
     /// Stringified reason (human-readable).
     pub reason: String,
+    /// How serious the reject is, `None` when the service did not report it. See
+    /// [`AnalysisReject::effective_severity`].
+    #[serde(default)]
+    pub severity: Option<RejectSeverity>,
+    /// Passes that can still be applied to a [`RejectSeverity::Degraded`] function, by
+    /// [`ObfuscationPass::name`].
+    #[serde(default)]
+    pub safe_passes: Option<Vec<String>>,
+}
+
+/// How serious an [`AnalysisReject`] is.
+///
+/// New severities may be added in minor releases, so matches must include a wildcard arm.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[non_exhaustive]
+pub enum RejectSeverity {
+    /// The function cannot be protected.
+    Fatal,
+    /// The function can be protected anyway when the config names it.
+    ForceResolvable,
+    /// The function can only be protected by the passes in [`AnalysisReject::safe_passes`].
+    Degraded,
+}
+
+/// A suggested fix for functions rejected for a kind of reason, see [`REJECT_REMEDIATIONS`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RejectRemediation {
+    /// Matches an [`AnalysisReject::ty`] containing this, ignoring case and `_`.
+    pub ty_contains: &'static str,
+    /// What to change in the config.
+    pub suggestion: &'static str,
+}
+
+/// Suggested fixes for the reject reasons of the analysis, most specific first.
+pub const REJECT_REMEDIATIONS: &[RejectRemediation] = &[
+    RejectRemediation {
+        ty_contains: "readwrite",
+        suggestion: "the function reads or writes its own code; enable `allow_code_reads_and_writes` in \
+                     disassembly_settings",
+    },
+    RejectRemediation {
+        ty_contains: "indirectjump",
+        suggestion: "the function has irregular control flow; enable `allow_unknown_indirect_jumps` in \
+                     disassembly_settings, or protect it with SigBreaker only",
+    },
+    RejectRemediation {
+        ty_contains: "branchcount",
+        suggestion: "enable `thunk_mismatched_branch_counts` in disassembly_settings, or \
+                     `allow_mismatched_branch_counts` if the function is known to be well-formed",
+    },
+    RejectRemediation {
+        ty_contains: "branchtarget",
+        suggestion: "the function starts blocks with `endbr64`; enable `thunk_branch_target_identifiers` in \
+                     disassembly_settings",
+    },
+    RejectRemediation {
+        ty_contains: "noprev",
+        suggestion: "blocks are only reachable from outside the function; enable `thunk_no_prev_block` in \
+                     disassembly_settings",
+    },
+    RejectRemediation {
+        ty_contains: "datareference",
+        suggestion: "code is referenced as data; enable `thunk_data_references` in disassembly_settings",
+    },
+    RejectRemediation {
+        ty_contains: "fault",
+        suggestion: "the function contains instructions that fault on purpose; enable \
+                     `follow_faulting_instructions` in disassembly_settings",
+    },
+    RejectRemediation {
+        ty_contains: "interrupt",
+        suggestion: "the function raises software interrupts; enable `pass_interrupts` in disassembly_settings",
+    },
+    RejectRemediation {
+        ty_contains: "exception",
+        suggestion: "the function raises exceptions; enable `pass_exceptions` in disassembly_settings, and mark \
+                     its profile `seh_safe` if it relies on unwinding",
+    },
+];
+
+impl AnalysisReject {
+    /// The reported severity. Results without one treat `ReadWriteToCode` rejects as
+    /// [`RejectSeverity::ForceResolvable`] and every other reject as [`RejectSeverity::Fatal`].
+    pub fn effective_severity(&self) -> RejectSeverity {
+        match self.severity {
+            Some(severity) => severity,
+            None if self.ty == "ReadWriteToCode" => RejectSeverity::ForceResolvable,
+            None => RejectSeverity::Fatal,
+        }
+    }
+
+    /// Whether the function can still be protected by a profile applying `passes`, named as by
+    /// [`ObfuscationPass::name`].
+    pub fn protectable_with(&self, passes: &[&str]) -> bool {
+        match self.effective_severity() {
+            RejectSeverity::ForceResolvable => true,
+            RejectSeverity::Degraded => self
+                .safe_passes
+                .as_ref()
+                .is_some_and(|safe| passes.iter().all(|p| safe.iter().any(|s| s == p))),
+            _ => false,
+        }
+    }
+
+    /// The suggested fix for this reject from [`REJECT_REMEDIATIONS`], if its reason is known.
+    pub fn remediation(&self) -> Option<&'static str> {
+        let ty = self.ty.to_lowercase().replace('_', "");
+        REJECT_REMEDIATIONS
+            .iter()
+            .find(|r| ty.contains(r.ty_contains))
+            .map(|r| r.suggestion)
+    }
 }
 
 /// Grouping of functions under a named macro profile.
-#[derive(Deserialize, Serialize, Clone, Debug)]
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct AnalysisMacroProfile {
     /// Name of the macro profile.
     pub name: String,
@@ -366,8 +1412,114 @@ pub struct AnalysisMacroProfile {
     pub rvas: Vec<u64>,
 }
 
+/// A named data symbol found during analysis.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct AnalysisDataSymbol {
+    /// RVA of the data.
+    pub rva: u64,
+    /// Symbol name.
+    pub symbol: String,
+    /// Size of the data in bytes.
+    pub size: u32,
+}
+
+/// Module-level facts about the analyzed image, used by [`YamlConfig::validate_for_module`].
+#[derive(Default, Deserialize, Serialize, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct AnalysisModuleInfo {
+    /// RVA of the entry point, `None` for images without one (such as resource-only DLLs).
+    #[serde(default)]
+    pub entry_point: Option<u64>,
+    /// RVAs of the TLS callbacks, which run before the entry point.
+    #[serde(default)]
+    pub tls_callbacks: Vec<u64>,
+    /// Whether the image has a load config directory.
+    #[serde(default)]
+    pub load_config: bool,
+    /// Whether the image is built with Control Flow Guard.
+    #[serde(default)]
+    pub cfg: bool,
+    /// Whether the image has an exception directory with unwind info.
+    #[serde(default)]
+    pub exception_directory: bool,
+    /// Whether any function registers an exception handler in its unwind info (`__try`/`__except`
+    /// or C++ exceptions).
+    #[serde(default)]
+    pub seh: bool,
+    /// The import table, `None` when the service did not report it.
+    #[serde(default)]
+    pub imports: Option<Vec<AnalysisImport>>,
+    /// The export table, `None` when the service did not report it.
+    #[serde(default)]
+    pub exports: Option<Vec<AnalysisExport>>,
+}
+
+/// A DLL the image imports from.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct AnalysisImport {
+    /// Name of the DLL as written in the image.
+    pub library: String,
+    /// Imported functions, `#N` for imports by ordinal.
+    pub functions: Vec<String>,
+}
+
+impl AnalysisImport {
+    /// Whether the DLL is part of the C/C++ runtime, whose imports gain little from
+    /// `import_protection`.
+    pub fn is_runtime(&self) -> bool {
+        let library = self.library.to_ascii_lowercase();
+        [
+            "api-ms-win-crt-",
+            "vcruntime",
+            "ucrtbase",
+            "msvcp",
+            "msvcrt",
+        ]
+        .iter()
+        .any(|prefix| library.starts_with(prefix))
+    }
+}
+
+/// An entry of the image's export table.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct AnalysisExport {
+    /// Exported name, `None` for exports by ordinal only.
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Export ordinal.
+    pub ordinal: u32,
+    /// RVA of the exported code or data, `None` for forwarded exports.
+    #[serde(default)]
+    pub rva: Option<u64>,
+    /// `DLL.name` or `DLL.#N` the export is forwarded to.
+    #[serde(default)]
+    pub forward: Option<String>,
+}
+
+impl AnalysisExport {
+    /// Whether `exports` still has this export under the same name, ordinal and forwarder. The
+    /// RVA is not compared, since obfuscation moves code.
+    pub fn preserved_in(&self, exports: &[AnalysisExport]) -> bool {
+        exports
+            .iter()
+            .any(|e| e.name == self.name && e.ordinal == self.ordinal && e.forward == self.forward)
+    }
+}
+
+impl fmt::Display for AnalysisExport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.name {
+            Some(name) => write!(f, "{} (ordinal {})", name, self.ordinal)?,
+            None => write!(f, "#{}", self.ordinal)?,
+        }
+        if let Some(forward) = &self.forward {
+            write!(f, " -> {}", forward)?;
+        }
+        Ok(())
+    }
+}
+
 /// Results from binary analysis, returned to the frontend.
-#[derive(Deserialize, Serialize, Clone, Debug)]
+#[derive(Default, Deserialize, Serialize, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct AnalysisResult {
     /// Environment type (UserMode, KernelMode, UEFI).
     pub environment: PeEnvironment,
@@ -377,59 +1529,2478 @@ pub struct AnalysisResult {
     pub rejects: Vec<AnalysisReject>,
     /// Macro profiles generated from analysis.
     pub macros: Vec<AnalysisMacroProfile>,
+    /// Named data symbols, used to resolve [`YamlSymbol::DataName`].
+    #[serde(default)]
+    pub data_symbols: Vec<AnalysisDataSymbol>,
+    /// Entry point, TLS callbacks and exception handling of the image. `None` when the service
+    /// did not report them.
+    #[serde(default)]
+    pub module: Option<AnalysisModuleInfo>,
 }
 
-#[derive(Deserialize, Serialize, Clone, Debug)]
+/// Settings controlling how the backend disassembles and recovers functions before obfuscation.
+///
+/// `allow_*` and `pass_*` options accept more functions at the cost of a higher risk of
+/// miscompiling unusual code, while `thunk_*` options keep problematic code in place and jump to
+/// it instead of rejecting the whole function. See [`DisassemblySettings::strict`] and
+/// [`DisassemblySettings::lenient`] for ready-made presets. The [`Default`] preset is the balanced
+/// configuration shipped in the example config.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct DisassemblySettings {
+    /// Accept functions that read from or write to code bytes.
     pub allow_code_reads_and_writes: bool,
+    /// Accept functions containing indirect jumps whose targets cannot be resolved.
     pub allow_unknown_indirect_jumps: bool,
+    /// Accept functions whose recovered branch counts disagree with the original.
     pub allow_mismatched_branch_counts: bool,
+    /// Thunk blocks with mismatched branch counts instead of rejecting the function.
     pub thunk_mismatched_branch_counts: bool,
+    /// Thunk blocks that start with a branch target identifier (`endbr64`).
     pub thunk_branch_target_identifiers: bool,
+    /// Thunk blocks that have no previous block and are only reachable from outside.
     pub thunk_no_prev_block: bool,
+    /// Thunk blocks that are referenced as data.
     pub thunk_data_references: bool,
+    /// Always leave a thunk at the function entry so external callers keep working.
     pub always_thunk_entry: bool,
+    /// Keep disassembling past instructions that are expected to fault (e.g. `ud2`).
     pub follow_faulting_instructions: bool,
+    /// Accept functions containing software interrupts.
     pub pass_interrupts: bool,
+    /// Accept functions containing exception raising instructions.
     pub pass_exceptions: bool,
+    /// Treat more constants as potential code pointers. Finds more code, but risks false positives.
     pub aggressive_pointer_analysis: bool,
+    /// Use the relocation table to discover code pointers.
     pub perform_relocation_analysis: bool,
+    /// Explore continuation addresses of C++ catch funclets.
     pub explore_catch_funclet_continuations: bool,
 }
 
-/// Symbol representation used in YAML: either name or RVA.
-#[derive(Debug, Serialize, Deserialize)]
-pub enum YamlSymbol {
-    /// Symbol name
-    Name(String),
-    /// Symbol RVA.
-    Rva(u64),
-    /// All Symbols
-    All,
+impl DisassemblySettings {
+    /// Conservative preset: rejects anything unusual and thunks wherever possible.
+    ///
+    /// Produces the fewest protected functions but the lowest risk of broken output. Use this when
+    /// protecting hand-written assembly or binaries with heavy self-modifying code.
+    pub fn strict() -> Self {
+        Self {
+            allow_code_reads_and_writes: false,
+            allow_unknown_indirect_jumps: false,
+            allow_mismatched_branch_counts: false,
+            thunk_mismatched_branch_counts: true,
+            thunk_branch_target_identifiers: true,
+            thunk_no_prev_block: true,
+            thunk_data_references: true,
+            always_thunk_entry: true,
+            follow_faulting_instructions: false,
+            pass_interrupts: false,
+            pass_exceptions: false,
+            aggressive_pointer_analysis: false,
+            perform_relocation_analysis: true,
+            explore_catch_funclet_continuations: true,
+        }
+    }
+
+    /// Permissive preset: accepts as many functions as possible.
+    ///
+    /// Maximizes coverage, but unusual code patterns are more likely to be miscompiled. Always
+    /// test the output thoroughly when using this preset.
+    pub fn lenient() -> Self {
+        Self {
+            allow_code_reads_and_writes: true,
+            allow_unknown_indirect_jumps: true,
+            allow_mismatched_branch_counts: true,
+            thunk_mismatched_branch_counts: false,
+            thunk_branch_target_identifiers: false,
+            thunk_no_prev_block: false,
+            thunk_data_references: false,
+            always_thunk_entry: false,
+            follow_faulting_instructions: true,
+            pass_interrupts: true,
+            pass_exceptions: true,
+            aggressive_pointer_analysis: true,
+            perform_relocation_analysis: true,
+            explore_catch_funclet_continuations: true,
+        }
+    }
 }
 
-/// Obfuscation profile for YAML configuration.
-#[derive(Debug, Serialize, Deserialize)]
+impl Default for DisassemblySettings {
+    /// Balanced preset, suitable for typical compiler generated code.
+    fn default() -> Self {
+        Self {
+            allow_code_reads_and_writes: false,
+            allow_unknown_indirect_jumps: false,
+            allow_mismatched_branch_counts: true,
+            thunk_mismatched_branch_counts: true,
+            thunk_branch_target_identifiers: true,
+            thunk_no_prev_block: true,
+            thunk_data_references: true,
+            always_thunk_entry: true,
+            follow_faulting_instructions: true,
+            pass_interrupts: false,
+            pass_exceptions: false,
+            aggressive_pointer_analysis: false,
+            perform_relocation_analysis: true,
+            explore_catch_funclet_continuations: true,
+        }
+    }
+}
+
+/// Per-profile overrides of [`DisassemblySettings`] fields. Unset fields inherit the global value.
+#[derive(Default, Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+pub struct DisassemblySettingsOverrides {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allow_code_reads_and_writes: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allow_unknown_indirect_jumps: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allow_mismatched_branch_counts: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thunk_mismatched_branch_counts: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thunk_branch_target_identifiers: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thunk_no_prev_block: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thunk_data_references: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub always_thunk_entry: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub follow_faulting_instructions: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pass_interrupts: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pass_exceptions: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aggressive_pointer_analysis: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub perform_relocation_analysis: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub explore_catch_funclet_continuations: Option<bool>,
+}
+
+impl DisassemblySettingsOverrides {
+    /// Whether no field is overridden.
+    pub fn is_empty(&self) -> bool {
+        self.allow_code_reads_and_writes.is_none()
+            && self.allow_unknown_indirect_jumps.is_none()
+            && self.allow_mismatched_branch_counts.is_none()
+            && self.thunk_mismatched_branch_counts.is_none()
+            && self.thunk_branch_target_identifiers.is_none()
+            && self.thunk_no_prev_block.is_none()
+            && self.thunk_data_references.is_none()
+            && self.always_thunk_entry.is_none()
+            && self.follow_faulting_instructions.is_none()
+            && self.pass_interrupts.is_none()
+            && self.pass_exceptions.is_none()
+            && self.aggressive_pointer_analysis.is_none()
+            && self.perform_relocation_analysis.is_none()
+            && self.explore_catch_funclet_continuations.is_none()
+    }
+}
+
+impl DisassemblySettings {
+    /// Returns these settings with every field set in `overrides` replaced.
+    pub fn with_overrides(mut self, overrides: &DisassemblySettingsOverrides) -> Self {
+        if let Some(value) = overrides.allow_code_reads_and_writes {
+            self.allow_code_reads_and_writes = value;
+        }
+        if let Some(value) = overrides.allow_unknown_indirect_jumps {
+            self.allow_unknown_indirect_jumps = value;
+        }
+        if let Some(value) = overrides.allow_mismatched_branch_counts {
+            self.allow_mismatched_branch_counts = value;
+        }
+        if let Some(value) = overrides.thunk_mismatched_branch_counts {
+            self.thunk_mismatched_branch_counts = value;
+        }
+        if let Some(value) = overrides.thunk_branch_target_identifiers {
+            self.thunk_branch_target_identifiers = value;
+        }
+        if let Some(value) = overrides.thunk_no_prev_block {
+            self.thunk_no_prev_block = value;
+        }
+        if let Some(value) = overrides.thunk_data_references {
+            self.thunk_data_references = value;
+        }
+        if let Some(value) = overrides.always_thunk_entry {
+            self.always_thunk_entry = value;
+        }
+        if let Some(value) = overrides.follow_faulting_instructions {
+            self.follow_faulting_instructions = value;
+        }
+        if let Some(value) = overrides.pass_interrupts {
+            self.pass_interrupts = value;
+        }
+        if let Some(value) = overrides.pass_exceptions {
+            self.pass_exceptions = value;
+        }
+        if let Some(value) = overrides.aggressive_pointer_analysis {
+            self.aggressive_pointer_analysis = value;
+        }
+        if let Some(value) = overrides.perform_relocation_analysis {
+            self.perform_relocation_analysis = value;
+        }
+        if let Some(value) = overrides.explore_catch_funclet_continuations {
+            self.explore_catch_funclet_continuations = value;
+        }
+        self
+    }
+}
+
+/// Disassembly settings applied to the symbols of a single profile instead of the global ones.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+pub struct ProfileDisassemblySettings {
+    /// Name of the profile the settings come from.
+    pub profile: String,
+    /// Symbols the settings apply to.
+    pub symbols: Vec<YamlSymbol>,
+    /// Fully resolved settings (global settings with the profile overrides applied).
+    pub settings: DisassemblySettings,
+}
+
+/// A range of data in the image.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DataRange {
+    /// RVA of the first byte.
+    pub rva: u64,
+    /// Size of the range in bytes.
+    pub size: u32,
+}
+
+/// Symbol representation used in YAML: either name or RVA.
+///
+/// `Name`, `Rva` and `All` target functions, `DataName` and `DataRva` target data (global keys,
+/// tables) protected by a [`DataProtection`] pass.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+pub enum YamlSymbol {
+    /// Symbol name
+    Name(String),
+    /// Symbol RVA.
+    Rva(u64),
+    /// All Symbols
+    All,
+    /// Data symbol name, resolved through [`AnalysisResult::data_symbols`].
+    DataName(String),
+    /// Data range given by RVA and size.
+    DataRva(DataRange),
+}
+
+/// Compiler settings of a [`YamlProfile`]: written inline, or referencing a named template from
+/// [`YamlConfig::compiler_settings`].
+///
+/// ```yaml
+/// compiler_settings: !ref fast
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub enum CompilerSettingsSource {
+    /// Settings written directly in the profile.
+    Inline(CompilerSettings),
+    /// Name of a template in [`YamlConfig::compiler_settings`].
+    Ref(String),
+}
+
+impl From<CompilerSettings> for CompilerSettingsSource {
+    fn from(value: CompilerSettings) -> Self {
+        CompilerSettingsSource::Inline(value)
+    }
+}
+
+impl Serialize for CompilerSettingsSource {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            CompilerSettingsSource::Inline(settings) => settings.serialize(serializer),
+            CompilerSettingsSource::Ref(name) => {
+                serializer.serialize_newtype_variant("CompilerSettingsSource", 1, "ref", name)
+            }
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for CompilerSettingsSource {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::{self, EnumAccess, MapAccess, VariantAccess};
+
+        struct SourceVisitor;
+
+        impl<'de> de::Visitor<'de> for SourceVisitor {
+            type Value = CompilerSettingsSource;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("compiler settings or a `!ref <name>` to a named template")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+                CompilerSettings::deserialize(de::value::MapAccessDeserializer::new(map))
+                    .map(CompilerSettingsSource::Inline)
+            }
+
+            fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<Self::Value, A::Error> {
+                let (tag, variant): (String, _) = data.variant()?;
+                if tag != "ref" {
+                    return Err(de::Error::unknown_variant(&tag, &["ref"]));
+                }
+                variant.newtype_variant().map(CompilerSettingsSource::Ref)
+            }
+        }
+
+        deserializer.deserialize_any(SourceVisitor)
+    }
+}
+
+/// A profile referenced a compiler settings template that does not exist.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownCompilerSettingsError {
+    /// Profile holding the reference.
+    pub profile: String,
+    /// The missing template name.
+    pub name: String,
+}
+
+impl fmt::Display for UnknownCompilerSettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "profile `{}` references unknown compiler settings `{}`",
+            self.profile, self.name
+        )
+    }
+}
+
+impl std::error::Error for UnknownCompilerSettingsError {}
+
+/// Obfuscation profile for YAML configuration.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct YamlProfile {
     /// Profile name (referenced by source macros).
     pub name: String,
-    /// Passes to apply to this profile.
+    /// Passes to apply to this profile. Passes with `enabled: false` keep their settings here but
+    /// are dropped before submission.
     pub passes: Vec<ObfuscationPass>,
-    /// Compiler configuration for this profile.
-    pub compiler_settings: CompilerSettings,
+    /// Compiler configuration for this profile, either inline or a `!ref` to a named template.
+    pub compiler_settings: CompilerSettingsSource,
     /// Symbols targeted by this profile.
     pub symbols: Vec<YamlSymbol>,
+    /// When several profiles target the same symbol, the one with the highest priority keeps it
+    /// and the symbol is dropped from the others. Equal priorities are rejected as ambiguous.
+    #[serde(default)]
+    pub priority: i32,
+    /// Keep stack unwinding intact for this profile's symbols so C++ exceptions and SEH still work.
+    /// Unwind-destroying settings and passes are rejected by
+    /// [`YamlConfig::validate_for_environment`].
+    #[serde(default)]
+    pub seh_safe: bool,
+    /// Disassembly settings overridden for this profile's symbols only.
+    #[serde(
+        default,
+        skip_serializing_if = "DisassemblySettingsOverrides::is_empty"
+    )]
+    pub disassembly_overrides: DisassemblySettingsOverrides,
+    /// Extension fields, see [`Extensions`].
+    #[serde(flatten, deserialize_with = "extension_fields")]
+    pub extensions: Extensions,
+}
+
+/// Runtime overhead a pass adds to the functions it protects, from cheapest to most expensive.
+#[derive(
+    Default, Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+pub enum OverheadClass {
+    /// No protection at all.
+    None,
+    /// Small, mostly constant-factor cost (signature breaking, constant hiding).
+    Low,
+    /// Noticeable cost on tight loops (MBA, opaque blocks, obscured references).
+    Medium,
+    /// Large cost (mutation, loop encoding, control flow obscuring, tether round trips).
+    #[default]
+    High,
+}
+
+/// What to do with a hot function whose profile uses passes above its overhead class.
+#[derive(Default, Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HotFunctionPolicy {
+    /// Leave the profile as is and report the offending passes.
+    Warn,
+    /// Move the function to a copy of its profile without the offending passes.
+    #[default]
+    Downgrade,
+}
+
+/// A performance-critical function, typically taken from ETW or VTune profiles.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+pub struct HotFunction {
+    /// The function.
+    pub symbol: YamlSymbol,
+    /// Most expensive class of passes allowed on it.
+    pub max_overhead: OverheadClass,
+}
+
+/// Functions that must stay fast, and how to treat protection that would slow them down.
+///
+/// ```yaml
+/// hot_functions:
+///   policy: Downgrade
+///   functions:
+///     - symbol: !Name RenderFrame
+///       max_overhead: Low
+/// ```
+#[derive(Default, Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+pub struct HotFunctions {
+    /// What to do when a profile exceeds a function's overhead class.
+    #[serde(default)]
+    pub policy: HotFunctionPolicy,
+    /// The hot functions.
+    #[serde(default)]
+    pub functions: Vec<HotFunction>,
+}
+
+impl HotFunctions {
+    /// Whether no hot functions are listed.
+    pub fn is_empty(&self) -> bool {
+        self.functions.is_empty()
+    }
+
+    /// Whether the section can be left out, keeping a policy set without any functions.
+    fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// How long the CLI waits for each stage, in seconds. Unset stages use the CLI default.
+///
+/// ```yaml
+/// timeouts:
+///   analysis_secs: 600
+///   obfuscation_secs: 3600
+/// ```
+#[derive(Default, Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Timeouts {
+    /// Time allowed for analysis.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub analysis_secs: Option<u64>,
+    /// Time allowed for obfuscation.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub obfuscation_secs: Option<u64>,
+}
+
+impl Timeouts {
+    /// Whether neither timeout is set.
+    pub fn is_empty(&self) -> bool {
+        self.analysis_secs.is_none() && self.obfuscation_secs.is_none()
+    }
 }
 
 /// Root YAML config structure.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct YamlConfig {
     /// Version of the config file format.
     pub version: String,
-    /// The global disassembly settings.
+    /// The global disassembly settings. Falls back to [`DisassemblySettings::default`] when
+    /// omitted.
+    #[serde(default)]
     pub disassembly_settings: DisassemblySettings,
     /// Global module-wide obfuscation settings.
     pub module_settings: ModuleSettings,
+    /// Named compiler settings templates that profiles can reference with `!ref <name>`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub compiler_settings: BTreeMap<String, CompilerSettings>,
     /// Obfuscation profiles to apply.
     pub profiles: Vec<YamlProfile>,
+    /// Performance-critical functions that limit which passes may apply to them.
+    #[serde(default, skip_serializing_if = "HotFunctions::is_default")]
+    pub hot_functions: HotFunctions,
+    /// Stage timeouts used by the CLI.
+    #[serde(default, skip_serializing_if = "Timeouts::is_empty")]
+    pub timeouts: Timeouts,
+    /// Extension fields, see [`Extensions`].
+    #[serde(flatten, deserialize_with = "extension_fields")]
+    pub extensions: Extensions,
+}
+
+/// Replaces a non-empty secret string with [`REDACTED_PLACEHOLDER`].
+fn redact_string(value: &mut String) {
+    if !value.is_empty() {
+        *value = REDACTED_PLACEHOLDER.to_owned();
+    }
+}
+
+impl ObfuscationPass {
+    /// Rough runtime cost of the pass. Unknown passes are assumed to be [`OverheadClass::High`].
+    pub fn overhead_class(&self) -> OverheadClass {
+        match self {
+            ObfuscationPass::SigBreaker(_)
+            | ObfuscationPass::LeaEncodeImm(_)
+            | ObfuscationPass::SuppressConstants(_)
+            | ObfuscationPass::SplitBlockPass(_)
+            | ObfuscationPass::IDADecompilerCrasher => OverheadClass::Low,
+            ObfuscationPass::MixedBooleanArithmetic(_)
+            | ObfuscationPass::OpaqueBlockDuplication(_)
+            | ObfuscationPass::ObscureConstants(_)
+            | ObfuscationPass::ObscureReferences(_)
+            | ObfuscationPass::DataProtection(_)
+            | ObfuscationPass::AntiEmulator => OverheadClass::Medium,
+            _ => OverheadClass::High,
+        }
+    }
+
+    /// Whether the pass is switched on. Passes without settings are always enabled.
+    pub fn is_enabled(&self) -> bool {
+        match self {
+            ObfuscationPass::LoopEncodeSemantics(pass) => pass.enabled,
+            ObfuscationPass::MixedBooleanArithmetic(pass) => pass.enabled,
+            ObfuscationPass::MutationEngine(pass) => pass.enabled,
+            ObfuscationPass::TetherExtraction(pass) => pass.enabled,
+            ObfuscationPass::SplitBlockPass(pass) => pass.enabled,
+            ObfuscationPass::OpaqueBlockDuplication(pass) => pass.enabled,
+            ObfuscationPass::ObscureControlFlow(pass) => pass.enabled,
+            ObfuscationPass::LeaEncodeImm(pass) => pass.enabled,
+            ObfuscationPass::ObscureConstants(pass) => pass.enabled,
+            ObfuscationPass::SuppressConstants(pass) => pass.enabled,
+            ObfuscationPass::ObscureReferences(pass) => pass.enabled,
+            ObfuscationPass::SigBreaker(pass) => pass.enabled,
+            ObfuscationPass::DataProtection(pass) => pass.enabled,
+            _ => true,
+        }
+    }
+
+    /// Name of the pass as it appears in the `type` tag of the YAML config.
+    pub fn name(&self) -> &str {
+        match self {
+            ObfuscationPass::LoopEncodeSemantics(_) => "LoopEncodeSemantics",
+            ObfuscationPass::MixedBooleanArithmetic(_) => "MixedBooleanArithmetic",
+            ObfuscationPass::MutationEngine(_) => "MutationEngine",
+            ObfuscationPass::TetherExtraction(_) => "TetherExtraction",
+            ObfuscationPass::SplitBlockPass(_) => "SplitBlockPass",
+            ObfuscationPass::OpaqueBlockDuplication(_) => "OpaqueBlockDuplication",
+            ObfuscationPass::ObscureControlFlow(_) => "ObscureControlFlow",
+            ObfuscationPass::LeaEncodeImm(_) => "LeaEncodeImm",
+            ObfuscationPass::ObscureConstants(_) => "ObscureConstants",
+            ObfuscationPass::SuppressConstants(_) => "SuppressConstants",
+            ObfuscationPass::ObscureReferences(_) => "ObscureReferences",
+            ObfuscationPass::SigBreaker(_) => "SigBreaker",
+            ObfuscationPass::DataProtection(_) => "DataProtection",
+            ObfuscationPass::IDADecompilerCrasher => "IDADecompilerCrasher",
+            ObfuscationPass::AntiEmulator => "AntiEmulator",
+            #[cfg(feature = "unknown-passes")]
+            ObfuscationPass::Unknown(pass) => &pass.ty,
+        }
+    }
+
+    /// Masks any secrets carried by this pass (tether endpoints and pinned keys).
+    pub fn redact(&mut self) {
+        if let ObfuscationPass::TetherExtraction(tether) = self {
+            redact_string(&mut tether.endpoint);
+            for fallback in &mut tether.fallback_endpoints {
+                redact_string(&mut fallback.endpoint);
+            }
+            for key in &mut tether.server_public_key.0 {
+                *key = ServerPublicKey::zeroed();
+            }
+        }
+    }
+}
+
+impl ModuleSettings {
+    /// Masks any secrets carried by the module settings (fake PDB string value).
+    pub fn redact(&mut self) {
+        redact_string(&mut self.fake_pdb_string.value);
+        if let FakePdbStringMode::Template(template) = &mut self.fake_pdb_string.mode {
+            redact_string(template);
+        }
+    }
+}
+
+impl YamlConfig {
+    /// The compiler settings `profile` ends up with, following a `!ref` to a named template.
+    pub fn resolve_compiler_settings(
+        &self,
+        profile: &YamlProfile,
+    ) -> Result<CompilerSettings, UnknownCompilerSettingsError> {
+        match &profile.compiler_settings {
+            CompilerSettingsSource::Inline(settings) => Ok(settings.clone()),
+            CompilerSettingsSource::Ref(name) => {
+                self.compiler_settings.get(name).cloned().ok_or_else(|| {
+                    UnknownCompilerSettingsError {
+                        profile: profile.name.clone(),
+                        name: name.clone(),
+                    }
+                })
+            }
+        }
+    }
+
+    /// Resolved disassembly settings for every profile that overrides the global ones.
+    pub fn profile_disassembly_settings(&self) -> Vec<ProfileDisassemblySettings> {
+        self.profiles
+            .iter()
+            .filter(|p| !p.disassembly_overrides.is_empty())
+            .map(|p| ProfileDisassemblySettings {
+                profile: p.name.clone(),
+                symbols: p.symbols.clone(),
+                settings: self
+                    .disassembly_settings
+                    .clone()
+                    .with_overrides(&p.disassembly_overrides),
+            })
+            .collect()
+    }
+
+    /// Consumes the config and returns it with secrets replaced, keeping the overall structure
+    /// intact.
+    ///
+    /// Tether server endpoints and fake PDB string values are replaced with
+    /// [`REDACTED_PLACEHOLDER`], and pinned public keys are zeroed so the config still parses. The
+    /// result is safe to attach to support tickets and bug reports.
+    pub fn redacted(mut self) -> Self {
+        self.module_settings.redact();
+        for profile in &mut self.profiles {
+            for pass in &mut profile.passes {
+                pass.redact();
+            }
+        }
+        self
+    }
+}
+
+impl ModuleSettings {
+    /// Names of the module-wide features that are switched on.
+    pub fn enabled_features(&self) -> Vec<&'static str> {
+        let flags = [
+            (self.ida_crasher, "ida_crasher"),
+            (self.import_protection, "import_protection"),
+            (self.pack_output_file, "pack_output_file"),
+            (self.obscure_entry_point, "obscure_entry_point"),
+            (self.clear_unwind_info, "clear_unwind_info"),
+            (self.fake_pdb_string.enabled, "fake_pdb_string"),
+            (self.custom_section_name.enabled, "custom_section_name"),
+            (self.preserve_cfg_tables, "preserve_cfg_tables"),
+            (self.cet_compat, "cet_compat"),
+        ];
+        flags
+            .into_iter()
+            .filter_map(|(enabled, name)| enabled.then_some(name))
+            .collect()
+    }
+}
+
+/// Writes the module settings line and profile table shared by the config summaries.
+fn write_summary<'a>(
+    f: &mut fmt::Formatter<'_>,
+    module_settings: &ModuleSettings,
+    profiles: impl Iterator<Item = (&'a str, &'a [ObfuscationPass], usize)>,
+) -> fmt::Result {
+    let features = module_settings.enabled_features();
+    if features.is_empty() {
+        writeln!(f, "Module settings: (none enabled)")?;
+    } else {
+        writeln!(f, "Module settings: {}", features.join(", "))?;
+    }
+    writeln!(
+        f,
+        "{:<24} {:>7} {:>8}  Pass list",
+        "Profile", "Passes", "Symbols"
+    )?;
+    for (name, passes, symbol_count) in profiles {
+        let pass_names: Vec<&str> = passes.iter().map(ObfuscationPass::name).collect();
+        writeln!(
+            f,
+            "{:<24} {:>7} {:>8}  {}",
+            name,
+            passes.len(),
+            symbol_count,
+            pass_names.join(", ")
+        )?;
+    }
+    Ok(())
+}
+
+impl Config {
+    /// Renders a compact, human-readable summary of the config. See the
+    /// [`Display`](fmt::Display) impl.
+    pub fn summary(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl fmt::Display for Config {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_summary(
+            f,
+            &self.module_settings,
+            self.profiles
+                .iter()
+                .map(|p| (p.name.as_str(), p.passes.as_slice(), p.symbols.len())),
+        )
+    }
+}
+
+impl YamlConfig {
+    /// Renders a compact, human-readable summary of the config. See the
+    /// [`Display`](fmt::Display) impl.
+    pub fn summary(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl fmt::Display for YamlConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Config version: {}", self.version)?;
+        write_summary(
+            f,
+            &self.module_settings,
+            self.profiles
+                .iter()
+                .map(|p| (p.name.as_str(), p.passes.as_slice(), p.symbols.len())),
+        )
+    }
+}
+
+/// An RVA that was targeted by more than one profile and resolved by priority.
+#[derive(Debug, Clone)]
+pub struct ProfileOverlap {
+    /// The contested RVA.
+    pub rva: u64,
+    /// Profile that kept the RVA.
+    pub winner: String,
+    /// Profiles the RVA was removed from.
+    pub losers: Vec<String>,
+}
+
+/// An RVA targeted by several profiles sharing the highest priority.
+#[derive(Debug, Clone)]
+pub struct ProfileOverlapError {
+    /// The contested RVA.
+    pub rva: u64,
+    /// Names of the profiles with equal priority that target it.
+    pub profiles: Vec<String>,
+}
+
+impl fmt::Display for ProfileOverlapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "RVA {:#X} is targeted by profiles with equal priority: {}",
+            self.rva,
+            self.profiles.join(", ")
+        )
+    }
+}
+
+impl std::error::Error for ProfileOverlapError {}
+
+impl Config {
+    /// Makes sure every RVA belongs to exactly one profile.
+    ///
+    /// Each RVA targeted by several profiles is kept by the profile with the highest `priority`
+    /// and removed from the rest. Returns the overlaps that were resolved, or an error for the
+    /// first RVA (in address order) whose top priority is shared by more than one profile.
+    pub fn resolve_profile_overlaps(&mut self) -> Result<Vec<ProfileOverlap>, ProfileOverlapError> {
+        let mut owners: std::collections::BTreeMap<u64, Vec<usize>> = Default::default();
+        for (index, profile) in self.profiles.iter().enumerate() {
+            for rva in &profile.symbols {
+                let entry = owners.entry(*rva).or_default();
+                if !entry.contains(&index) {
+                    entry.push(index);
+                }
+            }
+        }
+
+        let mut overlaps = Vec::new();
+        for (rva, indices) in owners.into_iter().filter(|(_, i)| i.len() > 1) {
+            let top = indices
+                .iter()
+                .map(|i| self.profiles[*i].priority)
+                .max()
+                .unwrap_or_default();
+            let (winners, losers): (Vec<usize>, Vec<usize>) = indices
+                .into_iter()
+                .partition(|i| self.profiles[*i].priority == top);
+            if winners.len() > 1 {
+                return Err(ProfileOverlapError {
+                    rva,
+                    profiles: winners
+                        .iter()
+                        .map(|i| self.profiles[*i].name.clone())
+                        .collect(),
+                });
+            }
+            for index in &losers {
+                self.profiles[*index].symbols.retain(|r| *r != rva);
+            }
+            overlaps.push(ProfileOverlap {
+                rva,
+                winner: self.profiles[winners[0]].name.clone(),
+                losers: losers
+                    .iter()
+                    .map(|i| self.profiles[*i].name.clone())
+                    .collect(),
+            });
+        }
+        Ok(overlaps)
+    }
+}
+
+/// A setting that does not fit the environment of the protected module.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct EnvironmentIssue {
+    /// Profile the offending pass belongs to, or `None` for module settings.
+    pub profile: Option<String>,
+    /// Name of the offending setting or pass.
+    pub setting: String,
+    /// Why the setting is a problem in this environment.
+    pub reason: String,
+}
+
+impl fmt::Display for EnvironmentIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.profile {
+            Some(profile) => write!(
+                f,
+                "profile `{}`: {}: {}",
+                profile, self.setting, self.reason
+            ),
+            None => write!(f, "{}: {}", self.setting, self.reason),
+        }
+    }
+}
+
+/// Settings that cannot work in the target environment, returned by
+/// [`YamlConfig::validate_for_environment`] and [`Config::validate_for_environment`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct EnvironmentValidationError {
+    /// The environment the config was checked against.
+    pub environment: PeEnvironment,
+    /// Every setting that has to change.
+    pub issues: Vec<EnvironmentIssue>,
+}
+
+impl fmt::Display for EnvironmentValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "config is not valid for {:?} modules", self.environment)?;
+        for issue in &self.issues {
+            write!(f, "\n  {}", issue)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for EnvironmentValidationError {}
+
+/// Checks module settings and enabled passes against the constraints of `environment`, and of
+/// `module` when analysis reported it.
+fn validate_environment<'a>(
+    environment: PeEnvironment,
+    module: Option<&AnalysisModuleInfo>,
+    module_settings: &ModuleSettings,
+    profiles: impl Iterator<Item = (&'a str, &'a [ObfuscationPass], bool)>,
+) -> Result<Vec<EnvironmentIssue>, EnvironmentValidationError> {
+    let mut errors = Vec::new();
+    let mut warnings = Vec::new();
+    let module_issue = |setting: &str, reason: &str| EnvironmentIssue {
+        profile: None,
+        setting: setting.to_owned(),
+        reason: reason.to_owned(),
+    };
+
+    match environment {
+        PeEnvironment::KernelMode => {
+            if module_settings.pack_output_file {
+                errors.push(module_issue(
+                    "pack_output_file",
+                    "packing is only supported for user-mode modules",
+                ));
+            }
+            // A fault inside a guarded region without unwind info bugchecks. Before analysis
+            // the driver cannot be inspected for __try/__except usage, so it is only a warning.
+            if module_settings.clear_unwind_info {
+                match module {
+                    Some(module) if module.seh => errors.push(module_issue(
+                        "clear_unwind_info",
+                        "kernel structured exception handling needs unwind info, clearing it bugchecks on the first exception",
+                    )),
+                    Some(_) => {}
+                    None => warnings.push(module_issue(
+                        "clear_unwind_info",
+                        "drivers using structured exception handling bugcheck on the first exception once unwind info is cleared",
+                    )),
+                }
+            }
+        }
+        PeEnvironment::UEFI => {
+            if module_settings.pack_output_file {
+                if module_settings.uefi.phase == UefiPhase::Pei {
+                    errors.push(module_issue(
+                        "pack_output_file",
+                        "PEI modules execute in place and cannot be unpacked at load time",
+                    ));
+                } else if module_settings.uefi.avoid_packing {
+                    errors.push(module_issue(
+                        "pack_output_file",
+                        "packing is disabled for this image by uefi.avoid_packing",
+                    ));
+                }
+            }
+            if module_settings.import_protection {
+                errors.push(module_issue(
+                    "import_protection",
+                    "UEFI images reach firmware services through the system table, not imports",
+                ));
+            }
+        }
+        _ => {}
+    }
+    if environment != PeEnvironment::UEFI && module_settings.uefi != UefiSettings::default() {
+        warnings.push(module_issue(
+            "uefi",
+            "UEFI settings are ignored for non-UEFI modules",
+        ));
+    }
+
+    for (profile, passes, seh_safe) in profiles {
+        if seh_safe && module_settings.clear_unwind_info {
+            errors.push(EnvironmentIssue {
+                profile: Some(profile.to_owned()),
+                setting: "clear_unwind_info".to_owned(),
+                reason: "the profile is seh_safe but unwind info is cleared for the whole module"
+                    .to_owned(),
+            });
+        }
+        for pass in passes.iter().filter(|p| p.is_enabled()) {
+            let issue = |reason: &str| EnvironmentIssue {
+                profile: Some(profile.to_owned()),
+                setting: pass.name().to_owned(),
+                reason: reason.to_owned(),
+            };
+            match (environment, pass) {
+                (PeEnvironment::KernelMode, ObfuscationPass::AntiEmulator) => warnings.push(issue(
+                    "timing checks may misfire in code running at raised IRQL",
+                )),
+                (PeEnvironment::UEFI, ObfuscationPass::AntiEmulator) => warnings.push(issue(
+                    "timing checks are unreliable before the firmware calibrates its timers",
+                )),
+                (PeEnvironment::UEFI, ObfuscationPass::TetherExtraction(_)) => errors.push(issue(
+                    "UEFI images have no guaranteed network stack to reach the tether server",
+                )),
+                _ => {}
+            }
+            match pass {
+                ObfuscationPass::ObscureControlFlow(_) if module_settings.preserve_cfg_tables => {
+                    warnings.push(issue(
+                        "computed jump targets are not registered in the CFG function table",
+                    ))
+                }
+                ObfuscationPass::ObscureReferences(_) if module_settings.preserve_cfg_tables => {
+                    warnings.push(issue(
+                        "obscured function pointers may reach call sites as unregistered targets",
+                    ))
+                }
+                _ => {}
+            }
+            if seh_safe && matches!(pass, ObfuscationPass::ObscureControlFlow(_)) {
+                errors.push(issue(
+                    "the profile is seh_safe but this pass moves the stack pointer in ways unwind codes cannot describe",
+                ));
+            }
+            if module_settings.cet_compat && matches!(pass, ObfuscationPass::ObscureControlFlow(_))
+            {
+                warnings.push(issue(
+                    "return-based dispatch does not match the CET shadow stack",
+                ));
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(warnings)
+    } else {
+        Err(EnvironmentValidationError {
+            environment,
+            issues: errors,
+        })
+    }
+}
+
+/// Checks module settings against what analysis found in the image. Every issue is a warning.
+fn validate_module(
+    module_settings: &ModuleSettings,
+    module: &AnalysisModuleInfo,
+) -> Vec<EnvironmentIssue> {
+    let mut warnings = Vec::new();
+    let mut warn = |setting: &str, reason: &str| {
+        warnings.push(EnvironmentIssue {
+            profile: None,
+            setting: setting.to_owned(),
+            reason: reason.to_owned(),
+        })
+    };
+    if module_settings.obscure_entry_point {
+        if module.entry_point.is_none() {
+            warn(
+                "obscure_entry_point",
+                "the module has no entry point to obscure",
+            );
+        } else if !module.tls_callbacks.is_empty() {
+            warn(
+                "obscure_entry_point",
+                "the module has TLS callbacks, which run before the obscured entry point and are not covered by its checks",
+            );
+        }
+    }
+    if module_settings.clear_unwind_info {
+        if module.seh {
+            warn(
+                "clear_unwind_info",
+                "the module registers exception handlers, which stop being called once unwind info is cleared",
+            );
+        } else if !module.exception_directory {
+            warn(
+                "clear_unwind_info",
+                "the module has no unwind info to clear",
+            );
+        }
+    }
+    if module_settings.import_protection
+        && let Some(imports) = &module.imports
+        && imports
+            .iter()
+            .all(|i| i.is_runtime() || i.functions.is_empty())
+    {
+        warn(
+            "import_protection",
+            if imports.iter().all(|i| i.functions.is_empty()) {
+                "the module imports nothing to protect"
+            } else {
+                "the module only imports C runtime functions, which gain little from import protection"
+            },
+        );
+    }
+    if module.cfg && !module_settings.preserve_cfg_tables {
+        warn(
+            "preserve_cfg_tables",
+            "the module is built with Control Flow Guard, so obfuscated code may fail CFG checks unless its tables are preserved",
+        );
+    }
+    warnings
+}
+
+impl YamlConfig {
+    /// Checks that the config can work for a module running in `environment`.
+    ///
+    /// Kernel drivers may not pack the output, and UEFI images may not protect imports, use tether
+    /// extraction or pack (unless `uefi.avoid_packing` is off for a DXE image). Clearing the unwind
+    /// info of a driver is a warning, see [`YamlConfig::validate_for_analysis`]. Profiles marked
+    /// `seh_safe` may not use `ObscureControlFlow` or be combined with `clear_unwind_info`. Passes
+    /// known to break Control Flow Guard or CET shadow stacks are reported when
+    /// `preserve_cfg_tables` or `cet_compat` is set. Only enabled passes are considered. Returns
+    /// the non-fatal issues (such as `AntiEmulator` timing checks at raised IRQL) on success.
+    pub fn validate_for_environment(
+        &self,
+        environment: PeEnvironment,
+    ) -> Result<Vec<EnvironmentIssue>, EnvironmentValidationError> {
+        validate_environment(
+            environment,
+            None,
+            &self.module_settings,
+            self.profiles
+                .iter()
+                .map(|p| (p.name.as_str(), p.passes.as_slice(), p.seh_safe)),
+        )
+    }
+
+    /// Same as [`YamlConfig::validate_for_environment`], for the module `analysis` describes.
+    /// Clearing the unwind info of a driver is only rejected when it registers exception handlers.
+    pub fn validate_for_analysis(
+        &self,
+        analysis: &AnalysisResult,
+    ) -> Result<Vec<EnvironmentIssue>, EnvironmentValidationError> {
+        validate_environment(
+            analysis.environment,
+            analysis.module.as_ref(),
+            &self.module_settings,
+            self.profiles
+                .iter()
+                .map(|p| (p.name.as_str(), p.passes.as_slice(), p.seh_safe)),
+        )
+    }
+
+    /// Checks the module settings against the facts analysis reported about the image, such as
+    /// `obscure_entry_point` on an image with TLS callbacks, `clear_unwind_info` on one that
+    /// registers exception handlers or `import_protection` on one that only imports the C runtime.
+    /// Every returned issue is a warning.
+    pub fn validate_for_module(&self, module: &AnalysisModuleInfo) -> Vec<EnvironmentIssue> {
+        validate_module(&self.module_settings, module)
+    }
+}
+
+impl Config {
+    /// Checks that the config can work for a module running in `environment`. See
+    /// [`YamlConfig::validate_for_environment`].
+    pub fn validate_for_environment(
+        &self,
+        environment: PeEnvironment,
+    ) -> Result<Vec<EnvironmentIssue>, EnvironmentValidationError> {
+        validate_environment(
+            environment,
+            None,
+            &self.module_settings,
+            self.profiles
+                .iter()
+                .map(|p| (p.name.as_str(), p.passes.as_slice(), p.seh_safe)),
+        )
+    }
+
+    /// Checks that the config can work for the module `analysis` describes. See
+    /// [`YamlConfig::validate_for_analysis`].
+    pub fn validate_for_analysis(
+        &self,
+        analysis: &AnalysisResult,
+    ) -> Result<Vec<EnvironmentIssue>, EnvironmentValidationError> {
+        validate_environment(
+            analysis.environment,
+            analysis.module.as_ref(),
+            &self.module_settings,
+            self.profiles
+                .iter()
+                .map(|p| (p.name.as_str(), p.passes.as_slice(), p.seh_safe)),
+        )
+    }
+
+    /// Checks the module settings against the facts analysis reported about the image. See
+    /// [`YamlConfig::validate_for_module`].
+    pub fn validate_for_module(&self, module: &AnalysisModuleInfo) -> Vec<EnvironmentIssue> {
+        validate_module(&self.module_settings, module)
+    }
+}
+
+/// How serious a [`ConfigDiagnostic`] is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum DiagnosticSeverity {
+    /// Likely a mistake, but the config still works.
+    Warning,
+    /// The config will be rejected or cannot work as written.
+    Error,
+}
+
+impl fmt::Display for DiagnosticSeverity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DiagnosticSeverity::Warning => write!(f, "warning"),
+            DiagnosticSeverity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// One step of the path to a value in the YAML document.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ConfigPathSegment {
+    /// A mapping key.
+    Key(String),
+    /// A sequence index.
+    Index(usize),
+}
+
+/// A problem found by [`YamlConfig::validate`], with the path to the offending value.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ConfigDiagnostic {
+    /// How serious the problem is.
+    pub severity: DiagnosticSeverity,
+    /// Path from the document root to the offending value.
+    pub path: Vec<ConfigPathSegment>,
+    /// What is wrong.
+    pub message: String,
+}
+
+impl ConfigDiagnostic {
+    /// The path in `profiles[0].passes[2].probability` form.
+    pub fn path_string(&self) -> String {
+        let mut out = String::new();
+        for segment in &self.path {
+            match segment {
+                ConfigPathSegment::Key(key) if out.is_empty() => out.push_str(key),
+                ConfigPathSegment::Key(key) => {
+                    out.push('.');
+                    out.push_str(key);
+                }
+                ConfigPathSegment::Index(index) => out.push_str(&format!("[{index}]")),
+            }
+        }
+        out
+    }
+}
+
+impl fmt::Display for ConfigDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {}: {}",
+            self.severity,
+            self.path_string(),
+            self.message
+        )
+    }
+}
+
+/// Builds a diagnostic path from keys and indices.
+macro_rules! config_path {
+    ($($segment:expr),* $(,)?) => {
+        vec![$(ConfigPathSegment::from($segment)),*]
+    };
+}
+
+impl From<&str> for ConfigPathSegment {
+    fn from(value: &str) -> Self {
+        ConfigPathSegment::Key(value.to_owned())
+    }
+}
+
+impl From<String> for ConfigPathSegment {
+    fn from(value: String) -> Self {
+        ConfigPathSegment::Key(value)
+    }
+}
+
+impl From<usize> for ConfigPathSegment {
+    fn from(value: usize) -> Self {
+        ConfigPathSegment::Index(value)
+    }
+}
+
+impl ObfuscationPass {
+    /// The chance (0–100) this pass applies a transformation, for passes that have one.
+    pub fn probability(&self) -> Option<&ParamValue> {
+        match self {
+            ObfuscationPass::LoopEncodeSemantics(pass) => Some(&pass.probability),
+            ObfuscationPass::MixedBooleanArithmetic(pass) => Some(&pass.probability),
+            ObfuscationPass::MutationEngine(pass) => Some(&pass.probability),
+            ObfuscationPass::ObscureConstants(pass) => Some(&pass.probability),
+            ObfuscationPass::ObscureControlFlow(pass) => Some(&pass.probability),
+            ObfuscationPass::OpaqueBlockDuplication(pass) => Some(&pass.probability),
+            ObfuscationPass::LeaEncodeImm(pass) => Some(&pass.probability),
+            _ => None,
+        }
+    }
+
+    /// The per bit width weights (0–100) of this pass, for passes that set them.
+    pub fn bitwidth_weights(&self) -> Option<&BitWidthWeights> {
+        match self {
+            ObfuscationPass::LoopEncodeSemantics(pass) => pass.bitwidth_weights.as_ref(),
+            ObfuscationPass::MixedBooleanArithmetic(pass) => pass.bitwidth_weights.as_ref(),
+            ObfuscationPass::MutationEngine(pass) => pass.bitwidth_weights.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// The per bit width weights this pass applies, for passes that weigh bit widths.
+    pub fn effective_bitwidth_weights(&self) -> Option<BitWidthWeights> {
+        let (bitwidths, weights) = match self {
+            ObfuscationPass::LoopEncodeSemantics(pass) => (&pass.bitwidths, &pass.bitwidth_weights),
+            ObfuscationPass::MixedBooleanArithmetic(pass) => {
+                (&pass.bitwidths, &pass.bitwidth_weights)
+            }
+            ObfuscationPass::MutationEngine(pass) => (&pass.bitwidths, &pass.bitwidth_weights),
+            _ => return None,
+        };
+        Some(BitWidthWeights::resolve(bitwidths, weights.as_ref()))
+    }
+}
+
+/// Reports the assembler settings at `path` that are out of range as errors: a junk byte chance
+/// outside 0–1, a block alignment other than 0, 1 or a power of two, and function padding whose
+/// minimum is above its maximum.
+fn validate_assembler_settings(
+    settings: &AssemblerSettings,
+    path: Vec<ConfigPathSegment>,
+    push: &mut impl FnMut(DiagnosticSeverity, Vec<ConfigPathSegment>, String),
+) {
+    let at = |segments: Vec<ConfigPathSegment>| [path.clone(), segments].concat();
+    let chance = settings.junk_bytes.chance;
+    if !(0.0..=1.0).contains(&chance) {
+        push(
+            DiagnosticSeverity::Error,
+            at(config_path!["junk_bytes", "chance"]),
+            format!("chance {chance} is not between 0 and 1"),
+        );
+    }
+    let alignment = settings.basic_block_alignment;
+    if alignment > 1 && !alignment.is_power_of_two() {
+        push(
+            DiagnosticSeverity::Error,
+            at(config_path!["basic_block_alignment"]),
+            format!("alignment {alignment} is not a power of two"),
+        );
+    }
+    let padding = &settings.function_padding;
+    if padding.min_bytes > padding.max_bytes {
+        push(
+            DiagnosticSeverity::Error,
+            at(config_path!["function_padding", "min_bytes"]),
+            format!(
+                "minimum {} is greater than maximum {}",
+                padding.min_bytes, padding.max_bytes
+            ),
+        );
+    }
+}
+
+impl YamlConfig {
+    /// Lints the config without needing an analysis result or network access.
+    ///
+    /// Reports unsupported versions, duplicate profile names, dangling `!ref`s, probabilities and
+    /// bit width weights above 100, tether passes without an endpoint, junk byte chances outside
+    /// 0–1, block alignments that are not a power of two and function padding whose minimum is
+    /// above its maximum as errors, and profiles without enabled passes, unused compiler settings
+    /// templates, unknown passes and empty fixed fake PDB strings as warnings.
+    pub fn validate(&self) -> Vec<ConfigDiagnostic> {
+        let mut diagnostics = Vec::new();
+        let mut push = |severity, path, message: String| {
+            diagnostics.push(ConfigDiagnostic {
+                severity,
+                path,
+                message,
+            })
+        };
+
+        if !is_supported_config_version(&self.version) {
+            push(
+                DiagnosticSeverity::Error,
+                config_path!["version"],
+                format!(
+                    "version {} is not supported, expected {} through {}",
+                    self.version, MIN_YAML_CONFIG_VERSION, YAML_CONFIG_VERSION
+                ),
+            );
+        }
+
+        for (key, value) in [
+            ("analysis_secs", self.timeouts.analysis_secs),
+            ("obfuscation_secs", self.timeouts.obfuscation_secs),
+        ] {
+            if value == Some(0) {
+                push(
+                    DiagnosticSeverity::Error,
+                    config_path!["timeouts", key],
+                    "timeout must be greater than zero".to_owned(),
+                );
+            }
+        }
+
+        let fake_pdb_string = &self.module_settings.fake_pdb_string;
+        if fake_pdb_string.enabled
+            && fake_pdb_string.mode == FakePdbStringMode::Fixed
+            && fake_pdb_string.value.is_empty()
+        {
+            push(
+                DiagnosticSeverity::Warning,
+                config_path!["module_settings", "fake_pdb_string", "value"],
+                "fake PDB string is enabled but empty".to_owned(),
+            );
+        }
+
+        for (i, profile) in self.profiles.iter().enumerate() {
+            if self.profiles[..i].iter().any(|p| p.name == profile.name) {
+                push(
+                    DiagnosticSeverity::Error,
+                    config_path!["profiles", i, "name"],
+                    format!("profile `{}` is defined more than once", profile.name),
+                );
+            }
+            match &profile.compiler_settings {
+                CompilerSettingsSource::Ref(name) if !self.compiler_settings.contains_key(name) => {
+                    push(
+                        DiagnosticSeverity::Error,
+                        config_path!["profiles", i, "compiler_settings"],
+                        format!("no compiler settings template named `{name}`"),
+                    );
+                }
+                CompilerSettingsSource::Inline(settings) => validate_assembler_settings(
+                    &settings.assembler_settings,
+                    config_path!["profiles", i, "compiler_settings", "assembler_settings"],
+                    &mut push,
+                ),
+                CompilerSettingsSource::Ref(_) => {}
+            }
+            if !profile.passes.iter().any(ObfuscationPass::is_enabled) {
+                push(
+                    DiagnosticSeverity::Warning,
+                    config_path!["profiles", i, "passes"],
+                    format!("profile `{}` has no enabled passes", profile.name),
+                );
+            }
+            for (j, pass) in profile.passes.iter().enumerate() {
+                if let Some(probability) = pass.probability()
+                    && probability.max() > 100
+                {
+                    push(
+                        DiagnosticSeverity::Error,
+                        config_path!["profiles", i, "passes", j, "probability"],
+                        format!("probability {} is above 100 percent", probability.max()),
+                    );
+                }
+                if let Some(weights) = pass.bitwidth_weights() {
+                    for (key, weight) in [
+                        ("bit8", weights.bit8),
+                        ("bit16", weights.bit16),
+                        ("bit32", weights.bit32),
+                        ("bit64", weights.bit64),
+                    ] {
+                        if weight > 100 {
+                            push(
+                                DiagnosticSeverity::Error,
+                                config_path!["profiles", i, "passes", j, "bitwidth_weights", key],
+                                format!("weight {weight} is above 100"),
+                            );
+                        }
+                    }
+                }
+                match pass {
+                    ObfuscationPass::TetherExtraction(tether)
+                        if tether.enabled && tether.endpoint.is_empty() =>
+                    {
+                        push(
+                            DiagnosticSeverity::Error,
+                            config_path!["profiles", i, "passes", j, "endpoint"],
+                            "tether extraction needs a server endpoint".to_owned(),
+                        )
+                    }
+                    #[cfg(feature = "unknown-passes")]
+                    ObfuscationPass::Unknown(unknown) => push(
+                        DiagnosticSeverity::Warning,
+                        config_path!["profiles", i, "passes", j, "type"],
+                        format!("unknown pass `{}` is forwarded as-is", unknown.ty),
+                    ),
+                    _ => {}
+                }
+            }
+        }
+
+        for (name, settings) in &self.compiler_settings {
+            validate_assembler_settings(
+                &settings.assembler_settings,
+                config_path!["compiler_settings", name.as_str(), "assembler_settings"],
+                &mut push,
+            );
+            let used = self.profiles.iter().any(
+                |p| matches!(&p.compiler_settings, CompilerSettingsSource::Ref(r) if r == name),
+            );
+            if !used {
+                push(
+                    DiagnosticSeverity::Warning,
+                    config_path!["compiler_settings", name.as_str()],
+                    format!("compiler settings template `{name}` is never referenced"),
+                );
+            }
+        }
+        diagnostics
+    }
+}
+
+/// A hot function whose profile uses passes above its overhead class.
+#[derive(Debug, Clone)]
+pub struct HotFunctionAdjustment {
+    /// RVA of the hot function.
+    pub rva: u64,
+    /// Profile that targeted the function.
+    pub profile: String,
+    /// Most expensive class of passes allowed on the function.
+    pub max_overhead: OverheadClass,
+    /// Names of the passes above that class.
+    pub removed_passes: Vec<String>,
+    /// Profile the function was moved to, `None` when the policy is [`HotFunctionPolicy::Warn`]
+    /// or no pass is cheap enough and the function was left unprotected.
+    pub moved_to: Option<String>,
+}
+
+impl Config {
+    /// Applies per-function overhead limits to the profiles.
+    ///
+    /// `limits` maps hot function RVAs to the most expensive [`OverheadClass`] allowed on them.
+    /// With [`HotFunctionPolicy::Downgrade`], each hot function whose profile exceeds its limit
+    /// moves to a copy of that profile named `<profile>@<class>` keeping only the cheap enough
+    /// passes, or is dropped when none are left. With [`HotFunctionPolicy::Warn`] the config is
+    /// left untouched. Returns every function that exceeded its limit.
+    pub fn apply_hot_function_limits(
+        &mut self,
+        limits: &BTreeMap<u64, OverheadClass>,
+        policy: HotFunctionPolicy,
+    ) -> Vec<HotFunctionAdjustment> {
+        let mut adjustments = Vec::new();
+        for index in 0..self.profiles.len() {
+            let exceeding: Vec<(u64, OverheadClass)> = self.profiles[index]
+                .symbols
+                .iter()
+                .filter_map(|rva| limits.get(rva).map(|class| (*rva, *class)))
+                .filter(|(_, class)| {
+                    self.profiles[index]
+                        .passes
+                        .iter()
+                        .any(|p| p.overhead_class() > *class)
+                })
+                .collect();
+            for (rva, max_overhead) in exceeding {
+                let profile = &self.profiles[index];
+                let (kept, removed): (Vec<ObfuscationPass>, Vec<ObfuscationPass>) = profile
+                    .passes
+                    .iter()
+                    .cloned()
+                    .partition(|p| p.overhead_class() <= max_overhead);
+                let mut adjustment = HotFunctionAdjustment {
+                    rva,
+                    profile: profile.name.clone(),
+                    max_overhead,
+                    removed_passes: removed.iter().map(|p| p.name().to_owned()).collect(),
+                    moved_to: None,
+                };
+                if policy == HotFunctionPolicy::Downgrade {
+                    self.profiles[index].symbols.retain(|r| *r != rva);
+                    if !kept.is_empty() {
+                        let name = format!("{}@{:?}", adjustment.profile, max_overhead);
+                        match self.profiles.iter_mut().find(|p| p.name == name) {
+                            Some(downgraded) => downgraded.symbols.push(rva),
+                            None => {
+                                let mut downgraded = self.profiles[index].clone();
+                                downgraded.name = name.clone();
+                                downgraded.passes = kept;
+                                downgraded.symbols = vec![rva];
+                                downgraded.data_symbols.clear();
+                                self.profiles.push(downgraded);
+                            }
+                        }
+                        adjustment.moved_to = Some(name);
+                    }
+                }
+                adjustments.push(adjustment);
+            }
+        }
+        adjustments
+    }
+}
+
+impl Semantics {
+    /// Every instruction semantic enabled.
+    pub fn all() -> Self {
+        Self {
+            add: true,
+            sub: true,
+            and: true,
+            xor: true,
+            or: true,
+            not: true,
+            neg: true,
+        }
+    }
+}
+
+impl BitWidths {
+    /// Every bit width enabled.
+    pub fn all() -> Self {
+        Self {
+            bit8: true,
+            bit16: true,
+            bit32: true,
+            bit64: true,
+        }
+    }
+}
+
+impl SsaOrigins {
+    /// Every SSA origin enabled.
+    pub fn all() -> Self {
+        Self {
+            normal: true,
+            memop: true,
+            fp_based_memop: true,
+            sp_based_memop: true,
+        }
+    }
+}
+
+/// Functions referenced at least this many times are placed in the generated "hot" profile.
+pub const HOT_FUNCTION_MIN_REF_COUNT: usize = 16;
+
+/// Name of the profile holding frequently referenced functions in a generated config.
+pub const HOT_PROFILE_NAME: &str = "hot";
+
+/// Protection strength used when generating a config skeleton.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ConfigPreset {
+    /// Cheap passes only, for performance sensitive code.
+    Light,
+    /// Reasonable protection with moderate overhead.
+    Balanced,
+    /// Stacks every transform, at a significant size and speed cost.
+    Aggressive,
+}
+
+impl ConfigPreset {
+    /// Obfuscation passes used by this preset.
+    pub fn passes(self) -> Vec<ObfuscationPass> {
+        let control_flow = ObfuscationPass::ObscureControlFlow(ObscureControlFlow {
+            enabled: true,
+            mba_enhance: false,
+            probability: 100.into(),
+        });
+        let references = ObfuscationPass::ObscureReferences(ObscureReferences {
+            enabled: true,
+            mba_enhance: false,
+        });
+        let constants = |mba_enhance| {
+            ObfuscationPass::ObscureConstants(ObscureConstants {
+                enabled: true,
+                mba_enhance,
+                probability: 100.into(),
+                iterations: 1.into(),
+                bitwidths: BitWidths::all(),
+                ethnicities: SsaOrigins::all(),
+            })
+        };
+        let mutation = |probability: u32, iterations: u32| {
+            ObfuscationPass::MutationEngine(MutationEngine {
+                enabled: true,
+                iterations: iterations.into(),
+                probability: probability.into(),
+                extension: MutationEngineExtension::SSE42,
+                semantics: Semantics::all(),
+                bitwidths: BitWidths::all(),
+                bitwidth_weights: None,
+                ethnicities: SsaOrigins::all(),
+            })
+        };
+        match self {
+            ConfigPreset::Light => vec![references, mutation(25, 1)],
+            ConfigPreset::Balanced => {
+                vec![control_flow, references, constants(false), mutation(100, 1)]
+            }
+            ConfigPreset::Aggressive => vec![
+                ObfuscationPass::SuppressConstants(SuppressConstants {
+                    enabled: true,
+                    mba_enhance: true,
+                    ethnicities: SsaOrigins::all(),
+                }),
+                control_flow,
+                references,
+                constants(true),
+                ObfuscationPass::MixedBooleanArithmetic(MixedBooleanArithmetic {
+                    enabled: true,
+                    iterations: 1.into(),
+                    probability: 50.into(),
+                    semantics: Semantics::all(),
+                    bitwidths: BitWidths::all(),
+                    bitwidth_weights: None,
+                    ethnicities: SsaOrigins::all(),
+                }),
+                mutation(100, 2),
+            ],
+        }
+    }
+
+    /// Compiler settings used by this preset.
+    pub fn compiler_settings(self) -> CompilerSettings {
+        CompilerSettings {
+            assembler_settings: AssemblerSettings {
+                shuffle_basic_blocks: self != ConfigPreset::Light,
+                instruction_prefix: String::new(),
+                random_prefix_chance: 0.0,
+                junk_bytes: JunkByteSettings::default(),
+                basic_block_alignment: 0,
+                function_padding: FunctionPadding::default(),
+            },
+            optimization_settings: OptimizationSettings {
+                constant_propagation: true,
+                instruction_combine: true,
+                dead_code_elim: true,
+                prune_useless_block_params: true,
+                iterations: if self == ConfigPreset::Light { 1 } else { 0 },
+                max_time_ms: None,
+                pass_iteration_caps: OptimizationPassCaps::default(),
+            },
+            lifter_settings: LifterSettings {
+                lift_calls: true,
+                calling_convention: "WindowsAbi".to_owned(),
+                max_stack_copy_size: 1024,
+                split_on_calls_fallback: true,
+            },
+        }
+    }
+
+    /// A profile called `name` using this preset's passes and compiler settings.
+    pub fn profile(self, name: String, symbols: Vec<YamlSymbol>) -> YamlProfile {
+        YamlProfile {
+            name,
+            passes: self.passes(),
+            compiler_settings: self.compiler_settings().into(),
+            symbols,
+            priority: 0,
+            seh_safe: false,
+            disassembly_overrides: DisassemblySettingsOverrides::default(),
+            extensions: Extensions::new(),
+        }
+    }
+}
+
+/// Refers to a symbol by name when the analysis found one, otherwise by RVA.
+fn yaml_symbol(name: &str, rva: u64) -> YamlSymbol {
+    if name.is_empty() {
+        YamlSymbol::Rva(rva)
+    } else {
+        YamlSymbol::Name(name.to_owned())
+    }
+}
+
+impl YamlConfig {
+    /// Builds a ready-to-edit config skeleton from an analysis result.
+    ///
+    /// Every macro profile found in the binary gets a profile using `preset`, and functions
+    /// referenced at least [`HOT_FUNCTION_MIN_REF_COUNT`] times are grouped into a
+    /// [`HOT_PROFILE_NAME`] profile using [`ConfigPreset::Light`] to limit the performance impact.
+    /// Rejected functions are not included, see [`rejected_symbols_comment`].
+    pub fn from_analysis(analysis: &AnalysisResult, preset: ConfigPreset) -> Self {
+        let mut profiles: Vec<YamlProfile> = Vec::new();
+        for macro_profile in &analysis.macros {
+            if !profiles.iter().any(|p| p.name == macro_profile.name) {
+                profiles.push(preset.profile(macro_profile.name.clone(), vec![]));
+            }
+        }
+
+        // Macro-decorated functions are already covered by their own profile.
+        let macro_rvas: std::collections::HashSet<u64> = analysis
+            .macros
+            .iter()
+            .flat_map(|m| m.rvas.iter().copied())
+            .collect();
+        let mut hot: Vec<&AnalysisFunction> = analysis
+            .functions
+            .iter()
+            .filter(|f| f.ref_count >= HOT_FUNCTION_MIN_REF_COUNT && !macro_rvas.contains(&f.rva))
+            .collect();
+        hot.sort_by(|a, b| b.ref_count.cmp(&a.ref_count).then(a.rva.cmp(&b.rva)));
+        if !hot.is_empty() && !profiles.iter().any(|p| p.name == HOT_PROFILE_NAME) {
+            let symbols = hot.iter().map(|f| yaml_symbol(&f.symbol, f.rva)).collect();
+            profiles.push(ConfigPreset::Light.profile(HOT_PROFILE_NAME.to_owned(), symbols));
+        }
+
+        Self {
+            version: YAML_CONFIG_VERSION.to_owned(),
+            disassembly_settings: DisassemblySettings::default(),
+            compiler_settings: BTreeMap::new(),
+            module_settings: ModuleSettings {
+                ida_crasher: false,
+                import_protection: false,
+                pack_output_file: false,
+                obscure_entry_point: false,
+                clear_unwind_info: false,
+                fake_pdb_string: FakePdbString::default(),
+                custom_section_name: CustomSectionName::default(),
+                uefi: UefiSettings::default(),
+                preserve_cfg_tables: false,
+                cet_compat: false,
+                output_constraints: OutputConstraints::default(),
+                extensions: Extensions::new(),
+            },
+            profiles,
+            hot_functions: HotFunctions::default(),
+            timeouts: Timeouts::default(),
+            extensions: Extensions::new(),
+        }
+    }
+}
+
+/// Renders the rejected functions of an analysis as commented-out YAML symbol entries.
+///
+/// Meant to be appended under a profile's `symbols:` list in a generated config so users can see
+/// what could not be protected, why and how to fix it, without the entries taking effect.
+pub fn rejected_symbols_comment(analysis: &AnalysisResult) -> String {
+    let mut out = String::new();
+    if analysis.rejects.is_empty() {
+        return out;
+    }
+    out.push_str("# Rejected during analysis, these cannot be protected as-is:\n");
+    for reject in &analysis.rejects {
+        let entry = match yaml_symbol(&reject.symbol, reject.rva) {
+            YamlSymbol::Name(name) => format!("!Name {name:?}"),
+            _ => format!("!Rva {:#X}", reject.rva),
+        };
+        out.push_str(&format!("# - {entry} # {}: {}\n", reject.ty, reject.reason));
+        if let Some(suggestion) = reject.remediation() {
+            out.push_str(&format!("#   fix: {suggestion}\n"));
+        }
+    }
+    out
+}
+
+impl ModuleSettings {
+    /// Returns these settings with IDA decompiler crashing switched on or off.
+    pub fn with_ida_crasher(mut self, enabled: bool) -> Self {
+        self.ida_crasher = enabled;
+        self
+    }
+
+    /// Returns these settings with import protection switched on or off.
+    pub fn with_import_protection(mut self, enabled: bool) -> Self {
+        self.import_protection = enabled;
+        self
+    }
+
+    /// Returns these settings with output packing switched on or off.
+    pub fn with_pack_output_file(mut self, enabled: bool) -> Self {
+        self.pack_output_file = enabled;
+        self
+    }
+
+    /// Returns these settings with entry point obscuring switched on or off.
+    pub fn with_obscure_entry_point(mut self, enabled: bool) -> Self {
+        self.obscure_entry_point = enabled;
+        self
+    }
+
+    /// Returns these settings with unwind info clearing switched on or off.
+    pub fn with_clear_unwind_info(mut self, enabled: bool) -> Self {
+        self.clear_unwind_info = enabled;
+        self
+    }
+
+    /// Returns these settings with the given fake PDB string settings.
+    pub fn with_fake_pdb_string(mut self, fake_pdb_string: FakePdbString) -> Self {
+        self.fake_pdb_string = fake_pdb_string;
+        self
+    }
+
+    /// Returns these settings with the `.text` section renamed to `name`.
+    pub fn with_custom_section_name(mut self, name: SectionName) -> Self {
+        self.custom_section_name = CustomSectionName {
+            enabled: true,
+            value: name,
+        };
+        self
+    }
+
+    /// Returns these settings with Control Flow Guard table preservation switched on or off.
+    pub fn with_preserve_cfg_tables(mut self, enabled: bool) -> Self {
+        self.preserve_cfg_tables = enabled;
+        self
+    }
+
+    /// Returns these settings with CET shadow stack compatibility switched on or off.
+    pub fn with_cet_compat(mut self, enabled: bool) -> Self {
+        self.cet_compat = enabled;
+        self
+    }
+
+    /// Returns these settings with the given output constraints.
+    pub fn with_output_constraints(mut self, output_constraints: OutputConstraints) -> Self {
+        self.output_constraints = output_constraints;
+        self
+    }
+
+    /// Returns these settings with the given UEFI constraints.
+    pub fn with_uefi(mut self, uefi: UefiSettings) -> Self {
+        self.uefi = uefi;
+        self
+    }
+}
+
+impl YamlConfig {
+    /// Returns this config with different global disassembly settings.
+    pub fn with_disassembly_settings(mut self, disassembly_settings: DisassemblySettings) -> Self {
+        self.disassembly_settings = disassembly_settings;
+        self
+    }
+
+    /// Returns this config with different module settings.
+    pub fn with_module_settings(mut self, module_settings: ModuleSettings) -> Self {
+        self.module_settings = module_settings;
+        self
+    }
+
+    /// Returns this config with `profile` added, replacing any existing profile of the same name.
+    pub fn with_profile(mut self, profile: YamlProfile) -> Self {
+        match self.profiles.iter_mut().find(|p| p.name == profile.name) {
+            Some(existing) => *existing = profile,
+            None => self.profiles.push(profile),
+        }
+        self
+    }
+
+    /// Returns this config without the profile called `name`.
+    pub fn without_profile(mut self, name: &str) -> Self {
+        self.profiles.retain(|p| p.name != name);
+        self
+    }
+
+    /// Keeps only the profiles named in `only`, or all of them when it is empty, minus those named
+    /// in `skip`. Returns the names from either list that match no profile.
+    pub fn select_profiles(&mut self, only: &[String], skip: &[String]) -> Vec<String> {
+        let unknown = only
+            .iter()
+            .chain(skip)
+            .filter(|name| !self.profiles.iter().any(|p| &p.name == *name))
+            .cloned()
+            .collect();
+        self.profiles
+            .retain(|p| (only.is_empty() || only.contains(&p.name)) && !skip.contains(&p.name));
+        unknown
+    }
+}
+
+impl Config {
+    /// Returns this config with different module settings.
+    pub fn with_module_settings(mut self, module_settings: ModuleSettings) -> Self {
+        self.module_settings = module_settings;
+        self
+    }
+
+    /// Returns this config with `profile` added, replacing any existing profile of the same name.
+    pub fn with_profile(mut self, profile: Profile) -> Self {
+        match self.profiles.iter_mut().find(|p| p.name == profile.name) {
+            Some(existing) => *existing = profile,
+            None => self.profiles.push(profile),
+        }
+        self
+    }
+
+    /// Returns this config without the profile called `name`.
+    pub fn without_profile(mut self, name: &str) -> Self {
+        self.profiles.retain(|p| p.name != name);
+        self
+    }
+}
+
+/// How many of the analyzed functions a resolved [`Config`] protects, see [`Config::coverage`].
+///
+/// Functions are counted, not code bytes, as the analysis result does not include function sizes.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Coverage {
+    /// Functions found by analysis, rejected ones included.
+    pub total_functions: usize,
+    /// Functions targeted by at least one profile.
+    pub protected_functions: usize,
+    /// Name of each profile and the number of functions it targets, in config order.
+    pub profiles: Vec<(String, usize)>,
+}
+
+impl Coverage {
+    /// Share of all functions that are protected, in percent.
+    pub fn percent(&self) -> f64 {
+        self.percent_of(self.protected_functions)
+    }
+
+    fn percent_of(&self, functions: usize) -> f64 {
+        if self.total_functions == 0 {
+            return 0.0;
+        }
+        functions as f64 * 100.0 / self.total_functions as f64
+    }
+}
+
+impl fmt::Display for Coverage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Protecting {} of {} functions ({:.1}%)",
+            self.protected_functions,
+            self.total_functions,
+            self.percent()
+        )?;
+        for (name, functions) in &self.profiles {
+            write!(
+                f,
+                "\n  {}: {} functions ({:.1}%)",
+                name,
+                functions,
+                self.percent_of(*functions)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Config {
+    /// Counts the functions of `analysis` targeted by the profiles of this config. Symbols that are
+    /// not functions of the analysis, such as data, are ignored.
+    pub fn coverage(&self, analysis: &AnalysisResult) -> Coverage {
+        let functions: HashSet<u64> = analysis
+            .functions
+            .iter()
+            .map(|f| f.rva)
+            .chain(analysis.rejects.iter().map(|r| r.rva))
+            .collect();
+        let mut protected: HashSet<u64> = HashSet::new();
+        let profiles = self
+            .profiles
+            .iter()
+            .map(|profile| {
+                let targeted: HashSet<u64> = profile
+                    .symbols
+                    .iter()
+                    .copied()
+                    .filter(|rva| functions.contains(rva))
+                    .collect();
+                protected.extend(&targeted);
+                (profile.name.clone(), targeted.len())
+            })
+            .collect();
+        Coverage {
+            total_functions: functions.len(),
+            protected_functions: protected.len(),
+            profiles,
+        }
+    }
+}
+
+impl From<LoopEncodeSemantics> for ObfuscationPass {
+    fn from(value: LoopEncodeSemantics) -> Self {
+        ObfuscationPass::LoopEncodeSemantics(value)
+    }
+}
+
+impl From<MixedBooleanArithmetic> for ObfuscationPass {
+    fn from(value: MixedBooleanArithmetic) -> Self {
+        ObfuscationPass::MixedBooleanArithmetic(value)
+    }
+}
+
+impl From<MutationEngine> for ObfuscationPass {
+    fn from(value: MutationEngine) -> Self {
+        ObfuscationPass::MutationEngine(value)
+    }
+}
+
+impl From<TetherExtraction> for ObfuscationPass {
+    fn from(value: TetherExtraction) -> Self {
+        ObfuscationPass::TetherExtraction(value)
+    }
+}
+
+impl From<SplitBlockPass> for ObfuscationPass {
+    fn from(value: SplitBlockPass) -> Self {
+        ObfuscationPass::SplitBlockPass(value)
+    }
+}
+
+impl From<OpaqueBlockDuplication> for ObfuscationPass {
+    fn from(value: OpaqueBlockDuplication) -> Self {
+        ObfuscationPass::OpaqueBlockDuplication(value)
+    }
+}
+
+impl From<ObscureControlFlow> for ObfuscationPass {
+    fn from(value: ObscureControlFlow) -> Self {
+        ObfuscationPass::ObscureControlFlow(value)
+    }
+}
+
+impl From<LeaEncodeImm> for ObfuscationPass {
+    fn from(value: LeaEncodeImm) -> Self {
+        ObfuscationPass::LeaEncodeImm(value)
+    }
+}
+
+impl From<ObscureConstants> for ObfuscationPass {
+    fn from(value: ObscureConstants) -> Self {
+        ObfuscationPass::ObscureConstants(value)
+    }
+}
+
+impl From<SuppressConstants> for ObfuscationPass {
+    fn from(value: SuppressConstants) -> Self {
+        ObfuscationPass::SuppressConstants(value)
+    }
+}
+
+impl From<ObscureReferences> for ObfuscationPass {
+    fn from(value: ObscureReferences) -> Self {
+        ObfuscationPass::ObscureReferences(value)
+    }
+}
+
+impl From<SigBreaker> for ObfuscationPass {
+    fn from(value: SigBreaker) -> Self {
+        ObfuscationPass::SigBreaker(value)
+    }
+}
+
+impl From<DataProtection> for ObfuscationPass {
+    fn from(value: DataProtection) -> Self {
+        ObfuscationPass::DataProtection(value)
+    }
+}
+
+impl ObfuscationPass {
+    /// The [`ObfuscationPass::IDADecompilerCrasher`] pass.
+    pub fn ida_decompiler_crasher() -> Self {
+        ObfuscationPass::IDADecompilerCrasher
+    }
+
+    /// The [`ObfuscationPass::AntiEmulator`] pass.
+    pub fn anti_emulator() -> Self {
+        ObfuscationPass::AntiEmulator
+    }
+
+    /// The [`LoopEncodeSemantics`] settings if this is that pass.
+    pub fn as_loop_encode_semantics(&self) -> Option<&LoopEncodeSemantics> {
+        match self {
+            ObfuscationPass::LoopEncodeSemantics(pass) => Some(pass),
+            _ => None,
+        }
+    }
+
+    /// The [`MixedBooleanArithmetic`] settings if this is that pass.
+    pub fn as_mixed_boolean_arithmetic(&self) -> Option<&MixedBooleanArithmetic> {
+        match self {
+            ObfuscationPass::MixedBooleanArithmetic(pass) => Some(pass),
+            _ => None,
+        }
+    }
+
+    /// The [`MutationEngine`] settings if this is that pass.
+    pub fn as_mutation_engine(&self) -> Option<&MutationEngine> {
+        match self {
+            ObfuscationPass::MutationEngine(pass) => Some(pass),
+            _ => None,
+        }
+    }
+
+    /// The [`TetherExtraction`] settings if this is that pass.
+    pub fn as_tether_extraction(&self) -> Option<&TetherExtraction> {
+        match self {
+            ObfuscationPass::TetherExtraction(pass) => Some(pass),
+            _ => None,
+        }
+    }
+
+    /// The [`SplitBlockPass`] settings if this is that pass.
+    pub fn as_split_block_pass(&self) -> Option<&SplitBlockPass> {
+        match self {
+            ObfuscationPass::SplitBlockPass(pass) => Some(pass),
+            _ => None,
+        }
+    }
+
+    /// The [`OpaqueBlockDuplication`] settings if this is that pass.
+    pub fn as_opaque_block_duplication(&self) -> Option<&OpaqueBlockDuplication> {
+        match self {
+            ObfuscationPass::OpaqueBlockDuplication(pass) => Some(pass),
+            _ => None,
+        }
+    }
+
+    /// The [`ObscureControlFlow`] settings if this is that pass.
+    pub fn as_obscure_control_flow(&self) -> Option<&ObscureControlFlow> {
+        match self {
+            ObfuscationPass::ObscureControlFlow(pass) => Some(pass),
+            _ => None,
+        }
+    }
+
+    /// The [`LeaEncodeImm`] settings if this is that pass.
+    pub fn as_lea_encode_imm(&self) -> Option<&LeaEncodeImm> {
+        match self {
+            ObfuscationPass::LeaEncodeImm(pass) => Some(pass),
+            _ => None,
+        }
+    }
+
+    /// The [`ObscureConstants`] settings if this is that pass.
+    pub fn as_obscure_constants(&self) -> Option<&ObscureConstants> {
+        match self {
+            ObfuscationPass::ObscureConstants(pass) => Some(pass),
+            _ => None,
+        }
+    }
+
+    /// The [`SuppressConstants`] settings if this is that pass.
+    pub fn as_suppress_constants(&self) -> Option<&SuppressConstants> {
+        match self {
+            ObfuscationPass::SuppressConstants(pass) => Some(pass),
+            _ => None,
+        }
+    }
+
+    /// The [`ObscureReferences`] settings if this is that pass.
+    pub fn as_obscure_references(&self) -> Option<&ObscureReferences> {
+        match self {
+            ObfuscationPass::ObscureReferences(pass) => Some(pass),
+            _ => None,
+        }
+    }
+
+    /// The [`SigBreaker`] settings if this is that pass.
+    pub fn as_sig_breaker(&self) -> Option<&SigBreaker> {
+        match self {
+            ObfuscationPass::SigBreaker(pass) => Some(pass),
+            _ => None,
+        }
+    }
+
+    /// The [`DataProtection`] settings if this is that pass.
+    pub fn as_data_protection(&self) -> Option<&DataProtection> {
+        match self {
+            ObfuscationPass::DataProtection(pass) => Some(pass),
+            _ => None,
+        }
+    }
+}
+
+impl PeEnvironment {
+    /// Whether this is a user-mode image (exe, dll).
+    pub fn is_user_mode(&self) -> bool {
+        matches!(self, PeEnvironment::UserMode)
+    }
+
+    /// Whether this is a kernel-mode image (sys).
+    pub fn is_kernel_mode(&self) -> bool {
+        matches!(self, PeEnvironment::KernelMode)
+    }
+
+    /// Whether this is a UEFI firmware image.
+    pub fn is_uefi(&self) -> bool {
+        matches!(self, PeEnvironment::UEFI)
+    }
+}
+
+impl MutationEngineExtension {
+    /// Name of the extension as written in the YAML config.
+    pub fn name(&self) -> &'static str {
+        match self {
+            MutationEngineExtension::Generic => "Generic",
+            MutationEngineExtension::SSE3 => "SSE3",
+            MutationEngineExtension::SSE42 => "SSE42",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{Value, json};
+
+    /// Every pass with a renamed field, written with the current names.
+    fn passes() -> Value {
+        let origins = json!({
+            "normal": true,
+            "memop": true,
+            "fp_based_memop": false,
+            "sp_based_memop": false
+        });
+        let semantics = json!({
+            "add": true, "sub": true, "and": true, "xor": true, "or": true, "not": true, "neg": true
+        });
+        let bitwidths = json!({ "bit8": false, "bit16": true, "bit32": true, "bit64": true });
+        json!([
+            {
+                "type": "LoopEncodeSemantics",
+                "iterations": 1,
+                "probability": 50,
+                "semantics": semantics,
+                "bitwidths": bitwidths,
+                "ethnicities": origins
+            },
+            {
+                "type": "MixedBooleanArithmetic",
+                "iterations": 2,
+                "probability": 100,
+                "semantics": semantics,
+                "bitwidths": bitwidths,
+                "ethnicities": origins
+            },
+            {
+                "type": "MutationEngine",
+                "iterations": 1,
+                "probability": 100,
+                "extension": "SSE42",
+                "semantics": semantics,
+                "bitwidths": bitwidths,
+                "ethnicities": origins
+            },
+            { "type": "SuppressConstants", "mba_enhance": true, "ethnicities": origins },
+            {
+                "type": "ObscureConstants",
+                "mba_enhance": false,
+                "probability": 100,
+                "iterations": 1,
+                "bitwidths": bitwidths,
+                "ethnicities": origins
+            },
+            {
+                "type": "LeaEncodeImm",
+                "mba_enhance": false,
+                "iterations": 1,
+                "probability": 75,
+                "ethnicities": origins
+            }
+        ])
+    }
+
+    /// `value` with the fields of `migration` under `path` renamed back to their old name.
+    fn unmigrate(value: &mut Value, path: &[&str], migration: &ConfigMigration) {
+        match (path.split_first(), value) {
+            (None, Value::Object(fields)) => {
+                if let Some(field) = fields.remove(migration.to) {
+                    fields.insert(migration.from.to_owned(), field);
+                }
+            }
+            (Some((&"*", rest)), Value::Array(items)) => {
+                items
+                    .iter_mut()
+                    .for_each(|item| unmigrate(item, rest, migration));
+            }
+            (Some((&"*", rest)), Value::Object(fields)) => {
+                fields
+                    .values_mut()
+                    .for_each(|field| unmigrate(field, rest, migration));
+            }
+            (Some((key, rest)), Value::Object(fields)) => {
+                if let Some(field) = fields.get_mut(*key) {
+                    unmigrate(field, rest, migration);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    #[test]
+    fn old_field_names_parse_and_serialize_as_the_current_ones() {
+        let current = json!({ "profiles": [{ "passes": passes() }] });
+        for migration in CONFIG_MIGRATIONS {
+            let mut old = current.clone();
+            unmigrate(&mut old, migration.path, migration);
+            assert_ne!(old, current, "{} is not used", migration.from);
+
+            let parse = |value: &Value| -> Vec<ObfuscationPass> {
+                serde_json::from_value(value["profiles"][0]["passes"].clone()).unwrap()
+            };
+            let passes = parse(&old);
+            assert_eq!(passes, parse(&current));
+            let written = serde_json::to_value(&passes).unwrap();
+            for pass in written.as_array().unwrap() {
+                assert!(pass.get(migration.to).is_some(), "{}", pass);
+                assert!(pass.get(migration.from).is_none(), "{}", pass);
+            }
+        }
+    }
+
+    #[test]
+    fn kernel_unwind_info_is_only_rejected_with_exception_handlers() {
+        let mut analysis = AnalysisResult {
+            environment: PeEnvironment::KernelMode,
+            ..AnalysisResult::default()
+        };
+        let mut config = YamlConfig::from_analysis(&analysis, ConfigPreset::Light);
+        config.module_settings.clear_unwind_info = true;
+
+        let warnings = config
+            .validate_for_environment(PeEnvironment::KernelMode)
+            .unwrap();
+        assert!(warnings.iter().any(|w| w.setting == "clear_unwind_info"));
+        config.validate_for_analysis(&analysis).unwrap();
+
+        analysis.module = Some(AnalysisModuleInfo::default());
+        config.validate_for_analysis(&analysis).unwrap();
+
+        analysis.module = Some(AnalysisModuleInfo {
+            seh: true,
+            ..AnalysisModuleInfo::default()
+        });
+        let error = config.validate_for_analysis(&analysis).unwrap_err();
+        assert_eq!(error.issues[0].setting, "clear_unwind_info");
+    }
+
+    #[test]
+    fn reversed_ranges_sample_between_their_bounds() {
+        let range = ParamValue::Range { min: 10, max: 4 };
+        for random in 0..20 {
+            assert!((4..=10).contains(&range.sample(random)));
+        }
+        let full = ParamValue::Range {
+            min: 0,
+            max: u32::MAX,
+        };
+        assert_eq!(full.sample(u64::from(u32::MAX)), u32::MAX);
+    }
+
+    #[test]
+    fn bit_width_weights_above_100_are_rejected() {
+        let mut config = YamlConfig::from_analysis(&AnalysisResult::default(), ConfigPreset::Light);
+        let mut pass: ObfuscationPass = serde_json::from_value(passes()[0].clone()).unwrap();
+        let ObfuscationPass::LoopEncodeSemantics(loop_encode) = &mut pass else {
+            unreachable!();
+        };
+        loop_encode.bitwidth_weights = Some(BitWidthWeights {
+            bit8: 0,
+            bit16: 100,
+            bit32: 101,
+            bit64: 50,
+        });
+        config.profiles.push(YamlProfile {
+            passes: vec![pass],
+            ..ConfigPreset::Light.profile("weighted".to_owned(), vec![])
+        });
+        let errors: Vec<String> = config
+            .validate()
+            .into_iter()
+            .filter(|d| d.severity == DiagnosticSeverity::Error)
+            .map(|d| d.path_string())
+            .collect();
+        let index = config.profiles.len() - 1;
+        assert_eq!(
+            errors,
+            [format!(
+                "profiles[{index}].passes[0].bitwidth_weights.bit32"
+            )]
+        );
+    }
+
+    #[test]
+    fn out_of_range_assembler_settings_are_rejected() {
+        let mut config = YamlConfig::from_analysis(&AnalysisResult::default(), ConfigPreset::Light);
+        let mut settings = ConfigPreset::Light.compiler_settings();
+        settings.assembler_settings.junk_bytes.chance = 1.5;
+        settings.assembler_settings.basic_block_alignment = 24;
+        settings.assembler_settings.function_padding = FunctionPadding {
+            min_bytes: 16,
+            max_bytes: 8,
+            fill: PaddingFill::Int3,
+        };
+        config
+            .compiler_settings
+            .insert("template".to_owned(), settings);
+        let errors: Vec<String> = config
+            .validate()
+            .into_iter()
+            .filter(|d| d.severity == DiagnosticSeverity::Error)
+            .map(|d| d.path_string())
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                "compiler_settings.template.assembler_settings.junk_bytes.chance",
+                "compiler_settings.template.assembler_settings.basic_block_alignment",
+                "compiler_settings.template.assembler_settings.function_padding.min_bytes",
+            ]
+        );
+    }
+
+    #[cfg(feature = "fuzzing")]
+    #[test]
+    fn arbitrary_configs_survive_a_yaml_round_trip() {
+        use arbitrary::{Arbitrary, Unstructured};
+
+        // A fixed xorshift stream stands in for fuzzer input.
+        let mut state = 0x9e37_79b9_7f4a_7c15u64;
+        for _ in 0..64 {
+            let data: Vec<u8> = (0..4096)
+                .map(|_| {
+                    state ^= state << 13;
+                    state ^= state >> 7;
+                    state ^= state << 17;
+                    state as u8
+                })
+                .collect();
+            let config = YamlConfig::arbitrary(&mut Unstructured::new(&data)).unwrap();
+            let yaml = serde_yaml::to_string(&config).unwrap();
+            let parsed: YamlConfig = serde_yaml::from_str(&yaml).unwrap();
+            assert_eq!(parsed, config, "{}", yaml);
+        }
+    }
 }
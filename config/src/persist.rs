@@ -0,0 +1,490 @@
+//! Saving and loading analysis results, with [`AnalysisResult::save`] and [`AnalysisResult::load`].
+//!
+//! Enabled with the `persist` feature. Results are stored either as the JSON the service returns,
+//! or in a compact binary format: the magic bytes `CDAR` ([`ANALYSIS_MAGIC`]), the schema version
+//! as a little endian `u16`, then the result encoded with bincode 2's
+//! [`standard`](bincode::config::standard) configuration in the layout of that version. Layouts
+//! are frozen once released, so results saved by an older release keep loading after
+//! [`AnalysisResult`] grows; fields a schema does not carry load as their defaults.
+
+use crate::*;
+use bincode::{Decode, Encode};
+use std::{fs, io, path::Path};
+
+/// Magic bytes binary analysis results start with.
+pub const ANALYSIS_MAGIC: [u8; 4] = *b"CDAR";
+
+/// Schema version of the binary analysis results this crate writes.
+pub const ANALYSIS_SCHEMA_VERSION: u16 = 4;
+
+/// How an analysis result is stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AnalysisFormat {
+    /// The JSON the service returns.
+    Json,
+    /// The compact binary format, see the [module documentation](self).
+    Binary,
+}
+
+impl AnalysisFormat {
+    /// JSON for paths ending in `.json`, binary otherwise.
+    pub fn for_path(path: &Path) -> Self {
+        match path.extension() {
+            Some(ext) if ext.eq_ignore_ascii_case("json") => AnalysisFormat::Json,
+            _ => AnalysisFormat::Binary,
+        }
+    }
+}
+
+/// Why an analysis result could not be saved or loaded.
+#[derive(Debug)]
+pub enum PersistError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    /// The binary result is cut short or does not match its schema.
+    Corrupt(String),
+    /// The binary result has a schema newer than [`ANALYSIS_SCHEMA_VERSION`].
+    UnsupportedSchema(u16),
+}
+
+impl fmt::Display for PersistError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PersistError::Io(e) => write!(f, "{}", e),
+            PersistError::Json(e) => write!(f, "invalid analysis result: {}", e),
+            PersistError::Corrupt(e) => write!(f, "corrupt analysis result: {}", e),
+            PersistError::UnsupportedSchema(version) => write!(
+                f,
+                "analysis result schema {} is newer than {}, the newest this version reads",
+                version, ANALYSIS_SCHEMA_VERSION
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PersistError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PersistError::Io(e) => Some(e),
+            PersistError::Json(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for PersistError {
+    fn from(e: io::Error) -> Self {
+        PersistError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for PersistError {
+    fn from(e: serde_json::Error) -> Self {
+        PersistError::Json(e)
+    }
+}
+
+impl AnalysisResult {
+    /// Writes the result to `path`, as JSON when it ends in `.json` and in the binary format
+    /// otherwise.
+    pub fn save(&self, path: &Path) -> Result<(), PersistError> {
+        Ok(fs::write(
+            path,
+            self.to_bytes(AnalysisFormat::for_path(path))?,
+        )?)
+    }
+
+    /// Reads a result [`save`](Self::save) wrote, in either format whatever the extension.
+    pub fn load(path: &Path) -> Result<Self, PersistError> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+
+    /// The result stored as `format`.
+    pub fn to_bytes(&self, format: AnalysisFormat) -> Result<Vec<u8>, PersistError> {
+        match format {
+            AnalysisFormat::Json => Ok(serde_json::to_vec_pretty(self)?),
+            AnalysisFormat::Binary => {
+                let mut bytes = ANALYSIS_MAGIC.to_vec();
+                bytes.extend_from_slice(&ANALYSIS_SCHEMA_VERSION.to_le_bytes());
+                bincode::encode_into_std_write(
+                    AnalysisV4::from(self),
+                    &mut bytes,
+                    bincode::config::standard(),
+                )
+                .map_err(|e| PersistError::Corrupt(e.to_string()))?;
+                Ok(bytes)
+            }
+        }
+    }
+
+    /// Reverses [`to_bytes`](Self::to_bytes), telling the formats apart by the magic bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, PersistError> {
+        let Some(rest) = bytes.strip_prefix(&ANALYSIS_MAGIC) else {
+            return Ok(serde_json::from_slice(bytes)?);
+        };
+        let (Some(version), Some(encoded)) = (rest.get(..2), rest.get(2..)) else {
+            return Err(PersistError::Corrupt("missing schema version".to_owned()));
+        };
+        match u16::from_le_bytes([version[0], version[1]]) {
+            1 => decode::<AnalysisV1>(encoded)?.try_into(),
+            2 => decode::<AnalysisV2>(encoded)?.try_into(),
+            3 => decode::<AnalysisV3>(encoded)?.try_into(),
+            4 => decode::<AnalysisV4>(encoded)?.try_into(),
+            version => Err(PersistError::UnsupportedSchema(version)),
+        }
+    }
+}
+
+/// Decodes `bytes`, which must hold exactly one `T`.
+fn decode<T: Decode<()>>(bytes: &[u8]) -> Result<T, PersistError> {
+    let (value, read) = bincode::decode_from_slice(bytes, bincode::config::standard())
+        .map_err(|e| PersistError::Corrupt(e.to_string()))?;
+    if read != bytes.len() {
+        return Err(PersistError::Corrupt("trailing bytes".to_owned()));
+    }
+    Ok(value)
+}
+
+/// An [`AnalysisResult`] in schema version 1.
+#[derive(Encode, Decode)]
+struct AnalysisV1 {
+    /// [`PeEnvironment`] as `0` for user mode, `1` for kernel mode and `2` for UEFI.
+    environment: u8,
+    functions: Vec<FunctionV1>,
+    rejects: Vec<RejectV1>,
+    macros: Vec<MacroV1>,
+    data_symbols: Vec<DataSymbolV1>,
+}
+
+#[derive(Encode, Decode)]
+struct FunctionV1 {
+    rva: u64,
+    symbol: String,
+    ref_count: u64,
+}
+
+#[derive(Encode, Decode)]
+struct RejectV1 {
+    rva: u64,
+    symbol: String,
+    ty: String,
+    reason: String,
+}
+
+#[derive(Encode, Decode)]
+struct MacroV1 {
+    name: String,
+    rvas: Vec<u64>,
+}
+
+#[derive(Encode, Decode)]
+struct DataSymbolV1 {
+    rva: u64,
+    symbol: String,
+    size: u32,
+}
+
+/// An [`AnalysisResult`] in schema version 2, which added [`AnalysisResult::module`].
+#[derive(Encode, Decode)]
+struct AnalysisV2 {
+    v1: AnalysisV1,
+    module: Option<ModuleV2>,
+}
+
+#[derive(Encode, Decode)]
+struct ModuleV2 {
+    entry_point: Option<u64>,
+    tls_callbacks: Vec<u64>,
+    load_config: bool,
+    cfg: bool,
+    exception_directory: bool,
+    seh: bool,
+}
+
+impl From<&AnalysisResult> for AnalysisV2 {
+    fn from(analysis: &AnalysisResult) -> Self {
+        Self {
+            v1: analysis.into(),
+            module: analysis.module.as_ref().map(|m| ModuleV2 {
+                entry_point: m.entry_point,
+                tls_callbacks: m.tls_callbacks.clone(),
+                load_config: m.load_config,
+                cfg: m.cfg,
+                exception_directory: m.exception_directory,
+                seh: m.seh,
+            }),
+        }
+    }
+}
+
+impl TryFrom<AnalysisV2> for AnalysisResult {
+    type Error = PersistError;
+
+    fn try_from(analysis: AnalysisV2) -> Result<Self, PersistError> {
+        Ok(AnalysisResult {
+            module: analysis.module.map(|m| AnalysisModuleInfo {
+                entry_point: m.entry_point,
+                tls_callbacks: m.tls_callbacks,
+                load_config: m.load_config,
+                cfg: m.cfg,
+                exception_directory: m.exception_directory,
+                seh: m.seh,
+                imports: None,
+                exports: None,
+            }),
+            ..analysis.v1.try_into()?
+        })
+    }
+}
+
+/// An [`AnalysisResult`] in schema version 3, which added [`AnalysisModuleInfo::imports`] and
+/// [`AnalysisModuleInfo::exports`]. They are only kept when the module info is present.
+#[derive(Encode, Decode)]
+struct AnalysisV3 {
+    v2: AnalysisV2,
+    imports: Option<Vec<ImportV3>>,
+    exports: Option<Vec<ExportV3>>,
+}
+
+#[derive(Encode, Decode)]
+struct ImportV3 {
+    library: String,
+    functions: Vec<String>,
+}
+
+#[derive(Encode, Decode)]
+struct ExportV3 {
+    name: Option<String>,
+    ordinal: u32,
+    rva: Option<u64>,
+    forward: Option<String>,
+}
+
+impl From<&AnalysisResult> for AnalysisV3 {
+    fn from(analysis: &AnalysisResult) -> Self {
+        let module = analysis.module.as_ref();
+        Self {
+            v2: analysis.into(),
+            imports: module.and_then(|m| m.imports.as_ref()).map(|imports| {
+                imports
+                    .iter()
+                    .map(|i| ImportV3 {
+                        library: i.library.clone(),
+                        functions: i.functions.clone(),
+                    })
+                    .collect()
+            }),
+            exports: module.and_then(|m| m.exports.as_ref()).map(|exports| {
+                exports
+                    .iter()
+                    .map(|e| ExportV3 {
+                        name: e.name.clone(),
+                        ordinal: e.ordinal,
+                        rva: e.rva,
+                        forward: e.forward.clone(),
+                    })
+                    .collect()
+            }),
+        }
+    }
+}
+
+impl TryFrom<AnalysisV3> for AnalysisResult {
+    type Error = PersistError;
+
+    fn try_from(analysis: AnalysisV3) -> Result<Self, PersistError> {
+        let mut result: AnalysisResult = analysis.v2.try_into()?;
+        if let Some(module) = &mut result.module {
+            module.imports = analysis.imports.map(|imports| {
+                imports
+                    .into_iter()
+                    .map(|i| AnalysisImport {
+                        library: i.library,
+                        functions: i.functions,
+                    })
+                    .collect()
+            });
+            module.exports = analysis.exports.map(|exports| {
+                exports
+                    .into_iter()
+                    .map(|e| AnalysisExport {
+                        name: e.name,
+                        ordinal: e.ordinal,
+                        rva: e.rva,
+                        forward: e.forward,
+                    })
+                    .collect()
+            });
+        }
+        Ok(result)
+    }
+}
+
+/// An [`AnalysisResult`] in schema version 4, which added [`AnalysisReject::severity`] and
+/// [`AnalysisReject::safe_passes`], stored in the order of the rejects.
+#[derive(Encode, Decode)]
+struct AnalysisV4 {
+    v3: AnalysisV3,
+    rejects: Vec<RejectV4>,
+}
+
+#[derive(Encode, Decode)]
+struct RejectV4 {
+    /// [`RejectSeverity`] as `0` for fatal, `1` for force resolvable and `2` for degraded.
+    severity: Option<u8>,
+    safe_passes: Option<Vec<String>>,
+}
+
+impl From<&AnalysisResult> for AnalysisV4 {
+    fn from(analysis: &AnalysisResult) -> Self {
+        Self {
+            v3: analysis.into(),
+            rejects: analysis
+                .rejects
+                .iter()
+                .map(|r| RejectV4 {
+                    severity: r.severity.map(|severity| match severity {
+                        RejectSeverity::Fatal => 0,
+                        RejectSeverity::ForceResolvable => 1,
+                        RejectSeverity::Degraded => 2,
+                    }),
+                    safe_passes: r.safe_passes.clone(),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl TryFrom<AnalysisV4> for AnalysisResult {
+    type Error = PersistError;
+
+    fn try_from(analysis: AnalysisV4) -> Result<Self, PersistError> {
+        let mut result: AnalysisResult = analysis.v3.try_into()?;
+        if analysis.rejects.len() != result.rejects.len() {
+            return Err(PersistError::Corrupt("reject count mismatch".to_owned()));
+        }
+        for (reject, details) in result.rejects.iter_mut().zip(analysis.rejects) {
+            reject.severity = match details.severity {
+                None => None,
+                Some(0) => Some(RejectSeverity::Fatal),
+                Some(1) => Some(RejectSeverity::ForceResolvable),
+                Some(2) => Some(RejectSeverity::Degraded),
+                Some(other) => {
+                    return Err(PersistError::Corrupt(format!(
+                        "unknown reject severity {}",
+                        other
+                    )));
+                }
+            };
+            reject.safe_passes = details.safe_passes;
+        }
+        Ok(result)
+    }
+}
+
+impl From<&AnalysisResult> for AnalysisV1 {
+    fn from(analysis: &AnalysisResult) -> Self {
+        Self {
+            environment: match analysis.environment {
+                PeEnvironment::UserMode => 0,
+                PeEnvironment::KernelMode => 1,
+                PeEnvironment::UEFI => 2,
+            },
+            functions: analysis
+                .functions
+                .iter()
+                .map(|f| FunctionV1 {
+                    rva: f.rva,
+                    symbol: f.symbol.clone(),
+                    ref_count: f.ref_count as u64,
+                })
+                .collect(),
+            rejects: analysis
+                .rejects
+                .iter()
+                .map(|r| RejectV1 {
+                    rva: r.rva,
+                    symbol: r.symbol.clone(),
+                    ty: r.ty.clone(),
+                    reason: r.reason.clone(),
+                })
+                .collect(),
+            macros: analysis
+                .macros
+                .iter()
+                .map(|m| MacroV1 {
+                    name: m.name.clone(),
+                    rvas: m.rvas.clone(),
+                })
+                .collect(),
+            data_symbols: analysis
+                .data_symbols
+                .iter()
+                .map(|d| DataSymbolV1 {
+                    rva: d.rva,
+                    symbol: d.symbol.clone(),
+                    size: d.size,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl TryFrom<AnalysisV1> for AnalysisResult {
+    type Error = PersistError;
+
+    fn try_from(analysis: AnalysisV1) -> Result<Self, PersistError> {
+        Ok(AnalysisResult {
+            environment: match analysis.environment {
+                0 => PeEnvironment::UserMode,
+                1 => PeEnvironment::KernelMode,
+                2 => PeEnvironment::UEFI,
+                other => {
+                    return Err(PersistError::Corrupt(format!(
+                        "unknown environment {}",
+                        other
+                    )));
+                }
+            },
+            functions: analysis
+                .functions
+                .into_iter()
+                .map(|f| AnalysisFunction {
+                    rva: f.rva,
+                    symbol: f.symbol,
+                    ref_count: f.ref_count as usize,
+                })
+                .collect(),
+            rejects: analysis
+                .rejects
+                .into_iter()
+                .map(|r| AnalysisReject {
+                    rva: r.rva,
+                    symbol: r.symbol,
+                    ty: r.ty,
+                    reason: r.reason,
+                    severity: None,
+                    safe_passes: None,
+                })
+                .collect(),
+            macros: analysis
+                .macros
+                .into_iter()
+                .map(|m| AnalysisMacroProfile {
+                    name: m.name,
+                    rvas: m.rvas,
+                })
+                .collect(),
+            data_symbols: analysis
+                .data_symbols
+                .into_iter()
+                .map(|d| AnalysisDataSymbol {
+                    rva: d.rva,
+                    symbol: d.symbol,
+                    size: d.size,
+                })
+                .collect(),
+            module: None,
+        })
+    }
+}
@@ -0,0 +1,2320 @@
+//! Debug symbol preprocessing for CodeDefender.
+//!
+//! The service learns function names and addresses from a symbol stream uploaded next to the
+//! binary. This crate produces it from a PDB ([`parse_pdb`], or [`parse_pdb_file`] to read a large
+//! PDB from disk instead of memory), from DWARF debug info embedded in the binary (`parse_dwarf`,
+//! behind the `dwarf` feature), from the export table ([`parse_exports`]) or from the static
+//! libraries linked into it ([`parse_libraries`]), and reads it back with [`decode_symbols`].
+//! [`infer_noreturn`] fills in `noreturn` from the binary when the debug info does not say.
+//!
+//! # Wire format
+//!
+//! The stream is a [`StreamHeader`] followed by a list of symbols encoded with bincode 2's
+//! [`standard`](bincode::config::standard) configuration and compressed as a single zstd frame.
+//! [`StreamVersion::V1`] streams, and those for services that predate the header (see
+//! [`SymbolOptions::headerless`]), are the zstd frame alone, as every stream was before the header
+//! was added. The header is not compressed, all of its fields are little endian:
+//!
+//! * the magic bytes `CDSY`, see [`STREAM_MAGIC`],
+//! * the size of the header in bytes as a `u16`, currently `18`. Readers skip fields after the
+//!   ones they know,
+//! * the [`StreamVersion`] as a `u16`, `2` for [`StreamVersion::V2`],
+//! * flags as a `u32`. Readers ignore unknown flags in the low 16 bits and reject streams with
+//!   unknown flags in the high 16 bits, see [`StreamHeader::REQUIRED_FLAGS`],
+//! * the major, minor and patch version of the crate that wrote the stream, as three `u16`s.
+//!
+//! The layout of the symbols depends on the [`StreamVersion`]:
+//!
+//! * the number of symbols, as a varint,
+//! * for each symbol, in field order:
+//!   * `address`, the RVA of the function, as a varint,
+//!   * `name`, the demangled name, as a string,
+//!   * `noreturn`, as one byte, `0` or `1`,
+//!   * since [`StreamVersion::V2`], `source`: `0` when unknown, or `1` followed by the file as a
+//!     string and the line as a varint,
+//!   * since [`StreamVersion::V3`], `len`: `0` when unknown, or `1` followed by the size of the
+//!     function in bytes as a varint,
+//!   * since [`StreamVersion::V4`], `mangled`: `0` when no decorated symbol is known, or `1`
+//!     followed by it as a string,
+//!   * since [`StreamVersion::V5`], `inlinees`: the number of functions inlined into it as a
+//!     varint, then for each its name as a string, the number of code ranges as a varint and the
+//!     address and length of each as varints.
+//!
+//! Strings are a varint byte length followed by UTF-8. Varints are bincode's: values below 251
+//! are one byte, larger ones a marker byte (`251` for `u16`, `252` for `u32`) followed by the
+//! value in little endian. Symbols are ordered by address, every address appears once, and names
+//! are unique, see [`DuplicateNames`].
+//!
+//! Streams without a header start with the zstd frame right away. [`decode_symbols`] reads them,
+//! guessing their version.
+
+use bincode::{Decode, Encode};
+use pdb::FallibleIterator;
+use std::{
+    borrow::Borrow,
+    collections::{BTreeMap, HashMap, HashSet, hash_map::Entry},
+    fmt,
+    fs::File,
+    io::{self, BufReader, Cursor, Write},
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc,
+    },
+    thread,
+};
+use symbolic_common::Name;
+use symbolic_demangle::{Demangle, DemangleOptions};
+
+/// Where a function is defined.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Encode, Decode)]
+pub struct SourceLocation {
+    /// Path of the source file as the compiler recorded it.
+    pub file: String,
+    /// Line the function starts at.
+    pub line: u32,
+}
+
+/// A range of code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Encode, Decode)]
+pub struct CodeRange {
+    /// RVA of the first byte.
+    pub address: u32,
+    /// Size in bytes.
+    pub len: u32,
+}
+
+/// A function inlined into another one.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Encode, Decode)]
+pub struct InlineSite {
+    /// Name of the inlined function, qualified by its namespace or class but without its
+    /// signature, e.g. `ns::License::check`.
+    pub name: String,
+    /// Where its inlined code is, ordered by address.
+    pub ranges: Vec<CodeRange>,
+}
+
+/// Info that is fed into decomposers. It is info about symbols within a binary.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DebugSymbolInfo {
+    /// Address in the virtual mapping.
+    pub address: u32,
+    /// Demangled name, unique within a stream.
+    pub name: String,
+    /// Does this function not return?
+    pub noreturn: bool,
+    /// Source file and line, when the debug info records them. Only [`StreamVersion::V2`] and
+    /// later streams carry it.
+    pub source: Option<SourceLocation>,
+    /// Size of the function in bytes, when the debug info records it. Only [`StreamVersion::V3`]
+    /// and later streams carry it.
+    pub len: Option<u32>,
+    /// The decorated symbol of the function, e.g. `?method@Class@ns@@QEAAXH@Z`, when the debug
+    /// info records one that demangling changed. Only [`StreamVersion::V4`] and later streams
+    /// carry it.
+    pub mangled: Option<String>,
+    /// Functions inlined into this one, ordered by name, when the debug info records them. Only
+    /// [`StreamVersion::V5`] streams carry them.
+    pub inlinees: Vec<InlineSite>,
+}
+
+/// Layout of a symbol stream, see the [crate documentation](crate).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum StreamVersion {
+    /// Address, name and noreturn flag of every function. What the service reads unless it is
+    /// told otherwise.
+    #[default]
+    V1,
+    /// Adds the source location of every function.
+    V2,
+    /// Adds the size of every function.
+    V3,
+    /// Adds the mangled name of every function.
+    V4,
+    /// Adds the functions inlined into every function.
+    V5,
+}
+
+impl StreamVersion {
+    /// The newest layout this crate writes.
+    pub const LATEST: Self = StreamVersion::V5;
+
+    /// Every layout, oldest first.
+    pub const ALL: [Self; 5] = [
+        StreamVersion::V1,
+        StreamVersion::V2,
+        StreamVersion::V3,
+        StreamVersion::V4,
+        StreamVersion::V5,
+    ];
+
+    /// The number a [`StreamHeader`] records the layout as.
+    pub fn number(self) -> u16 {
+        self as u16 + 1
+    }
+
+    /// The layout a [`StreamHeader`] records as `number`, `None` if this crate does not know it.
+    pub fn from_number(number: u16) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|version| version.number() == number)
+    }
+
+    /// The newest layout up to this one that a reader of layouts up to `supported`, such as the
+    /// service, understands. `None` if it reads none this crate writes.
+    pub fn negotiate(self, supported: u16) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .filter(|version| *version <= self && version.number() <= supported)
+            .max()
+    }
+}
+
+/// How a symbol stream is compressed. Neither changes what it decodes to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Compression {
+    /// zstd level, from `1`, the fastest, to `22`, the smallest. `3` by default.
+    pub level: i32,
+    /// Threads compressing the stream, `1` by default. More need the `multithread` feature and
+    /// are ignored without it.
+    pub threads: u32,
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        // Level 3: balanced speed/compression
+        Self {
+            level: 3,
+            threads: 1,
+        }
+    }
+}
+
+/// Magic bytes a symbol stream starts with.
+pub const STREAM_MAGIC: [u8; 4] = *b"CDSY";
+
+/// The header in front of a symbol stream, see the [crate documentation](crate).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct StreamHeader {
+    /// Layout of the symbols.
+    pub version: StreamVersion,
+    /// How the symbols were produced, see [`StreamHeader::NORETURN_INFERRED`],
+    /// [`StreamHeader::MODULES_FILTERED`] and [`StreamHeader::GENERATED_FILTERED`].
+    pub flags: u32,
+    /// Major, minor and patch version of the crate that wrote the stream.
+    pub producer: [u16; 3],
+}
+
+impl StreamHeader {
+    /// Size in bytes of the header this crate writes.
+    pub const SIZE: u16 = 18;
+    /// `noreturn` flags were filled in by [`infer_noreturn`].
+    pub const NORETURN_INFERRED: u32 = 1 << 0;
+    /// The functions of some modules were left out, see [`ModuleFilter`].
+    pub const MODULES_FILTERED: u32 = 1 << 1;
+    /// Some generated functions were left out, see [`GeneratedFilter`].
+    pub const GENERATED_FILTERED: u32 = 1 << 2;
+    /// Flags a reader must know to decode the stream. No such flag is defined yet.
+    pub const REQUIRED_FLAGS: u32 = 0xFFFF_0000;
+
+    /// The header of a stream of `version` written by this crate, without flags.
+    pub fn new(version: StreamVersion) -> Self {
+        let mut producer = [0; 3];
+        for (part, number) in producer
+            .iter_mut()
+            .zip(env!("CARGO_PKG_VERSION").split(['.', '-', '+']))
+        {
+            *part = number.parse().unwrap_or(0);
+        }
+        Self {
+            version,
+            flags: 0,
+            producer,
+        }
+    }
+
+    /// Reads the header at the start of `stream`. Returns it and the compressed symbols after it.
+    pub fn read(stream: &[u8]) -> Result<(Self, &[u8]), StreamError> {
+        let field = |at: usize| {
+            stream
+                .get(at..at + 2)
+                .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+        };
+        if !stream.starts_with(&STREAM_MAGIC) {
+            return Err(StreamError::NotAStream);
+        }
+        let (
+            Some(size),
+            Some(number),
+            Some(flags_low),
+            Some(flags_high),
+            Some(major),
+            Some(minor),
+            Some(patch),
+        ) = (
+            field(4),
+            field(6),
+            field(8),
+            field(10),
+            field(12),
+            field(14),
+            field(16),
+        )
+        else {
+            return Err(StreamError::NotAStream);
+        };
+        let Some(symbols) = stream.get(usize::from(size.max(Self::SIZE))..) else {
+            return Err(StreamError::NotAStream);
+        };
+        let flags = u32::from(flags_low) | u32::from(flags_high) << 16;
+        let producer = [major, minor, patch];
+        let version =
+            StreamVersion::from_number(number).ok_or(StreamError::UnsupportedVersion {
+                version: number,
+                producer,
+            })?;
+        if flags & Self::REQUIRED_FLAGS != 0 {
+            return Err(StreamError::UnsupportedFlags(flags & Self::REQUIRED_FLAGS));
+        }
+        Ok((
+            Self {
+                version,
+                flags,
+                producer,
+            },
+            symbols,
+        ))
+    }
+
+    /// The header as written in front of a stream.
+    pub fn to_bytes(&self) -> [u8; Self::SIZE as usize] {
+        let mut bytes = [0; Self::SIZE as usize];
+        bytes[..4].copy_from_slice(&STREAM_MAGIC);
+        bytes[4..6].copy_from_slice(&Self::SIZE.to_le_bytes());
+        bytes[6..8].copy_from_slice(&self.version.number().to_le_bytes());
+        bytes[8..12].copy_from_slice(&self.flags.to_le_bytes());
+        for (at, part) in self.producer.iter().enumerate() {
+            bytes[12 + at * 2..14 + at * 2].copy_from_slice(&part.to_le_bytes());
+        }
+        bytes
+    }
+}
+
+/// Why a symbol stream cannot be read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamError {
+    /// It does not start with a [`StreamHeader`].
+    NotAStream,
+    /// Its layout is newer than [`StreamVersion::LATEST`]. `producer` is the version of the crate
+    /// that wrote it.
+    UnsupportedVersion { version: u16, producer: [u16; 3] },
+    /// It sets [required flags](StreamHeader::REQUIRED_FLAGS) this crate does not know.
+    UnsupportedFlags(u32),
+    /// Its symbols do not decompress or do not match its layout.
+    Corrupt,
+}
+
+impl fmt::Display for StreamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StreamError::NotAStream => write!(f, "not a symbol stream"),
+            StreamError::UnsupportedVersion {
+                version,
+                producer: [major, minor, patch],
+            } => write!(
+                f,
+                "symbol stream v{}, written by codedefender-symbols {}.{}.{}, is newer than v{}, the newest this reader knows",
+                version,
+                major,
+                minor,
+                patch,
+                StreamVersion::LATEST.number()
+            ),
+            StreamError::UnsupportedFlags(flags) => {
+                write!(f, "symbol stream uses unknown required flags {:#x}", flags)
+            }
+            StreamError::Corrupt => write!(f, "symbol stream is corrupt"),
+        }
+    }
+}
+
+impl std::error::Error for StreamError {}
+
+/// How symbol names are demangled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Demangling {
+    /// The full signature, e.g. `public: void __cdecl ns::Class::method(int)`.
+    #[default]
+    Complete,
+    /// The qualified name without parameters, return type and qualifiers, e.g. `ns::Class::method`.
+    NameOnly,
+    /// Symbols are kept as the debug info records them.
+    None,
+}
+
+impl Demangling {
+    /// `mangled` demangled in this style. Names that are not mangled are returned unchanged.
+    pub fn demangle(self, mangled: &str) -> String {
+        let options = match self {
+            Demangling::Complete => DemangleOptions::complete(),
+            Demangling::NameOnly => DemangleOptions::name_only(),
+            Demangling::None => return mangled.to_owned(),
+        };
+        Name::from(mangled).try_demangle(options).to_string()
+    }
+}
+
+/// How functions that share a name at different addresses are told apart. Duplicates are visited
+/// by address, and the lowest address always keeps the plain name, so the result is the same for
+/// every run on the same input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DuplicateNames {
+    /// `name_1`, `name_2` and so on, numbered in hex by address.
+    #[default]
+    Counter,
+    /// `name_<rva>`, the address in hex. Stays the same when other duplicates come and go, but
+    /// changes with every build that moves the function.
+    Rva,
+    /// `name@<module>`, the object file or compilation unit the function is defined in. Falls
+    /// back to [`DuplicateNames::Counter`] when the module is unknown or not unique.
+    Module,
+    /// Only the function at the lowest address is kept.
+    KeepFirst,
+}
+
+/// Which functions are kept, by the object file or compilation unit that defines them. Patterns
+/// match the file name of the module (`vcruntime*.obj`) or of the static library it was linked
+/// from (`libcmt.lib`), ignoring case, where `*` matches any run of characters and `?` one.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ModuleFilter {
+    /// Keep only functions of modules matching one of these. Everything when empty.
+    pub include: Vec<String>,
+    /// Drop functions of modules matching one of these, even if included.
+    pub exclude: Vec<String>,
+}
+
+impl ModuleFilter {
+    /// Whether a function of `module`, linked from `library`, is kept. Functions of unknown
+    /// modules, such as those only known from public symbols or exports, are only kept when
+    /// nothing is included explicitly.
+    fn keeps(&self, module: Option<&str>, library: Option<&str>) -> bool {
+        let matches = |pattern: &String| {
+            [module, library]
+                .into_iter()
+                .flatten()
+                .any(|name| wildcard_match(&pattern.to_lowercase(), &name.to_lowercase()))
+        };
+        !self.exclude.iter().any(matches)
+            && (self.include.is_empty() || self.include.iter().any(matches))
+    }
+}
+
+/// Which generated functions are left out. Each is noise in analysis results, and targeting it
+/// protects code every caller shares.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GeneratedFilter {
+    /// Drop thunks, such as the jumps to imports, those of the incremental linking table
+    /// (`@ILT+...`) and adjustor thunks.
+    pub thunks: bool,
+    /// Drop helpers the compiler calls on its own, such as `__security_check_cookie` or
+    /// `__chkstk`, and the bodies it generates, such as deleting destructors and dynamic
+    /// initializers.
+    pub compiler_helpers: bool,
+    /// Drop functions identical COMDAT folding (`/OPT:ICF`) merged with differently named ones,
+    /// whose code is shared by all of them.
+    pub folded: bool,
+}
+
+/// Names of compiler helpers and generated bodies, demangled or decorated, see
+/// [`GeneratedFilter::compiler_helpers`].
+const COMPILER_HELPERS: &[&str] = &[
+    "__security_check_cookie",
+    "@__security_check_cookie@*",
+    "__report_gsfailure",
+    "__GSHandlerCheck*",
+    "__chkstk",
+    "_chkstk",
+    "__alloca_probe*",
+    "_alloca_probe*",
+    "__C_specific_handler",
+    "__CxxFrameHandler*",
+    "_guard_check_icall*",
+    "_guard_dispatch_icall*",
+    "_RTC_*",
+    "*`scalar deleting destructor'*",
+    "*`vector deleting destructor'*",
+    "*`dynamic initializer for '*",
+    "*`dynamic atexit destructor for '*",
+    "??_G*",
+    "??_E*",
+    "??__E*",
+    "??__F*",
+];
+
+impl GeneratedFilter {
+    /// Whether `function` is kept.
+    fn keeps(&self, function: &Function) -> bool {
+        let names = || std::iter::once(&function.name).chain(&function.mangled);
+        let thunk = function.thunk || names().any(|name| name.starts_with("@ILT+"));
+        let helper = || {
+            names().any(|name| {
+                COMPILER_HELPERS
+                    .iter()
+                    .any(|pattern| wildcard_match(pattern, name))
+            })
+        };
+        let dropped = (self.thunks && thunk)
+            || (self.folded && function.folded)
+            || (self.compiler_helpers && helper());
+        !dropped
+    }
+
+    /// Whether any function is dropped.
+    fn is_active(&self) -> bool {
+        self.thunks || self.compiler_helpers || self.folded
+    }
+}
+
+/// Matches `name` against a pattern where `*` matches any run of characters and `?` one.
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    let mut backtrack = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some('?') => {
+                p += 1;
+                n += 1;
+            }
+            Some(c) if *c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    p = star + 1;
+                    n = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// How symbol streams are produced.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SymbolOptions {
+    /// Layout of the stream.
+    pub version: StreamVersion,
+    /// How function names are demangled.
+    pub demangling: Demangling,
+    /// How functions sharing a name are told apart.
+    pub duplicates: DuplicateNames,
+    /// Run [`infer_noreturn`] on the binary. `parse_dwarf` and [`parse_exports`] do so
+    /// themselves; PDB symbols are read without the binary, so callers pass them to it.
+    pub infer_noreturn: bool,
+    /// Which modules' functions are kept.
+    pub modules: ModuleFilter,
+    /// Which generated functions are left out.
+    pub generated: GeneratedFilter,
+    /// How the stream is compressed.
+    pub compression: Compression,
+    /// Static libraries and object files whose functions are looked for in the binary, see
+    /// [`parse_libraries`]. The other functions leave them out, callers add them with
+    /// [`merge_symbols`].
+    pub libraries: Vec<PathBuf>,
+    /// Leave the [`StreamHeader`] out, for services that do not read it.
+    /// [`StreamVersion::V1`] streams never have one.
+    pub headerless: bool,
+}
+
+impl SymbolOptions {
+    /// The header of a stream produced with these options, flagged as if [`infer_noreturn`] ran
+    /// when they ask for it.
+    pub fn header(&self) -> StreamHeader {
+        let mut header = StreamHeader::new(self.version);
+        if self.infer_noreturn {
+            header.flags |= StreamHeader::NORETURN_INFERRED;
+        }
+        if !self.modules.include.is_empty() || !self.modules.exclude.is_empty() {
+            header.flags |= StreamHeader::MODULES_FILTERED;
+        }
+        if self.generated.is_active() {
+            header.flags |= StreamHeader::GENERATED_FILTERED;
+        }
+        header
+    }
+
+    /// Same as [`SymbolOptions::header`], for PDB symbols, which are read without the binary.
+    fn pdb_header(&self) -> StreamHeader {
+        let header = self.header();
+        StreamHeader {
+            flags: header.flags & !StreamHeader::NORETURN_INFERRED,
+            ..header
+        }
+    }
+}
+
+/// A symbol in a [`StreamVersion::V1`] stream.
+#[derive(Encode, Decode)]
+struct SymbolV1 {
+    address: u32,
+    name: String,
+    noreturn: bool,
+}
+
+/// A symbol in a [`StreamVersion::V2`] stream.
+#[derive(Encode, Decode)]
+struct SymbolV2 {
+    address: u32,
+    name: String,
+    noreturn: bool,
+    source: Option<SourceLocation>,
+}
+
+/// A symbol in a [`StreamVersion::V3`] stream.
+#[derive(Encode, Decode)]
+struct SymbolV3 {
+    address: u32,
+    name: String,
+    noreturn: bool,
+    source: Option<SourceLocation>,
+    len: Option<u32>,
+}
+
+/// A symbol in a [`StreamVersion::V4`] stream.
+#[derive(Encode, Decode)]
+struct SymbolV4 {
+    address: u32,
+    name: String,
+    noreturn: bool,
+    source: Option<SourceLocation>,
+    len: Option<u32>,
+    mangled: Option<String>,
+}
+
+/// A symbol in a [`StreamVersion::V5`] stream.
+#[derive(Encode, Decode)]
+struct SymbolV5 {
+    address: u32,
+    name: String,
+    noreturn: bool,
+    source: Option<SourceLocation>,
+    len: Option<u32>,
+    mangled: Option<String>,
+    inlinees: Vec<InlineSite>,
+}
+
+impl From<&DebugSymbolInfo> for SymbolV1 {
+    fn from(symbol: &DebugSymbolInfo) -> Self {
+        Self {
+            address: symbol.address,
+            name: symbol.name.clone(),
+            noreturn: symbol.noreturn,
+        }
+    }
+}
+
+impl From<&DebugSymbolInfo> for SymbolV2 {
+    fn from(symbol: &DebugSymbolInfo) -> Self {
+        Self {
+            address: symbol.address,
+            name: symbol.name.clone(),
+            noreturn: symbol.noreturn,
+            source: symbol.source.clone(),
+        }
+    }
+}
+
+impl From<&DebugSymbolInfo> for SymbolV3 {
+    fn from(symbol: &DebugSymbolInfo) -> Self {
+        Self {
+            address: symbol.address,
+            name: symbol.name.clone(),
+            noreturn: symbol.noreturn,
+            source: symbol.source.clone(),
+            len: symbol.len,
+        }
+    }
+}
+
+impl From<&DebugSymbolInfo> for SymbolV4 {
+    fn from(symbol: &DebugSymbolInfo) -> Self {
+        Self {
+            address: symbol.address,
+            name: symbol.name.clone(),
+            noreturn: symbol.noreturn,
+            source: symbol.source.clone(),
+            len: symbol.len,
+            mangled: symbol.mangled.clone(),
+        }
+    }
+}
+
+impl From<&DebugSymbolInfo> for SymbolV5 {
+    fn from(symbol: &DebugSymbolInfo) -> Self {
+        Self {
+            address: symbol.address,
+            name: symbol.name.clone(),
+            noreturn: symbol.noreturn,
+            source: symbol.source.clone(),
+            len: symbol.len,
+            mangled: symbol.mangled.clone(),
+            inlinees: symbol.inlinees.clone(),
+        }
+    }
+}
+
+impl From<SymbolV1> for DebugSymbolInfo {
+    fn from(symbol: SymbolV1) -> Self {
+        Self {
+            address: symbol.address,
+            name: symbol.name,
+            noreturn: symbol.noreturn,
+            source: None,
+            len: None,
+            mangled: None,
+            inlinees: Vec::new(),
+        }
+    }
+}
+
+impl From<SymbolV2> for DebugSymbolInfo {
+    fn from(symbol: SymbolV2) -> Self {
+        Self {
+            address: symbol.address,
+            name: symbol.name,
+            noreturn: symbol.noreturn,
+            source: symbol.source,
+            len: None,
+            mangled: None,
+            inlinees: Vec::new(),
+        }
+    }
+}
+
+impl From<SymbolV3> for DebugSymbolInfo {
+    fn from(symbol: SymbolV3) -> Self {
+        Self {
+            address: symbol.address,
+            name: symbol.name,
+            noreturn: symbol.noreturn,
+            source: symbol.source,
+            len: symbol.len,
+            mangled: None,
+            inlinees: Vec::new(),
+        }
+    }
+}
+
+impl From<SymbolV4> for DebugSymbolInfo {
+    fn from(symbol: SymbolV4) -> Self {
+        Self {
+            address: symbol.address,
+            name: symbol.name,
+            noreturn: symbol.noreturn,
+            source: symbol.source,
+            len: symbol.len,
+            mangled: symbol.mangled,
+            inlinees: Vec::new(),
+        }
+    }
+}
+
+impl From<SymbolV5> for DebugSymbolInfo {
+    fn from(symbol: SymbolV5) -> Self {
+        Self {
+            address: symbol.address,
+            name: symbol.name,
+            noreturn: symbol.noreturn,
+            source: symbol.source,
+            len: symbol.len,
+            mangled: symbol.mangled,
+            inlinees: symbol.inlinees,
+        }
+    }
+}
+
+/// Encodes symbols into a stream of `version`, see the [crate documentation](crate).
+pub fn encode_symbols(funcs: &[DebugSymbolInfo], version: StreamVersion) -> Option<Vec<u8>> {
+    encode_stream(funcs, &StreamHeader::new(version), &Compression::default())
+}
+
+/// Same as [`encode_symbols`], behind `header` and compressed as `compression` says.
+pub fn encode_stream(
+    funcs: &[DebugSymbolInfo],
+    header: &StreamHeader,
+    compression: &Compression,
+) -> Option<Vec<u8>> {
+    write_stream(funcs, header, compression, Vec::new()).ok()
+}
+
+/// Same as [`encode_symbols`], but compresses each symbol into `writer` as it is encoded instead of
+/// encoding all of them first. Returns `writer`.
+pub fn write_symbols<W: Write>(
+    funcs: &[DebugSymbolInfo],
+    version: StreamVersion,
+    writer: W,
+) -> io::Result<W> {
+    write_stream(
+        funcs,
+        &StreamHeader::new(version),
+        &Compression::default(),
+        writer,
+    )
+}
+
+/// Same as [`write_symbols`], behind `header` and compressed as `compression` says.
+/// [`StreamVersion::V1`] streams are written without the header, see [`write_frame`].
+pub fn write_stream<W: Write>(
+    funcs: &[DebugSymbolInfo],
+    header: &StreamHeader,
+    compression: &Compression,
+    mut writer: W,
+) -> io::Result<W> {
+    if header.version != StreamVersion::V1 {
+        writer.write_all(&header.to_bytes())?;
+    }
+    write_frame(funcs, header.version, compression, writer)
+}
+
+/// Same as [`write_stream`], without a header, for services that predate it.
+pub fn write_frame<W: Write>(
+    funcs: &[DebugSymbolInfo],
+    version: StreamVersion,
+    compression: &Compression,
+    writer: W,
+) -> io::Result<W> {
+    write_each(funcs.len(), funcs, version, compression, writer)
+}
+
+/// Same as [`write_frame`], for the `count` symbols `funcs` yields, each encoded as it is produced.
+fn write_each<W: Write, B: Borrow<DebugSymbolInfo>>(
+    count: usize,
+    funcs: impl IntoIterator<Item = B>,
+    version: StreamVersion,
+    compression: &Compression,
+    writer: W,
+) -> io::Result<W> {
+    let config = bincode::config::standard();
+    let mut encoder = zstd::Encoder::new(writer, compression.level)?;
+    #[cfg(feature = "multithread")]
+    if compression.threads > 1 {
+        encoder.multithread(compression.threads)?;
+    }
+    // Laid out like the `Vec` bincode would encode: its length, then the elements
+    bincode::encode_into_std_write(count as u64, &mut encoder, config).map_err(io::Error::other)?;
+    let mut written = 0;
+    for symbol in funcs {
+        let symbol = symbol.borrow();
+        written += 1;
+        match version {
+            StreamVersion::V1 => {
+                bincode::encode_into_std_write(SymbolV1::from(symbol), &mut encoder, config)
+            }
+            StreamVersion::V2 => {
+                bincode::encode_into_std_write(SymbolV2::from(symbol), &mut encoder, config)
+            }
+            StreamVersion::V3 => {
+                bincode::encode_into_std_write(SymbolV3::from(symbol), &mut encoder, config)
+            }
+            StreamVersion::V4 => {
+                bincode::encode_into_std_write(SymbolV4::from(symbol), &mut encoder, config)
+            }
+            StreamVersion::V5 => {
+                bincode::encode_into_std_write(SymbolV5::from(symbol), &mut encoder, config)
+            }
+        }
+        .map_err(io::Error::other)?;
+    }
+    if written != count {
+        return Err(io::Error::other("symbol count does not match the symbols"));
+    }
+    encoder.finish()
+}
+
+/// Encodes symbols into a stream behind `header`, or without one when `options` says so.
+pub fn encode_with(
+    funcs: &[DebugSymbolInfo],
+    header: &StreamHeader,
+    options: &SymbolOptions,
+) -> Option<Vec<u8>> {
+    write_with(funcs.len(), funcs, header, options, Vec::new()).ok()
+}
+
+/// Same as [`encode_with`], for the `count` symbols `funcs` yields, writing the stream to `writer`.
+/// Returns `writer`.
+fn write_with<W: Write, B: Borrow<DebugSymbolInfo>>(
+    count: usize,
+    funcs: impl IntoIterator<Item = B>,
+    header: &StreamHeader,
+    options: &SymbolOptions,
+    mut writer: W,
+) -> io::Result<W> {
+    if !options.headerless && header.version != StreamVersion::V1 {
+        writer.write_all(&header.to_bytes())?;
+    }
+    write_each(count, funcs, header.version, &options.compression, writer)
+}
+
+/// Reads the functions of a PDB into a symbol stream. `None` if the PDB cannot be read.
+pub fn parse_pdb(pdb_bytes: &[u8], options: &SymbolOptions) -> Option<Vec<u8>> {
+    let Ok(funcs) = parse_pdb_impl(pdb_bytes, options) else {
+        return None;
+    };
+    encode_with(&funcs, &options.pdb_header(), options)
+}
+
+/// Same as [`parse_pdb`], but reads the PDB from the file at `path` as its streams are needed and
+/// writes the symbol stream to `writer`, so neither the PDB nor the uncompressed stream is held in
+/// memory. Returns `writer`.
+pub fn parse_pdb_file<W: Write>(path: &Path, options: &SymbolOptions, writer: W) -> pdb::Result<W> {
+    let (functions, inlinee_names) = open_pdb_file(path, options)?;
+    let count = functions.len();
+    let funcs = functions
+        .into_iter()
+        .map(|function| into_symbol(function, &inlinee_names));
+    Ok(write_with(
+        count,
+        funcs,
+        &options.pdb_header(),
+        options,
+        writer,
+    )?)
+}
+
+/// Same as [`parse_pdb_impl`], but reads the PDB from the file at `path`, see [`parse_pdb_file`].
+pub fn parse_pdb_file_impl(
+    path: &Path,
+    options: &SymbolOptions,
+) -> pdb::Result<Vec<DebugSymbolInfo>> {
+    let (functions, inlinee_names) = open_pdb_file(path, options)?;
+    Ok(functions
+        .into_iter()
+        .map(|function| into_symbol(function, &inlinee_names))
+        .collect())
+}
+
+/// [`collect_pdb`] of the PDB file at `path`.
+fn open_pdb_file(path: &Path, options: &SymbolOptions) -> pdb::Result<PdbFunctions> {
+    collect_pdb(
+        || pdb::PDB::open(BufReader::new(File::open(path)?)),
+        options,
+    )
+}
+
+/// A function while symbols are collected, before names are made unique.
+struct Function {
+    name: String,
+    noreturn: bool,
+    source: Option<SourceLocation>,
+    len: Option<u32>,
+    mangled: Option<String>,
+    /// File name of the object file or compilation unit that defines the function.
+    module: Option<String>,
+    /// File name of the static library `module` was linked from.
+    library: Option<String>,
+    /// Code inlined into the function, by id of the inlined function in the IPI stream.
+    inlinees: Vec<(u32, CodeRange)>,
+    /// Whether a thunk record of the debug info describes the function.
+    thunk: bool,
+    /// Smallest name a procedure record of the debug info gives the function. Public symbols
+    /// and exports do not count, they often name a function differently than its record.
+    procedure: Option<String>,
+    /// Whether procedure records of different names describe the function.
+    folded: bool,
+}
+
+impl Function {
+    /// A function named by the symbol `mangled`, demangled with `demangling`.
+    fn new(
+        mangled: &str,
+        demangling: Demangling,
+        noreturn: bool,
+        source: Option<SourceLocation>,
+        len: Option<u32>,
+    ) -> Self {
+        let name = demangling.demangle(mangled);
+        let mangled = (name != mangled).then(|| mangled.to_owned());
+        Self {
+            name,
+            noreturn,
+            source,
+            len,
+            mangled,
+            module: None,
+            library: None,
+            inlinees: Vec::new(),
+            thunk: false,
+            procedure: None,
+            folded: false,
+        }
+    }
+}
+
+/// Records `function` at `rva`. Of several names for an address the smallest demangled one wins,
+/// the function is noreturn if any of its symbols says so, and the smallest known source location
+/// decorated symbol and module, with its library, and the largest known length are kept, and the
+/// inlined code of all of them is merged, so the result does not depend on the order symbols
+/// arrive in. It is a thunk if any record says so, and folded once procedure records disagree on
+/// its name.
+fn add_function(functions: &mut HashMap<u32, Function>, rva: u32, function: Function) {
+    match functions.entry(rva) {
+        Entry::Occupied(mut e) => {
+            let existing = e.get_mut();
+            if function.name < existing.name {
+                existing.name = function.name;
+            }
+            existing.noreturn = existing.noreturn || function.noreturn;
+            existing.source = match (existing.source.take(), function.source) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            };
+            existing.mangled = match (existing.mangled.take(), function.mangled) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            };
+            if function.module.is_some()
+                && (existing.module.is_none() || function.module < existing.module)
+            {
+                existing.module = function.module;
+                existing.library = function.library;
+            }
+            existing.len = existing.len.max(function.len);
+            existing.inlinees.extend(function.inlinees);
+            existing.inlinees.sort_unstable();
+            existing.inlinees.dedup();
+            existing.thunk = existing.thunk || function.thunk;
+            existing.folded = existing.folded
+                || function.folded
+                || matches!((&existing.procedure, &function.procedure), (Some(a), Some(b)) if a != b);
+            existing.procedure = match (existing.procedure.take(), function.procedure) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            };
+        }
+        Entry::Vacant(e) => {
+            e.insert(function);
+        }
+    }
+}
+
+/// Turns the collected functions `options` keeps into symbols ordered by address, with names made
+/// unique as it says. Inlined functions are named by `inlinee_names`, those without a name are
+/// left out.
+fn into_symbols(
+    functions: HashMap<u32, Function>,
+    options: &SymbolOptions,
+    inlinee_names: &HashMap<u32, String>,
+) -> Vec<DebugSymbolInfo> {
+    name_functions(functions, options)
+        .into_iter()
+        .map(|function| into_symbol(function, inlinee_names))
+        .collect()
+}
+
+/// The collected functions `options` keeps, ordered by address, with the unique name each is
+/// given, see [`into_symbols`].
+fn name_functions(
+    functions: HashMap<u32, Function>,
+    options: &SymbolOptions,
+) -> Vec<(u32, String, Function)> {
+    let mut functions: Vec<(u32, Function)> = functions
+        .into_iter()
+        .filter(|(_, f)| {
+            options
+                .modules
+                .keeps(f.module.as_deref(), f.library.as_deref())
+                && options.generated.keeps(f)
+        })
+        .collect();
+    functions.sort_unstable_by_key(|(address, _)| *address);
+    // How often each name was seen so far, and every name handed out
+    let mut counts: HashMap<String, u32> = HashMap::default();
+    let mut used: HashSet<String> = HashSet::default();
+    let mut symbols = Vec::with_capacity(functions.len());
+    for (address, mut function) in functions {
+        let count = counts.entry(function.name.clone()).or_insert(0);
+        *count += 1;
+        let seen = *count - 1;
+        let mut name = match (seen, options.duplicates, &function.module) {
+            (0, ..) => std::mem::take(&mut function.name),
+            (_, DuplicateNames::KeepFirst, _) => continue,
+            (_, DuplicateNames::Rva, _) => format!("{}_{:x}", function.name, address),
+            (_, DuplicateNames::Module, Some(module)) => format!("{}@{}", function.name, module),
+            (_, DuplicateNames::Counter | DuplicateNames::Module, _) => {
+                format!("{}_{:x}", function.name, seen)
+            }
+        };
+        // A suffixed name can be taken by another function or module, count on until it is not
+        while used.contains(&name) {
+            name = format!("{}_{:x}", name, seen);
+        }
+        used.insert(name.clone());
+        symbols.push((address, name, function));
+    }
+    symbols
+}
+
+/// The symbol of a function [`name_functions`] named, see [`into_symbols`].
+fn into_symbol(
+    (address, name, function): (u32, String, Function),
+    inlinee_names: &HashMap<u32, String>,
+) -> DebugSymbolInfo {
+    // Sites of the same function, or of functions sharing a name, are one entry
+    let mut inlinees: BTreeMap<&str, Vec<CodeRange>> = BTreeMap::new();
+    for (id, range) in &function.inlinees {
+        if let Some(name) = inlinee_names.get(id) {
+            inlinees.entry(name).or_default().push(*range);
+        }
+    }
+    DebugSymbolInfo {
+        address,
+        name,
+        noreturn: function.noreturn,
+        source: function.source,
+        len: function.len,
+        mangled: function.mangled,
+        inlinees: inlinees
+            .into_iter()
+            .map(|(name, mut ranges)| {
+                ranges.sort_unstable();
+                ranges.dedup();
+                InlineSite {
+                    name: name.to_owned(),
+                    ranges,
+                }
+            })
+            .collect(),
+    }
+}
+
+/// File name of a module or compilation unit path, which uses the separators of the machine it
+/// was built on.
+fn module_file_name(path: &str) -> Option<String> {
+    let name = path.rsplit(['\\', '/']).next()?;
+    (!name.is_empty()).then(|| name.to_owned())
+}
+
+/// File and line of the first line record of the code at `offset`.
+fn pdb_source(
+    program: &pdb::LineProgram,
+    strings: &pdb::StringTable,
+    offset: pdb::PdbInternalSectionOffset,
+) -> Option<SourceLocation> {
+    let line = program.lines_for_symbol(offset).next().ok()??;
+    let file = program.get_file_info(line.file_index).ok()?;
+    Some(SourceLocation {
+        file: file.name.to_string_lossy(strings).ok()?.into_owned(),
+        line: line.line_start,
+    })
+}
+
+/// Code ranges of the line records of an inline site, adjacent ones merged.
+fn inline_ranges(
+    mut lines: pdb::InlineeLineIterator,
+    address_map: &pdb::AddressMap,
+) -> Vec<CodeRange> {
+    let mut ranges = Vec::new();
+    while let Ok(Some(line)) = lines.next() {
+        if let (Some(rva), Some(len)) = (line.offset.to_rva(address_map), line.length) {
+            ranges.push(CodeRange {
+                address: rva.0,
+                len,
+            });
+        }
+    }
+    merge_ranges(ranges)
+}
+
+/// `ranges` ordered, overlapping and adjacent ones merged.
+fn merge_ranges(mut ranges: Vec<CodeRange>) -> Vec<CodeRange> {
+    ranges.sort_unstable();
+    let mut merged: Vec<CodeRange> = Vec::with_capacity(ranges.len());
+    // Ends in u64, a range can reach past the end of the address space
+    let end = |range: &CodeRange| u64::from(range.address) + u64::from(range.len);
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if end(last) >= u64::from(range.address) => {
+                let len = end(&range).saturating_sub(u64::from(last.address));
+                last.len = last.len.max(u32::try_from(len).unwrap_or(u32::MAX));
+            }
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+/// Names of the inlined functions `ids` of the IPI stream, qualified by their namespace or class.
+/// Ids that are not functions are left out.
+fn inlinee_names<'s, S: pdb::Source<'s> + 's>(
+    pdb: &mut pdb::PDB<'s, S>,
+    ids: &HashSet<u32>,
+) -> pdb::Result<HashMap<u32, String>> {
+    let id_info = pdb.id_information()?;
+    let mut id_finder = id_info.finder();
+    let mut id_iter = id_info.iter();
+    while id_iter.next()?.is_some() {
+        id_finder.update(&id_iter);
+    }
+    // Classes of member functions are in the TPI stream, only indexed when one is met
+    let type_info = pdb.type_information()?;
+    let mut type_finder = None;
+    let mut names = HashMap::new();
+    for &id in ids {
+        let Ok(item) = id_finder.find(pdb::IdIndex(id)) else {
+            continue;
+        };
+        let (scope, name) = match item.parse() {
+            Ok(pdb::IdData::Function(function)) => {
+                let scope =
+                    function
+                        .scope
+                        .and_then(|scope| match id_finder.find(scope).ok()?.parse() {
+                            Ok(pdb::IdData::String(scope)) => {
+                                Some(scope.name.to_string().into_owned())
+                            }
+                            _ => None,
+                        });
+                (scope, function.name)
+            }
+            Ok(pdb::IdData::MemberFunction(function)) => {
+                let finder = match &mut type_finder {
+                    Some(finder) => finder,
+                    None => {
+                        let mut finder = type_info.finder();
+                        let mut iter = type_info.iter();
+                        while iter.next()?.is_some() {
+                            finder.update(&iter);
+                        }
+                        type_finder.insert(finder)
+                    }
+                };
+                let class = match finder.find(function.parent).and_then(|item| item.parse()) {
+                    Ok(pdb::TypeData::Class(class)) => Some(class.name.to_string().into_owned()),
+                    Ok(pdb::TypeData::Union(union)) => Some(union.name.to_string().into_owned()),
+                    _ => None,
+                };
+                (class, function.name)
+            }
+            _ => continue,
+        };
+        let name = match scope {
+            Some(scope) => format!("{}::{}", scope, name),
+            None => name.to_string().into_owned(),
+        };
+        names.insert(id, name);
+    }
+    Ok(names)
+}
+
+/// Parses the items handed out by `next`, each module and then the global symbols, on a PDB of its
+/// own from `open` and sends the functions of each through `sender`.
+fn parse_pdb_items<'s, S: pdb::Source<'s> + 's>(
+    open: &impl Fn() -> pdb::Result<pdb::PDB<'s, S>>,
+    demangling: Demangling,
+    next: &AtomicUsize,
+    sender: &mpsc::Sender<pdb::Result<Vec<(u32, Function)>>>,
+) -> pdb::Result<()> {
+    let mut pdb = open()?;
+    let address_map = pdb.address_map()?;
+    // File names of line records, missing in PDBs without line information
+    let strings = pdb.string_table().ok();
+    let debug_info = pdb.debug_information()?;
+    let mut modules = Vec::new();
+    let mut module_iter = debug_info.modules()?;
+    while let Ok(Some(module)) = module_iter.next() {
+        modules.push(module);
+    }
+    loop {
+        let index = next.fetch_add(1, Ordering::Relaxed);
+        let mut functions = Vec::new();
+        if let Some(module) = modules.get(index) {
+            let Some(info) = pdb.module_info(module)? else {
+                continue;
+            };
+            let module_name = module_file_name(&module.module_name());
+            // The static library for modules linked from one, else the object file again
+            let library = module_file_name(&module.object_file_name());
+            let lines = info.line_program().ok();
+            // Line records of inlined functions by id, read at the first inline site
+            let mut inlinees = None;
+            // Offset of the procedure being visited, the index its records end at and its index
+            // in `functions`
+            let mut procedure = None;
+            let mut symbols = info.symbols()?;
+            // Nested scopes are part of the flat record stream, so procedures inside them are
+            // visited like top level ones
+            while let Ok(Some(sym)) = symbols.next() {
+                let (offset, name, noreturn, len, end, thunk) = match sym.parse() {
+                    Ok(pdb::SymbolData::Procedure(data)) => (
+                        data.offset,
+                        data.name,
+                        data.flags.never,
+                        data.len,
+                        Some(data.end),
+                        false,
+                    ),
+                    Ok(pdb::SymbolData::Thunk(data)) => {
+                        (data.offset, data.name, false, data.len.into(), None, true)
+                    }
+                    // Sites nested in other sites are attributed to the procedure, whose offset
+                    // their line records are relative to
+                    Ok(pdb::SymbolData::InlineSite(site)) => {
+                        if let Some((parent, end, index)) = procedure
+                            && sym.index() < end
+                        {
+                            let inlinees = match &mut inlinees {
+                                Some(inlinees) => inlinees,
+                                None => inlinees.insert(
+                                    info.inlinees()?
+                                        .map(|inlinee| Ok((inlinee.index(), inlinee)))
+                                        .collect::<HashMap<_, _>>()?,
+                                ),
+                            };
+                            if let Some(inlinee) = inlinees.get(&site.inlinee) {
+                                let (_, function): &mut (u32, Function) = &mut functions[index];
+                                for range in
+                                    inline_ranges(inlinee.lines(parent, &site), &address_map)
+                                {
+                                    function.inlinees.push((site.inlinee.0, range));
+                                }
+                            }
+                        }
+                        continue;
+                    }
+                    _ => continue,
+                };
+                procedure = None;
+                if let Some(rva) = offset.to_rva(&address_map)
+                    && !name.is_empty()
+                {
+                    let source = match (&lines, &strings) {
+                        (Some(lines), Some(strings)) => pdb_source(lines, strings, offset),
+                        _ => None,
+                    };
+                    let name = name.to_string();
+                    let function = Function {
+                        module: module_name.clone(),
+                        library: library.clone(),
+                        thunk,
+                        procedure: (!thunk).then(|| name.clone().into_owned()),
+                        ..Function::new(&name, demangling, noreturn, source, Some(len))
+                    };
+                    procedure = end.map(|end| (offset, end, functions.len()));
+                    functions.push((rva.0, function));
+                }
+            }
+        } else if index == modules.len() {
+            // Try and parse the public/global table now (for stripped PDB files)
+            if let Ok(global_symbols) = pdb.global_symbols() {
+                let mut symbols = global_symbols.iter();
+                while let Ok(Some(symbol)) = symbols.next() {
+                    match symbol.parse() {
+                        Ok(pdb::SymbolData::Public(data)) if data.function => {
+                            // Publics of sections the linker discarded have no address
+                            let Some(rva) = data.offset.to_rva(&address_map) else {
+                                continue;
+                            };
+                            // For globals, noreturn defaults to false
+                            functions.push((
+                                rva.0,
+                                Function::new(
+                                    &data.name.to_string(),
+                                    demangling,
+                                    false,
+                                    None,
+                                    None,
+                                ),
+                            ));
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        } else {
+            return Ok(());
+        }
+        if sender.send(Ok(functions)).is_err() {
+            return Ok(());
+        }
+    }
+}
+
+/// The functions of a PDB, from the symbols of every module and the public function symbols for
+/// stripped PDBs. Module symbols are procedures, global and local alike (static functions, DPC
+/// routines, and funclets such as SEH filters nested in another procedure's scope), and thunks
+/// (import stubs, incremental linking and adjustor thunks). The smallest demangled name wins for
+/// an address, and names seen at several addresses are told apart as `options.duplicates` says.
+/// Module symbols are given the source location of their first line record.
+///
+/// Modules are read and demangled on one thread per CPU, and their functions merged as they
+/// arrive.
+pub fn parse_pdb_impl(
+    pdb_bytes: &[u8],
+    options: &SymbolOptions,
+) -> pdb::Result<Vec<DebugSymbolInfo>> {
+    let (functions, inlinee_names) =
+        collect_pdb(|| pdb::PDB::open(Cursor::new(pdb_bytes)), options)?;
+    Ok(functions
+        .into_iter()
+        .map(|function| into_symbol(function, &inlinee_names))
+        .collect())
+}
+
+/// Functions of a PDB named by [`name_functions`], and the names of the functions inlined into
+/// them by id.
+type PdbFunctions = (Vec<(u32, String, Function)>, HashMap<u32, String>);
+
+/// The functions of the PDB `open` opens, once per thread, see [`into_symbols`].
+fn collect_pdb<'s, S: pdb::Source<'s> + 's>(
+    open: impl Fn() -> pdb::Result<pdb::PDB<'s, S>> + Sync,
+    options: &SymbolOptions,
+) -> pdb::Result<PdbFunctions> {
+    let mut pdb = open()?;
+    // One item per module, and one more for the global symbols
+    let items = pdb.debug_information()?.modules()?.count().unwrap_or(0) + 1;
+    let workers = thread::available_parallelism()
+        .map_or(1, NonZeroUsize::get)
+        .min(items);
+    let next = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+    // Use address-based map to collect unique functions, choosing min demangled name per address
+    let mut functions: HashMap<u32, Function> = HashMap::default();
+    let mut error = None;
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            let sender = sender.clone();
+            let (next, open) = (&next, &open);
+            scope.spawn(move || {
+                if let Err(e) = parse_pdb_items(open, options.demangling, next, &sender) {
+                    let _ = sender.send(Err(e));
+                }
+            });
+        }
+        drop(sender);
+        for item in receiver {
+            match item {
+                Ok(item) => {
+                    for (rva, function) in item {
+                        add_function(&mut functions, rva, function);
+                    }
+                }
+                Err(e) => {
+                    error.get_or_insert(e);
+                }
+            }
+        }
+    });
+    if let Some(e) = error {
+        return Err(e);
+    }
+    let ids: HashSet<u32> = functions
+        .values()
+        .flat_map(|f| f.inlinees.iter().map(|(id, _)| *id))
+        .collect();
+    // Without readable id records the inlined code is left unnamed rather than failing the parse
+    let inlinee_names = match ids.is_empty() {
+        true => HashMap::new(),
+        false => open()
+            .and_then(|mut pdb| inlinee_names(&mut pdb, &ids))
+            .unwrap_or_default(),
+    };
+    Ok((name_functions(functions, options), inlinee_names))
+}
+
+/// How much of the debug info a PDB carries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PdbCompleteness {
+    /// The procedure records of the modules, with sizes and noreturn flags.
+    Full,
+    /// Linked with `/DEBUG:FASTLINK`: the procedure records stay in the object files, so only the
+    /// public symbols name functions.
+    FastLink,
+    /// Private symbols were stripped (`/PDBSTRIPPED`), leaving the public symbols.
+    Stripped,
+    /// No module has procedure records, for example because the objects were compiled without
+    /// debug info, leaving the public symbols.
+    PublicsOnly,
+}
+
+/// The `MINI` feature signature of the PDB info stream, written for `/DEBUG:FASTLINK`.
+const PDB_FEATURE_MINIMAL_DEBUG_INFO: u32 = 0x494E_494D;
+
+/// Reads a little endian `u32` at `offset` of `data`.
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        data.get(offset..offset.checked_add(4)?)?.try_into().ok()?,
+    ))
+}
+
+/// Whether the feature signatures at the end of the PDB info stream include
+/// [`PDB_FEATURE_MINIMAL_DEBUG_INFO`]. They follow the names of the named streams and the hash
+/// table mapping them to stream numbers.
+fn is_fastlink(info: &[u8]) -> Option<bool> {
+    // Version, signature, age and GUID
+    let names_size = read_u32(info, 28)? as usize;
+    let mut offset = 32usize.checked_add(names_size)?;
+    let size = read_u32(info, offset)? as usize;
+    offset += 8;
+    // Present and deleted bit vectors, each a word count and the words
+    for _ in 0..2 {
+        let words = read_u32(info, offset)? as usize;
+        offset = offset.checked_add(4 + words.checked_mul(4)?)?;
+    }
+    // The entries, then an unused word
+    offset = offset.checked_add(size.checked_mul(8)?)?.checked_add(4)?;
+    let mut features = info.get(offset..)?.chunks_exact(4);
+    Some(features.any(|feature| {
+        u32::from_le_bytes(feature.try_into().unwrap()) == PDB_FEATURE_MINIMAL_DEBUG_INFO
+    }))
+}
+
+/// How much of the debug info `pdb` carries, to tell why a PDB only names a few functions.
+/// [`PdbCompleteness::PublicsOnly`] is found by looking for procedure records, which reads every
+/// module of a PDB that has none.
+pub fn pdb_completeness<'s, S: pdb::Source<'s> + 's>(
+    pdb: &mut pdb::PDB<'s, S>,
+) -> pdb::Result<PdbCompleteness> {
+    if let Some(info) = pdb.raw_stream(pdb::StreamIndex(1))?
+        && is_fastlink(&info) == Some(true)
+    {
+        return Ok(PdbCompleteness::FastLink);
+    }
+    // `fStripped` in the flags of the DBI header
+    if let Some(dbi) = pdb.raw_stream(pdb::StreamIndex(3))?
+        && dbi.get(56..58).is_some_and(|flags| flags[0] & 0x2 != 0)
+    {
+        return Ok(PdbCompleteness::Stripped);
+    }
+    let debug_info = pdb.debug_information()?;
+    let mut modules = debug_info.modules()?;
+    while let Some(module) = modules.next()? {
+        let Some(info) = pdb.module_info(&module)? else {
+            continue;
+        };
+        let mut symbols = info.symbols()?;
+        while let Some(symbol) = symbols.next()? {
+            if matches!(symbol.parse(), Ok(pdb::SymbolData::Procedure(_))) {
+                return Ok(PdbCompleteness::Full);
+            }
+        }
+    }
+    Ok(PdbCompleteness::PublicsOnly)
+}
+
+/// Whether the binary carries DWARF debug info, as MinGW and Clang builds without a PDB do.
+#[cfg(feature = "dwarf")]
+pub fn has_dwarf(binary_bytes: &[u8]) -> bool {
+    use object::Object;
+    object::read::File::parse(binary_bytes)
+        .is_ok_and(|file| file.section_by_name(".debug_info").is_some())
+}
+
+/// Same as [`parse_pdb`], but reads the DWARF debug info embedded in the binary.
+#[cfg(feature = "dwarf")]
+pub fn parse_dwarf(binary_bytes: &[u8], options: &SymbolOptions) -> Option<Vec<u8>> {
+    let Ok(file) = object::read::File::parse(binary_bytes) else {
+        return None;
+    };
+    let Ok(mut funcs) = parse_dwarf_impl(&file, options) else {
+        return None;
+    };
+    if options.infer_noreturn {
+        infer_noreturn(binary_bytes, &mut funcs);
+    }
+    encode_with(&funcs, &options.header(), options)
+}
+
+/// File and line from the `DW_AT_decl_file` and `DW_AT_decl_line` of `entry`.
+#[cfg(feature = "dwarf")]
+fn dwarf_source<R: gimli::Reader>(
+    dwarf: &gimli::Dwarf<R>,
+    unit: &gimli::Unit<R>,
+    entry: &gimli::DebuggingInformationEntry<R>,
+) -> gimli::Result<Option<SourceLocation>> {
+    let (Some(gimli::AttributeValue::FileIndex(index)), Some(line)) = (
+        entry.attr_value(gimli::DW_AT_decl_file)?,
+        entry.attr_value(gimli::DW_AT_decl_line)?,
+    ) else {
+        return Ok(None);
+    };
+    let Some(program) = &unit.line_program else {
+        return Ok(None);
+    };
+    let header = program.header();
+    let Some(file_entry) = header.file(index) else {
+        return Ok(None);
+    };
+    let mut file = dwarf
+        .attr_string(unit, file_entry.path_name())?
+        .to_string_lossy()?
+        .into_owned();
+    if !file.starts_with('/')
+        && let Some(dir) = file_entry.directory(header)
+    {
+        let dir = dwarf
+            .attr_string(unit, dir)?
+            .to_string_lossy()?
+            .into_owned();
+        if !dir.is_empty() {
+            file = format!("{}/{}", dir.trim_end_matches('/'), file);
+        }
+    }
+    Ok(Some(SourceLocation {
+        file,
+        line: line
+            .udata_value()
+            .and_then(|line| u32::try_from(line).ok())
+            .unwrap_or(0),
+    }))
+}
+
+#[cfg(feature = "dwarf")]
+pub fn parse_dwarf_impl(
+    file: &object::read::File,
+    options: &SymbolOptions,
+) -> gimli::Result<Vec<DebugSymbolInfo>> {
+    use gimli::{AttributeValue, EndianSlice, LittleEndian};
+    use object::{Object, ObjectSection};
+
+    let image_base = file.relative_address_base();
+    let load_section =
+        |id: gimli::SectionId| -> Result<EndianSlice<'_, LittleEndian>, gimli::Error> {
+            let data = file
+                .section_by_name(id.name())
+                .and_then(|section| section.data().ok())
+                .unwrap_or(&[]);
+            Ok(EndianSlice::new(data, LittleEndian))
+        };
+    let dwarf = gimli::Dwarf::load(load_section)?;
+
+    // Same as for PDBs: one name per address, the smallest demangled one wins
+    let mut functions: HashMap<u32, Function> = HashMap::default();
+    let mut units = dwarf.units();
+    while let Some(header) = units.next()? {
+        let unit = dwarf.unit(header)?;
+        let module = match &unit.name {
+            Some(name) => module_file_name(&name.to_string_lossy()),
+            None => None,
+        };
+        let mut entries = unit.entries();
+        while let Some((_, entry)) = entries.next_dfs()? {
+            if entry.tag() != gimli::DW_TAG_subprogram {
+                continue;
+            }
+            let Some(low_pc) = entry.attr_value(gimli::DW_AT_low_pc)? else {
+                continue;
+            };
+            let Some(address) = dwarf.attr_address(&unit, low_pc)? else {
+                continue;
+            };
+            let Some(rva) = address
+                .checked_sub(image_base)
+                .and_then(|rva| u32::try_from(rva).ok())
+            else {
+                continue;
+            };
+            // Out of line definitions keep their name on the declaration they refer to
+            let mut named = entry.clone();
+            for _ in 0..2 {
+                if named.attr(gimli::DW_AT_linkage_name)?.is_some()
+                    || named.attr(gimli::DW_AT_name)?.is_some()
+                {
+                    break;
+                }
+                let origin = match named.attr_value(gimli::DW_AT_specification)? {
+                    Some(origin) => Some(origin),
+                    None => named.attr_value(gimli::DW_AT_abstract_origin)?,
+                };
+                match origin {
+                    Some(AttributeValue::UnitRef(offset)) => named = unit.entry(offset)?,
+                    _ => break,
+                }
+            }
+            let name_attr = match named.attr_value(gimli::DW_AT_linkage_name)? {
+                Some(name) => Some(name),
+                None => named.attr_value(gimli::DW_AT_name)?,
+            };
+            let Some(name_attr) = name_attr else {
+                continue;
+            };
+            let mangled = dwarf
+                .attr_string(&unit, name_attr)?
+                .to_string_lossy()
+                .into_owned();
+            let noreturn = matches!(
+                entry.attr_value(gimli::DW_AT_noreturn)?,
+                Some(AttributeValue::Flag(true))
+            );
+            // Declarations carry the file and line, definitions only when they differ
+            let source = match dwarf_source(&dwarf, &unit, entry)? {
+                Some(source) => Some(source),
+                None => dwarf_source(&dwarf, &unit, &named)?,
+            };
+            // DWARF 4 and later record the size, older versions the end address
+            let len = match entry.attr_value(gimli::DW_AT_high_pc)? {
+                Some(AttributeValue::Udata(len)) => u32::try_from(len).ok(),
+                Some(high_pc) => dwarf
+                    .attr_address(&unit, high_pc)?
+                    .and_then(|high_pc| high_pc.checked_sub(address))
+                    .and_then(|len| u32::try_from(len).ok()),
+                None => None,
+            };
+            let function = Function {
+                module: module.clone(),
+                procedure: Some(mangled.clone()),
+                ..Function::new(&mangled, options.demangling, noreturn, source, len)
+            };
+            add_function(&mut functions, rva, function);
+        }
+    }
+    Ok(into_symbols(functions, options, &HashMap::new()))
+}
+
+/// Same as [`parse_pdb`], but names only the exported functions of the binary. Used when there is
+/// no other debug info, so that DLLs can still be targeted by export name. `None` when the binary
+/// exports no functions.
+pub fn parse_exports(binary_bytes: &[u8], options: &SymbolOptions) -> Option<Vec<u8>> {
+    let Ok(file) = object::read::File::parse(binary_bytes) else {
+        return None;
+    };
+    let mut funcs = parse_exports_impl(&file, options).ok()?;
+    if funcs.is_empty() {
+        return None;
+    }
+    if options.infer_noreturn {
+        infer_noreturn(binary_bytes, &mut funcs);
+    }
+    encode_with(&funcs, &options.header(), options)
+}
+
+pub fn parse_exports_impl(
+    file: &object::read::File,
+    options: &SymbolOptions,
+) -> object::Result<Vec<DebugSymbolInfo>> {
+    use object::{Object, ObjectSection, SectionKind};
+
+    let image_base = file.relative_address_base();
+    // Same as for PDBs: one name per address, the smallest demangled one wins
+    let mut functions: HashMap<u32, Function> = HashMap::default();
+    for export in file.exports()? {
+        // Exported variables live in data sections
+        let is_code = file.sections().any(|section| {
+            section.kind() == SectionKind::Text
+                && (section.address()..section.address() + section.size())
+                    .contains(&export.address())
+        });
+        if !is_code {
+            continue;
+        }
+        let Some(rva) = export
+            .address()
+            .checked_sub(image_base)
+            .and_then(|rva| u32::try_from(rva).ok())
+        else {
+            continue;
+        };
+        let mangled = String::from_utf8_lossy(export.name());
+        add_function(
+            &mut functions,
+            rva,
+            Function::new(&mangled, options.demangling, false, None, None),
+        );
+    }
+    Ok(into_symbols(functions, options, &HashMap::new()))
+}
+
+/// Shortest code, in bytes not covered by relocations, a library function needs to be looked for.
+/// Shorter ones are found in too many places.
+const MIN_LIBRARY_CODE: usize = 16;
+
+/// Same as [`parse_pdb`], but names the functions of the static libraries and object files of
+/// [`SymbolOptions::libraries`] by finding their code in the binary. `None` when none is found.
+pub fn parse_libraries(binary_bytes: &[u8], options: &SymbolOptions) -> Option<Vec<u8>> {
+    let Ok(file) = object::read::File::parse(binary_bytes) else {
+        return None;
+    };
+    let funcs = parse_libraries_impl(&file, options).ok()?;
+    if funcs.is_empty() {
+        return None;
+    }
+    encode_with(&funcs, &options.header(), options)
+}
+
+/// The functions of [`SymbolOptions::libraries`] whose code appears exactly once in the
+/// executable sections of `file`. Bytes covered by relocations differ once linked and are not
+/// compared. Objects built for link-time code generation (`/GL`) carry no code and are skipped.
+pub fn parse_libraries_impl(
+    file: &object::read::File,
+    options: &SymbolOptions,
+) -> io::Result<Vec<DebugSymbolInfo>> {
+    use object::{Object, ObjectSection, SectionKind};
+
+    let mut candidates = Vec::new();
+    for path in &options.libraries {
+        let data = std::fs::read(path)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+        let library = module_file_name(&path.to_string_lossy());
+        library_functions(&data, library, options.demangling, &mut candidates);
+    }
+    // Candidates by the first 8 bytes of their anchor, so the binary is scanned once for all
+    let mut anchors: HashMap<u64, Vec<usize>> = HashMap::default();
+    for (index, candidate) in candidates.iter().enumerate() {
+        anchors
+            .entry(read_u64(&candidate.code, candidate.anchor))
+            .or_default()
+            .push(index);
+    }
+    // Per candidate, `Some(Some(rva))` when found once, `Some(None)` when found more than once
+    let mut found: Vec<Option<Option<u32>>> = vec![None; candidates.len()];
+    let image_base = file.relative_address_base();
+    for section in file
+        .sections()
+        .filter(|section| section.kind() == SectionKind::Text)
+    {
+        let (Ok(data), Some(base)) = (section.data(), section.address().checked_sub(image_base))
+        else {
+            continue;
+        };
+        for at in 0..data.len().saturating_sub(7) {
+            let Some(indices) = anchors.get(&read_u64(data, at)) else {
+                continue;
+            };
+            for &index in indices {
+                let candidate = &candidates[index];
+                let Some(start) = at.checked_sub(candidate.anchor) else {
+                    continue;
+                };
+                let Some(code) = data.get(start..start + candidate.code.len()) else {
+                    continue;
+                };
+                if !candidate.matches(code) {
+                    continue;
+                }
+                let Ok(rva) = u32::try_from(base + start as u64) else {
+                    continue;
+                };
+                found[index] = match found[index] {
+                    None => Some(Some(rva)),
+                    Some(Some(first)) if first == rva => Some(Some(rva)),
+                    _ => Some(None),
+                };
+            }
+        }
+    }
+    let mut functions: HashMap<u32, Function> = HashMap::default();
+    for (candidate, found) in candidates.into_iter().zip(found) {
+        if let Some(Some(rva)) = found {
+            add_function(&mut functions, rva, candidate.function);
+        }
+    }
+    Ok(into_symbols(functions, options, &HashMap::new()))
+}
+
+/// Adds the functions of `extra`, such as those of [`parse_libraries_impl`], at the addresses
+/// `funcs` names none at, keeping `funcs` ordered by address. Those whose name `funcs` already
+/// uses are left out. Returns how many were added.
+pub fn merge_symbols(funcs: &mut Vec<DebugSymbolInfo>, extra: Vec<DebugSymbolInfo>) -> usize {
+    let addresses: HashSet<u32> = funcs.iter().map(|f| f.address).collect();
+    let mut names: HashSet<String> = funcs.iter().map(|f| f.name.clone()).collect();
+    let count = funcs.len();
+    for symbol in extra {
+        if !addresses.contains(&symbol.address) && names.insert(symbol.name.clone()) {
+            funcs.push(symbol);
+        }
+    }
+    funcs.sort_by_key(|f| f.address);
+    funcs.len() - count
+}
+
+/// A function of a static library or object file, to be found in a binary by its code.
+struct LibraryFunction {
+    function: Function,
+    code: Vec<u8>,
+    /// Which bytes of `code` are covered by relocations.
+    relocated: Vec<bool>,
+    /// Offset of the first 8 bytes of `code` without relocations.
+    anchor: usize,
+}
+
+impl LibraryFunction {
+    /// Whether `code`, as long as the function, is the function once linked.
+    fn matches(&self, code: &[u8]) -> bool {
+        code.iter()
+            .zip(&self.code)
+            .zip(&self.relocated)
+            .all(|((linked, own), relocated)| *relocated || linked == own)
+    }
+}
+
+/// Little endian `u64` at `offset` of `bytes`, which must hold it.
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    let mut value = [0; 8];
+    value.copy_from_slice(&bytes[offset..offset + 8]);
+    u64::from_le_bytes(value)
+}
+
+/// Adds the functions of the archive or object file `data`, named `library`, to `out`.
+fn library_functions(
+    data: &[u8],
+    library: Option<String>,
+    demangling: Demangling,
+    out: &mut Vec<LibraryFunction>,
+) {
+    match object::read::archive::ArchiveFile::parse(data) {
+        Ok(archive) => {
+            for member in archive.members().flatten() {
+                let Ok(member_data) = member.data(data) else {
+                    continue;
+                };
+                let module = module_file_name(&String::from_utf8_lossy(member.name()));
+                object_functions(member_data, module, library.clone(), demangling, out);
+            }
+        }
+        Err(_) => object_functions(data, library, None, demangling, out),
+    }
+}
+
+/// Adds the functions of the object file `data`, the module `module` of `library`, to `out`.
+/// Anything that is not an object file with code, such as the import descriptions of an import
+/// library, is skipped.
+fn object_functions(
+    data: &[u8],
+    module: Option<String>,
+    library: Option<String>,
+    demangling: Demangling,
+    out: &mut Vec<LibraryFunction>,
+) {
+    use object::{Object, ObjectSection, ObjectSymbol, SectionKind, SymbolKind};
+
+    let Ok(file) = object::read::File::parse(data) else {
+        return;
+    };
+    for section in file
+        .sections()
+        .filter(|section| section.kind() == SectionKind::Text)
+    {
+        let Ok(bytes) = section.data() else {
+            continue;
+        };
+        let mut relocated = vec![false; bytes.len()];
+        for (offset, relocation) in section.relocations() {
+            let len = match relocation.size() {
+                0 => 4,
+                bits => usize::from(bits / 8),
+            };
+            let start = (offset as usize).min(bytes.len());
+            for byte in &mut relocated[start..(start + len).min(bytes.len())] {
+                *byte = true;
+            }
+        }
+        // Functions end where the next one starts, or with the section
+        let mut starts: Vec<(usize, String)> = file
+            .symbols()
+            .filter(|symbol| {
+                symbol.section_index() == Some(section.index()) && symbol.kind() == SymbolKind::Text
+            })
+            .filter_map(|symbol| {
+                let offset = symbol.address().checked_sub(section.address())?;
+                Some((
+                    usize::try_from(offset).ok()?,
+                    symbol.name().ok()?.to_owned(),
+                ))
+            })
+            .filter(|(offset, name)| {
+                *offset < bytes.len() && !name.is_empty() && !name.starts_with(['$', '.'])
+            })
+            .collect();
+        starts.sort();
+        for (index, (start, name)) in starts.iter().enumerate() {
+            let mut end = starts[index + 1..]
+                .iter()
+                .map(|(next, _)| *next)
+                .find(|next| next > start)
+                .unwrap_or(bytes.len());
+            // Padding up to the next function is not part of this one
+            while end > *start && bytes[end - 1] == 0xCC && !relocated[end - 1] {
+                end -= 1;
+            }
+            let relocated = &relocated[*start..end];
+            if relocated.iter().filter(|relocated| !**relocated).count() < MIN_LIBRARY_CODE {
+                continue;
+            }
+            let Some(anchor) = relocated
+                .windows(8)
+                .position(|window| window.iter().all(|relocated| !relocated))
+            else {
+                continue;
+            };
+            let len = (end - start) as u32;
+            out.push(LibraryFunction {
+                function: Function {
+                    module: module.clone(),
+                    library: library.clone(),
+                    ..Function::new(name, demangling, false, None, Some(len))
+                },
+                code: bytes[*start..end].to_vec(),
+                relocated: relocated.to_vec(),
+                anchor,
+            });
+        }
+    }
+}
+
+/// Imports that never return.
+const NORETURN_IMPORTS: &[&[u8]] = &[
+    b"ExitProcess",
+    b"ExitThread",
+    b"FatalAppExitA",
+    b"FatalAppExitW",
+    b"FatalExit",
+    b"FreeLibraryAndExitThread",
+    b"RaiseFailFastException",
+    b"RtlExitUserProcess",
+    b"RtlExitUserThread",
+    b"RtlRaiseStatus",
+    b"ExRaiseStatus",
+    b"KeBugCheck",
+    b"KeBugCheckEx",
+    b"_CxxThrowException",
+    b"_Exit",
+    b"_exit",
+    b"_invalid_parameter_noinfo_noreturn",
+    b"_invoke_watson",
+    b"__std_terminate",
+    b"abort",
+    b"exit",
+    b"longjmp",
+    b"quick_exit",
+    b"terminate",
+];
+
+/// Marks functions of an x64 PE binary as `noreturn` from its machine code, for PDBs stripped of
+/// procedure flags and for symbols that carry none. Returns how many functions were marked.
+///
+/// Functions end where the unwind table (`.pdata`) says, or after `len` bytes. A function is
+/// noreturn when another one ends with a direct call to it, since compilers emit nothing but an
+/// `int3` after a call that does not return, or when it is a thunk jumping to a noreturn import
+/// or function. Other binaries are left as they are.
+pub fn infer_noreturn(binary_bytes: &[u8], funcs: &mut [DebugSymbolInfo]) -> usize {
+    use object::{
+        LittleEndian as LE, pe,
+        read::pe::{Import, PeFile64},
+    };
+
+    let Ok(file) = PeFile64::parse(binary_bytes) else {
+        return 0;
+    };
+    if file.nt_headers().file_header.machine.get(LE) != pe::IMAGE_FILE_MACHINE_AMD64 {
+        return 0;
+    }
+    let sections = file.section_table();
+    let code = |rva: u32| sections.pe_data_at(binary_bytes, rva).unwrap_or_default();
+    let rel32 = |bytes: &[u8]| {
+        bytes
+            .get(..4)
+            .map(|d| i32::from_le_bytes(d.try_into().unwrap()))
+    };
+
+    // IAT slots of noreturn imports
+    let mut slots = HashSet::new();
+    if let Ok(Some(imports)) = file.import_table()
+        && let Ok(mut descriptors) = imports.descriptors()
+    {
+        while let Ok(Some(descriptor)) = descriptors.next() {
+            let first_thunk = descriptor.first_thunk.get(LE);
+            let lookup = match descriptor.original_first_thunk.get(LE) {
+                0 => first_thunk,
+                lookup => lookup,
+            };
+            let Ok(mut thunks) = imports.thunks(lookup) else {
+                continue;
+            };
+            let mut slot = first_thunk;
+            while let Ok(Some(thunk)) = thunks.next::<pe::ImageNtHeaders64>() {
+                if let Ok(Import::Name(_, name)) = imports.import::<pe::ImageNtHeaders64>(thunk)
+                    && NORETURN_IMPORTS.contains(&name)
+                {
+                    slots.insert(slot);
+                }
+                slot = slot.wrapping_add(8);
+            }
+        }
+    }
+
+    let mut ends: HashMap<u32, u32> = funcs
+        .iter()
+        .filter_map(|f| Some((f.address, f.address.checked_add(f.len?)?)))
+        .collect();
+    if let Some(directory) = file.data_directory(pe::IMAGE_DIRECTORY_ENTRY_EXCEPTION)
+        && let Ok(data) = directory.data(binary_bytes, &sections)
+        && let Ok(entries) =
+            object::pod::slice_from_all_bytes::<pe::ImageRuntimeFunctionEntry>(data)
+    {
+        for entry in entries {
+            ends.insert(entry.begin_address.get(LE), entry.end_address.get(LE));
+        }
+    }
+
+    let starts: HashSet<u32> = funcs.iter().map(|f| f.address).collect();
+    let mut noreturn: HashSet<u32> = funcs
+        .iter()
+        .filter(|f| f.noreturn)
+        .map(|f| f.address)
+        .collect();
+    // A direct call as the last instruction, only followed by `int3`
+    for f in funcs.iter() {
+        let Some(&end) = ends.get(&f.address) else {
+            continue;
+        };
+        let Some(body) = code(f.address).get(..end.saturating_sub(f.address) as usize) else {
+            continue;
+        };
+        let body = &body[..body.iter().rposition(|&b| b != 0xCC).map_or(0, |i| i + 1)];
+        if let Some(call) = body.len().checked_sub(5).map(|at| &body[at..])
+            && call[0] == 0xE8
+            && let Some(disp) = rel32(&call[1..])
+        {
+            let target = f
+                .address
+                .wrapping_add(body.len() as u32)
+                .wrapping_add_signed(disp);
+            if starts.contains(&target) {
+                noreturn.insert(target);
+            }
+        }
+    }
+    // Thunks, until no more are found since they may jump to each other
+    loop {
+        let mut changed = false;
+        for f in funcs.iter() {
+            if noreturn.contains(&f.address) {
+                continue;
+            }
+            let body = code(f.address);
+            let (target, via_iat) = match body {
+                [0xFF, 0x25, disp @ ..] => (
+                    rel32(disp).map(|d| f.address.wrapping_add(6).wrapping_add_signed(d)),
+                    true,
+                ),
+                [0x48, 0xFF, 0x25, disp @ ..] => (
+                    rel32(disp).map(|d| f.address.wrapping_add(7).wrapping_add_signed(d)),
+                    true,
+                ),
+                [0xE9, disp @ ..] => (
+                    rel32(disp).map(|d| f.address.wrapping_add(5).wrapping_add_signed(d)),
+                    false,
+                ),
+                _ => (None, false),
+            };
+            let Some(target) = target else {
+                continue;
+            };
+            if (via_iat && slots.contains(&target)) || (!via_iat && noreturn.contains(&target)) {
+                noreturn.insert(f.address);
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    let mut marked = 0;
+    for f in funcs.iter_mut() {
+        if !f.noreturn && noreturn.contains(&f.address) {
+            f.noreturn = true;
+            marked += 1;
+        }
+    }
+    marked
+}
+
+/// Reverses [`encode_stream`], and so [`parse_pdb`], [`parse_exports`] and `parse_dwarf`, for
+/// streams with a header. Returns the header of the stream and its symbols.
+pub fn read_stream(stream: &[u8]) -> Result<(StreamHeader, Vec<DebugSymbolInfo>), StreamError> {
+    let (header, compressed) = StreamHeader::read(stream)?;
+    let serialized = zstd::decode_all(compressed).map_err(|_| StreamError::Corrupt)?;
+    let funcs = decode_serialized(&serialized, header.version).ok_or(StreamError::Corrupt)?;
+    Ok((header, funcs))
+}
+
+/// Same as [`read_stream`], without the header. Also reads streams written before the header was
+/// added, which do not record their version, so the newest one that reads the whole stream wins.
+pub fn decode_symbols(stream: &[u8]) -> Option<Vec<DebugSymbolInfo>> {
+    if !stream.starts_with(&STREAM_MAGIC) {
+        let serialized = zstd::decode_all(stream).ok()?;
+        return StreamVersion::ALL
+            .into_iter()
+            .rev()
+            .find_map(|version| decode_serialized(&serialized, version));
+    }
+    read_stream(stream).ok().map(|(_, funcs)| funcs)
+}
+
+/// Same as [`decode_symbols`], for a stream known to be of `version`. `None` if its header says
+/// otherwise.
+pub fn decode_symbols_as(stream: &[u8], version: StreamVersion) -> Option<Vec<DebugSymbolInfo>> {
+    if !stream.starts_with(&STREAM_MAGIC) {
+        let serialized = zstd::decode_all(stream).ok()?;
+        return decode_serialized(&serialized, version);
+    }
+    read_stream(stream)
+        .ok()
+        .filter(|(header, _)| header.version == version)
+        .map(|(_, funcs)| funcs)
+}
+
+/// Decodes decompressed symbols of `version`, `None` unless they fill `serialized` exactly.
+fn decode_serialized(serialized: &[u8], version: StreamVersion) -> Option<Vec<DebugSymbolInfo>> {
+    let config = bincode::config::standard();
+    let (funcs, read) = match version {
+        StreamVersion::V1 => {
+            let (symbols, read): (Vec<SymbolV1>, usize) =
+                bincode::decode_from_slice(serialized, config).ok()?;
+            (
+                symbols.into_iter().map(DebugSymbolInfo::from).collect(),
+                read,
+            )
+        }
+        StreamVersion::V2 => {
+            let (symbols, read): (Vec<SymbolV2>, usize) =
+                bincode::decode_from_slice(serialized, config).ok()?;
+            (
+                symbols.into_iter().map(DebugSymbolInfo::from).collect(),
+                read,
+            )
+        }
+        StreamVersion::V3 => {
+            let (symbols, read): (Vec<SymbolV3>, usize) =
+                bincode::decode_from_slice(serialized, config).ok()?;
+            (
+                symbols.into_iter().map(DebugSymbolInfo::from).collect(),
+                read,
+            )
+        }
+        StreamVersion::V4 => {
+            let (symbols, read): (Vec<SymbolV4>, usize) =
+                bincode::decode_from_slice(serialized, config).ok()?;
+            (
+                symbols.into_iter().map(DebugSymbolInfo::from).collect(),
+                read,
+            )
+        }
+        StreamVersion::V5 => {
+            let (symbols, read): (Vec<SymbolV5>, usize) =
+                bincode::decode_from_slice(serialized, config).ok()?;
+            (
+                symbols.into_iter().map(DebugSymbolInfo::from).collect(),
+                read,
+            )
+        }
+    };
+    (read == serialized.len()).then_some(funcs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn symbols() -> Vec<DebugSymbolInfo> {
+        vec![
+            DebugSymbolInfo {
+                address: 0x1000,
+                name: "main".to_owned(),
+                noreturn: false,
+                source: None,
+                len: Some(0x20),
+                mangled: None,
+                inlinees: Vec::new(),
+            },
+            DebugSymbolInfo {
+                address: 0x1020,
+                name: "abort".to_owned(),
+                noreturn: true,
+                source: None,
+                len: Some(0x10),
+                mangled: None,
+                inlinees: Vec::new(),
+            },
+        ]
+    }
+
+    #[test]
+    fn v1_stream_is_a_bare_zstd_frame() {
+        let funcs = symbols();
+        let stream = encode_symbols(&funcs, StreamVersion::V1).unwrap();
+        assert!(!stream.starts_with(&STREAM_MAGIC));
+        // What the service read before the header was added
+        let (legacy, _): (Vec<SymbolV1>, usize) = bincode::decode_from_slice(
+            &zstd::decode_all(&stream[..]).unwrap(),
+            bincode::config::standard(),
+        )
+        .unwrap();
+        assert_eq!(legacy.len(), funcs.len());
+        let decoded = decode_symbols_as(&stream, StreamVersion::V1).unwrap();
+        assert_eq!(decoded[1].name, "abort");
+        assert!(decoded[1].noreturn);
+        assert_eq!(decoded[0].len, None);
+    }
+
+    #[test]
+    fn headered_stream_round_trips() {
+        let funcs = symbols();
+        let mut header = StreamHeader::new(StreamVersion::V3);
+        header.flags = StreamHeader::NORETURN_INFERRED;
+        let stream = encode_stream(&funcs, &header, &Compression::default()).unwrap();
+        assert!(stream.starts_with(&STREAM_MAGIC));
+        let (read, decoded) = read_stream(&stream).unwrap();
+        assert_eq!(read, header);
+        assert_eq!(decoded, funcs);
+        assert_eq!(decode_symbols(&stream).unwrap(), funcs);
+    }
+
+    #[test]
+    fn headerless_option_leaves_the_header_out() {
+        let funcs = symbols();
+        let options = SymbolOptions {
+            version: StreamVersion::V3,
+            headerless: true,
+            ..SymbolOptions::default()
+        };
+        let stream = encode_with(&funcs, &options.header(), &options).unwrap();
+        assert!(!stream.starts_with(&STREAM_MAGIC));
+        assert_eq!(read_stream(&stream), Err(StreamError::NotAStream));
+        assert_eq!(
+            decode_symbols_as(&stream, StreamVersion::V3).unwrap(),
+            funcs
+        );
+    }
+
+    #[test]
+    fn ranges_at_the_end_of_the_address_space_merge() {
+        let range = |address, len| CodeRange { address, len };
+        assert_eq!(
+            merge_ranges(vec![
+                range(u32::MAX - 4, 8),
+                range(0x10, 4),
+                range(u32::MAX - 8, 6),
+                range(0x14, 2),
+            ]),
+            vec![range(0x10, 6), range(u32::MAX - 8, 12)]
+        );
+    }
+}